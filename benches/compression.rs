@@ -0,0 +1,68 @@
+// sizes and timings of compressing snapshots, run with
+// `cargo bench --features compression`
+use std::time::Instant;
+
+use rsp::codec::{Compression, Compressor, Envelope};
+use rsp::{Snapshot, SnapshotEntry};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+struct Doggo {
+    id: u32,
+    name: String,
+    breed: String,
+    good: bool,
+}
+
+const RUNS: u32 = 20;
+
+fn snapshot(records: u32) -> Snapshot<u32, Doggo, String> {
+    let breeds = ["Poodle", "Beagle", "Labrador", "Dachshund"];
+    let records = (0..records)
+        .map(|id| {
+            let doggo = Doggo {
+                id,
+                name: format!("Doggo number {id}"),
+                breed: breeds[id as usize % breeds.len()].to_owned(),
+                good: true,
+            };
+            SnapshotEntry::new(id, doggo)
+        })
+        .collect();
+    Snapshot::new("dogs".to_owned(), 0, records)
+}
+
+fn main() {
+    println!("records  codec    bytes    ratio  seal/run  open/run");
+    for records in [100, 1_000, 10_000] {
+        let snapshot = snapshot(records);
+        let plain = serde_json::to_vec(&snapshot).unwrap().len();
+        println!("{records:>7}  json   {plain:>7}   1.00");
+
+        for compression in [Compression::Deflate, Compression::Gzip] {
+            let compressor = Compressor::new(compression);
+            let started = Instant::now();
+            for _ in 0..RUNS {
+                compressor.seal(&snapshot).unwrap();
+            }
+            let seal = started.elapsed() / RUNS;
+
+            let json = serde_json::to_string(&compressor.seal(&snapshot).unwrap()).unwrap();
+            let started = Instant::now();
+            for _ in 0..RUNS {
+                let envelope: Envelope<Snapshot<u32, Doggo, String>> =
+                    serde_json::from_str(&json).unwrap();
+                envelope.open(plain).unwrap();
+            }
+            let open = started.elapsed() / RUNS;
+
+            println!(
+                "{records:>7}  {:<7}{:>7}   {:.2}  {seal:>8.2?}  {open:>8.2?}",
+                compression.name(),
+                json.len(),
+                json.len() as f64 / plain as f64,
+            );
+        }
+    }
+}
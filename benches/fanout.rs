@@ -0,0 +1,104 @@
+// cpu time of fanning events out to many listeners that each need the json,
+// encoding per listener versus once with `publish_serialized`. run with
+// `cargo bench --bench fanout`
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use rsp::{
+    Appendable, BroadcastService, Event, Listener, PublishSerialized, Serialized, Service, Syncable,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+struct Doggo {
+    id: u32,
+    name: String,
+    breed: String,
+}
+
+impl Appendable for Doggo {
+    type Collection = String;
+
+    fn collection(&self) -> String {
+        "dogs".to_owned()
+    }
+}
+
+impl Syncable for Doggo {
+    type Id = u32;
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+type DoggoEvent = Event<u32, Doggo, String>;
+
+const EVENTS: u32 = 100;
+
+fn doggo(id: u32) -> DoggoEvent {
+    Doggo {
+        id,
+        name: format!("Doggo number {id}"),
+        breed: "Poodle".to_owned(),
+    }
+    .to_upsert_event()
+}
+
+// every event is already buffered when a listener asks, so one poll is enough
+fn ready<F: Future>(future: F) -> F::Output {
+    let mut cx = Context::from_waker(Waker::noop());
+    match pin!(future).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("listener had nothing buffered"),
+    }
+}
+
+fn per_listener(listeners: usize) -> (Duration, usize) {
+    let service = BroadcastService::with_capacity(EVENTS as usize);
+    let mut listeners: Vec<_> = (0..listeners).map(|_| service.listener()).collect();
+    let started = Instant::now();
+    for id in 0..EVENTS {
+        service.publish(doggo(id)).unwrap();
+    }
+    let mut bytes = 0;
+    for listener in &mut listeners {
+        for _ in 0..EVENTS {
+            let event = ready(listener.recv()).unwrap();
+            bytes += event.into_ws_body().try_json().unwrap().len();
+        }
+    }
+    (started.elapsed(), bytes)
+}
+
+fn shared(listeners: usize) -> (Duration, usize) {
+    let service = BroadcastService::<Serialized<DoggoEvent>>::with_capacity(EVENTS as usize);
+    let mut listeners: Vec<_> = (0..listeners).map(|_| service.listener()).collect();
+    let started = Instant::now();
+    for id in 0..EVENTS {
+        service.publish_serialized(doggo(id)).unwrap();
+    }
+    let mut bytes = 0;
+    for listener in &mut listeners {
+        for _ in 0..EVENTS {
+            bytes += ready(listener.recv()).unwrap().json().unwrap().len();
+        }
+    }
+    (started.elapsed(), bytes)
+}
+
+fn main() {
+    println!("listeners  per listener      shared  speedup");
+    for listeners in [1, 10, 100, 1_000] {
+        let (per_listener, expected) = per_listener(listeners);
+        let (shared, bytes) = shared(listeners);
+        assert_eq!(bytes, expected);
+        println!(
+            "{listeners:>9}  {per_listener:>12.2?}  {shared:>10.2?}  {:>6.1}x",
+            per_listener.as_secs_f64() / shared.as_secs_f64()
+        );
+    }
+}
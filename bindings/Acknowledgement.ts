@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Ack } from "./Ack";
+import type { Nack } from "./Nack";
+
+export type Acknowledgement = { "type": "ack", "payload": Ack } | { "type": "nack", "payload": Nack };
\ No newline at end of file
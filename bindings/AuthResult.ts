@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Claims } from "./Claims";
+
+export type AuthResult<C> = { "type": "accepted", "payload": Claims<C> } | { "type": "rejected", "payload": string };
\ No newline at end of file
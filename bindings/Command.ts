@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Acknowledgement } from "./Acknowledgement";
+import type { Authenticate } from "./Authenticate";
+import type { Ephemeral } from "./Ephemeral";
+import type { Join } from "./Join";
+import type { Leave } from "./Leave";
+import type { LiveQuery } from "./LiveQuery";
+import type { Mutate } from "./Mutate";
+import type { Ping } from "./Ping";
+import type { Pong } from "./Pong";
+import type { PresenceHeartbeat } from "./PresenceHeartbeat";
+import type { Query } from "./Query";
+import type { Subscribe } from "./Subscribe";
+import type { Unsubscribe } from "./Unsubscribe";
+
+export type Command<ID, T, C, P = never> = { "type": "subscribe", "payload": Subscribe<C> } | { "type": "unsubscribe", "payload": Unsubscribe<C> } | { "type": "query", "payload": Query<ID, C> } | { "type": "live_query", "payload": LiveQuery<C> } | { "type": "mutate", "payload": Mutate<ID, T, C, P> } | { "type": "ack", "payload": Acknowledgement } | { "type": "ping", "payload": Ping } | { "type": "pong", "payload": Pong } | { "type": "authenticate", "payload": Authenticate } | { "type": "join", "payload": Join } | { "type": "leave", "payload": Leave } | { "type": "presence_heartbeat", "payload": PresenceHeartbeat } | { "type": "ephemeral", "payload": Ephemeral<ID, C> };
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ConflictError<ID, C> { collection: C, id: ID | null, expected_revision: number, current_revision: number | null, }
\ No newline at end of file
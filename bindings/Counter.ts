@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { VectorClock } from "./VectorClock";
+
+export interface Counter<ID, C> { id: ID, collection: C, increments: VectorClock, decrements: VectorClock, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Location } from "./Location";
+
+export interface DeletableResource<ID, C> { location: Location<ID, C>, deleted_at?: number, }
\ No newline at end of file
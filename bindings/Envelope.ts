@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Compressed } from "./Compressed";
+
+export type Envelope<T> = { "type": "plain", "payload": T } | { "type": "compressed", "payload": Compressed };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Scope } from "./Scope";
+
+export interface Ephemeral<ID, C> { collection: C, id?: ID, data: unknown, origin?: string, scope?: Scope, }
\ No newline at end of file
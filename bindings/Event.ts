@@ -1,4 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EventMeta } from "./EventMeta";
 import type { EventVerb } from "./EventVerb";
+import type { Scope } from "./Scope";
+import type { VectorClock } from "./VectorClock";
 
-export interface Event<ID, T, C> { verb: EventVerb<ID, T, C>, }
\ No newline at end of file
+export interface Event<ID, T, C, P = never> { seq?: number, occurred_at?: number, schema_version?: number, scope?: Scope, idempotency_key?: string, expires_at?: number, meta?: EventMeta, causality?: VectorClock, verb: EventVerb<ID, T, C, P>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Event } from "./Event";
+
+export interface EventBatch<ID, T, C, P = never> { seq: number, txn_id: number | null, events: Array<Event<ID, T, C, P>>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface EventMeta { actor_id?: string, origin?: string, correlation_id?: string, causation_id?: string, extra: Record<string, unknown>, }
\ No newline at end of file
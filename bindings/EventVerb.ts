@@ -1,6 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { AppendableResource } from "./AppendableResource";
-import type { ResourceId } from "./ResourceId";
+import type { DeletableResource } from "./DeletableResource";
+import type { PatchResource } from "./PatchResource";
 import type { UpdatableResource } from "./UpdatableResource";
 
-export type EventVerb<ID, T, C> = { "type": "insert", "payload": AppendableResource<ID, T, C> } | { "type": "update", "payload": UpdatableResource<ID, T, C> } | { "type": "upsert", "payload": UpdatableResource<ID, T, C> } | { "type": "delete", "payload": ResourceId };
\ No newline at end of file
+export type EventVerb<ID, T, C, P = never> = { "type": "insert", "payload": AppendableResource<ID, T, C> } | { "type": "update", "payload": UpdatableResource<ID, T, C> } | { "type": "upsert", "payload": UpdatableResource<ID, T, C> } | { "type": "patch", "payload": PatchResource<ID, P, C> } | { "type": "delete", "payload": DeletableResource<ID, C> } | { "type": "txn_begin", "payload": number } | { "type": "txn_commit", "payload": number } | { "type": "txn_abort", "payload": number };
\ No newline at end of file
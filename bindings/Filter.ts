@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Filter = { "op": "eq", field: string, value: unknown, } | { "op": "ne", field: string, value: unknown, } | { "op": "lt", field: string, value: unknown, } | { "op": "lte", field: string, value: unknown, } | { "op": "gt", field: string, value: unknown, } | { "op": "gte", field: string, value: unknown, } | { "op": "in", field: string, values: Array<unknown>, } | { "op": "and", filters: Array<Filter>, } | { "op": "or", filters: Array<Filter>, } | { "op": "not", filter: Filter, };
\ No newline at end of file
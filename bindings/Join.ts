@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PresenceState } from "./PresenceState";
+
+export interface Join { room: string, state: PresenceState, }
\ No newline at end of file
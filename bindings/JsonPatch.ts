@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PatchOperation } from "./PatchOperation";
+
+export type JsonPatch = Array<PatchOperation>;
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Filter } from "./Filter";
+
+export interface LiveQuery<C> { query_id: number, collection: C, filter: Filter, }
\ No newline at end of file
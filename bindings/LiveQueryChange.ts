@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type LiveQueryChange<ID, T> = { "type": "added", id: ID, data: T, } | { "type": "updated", id: ID, data: T, } | { "type": "removed", id: ID, } | { "type": "entered_result_set", id: ID, data: T, } | { "type": "left_result_set", id: ID, };
\ No newline at end of file
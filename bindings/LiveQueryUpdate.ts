@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LiveQueryChange } from "./LiveQueryChange";
+
+export interface LiveQueryUpdate<ID, T> { query_id: number, seq?: number, change: LiveQueryChange<ID, T>, }
\ No newline at end of file
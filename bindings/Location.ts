@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export interface Location<ID, C> { id: ID | null, txn_id: number | null, collection: C, }
\ No newline at end of file
+export interface Location<ID, C> { id: ID | null, txn_id: number | null, collection: C, revision?: number, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface LwwRegister<ID, V, C> { id: ID, collection: C, value: V, timestamp: number, node: string, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MutationStatus } from "./MutationStatus";
+
+export interface MutationResult<ID, C> { request_id: number, status: MutationStatus<ID, C>, assigned_id?: ID, seq?: number, }
\ No newline at end of file
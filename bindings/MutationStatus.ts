@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Rejection } from "./Rejection";
+
+export type MutationStatus<ID, C> = { "type": "accepted" } | { "type": "rejected", "payload": Rejection<ID, C> };
\ No newline at end of file
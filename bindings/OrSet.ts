@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Dot } from "./Dot";
+import type { OrSetEntry } from "./OrSetEntry";
+import type { VectorClock } from "./VectorClock";
+
+export interface OrSet<ID, V, C> { id: ID, collection: C, entries: Array<OrSetEntry<V>>, removed: Array<Dot>, clock: VectorClock, }
\ No newline at end of file
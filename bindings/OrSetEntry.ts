@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Dot } from "./Dot";
+
+export interface OrSetEntry<V> { value: V, dot: Dot, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PatchOperation = { "op": "add", path: string, value: unknown, } | { "op": "remove", path: string, } | { "op": "replace", path: string, value: unknown, } | { "op": "move", from: string, path: string, } | { "op": "copy", from: string, path: string, } | { "op": "test", path: string, value: unknown, };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PresenceEntry } from "./PresenceEntry";
+
+export interface PresenceDelta { room: string, joins: Array<PresenceEntry>, leaves: Array<string>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PresenceStatus } from "./PresenceStatus";
+
+export interface PresenceState { status: PresenceStatus, payload?: unknown, }
\ No newline at end of file
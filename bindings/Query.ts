@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface Query<ID, C> { request_id: number, collection: C, ids?: Array<ID>, filter?: Record<string, unknown>, cursor?: string, limit?: number, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SnapshotEntry } from "./SnapshotEntry";
+
+export interface QueryResult<ID, T, C> { request_id: number, collection: C, seq: number, records: Array<SnapshotEntry<ID, T>>, next_cursor?: string, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConflictError } from "./ConflictError";
+import type { ValidationErrors } from "./ValidationErrors";
+
+export type Rejection<ID, C> = { "type": "conflict", "payload": ConflictError<ID, C> } | { "type": "not_found" } | { "type": "forbidden" } | { "type": "invalid", "payload": string } | { "type": "validation", "payload": ValidationErrors };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SchemaEntry } from "./SchemaEntry";
+
+export interface SchemaManifest { schemas: Array<SchemaEntry>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SnapshotEntry } from "./SnapshotEntry";
+
+export interface SnapshotChunk<ID, T, C> { collection: C, seq: number, idx: number, total: number, records: Array<SnapshotEntry<ID, T>>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CollectionPattern } from "./CollectionPattern";
+
+export interface Unsubscribe<C> { collections: Array<C>, patterns: Array<CollectionPattern>, }
\ No newline at end of file
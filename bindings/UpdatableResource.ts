@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Location } from "./Location";
 
-export interface UpdatableResource<ID, T, C> { location: Location<ID, C>, data: T, }
\ No newline at end of file
+export interface UpdatableResource<ID, T, C> { location: Location<ID, C>, data: T, expected_revision?: number, }
\ No newline at end of file
@@ -0,0 +1,14 @@
+fn main() {
+    // the messages and the `EventStream` server of `proto/rsp.proto`, see
+    // `rsp::proto`
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/rsp.proto");
+        let descriptors =
+            protox::compile(["rsp.proto"], ["proto"]).expect("proto/rsp.proto does not compile");
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("failed to generate the grpc service");
+    }
+}
@@ -0,0 +1,24 @@
+// client frames as the server decodes them, in every codec. the first byte
+// picks the codec, the rest is the frame. run with
+// `cargo +nightly fuzz run decode_envelope` from the crate's root
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsp::codec::{CborCodec, JsonCodec, MessagePackCodec, StyledJsonCodec, WireStyle};
+use rsp::testing::Probe;
+use rsp::{decode_envelope, AnyMessage, Error, JsonPatch};
+
+type Message = AnyMessage<u64, Probe, String, JsonPatch>;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((codec, frame)) = data.split_first() else {
+        return;
+    };
+    // malformed frames must come back as errors, never panics
+    let _: Result<Message, Error> = match codec % 4 {
+        0 => decode_envelope(frame, &JsonCodec),
+        1 => decode_envelope(frame, &StyledJsonCodec::new(WireStyle::CamelCase)),
+        2 => decode_envelope(frame, &MessagePackCodec),
+        _ => decode_envelope(frame, &CborCodec),
+    };
+});
@@ -0,0 +1,17 @@
+// the length-prefixed frames of `rsp::framing`, as read off a byte stream
+// and then decoded as client messages. run with
+// `cargo +nightly fuzz run read_frame` from the crate's root
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsp::framing::FrameCodec;
+use rsp::testing::Probe;
+use rsp::{Command, JsonPatch, WsBody};
+
+fuzz_target!(|data: &[u8]| {
+    let codec = FrameCodec::new().with_max_frame_len(64 * 1024);
+    let mut src = data.to_vec();
+    while let Ok(Some(frame)) = codec.decode(&mut src) {
+        let _ = frame.decode::<WsBody<Command<u64, Probe, String, JsonPatch>>>();
+    }
+});
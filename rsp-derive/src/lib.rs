@@ -0,0 +1,323 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, Fields, Lit, Meta};
+
+// `#[derive(Appendable)]` with `#[rsp(collection = "Dogs")]` uses a `String`
+// collection; `#[rsp(collection = Collection::Dogs)]` uses the enum the
+// variant belongs to
+#[proc_macro_derive(Appendable, attributes(rsp))]
+pub fn derive_appendable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_appendable(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// `#[derive(Syncable)]` uses the field marked `#[rsp(id)]`, or the field
+// named `id` when none is marked
+#[proc_macro_derive(Syncable, attributes(rsp))]
+pub fn derive_syncable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_syncable(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// `#[derive(TsDocs)]` collects the doc comments of the type and of its fields
+// or variants, under the names serde gives them, for `rsp::tsgen` to carry
+// into the typescript bindings
+#[proc_macro_derive(TsDocs)]
+pub fn derive_ts_docs(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_ts_docs(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_appendable(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut collection = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("rsp"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("collection") {
+                collection = Some(meta.value()?.parse::<Expr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported rsp attribute"))
+            }
+        })?;
+    }
+
+    let collection = collection.ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, "missing `#[rsp(collection = ...)]` attribute")
+    })?;
+
+    let (collection_type, collection_value) = match &collection {
+        Expr::Lit(expr) if matches!(expr.lit, Lit::Str(_)) => (
+            quote!(::std::string::String),
+            quote!(::std::string::ToString::to_string(#collection)),
+        ),
+        Expr::Path(expr) if expr.path.segments.len() > 1 => {
+            let mut path = expr.path.clone();
+            path.segments.pop();
+            path.segments.pop_punct();
+            (quote!(#path), quote!(#collection))
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                collection,
+                "expected a string literal or an enum variant path",
+            ))
+        }
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rsp::Appendable for #ident #ty_generics #where_clause {
+            type Collection = #collection_type;
+
+            fn collection(&self) -> Self::Collection {
+                #collection_value
+            }
+        }
+    })
+}
+
+fn expand_syncable(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Syncable can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Syncable can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut marked = None;
+    for field in fields {
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("rsp"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    if marked.is_some() {
+                        return Err(meta.error("only one field can be marked `#[rsp(id)]`"));
+                    }
+                    marked = Some(field);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported rsp attribute"))
+                }
+            })?;
+        }
+    }
+
+    let id_field = marked
+        .or_else(|| {
+            fields
+                .iter()
+                .find(|field| field.ident.as_ref().is_some_and(|ident| ident == "id"))
+        })
+        .ok_or_else(|| {
+            syn::Error::new_spanned(
+                &input.ident,
+                "no `id` field; mark the id field with `#[rsp(id)]`",
+            )
+        })?;
+
+    let ident = &input.ident;
+    let id_ident = &id_field.ident;
+    let id_type = &id_field.ty;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rsp::Syncable for #ident #ty_generics #where_clause {
+            type Id = #id_type;
+
+            fn id(&self) -> Self::Id {
+                ::std::clone::Clone::clone(&self.#id_ident)
+            }
+        }
+    })
+}
+
+fn expand_ts_docs(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let rename_all = serde_value(&input.attrs, "rename_all")?;
+    let mut fields = Vec::new();
+    let mut variants = Vec::new();
+    match &input.data {
+        Data::Struct(data) => {
+            for field in &data.fields {
+                let (Some(ident), Some(doc)) = (&field.ident, doc_comment(&field.attrs)) else {
+                    continue;
+                };
+                let name = match serde_value(&field.attrs, "rename")? {
+                    Some(name) => name,
+                    None => rename_field(&ident.to_string(), rename_all.as_deref()),
+                };
+                fields.push(quote!((#name, #doc)));
+            }
+        }
+        Data::Enum(data) => {
+            for variant in &data.variants {
+                let Some(doc) = doc_comment(&variant.attrs) else {
+                    continue;
+                };
+                let name = match serde_value(&variant.attrs, "rename")? {
+                    Some(name) => name,
+                    None => rename_variant(&variant.ident.to_string(), rename_all.as_deref()),
+                };
+                variants.push(quote!((#name, #doc)));
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "TsDocs can only be derived for structs and enums",
+            ))
+        }
+    }
+
+    let doc = match doc_comment(&input.attrs) {
+        Some(doc) => quote!(::std::option::Option::Some(#doc)),
+        None => quote!(::std::option::Option::None),
+    };
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rsp::tsgen::TsDocs for #ident #ty_generics #where_clause {
+            fn docs() -> ::rsp::tsgen::TypeDocs {
+                ::rsp::tsgen::TypeDocs {
+                    doc: #doc,
+                    fields: &[#(#fields),*],
+                    variants: &[#(#variants),*],
+                }
+            }
+        }
+    })
+}
+
+// the `///` lines, without the space after the slashes
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<_> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(expr) => match &expr.lit {
+                    Lit::Str(line) => Some(line.value()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| {
+            line.strip_prefix(' ')
+                .unwrap_or(&line)
+                .trim_end()
+                .to_owned()
+        })
+        .collect();
+    let doc = lines.join("\n").trim().to_owned();
+    (!doc.is_empty()).then_some(doc)
+}
+
+// `#[serde(key = "value")]`, skipping every other serde attribute
+fn serde_value(attrs: &[Attribute], key: &str) -> syn::Result<Option<String>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.input.peek(syn::Token![=]) {
+                let expr = meta.value()?.parse::<Expr>()?;
+                if let (true, Expr::Lit(expr)) = (meta.path.is_ident(key), expr) {
+                    if let Lit::Str(lit) = expr.lit {
+                        value = Some(lit.value());
+                    }
+                }
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.parse_nested_meta(|nested| {
+                    if nested.input.peek(syn::Token![=]) {
+                        nested.value()?.parse::<Expr>()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(value)
+}
+
+// serde's `rename_all` rules, for the snake_case names of fields
+fn rename_field(field: &str, rule: Option<&str>) -> String {
+    match rule {
+        Some("UPPERCASE" | "SCREAMING_SNAKE_CASE") => field.to_ascii_uppercase(),
+        Some("PascalCase") => pascal_case(field),
+        Some("camelCase") => {
+            let pascal = pascal_case(field);
+            let mut chars = pascal.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_ascii_lowercase().to_string() + chars.as_str()
+            })
+        }
+        Some("kebab-case") => field.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => field.to_ascii_uppercase().replace('_', "-"),
+        _ => field.to_owned(),
+    }
+}
+
+// and for the PascalCase names of variants
+fn rename_variant(variant: &str, rule: Option<&str>) -> String {
+    let snake = || {
+        let mut snake = String::new();
+        for (index, c) in variant.char_indices() {
+            if index > 0 && c.is_uppercase() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+    match rule {
+        Some("lowercase") => variant.to_ascii_lowercase(),
+        Some("UPPERCASE") => variant.to_ascii_uppercase(),
+        Some("camelCase") => {
+            let mut chars = variant.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_ascii_lowercase().to_string() + chars.as_str()
+            })
+        }
+        Some("snake_case") => snake(),
+        Some("SCREAMING_SNAKE_CASE") => snake().to_ascii_uppercase(),
+        Some("kebab-case") => snake().replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => snake().to_ascii_uppercase().replace('_', "-"),
+        _ => variant.to_owned(),
+    }
+}
+
+fn pascal_case(snake: &str) -> String {
+    snake
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_ascii_uppercase().to_string() + chars.as_str()
+            })
+        })
+        .collect()
+}
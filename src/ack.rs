@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Listener, Sequenced};
+
+const DEFAULT_MAX_UNACKED: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Ack {
+    #[ts(type = "number")]
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Nack {
+    #[ts(type = "number")]
+    pub seq: u64,
+    pub reason: Option<String>,
+}
+
+// client -> server acknowledgement of a delivered event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+#[ts(export)]
+pub enum Acknowledgement {
+    Ack(Ack),
+    Nack(Nack),
+}
+
+// retains every event it yields until it is acked. nacked events, and all
+// unacked events after `redeliver_unacked`, are yielded again before anything
+// new. once `max_unacked` events are waiting for acks it yields nothing new
+// until some are acked, leaving the rest to back up in `inner`
+pub struct AckListener<L: Listener> {
+    inner: L,
+    unacked: BTreeMap<u64, L::Item>,
+    redeliver: VecDeque<u64>,
+    max_unacked: usize,
+}
+
+impl<L: Listener> AckListener<L> {
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            unacked: BTreeMap::new(),
+            redeliver: VecDeque::new(),
+            max_unacked: DEFAULT_MAX_UNACKED,
+        }
+    }
+
+    pub fn with_max_unacked(mut self, max_unacked: usize) -> Self {
+        assert!(max_unacked > 0, "max unacked must be greater than zero");
+        self.max_unacked = max_unacked;
+        self
+    }
+
+    pub fn ack(&mut self, seq: u64) {
+        self.unacked.remove(&seq);
+    }
+
+    pub fn nack(&mut self, seq: u64) {
+        if self.unacked.contains_key(&seq) && !self.redeliver.contains(&seq) {
+            self.redeliver.push_back(seq);
+        }
+    }
+
+    pub fn handle(&mut self, acknowledgement: &Acknowledgement) {
+        match acknowledgement {
+            Acknowledgement::Ack(ack) => self.ack(ack.seq),
+            Acknowledgement::Nack(nack) => self.nack(nack.seq),
+        }
+    }
+
+    // e.g. after the client reconnected
+    pub fn redeliver_unacked(&mut self) {
+        self.redeliver = self.unacked.keys().copied().collect();
+    }
+
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+
+    // whether it's holding new events back until the client acks
+    pub fn is_full(&self) -> bool {
+        self.unacked.len() >= self.max_unacked
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for AckListener<L>
+where
+    L: Listener + Send,
+    L::Item: Clone + Sequenced + Send,
+{
+    type Error = L::Error;
+    type Item = L::Item;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        while let Some(seq) = self.redeliver.pop_front() {
+            // skip events acked after they were queued for redelivery
+            if let Some(event) = self.unacked.get(&seq) {
+                return Ok(event.clone());
+            }
+        }
+
+        // acks come in through `&mut self`, so this stays pending until the
+        // caller drops it to handle one, as a select over the socket does
+        if self.is_full() {
+            return std::future::pending().await;
+        }
+        let event = self.inner.recv().await?;
+        if let Some(seq) = event.seq() {
+            self.unacked.insert(seq, event.clone());
+        }
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    use crate::test::{block_on, Collection};
+    use crate::{Acknowledgement, BroadcastService, Event, Listener, Service, WsBody};
+
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        std::pin::pin!(future)
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn nacked_events_are_redelivered() {
+        let service = BroadcastService::new();
+        let mut listener = service.acked_listener();
+        for id in 1..=2 {
+            service
+                .publish(Event::<u32, (), Collection>::new_delete_event(
+                    id,
+                    Collection::Dogs,
+                ))
+                .unwrap();
+        }
+
+        block_on(async {
+            assert_eq!(listener.recv().await.unwrap().seq(), Some(0));
+            assert_eq!(listener.recv().await.unwrap().seq(), Some(1));
+
+            let nack = WsBody::<Acknowledgement>::from_json(
+                r#"{"data":{"type":"nack","payload":{"seq":0,"reason":"busy"}}}"#,
+            )
+            .unwrap();
+            listener.handle(&nack.into_data());
+            listener.ack(1);
+            assert_eq!(listener.recv().await.unwrap().seq(), Some(0));
+            assert_eq!(listener.unacked(), 1);
+        });
+    }
+
+    #[test]
+    fn clients_that_dont_ack_are_held_back() {
+        let service = BroadcastService::new();
+        let mut listener = service.acked_listener().with_max_unacked(2);
+        for id in 1..=3 {
+            service
+                .publish(Event::<u32, (), Collection>::new_delete_event(
+                    id,
+                    Collection::Dogs,
+                ))
+                .unwrap();
+        }
+
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(1));
+        assert!(listener.is_full());
+        assert!(poll_once(listener.recv()).is_pending());
+        // redelivery isn't held back
+        listener.nack(1);
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(1));
+
+        listener.ack(0);
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(2));
+        assert_eq!(listener.unacked(), 2);
+    }
+}
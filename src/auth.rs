@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Error, WsBody};
+
+// client -> server, right after the handshake and again whenever the token is
+// refreshed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Authenticate {
+    pub token: String,
+}
+
+// what a token lets its connection do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Claims<C> {
+    // who the token was issued to
+    subject: String,
+    // the collections the connection may read. `None` for all of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collections: Option<Vec<C>>,
+}
+
+impl<C> Claims<C> {
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            collections: None,
+        }
+    }
+
+    pub fn with_collections(mut self, collections: Vec<C>) -> Self {
+        self.collections = Some(collections);
+        self
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn collections(&self) -> Option<&[C]> {
+        self.collections.as_deref()
+    }
+
+    pub fn can_read(&self, collection: &C) -> bool
+    where
+        C: PartialEq,
+    {
+        self.collections
+            .as_ref()
+            .is_none_or(|collections| collections.contains(collection))
+    }
+}
+
+// server -> client reply to an `Authenticate`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+#[ts(export)]
+pub enum AuthResult<C> {
+    Accepted(Claims<C>),
+    // why the token was refused
+    Rejected(String),
+}
+
+impl<C> AuthResult<C> {
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+impl<C> From<Result<Claims<C>, Error>> for AuthResult<C> {
+    fn from(result: Result<Claims<C>, Error>) -> Self {
+        match result {
+            Ok(claims) => AuthResult::Accepted(claims),
+            Err(err) => AuthResult::Rejected(err.to_string()),
+        }
+    }
+}
+
+// checks the tokens of `Authenticate` messages, e.g. by verifying a jwt. a
+// refused token is an `Error::Unauthorized`
+pub trait Authenticator<C>: Send + Sync {
+    fn authenticate(&self, token: &str) -> Result<Claims<C>, Error>;
+}
+
+impl<C, F: Fn(&str) -> Result<Claims<C>, Error> + Send + Sync> Authenticator<C> for F {
+    fn authenticate(&self, token: &str) -> Result<Claims<C>, Error> {
+        self(token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        AuthResult, Authenticate, Claims, Command, Error, Event, Listener, Subscribe,
+        SubscriptionManager, Syncable, WsBody,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn connections_only_read_what_their_token_allows() {
+        let authenticator = |token: &str| match token {
+            "dog-walker" => Ok(Claims::new("walker").with_collections(vec![Collection::Dogs])),
+            "admin" => Ok(Claims::new("admin")),
+            _ => Err(Error::Unauthorized("unknown token".to_owned())),
+        };
+        let manager = SubscriptionManager::<DoggoEvent>::new().with_authenticator(authenticator);
+        let mut walker = manager.connect();
+        let mut admin = manager.connect();
+        let anonymous = manager.connect();
+
+        let json = r#"{"data":{"type":"authenticate","payload":{"token":"dog-walker"}}}"#;
+        let Command::Authenticate(authenticate) =
+            WsBody::<Command<u32, DoggoRecord, Collection>>::from_json(json)
+                .unwrap()
+                .into_data()
+        else {
+            panic!("expected an authenticate");
+        };
+        let result = manager.handle_authenticate(walker.connection(), &authenticate);
+        let json = result.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"type":"accepted","payload":{"subject":"walker","collections":["Dogs"]}}}"###);
+        let token = |token: &str| Authenticate {
+            token: token.to_owned(),
+        };
+        manager.handle_authenticate(admin.connection(), &token("admin"));
+        let rejected = manager.handle_authenticate(anonymous.connection(), &token("guest"));
+        assert!(
+            matches!(rejected, AuthResult::Rejected(reason) if reason == "not authorized: unknown token")
+        );
+        assert!(manager.claims(anonymous.connection()).is_none());
+        assert!(!manager.can_read(walker.connection(), &Collection::Cats));
+
+        let subscribe = Subscribe {
+            collections: vec![Collection::Dogs, Collection::Cats],
+            patterns: Vec::new(),
+            from_seq: None,
+        };
+        for connection in [
+            walker.connection(),
+            admin.connection(),
+            anonymous.connection(),
+        ] {
+            manager.handle_subscribe(connection, &subscribe);
+        }
+        assert_eq!(
+            manager.publish(DoggoEvent::new_delete_event(1, Collection::Cats)),
+            1
+        );
+        let doggo = doggo(1);
+        assert_eq!(manager.publish(doggo.to_upsert_event()), 2);
+
+        block_on(async {
+            assert_eq!(walker.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(admin.recv().await.unwrap().seq(), Some(0));
+        });
+    }
+}
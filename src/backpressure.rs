@@ -0,0 +1,376 @@
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+use crate::{Error, Listener, Routable};
+
+// what a full buffer does with the next event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+    // discards the buffer and fails the listener with `Error::Overflowed`
+    CloseConnection,
+    // replaces the queued event for the same collection and id, falling back
+    // to `DropOldest` when there is none
+    CoalesceByKey,
+}
+
+// queue of events waiting for a single listener
+pub(crate) struct Buffer<T> {
+    items: VecDeque<T>,
+    // `None` for unbounded
+    capacity: Option<usize>,
+    policy: OverflowPolicy,
+    dropped: u64,
+    overflowed: bool,
+    reported: bool,
+    // what closing on overflow threw away, until taken for dead-lettering
+    discarded: Vec<T>,
+}
+
+impl<T> Buffer<T> {
+    pub(crate) fn unbounded() -> Self {
+        Self::build(None, OverflowPolicy::DropOldest)
+    }
+
+    pub(crate) fn bounded(capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "buffer capacity must be greater than zero");
+        Self::build(Some(capacity), policy)
+    }
+
+    fn build(capacity: Option<usize>, policy: OverflowPolicy) -> Self {
+        Self {
+            items: VecDeque::new(),
+            capacity,
+            policy,
+            dropped: 0,
+            overflowed: false,
+            reported: false,
+            discarded: Vec::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    // false if `item` was dropped (or the buffer closed) instead of queued
+    pub(crate) fn push(&mut self, item: T) -> bool
+    where
+        T: Routable,
+        T::Collection: PartialEq,
+        T::Id: PartialEq,
+    {
+        if self.overflowed {
+            return false;
+        }
+        if self
+            .capacity
+            .is_none_or(|capacity| self.items.len() < capacity)
+        {
+            self.items.push_back(item);
+            return true;
+        }
+        self.dropped += 1;
+        match self.policy {
+            OverflowPolicy::DropNewest => false,
+            OverflowPolicy::CloseConnection => {
+                self.dropped += self.items.len() as u64;
+                self.discarded = self.items.drain(..).collect();
+                self.discarded.push(item);
+                self.overflowed = true;
+                false
+            }
+            OverflowPolicy::CoalesceByKey => {
+                match self
+                    .items
+                    .iter_mut()
+                    .find(|queued| same_key(*queued, &item))
+                {
+                    Some(queued) => *queued = item,
+                    None => {
+                        self.items.pop_front();
+                        self.items.push_back(item);
+                    }
+                }
+                true
+            }
+            OverflowPolicy::DropOldest => {
+                self.items.pop_front();
+                self.items.push_back(item);
+                true
+            }
+        }
+    }
+
+    // `Error::Overflowed` is reported once the queued events are gone, and
+    // only once
+    pub(crate) fn pop(&mut self) -> Result<Option<T>, Error> {
+        if let Some(item) = self.items.pop_front() {
+            return Ok(Some(item));
+        }
+        if !self.overflowed {
+            return Ok(None);
+        }
+        if self.reported {
+            return Err(Error::Closed);
+        }
+        self.reported = true;
+        Err(self.overflow_error())
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.overflowed
+    }
+
+    // the error the listener fails with once it has overflowed
+    pub(crate) fn overflow_error(&self) -> Error {
+        // only bounded buffers overflow
+        Error::Overflowed(self.capacity.unwrap_or_default())
+    }
+
+    pub(crate) fn take_discarded(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.discarded)
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+fn same_key<T>(queued: &T, item: &T) -> bool
+where
+    T: Routable,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    item.id().is_some() && queued.id() == item.id() && queued.collection() == item.collection()
+}
+
+// a bounded queue between whatever produces events and a slow consumer, so
+// the producer never waits on (or buffers without limit for) the consumer
+pub fn buffered<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (BufferedSender<T>, BufferedListener<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: Buffer::bounded(capacity, policy),
+            waker: None,
+            senders: 1,
+            listening: true,
+        }),
+    });
+    (
+        BufferedSender {
+            shared: shared.clone(),
+        },
+        BufferedListener { shared },
+    )
+}
+
+pub struct BufferedSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct BufferedListener<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    buffer: Buffer<T>,
+    waker: Option<Waker>,
+    senders: usize,
+    listening: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> State<T> {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> BufferedSender<T>
+where
+    T: Routable,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    // never waits. dropping `item` because the buffer is full is not an
+    // error, but the listener being gone (or closed on overflow) is
+    pub fn send(&self, item: T) -> Result<(), Error> {
+        let mut state = self.shared.lock();
+        if !state.listening || state.buffer.is_closed() {
+            return Err(Error::Closed);
+        }
+        state.buffer.push(item);
+        state.wake();
+        Ok(())
+    }
+
+    // true once `send` can no longer succeed
+    pub fn is_closed(&self) -> bool {
+        let state = self.shared.lock();
+        !state.listening || state.buffer.is_closed()
+    }
+
+    // moves everything `inner` yields into the buffer until the buffered
+    // listener is gone. meant to run as its own task
+    pub async fn forward<L>(&self, inner: &mut L) -> Result<(), L::Error>
+    where
+        L: Listener<Item = T> + Send,
+        T: Send,
+    {
+        loop {
+            let item = inner.recv().await?;
+            if self.send(item).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T> Clone for BufferedSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for BufferedSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.wake();
+        }
+    }
+}
+
+impl<T> BufferedListener<T> {
+    // events lost to the overflow policy so far
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().buffer.dropped()
+    }
+}
+
+impl<T> Drop for BufferedListener<T> {
+    fn drop(&mut self) {
+        self.shared.lock().listening = false;
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Send> Listener for BufferedListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            let mut state = self.shared.lock();
+            if let Some(item) = state.buffer.pop()? {
+                return Poll::Ready(Ok(item));
+            }
+            if state.senders == 0 {
+                return Poll::Ready(Err(Error::Closed));
+            }
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        buffered, BroadcastService, BufferedListener, Error, Event, Listener, Location,
+        OverflowPolicy, Service,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn full_buffers_apply_their_policy() {
+        let named = |id, name: &str| {
+            DoggoEvent::builder().upsert(
+                Location::builder(Collection::Dogs).id(id).build(),
+                DoggoRecord {
+                    name: name.to_string(),
+                    ..doggo(id)
+                },
+            )
+        };
+        let names = |listener: &mut BufferedListener<DoggoEvent>, count| {
+            block_on(async {
+                let mut names = Vec::new();
+                for _ in 0..count {
+                    let event = listener.recv().await.unwrap();
+                    names.push(event.data().unwrap().name.clone());
+                }
+                names
+            })
+        };
+
+        let (sender, mut listener) = buffered(2, OverflowPolicy::DropOldest);
+        for name in ["a", "b", "c"] {
+            sender.send(named(1, name)).unwrap();
+        }
+        assert_eq!(names(&mut listener, 2), ["b", "c"]);
+        assert_eq!(listener.dropped(), 1);
+
+        let (sender, mut listener) = buffered(2, OverflowPolicy::DropNewest);
+        for name in ["a", "b", "c"] {
+            sender.send(named(1, name)).unwrap();
+        }
+        assert_eq!(names(&mut listener, 2), ["a", "b"]);
+
+        let (sender, mut listener) = buffered(2, OverflowPolicy::CoalesceByKey);
+        for (id, name) in [(1, "a"), (2, "b"), (1, "c"), (3, "d")] {
+            sender.send(named(id, name)).unwrap();
+        }
+        assert_eq!(names(&mut listener, 2), ["b", "d"]);
+
+        let (sender, mut listener) = buffered(1, OverflowPolicy::CloseConnection);
+        sender.send(named(1, "a")).unwrap();
+        sender.send(named(2, "b")).unwrap();
+        assert!(matches!(sender.send(named(3, "c")), Err(Error::Closed)));
+        block_on(async {
+            assert!(matches!(listener.recv().await, Err(Error::Overflowed(1))));
+            assert!(matches!(listener.recv().await, Err(Error::Closed)));
+        });
+
+        let service = BroadcastService::new();
+        let mut inner = service.listener();
+        let (sender, mut listener) = buffered(1, OverflowPolicy::DropOldest);
+        service.publish(named(1, "a")).unwrap();
+        service.publish(named(1, "b")).unwrap();
+        drop(service);
+        block_on(async {
+            assert!(matches!(
+                sender.forward(&mut inner).await,
+                Err(Error::Closed)
+            ));
+            assert_eq!(listener.recv().await.unwrap().seq(), Some(1));
+        });
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Event, NoPatch, Sequenced, Syncable, WsBody};
+
+// several events coalesced into a single websocket frame
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct EventBatch<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    #[ts(type = "number")]
+    seq: u64,
+    txn_id: Option<u32>,
+    events: Vec<Event<ID, T, C, P>>,
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> EventBatch<ID, T, C, P> {
+    pub fn new(seq: u64) -> Self {
+        Self {
+            seq,
+            txn_id: None,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn with_txn_id(mut self, txn_id: u32) -> Self {
+        self.txn_id = Some(txn_id);
+        self
+    }
+
+    pub fn push(&mut self, event: Event<ID, T, C, P>) -> &mut Self {
+        self.events.push(event);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Sequenced for EventBatch<ID, T, C, P> {
+    fn seq(&self) -> Option<u64> {
+        Some(self.seq)
+    }
+
+    fn set_seq(&mut self, seq: u64) {
+        self.seq = seq;
+    }
+}
+
+impl<ID, T: Serialize + TS, C> EventBatch<ID, T, C> {
+    pub fn extend_from_syncables(&mut self, records: impl IntoIterator<Item = T>) -> &mut Self
+    where
+        T: Syncable<Id = ID, Collection = C>,
+    {
+        self.events
+            .extend(records.into_iter().map(Syncable::to_upsert_event));
+        self
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Extend<Event<ID, T, C, P>>
+    for EventBatch<ID, T, C, P>
+{
+    fn extend<I: IntoIterator<Item = Event<ID, T, C, P>>>(&mut self, iter: I) {
+        self.events.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::doggo;
+    use crate::{Appendable, EventBatch};
+
+    #[test]
+    fn batch_serializes_into_one_body() {
+        let mut batch = EventBatch::new(7).with_txn_id(3);
+        batch.extend_from_syncables([doggo(1), doggo(2)]);
+        assert_eq!(batch.len(), 2);
+
+        let json = batch.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"seq":7,"txn_id":3,"events":[{"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}},{"verb":{"type":"upsert","payload":{"location":{"id":2,"txn_id":null,"collection":"Dogs"},"data":{"id":2,"name":"Barky","breed":"Poodle"}}}}]}}"###);
+
+        let mut batch = EventBatch::new(8);
+        batch.push(doggo(3).to_insert_event());
+        assert!(!batch.is_empty());
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+use crate::shutdown::{Drain, DEFAULT_RECONNECT_AFTER};
+use crate::{metrics, Error, EventStore, GoingAway, Listener, Sequenced, Service, Shutdown};
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+// fans every published event out to all listeners, stamping each with its
+// position in the stream. only the last `capacity` events are retained, so a
+// listener that falls further behind than that (or resumes from an older seq)
+// gets `Error::Lagged` and continues from the oldest retained event. with a
+// store, events are written through to it and such listeners are caught up
+// from the store instead. once it's shut down, publishes fail and each
+// listener gets `Error::GoingAway` after the events published before
+pub struct BroadcastService<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct BroadcastListener<T> {
+    shared: Arc<Shared<T>>,
+    // replayed from the store, delivered before anything from the buffer
+    backlog: VecDeque<T>,
+    next: u64,
+    // whether it has been told the service is going away
+    told: bool,
+    // its waker's key in `State::wakers`
+    id: u64,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // held by publishes through their store's append, so events reach the
+    // store in seq order without keeping listeners waiting on its i/o
+    publishing: Mutex<()>,
+    store: Option<Box<dyn EventStore<T>>>,
+    drain: Arc<Drain>,
+}
+
+struct State<T> {
+    buffer: VecDeque<T>,
+    // position of `buffer[0]` in the overall stream
+    head: u64,
+    capacity: usize,
+    services: usize,
+    // the waker of every listener waiting for the next event, by listener
+    wakers: HashMap<u64, Waker>,
+    next_listener: u64,
+    // set once the service is shut down
+    going_away: Option<GoingAway>,
+    reconnect_after: Duration,
+}
+
+impl<T> State<T> {
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    fn wake_all(&mut self) {
+        self.wakers.drain().for_each(|(_, waker)| waker.wake());
+    }
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_publishing(&self) -> MutexGuard<'_, ()> {
+        self.publishing
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> BroadcastService<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::build(capacity, None)
+    }
+
+    pub fn with_store(capacity: usize, store: impl EventStore<T> + 'static) -> Self {
+        Self::build(capacity, Some(Box::new(store)))
+    }
+
+    fn build(capacity: usize, store: Option<Box<dyn EventStore<T>>>) -> Self {
+        assert!(capacity > 0, "broadcast capacity must be greater than zero");
+        // a store that can't report where it left off starts a fresh stream
+        let head = store
+            .as_ref()
+            .and_then(|store| store.next_seq().ok())
+            .unwrap_or(0);
+        let state = State {
+            buffer: VecDeque::with_capacity(capacity),
+            head,
+            capacity,
+            services: 1,
+            wakers: HashMap::new(),
+            next_listener: 0,
+            going_away: None,
+            reconnect_after: DEFAULT_RECONNECT_AFTER,
+        };
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                publishing: Mutex::new(()),
+                store,
+                drain: Arc::default(),
+            }),
+        }
+    }
+
+    // how long clients are told to wait before reconnecting once the
+    // service shuts down
+    pub fn with_reconnect_after(self, reconnect_after: Duration) -> Self {
+        self.shared.lock().reconnect_after = reconnect_after;
+        self
+    }
+}
+
+impl<T> Default for BroadcastService<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for BroadcastService<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().services += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for BroadcastService<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.services -= 1;
+        if state.services == 0 {
+            state.wake_all();
+        }
+    }
+}
+
+impl<T: Clone + Send + Sequenced> Service<T> for BroadcastService<T> {
+    type Listener = BroadcastListener<T>;
+    type Error = Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        // only publishes move the tail, so it holds until this one is done
+        let _publishing = self.shared.lock_publishing();
+        let seq = {
+            let state = self.shared.lock();
+            if let Some(going_away) = state.going_away {
+                return Err(Error::GoingAway(going_away));
+            }
+            state.tail()
+        };
+        event.set_seq(seq);
+        // on the span of a `TracedService` publish, if there is one
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("seq", seq);
+        if let Some(store) = &self.shared.store {
+            store.append(&event)?;
+        }
+        let mut state = self.shared.lock();
+        if state.buffer.len() == state.capacity {
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(event);
+        state.wake_all();
+        metrics::counter(metrics::EVENTS_PUBLISHED, 1);
+        Ok(())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.listener_from(self.head_seq())
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.shared.lock().tail()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        let mut state = self.shared.lock();
+        let mut next = seq.min(state.tail());
+        let mut backlog = VecDeque::new();
+        if let Some(store) = self.shared.store.as_ref().filter(|_| next < state.head) {
+            // if the store fails the listener just sees the gap as `Error::Lagged`
+            if let Ok(events) = store.replay(next) {
+                backlog.extend(
+                    events.take_while(|event| event.seq().is_some_and(|seq| seq < state.head)),
+                );
+                next = state.head;
+            }
+        }
+        let id = state.next_listener;
+        state.next_listener += 1;
+        self.shared.drain.add();
+        BroadcastListener {
+            shared: self.shared.clone(),
+            backlog,
+            next,
+            told: false,
+            id,
+        }
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        // after any publish already under way, so it's delivered before
+        let _publishing = self.shared.lock_publishing();
+        let mut state = self.shared.lock();
+        let going_away = GoingAway::new(state.reconnect_after);
+        state.going_away.get_or_insert(going_away);
+        state.wake_all();
+        self.shared.drain.wait(deadline)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Clone + Send> Listener for BroadcastListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        if let Some(event) = self.backlog.pop_front() {
+            return Ok(event);
+        }
+        poll_fn(|cx| {
+            let mut state = self.shared.lock();
+            if self.next < state.head {
+                let missed = state.head - self.next;
+                self.next = state.head;
+                return Poll::Ready(Err(Error::Lagged(missed)));
+            }
+            if self.next < state.tail() {
+                let event = state.buffer[(self.next - state.head) as usize].clone();
+                self.next += 1;
+                metrics::counter(metrics::EVENTS_DELIVERED, 1);
+                return Poll::Ready(Ok(event));
+            }
+            if let Some(going_away) = state.going_away {
+                if !self.told {
+                    self.told = true;
+                    self.shared.drain.done();
+                }
+                return Poll::Ready(Err(Error::GoingAway(going_away)));
+            }
+            if state.services == 0 {
+                return Poll::Ready(Err(Error::Closed));
+            }
+            // one waker per listener, however often it's polled
+            let waker = state
+                .wakers
+                .entry(self.id)
+                .or_insert_with(|| cx.waker().clone());
+            if !waker.will_wake(cx.waker()) {
+                *waker = cx.waker().clone();
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl<T> Drop for BroadcastListener<T> {
+    fn drop(&mut self) {
+        self.shared.lock().wakers.remove(&self.id);
+        if !self.told {
+            self.shared.drain.done();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    use crate::test::{block_on, Collection};
+    use crate::{BroadcastService, Error, Event, Listener, Service};
+
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        std::pin::pin!(future)
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn listeners_receive_lag_and_resume() {
+        let delete = |id| Event::<u32, (), Collection>::new_delete_event(id, Collection::Dogs);
+
+        let service = BroadcastService::with_capacity(2);
+        let mut early = service.listener();
+        service.publish(delete(1)).unwrap();
+        let mut late = service.listener();
+        service.publish(delete(2)).unwrap();
+        service.publish(delete(3)).unwrap();
+        let mut resumed = service.listener_from(2);
+
+        block_on(async {
+            assert!(matches!(early.recv().await, Err(Error::Lagged(1))));
+            assert_eq!(early.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(late.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(late.recv().await.unwrap().seq(), Some(2));
+            assert_eq!(resumed.recv().await.unwrap().seq(), Some(2));
+
+            drop(service);
+            assert_eq!(early.recv().await.unwrap().seq(), Some(2));
+            assert!(matches!(early.recv().await, Err(Error::Closed)));
+        });
+    }
+
+    #[test]
+    fn listeners_keep_one_waker_however_often_polled() {
+        let service = BroadcastService::<Event<u32, (), Collection>>::new();
+        let (mut first, mut second) = (service.listener(), service.listener());
+        for _ in 0..100 {
+            assert!(poll_once(first.recv()).is_pending());
+            assert!(poll_once(second.recv()).is_pending());
+        }
+        assert_eq!(service.shared.lock().wakers.len(), 2);
+        drop(second);
+        assert_eq!(service.shared.lock().wakers.len(), 1);
+
+        service
+            .publish(Event::new_delete_event(1, Collection::Dogs))
+            .unwrap();
+        assert!(service.shared.lock().wakers.is_empty());
+        assert!(matches!(poll_once(first.recv()), Poll::Ready(Ok(_))));
+    }
+}
@@ -0,0 +1,224 @@
+use std::marker::PhantomData;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    AppendableResource, DeletableResource, Event, EventMeta, EventVerb, Location, NoPatch,
+    PatchResource, Scope, UpdatableResource, VectorClock,
+};
+
+#[derive(Debug, Clone)]
+pub struct LocationBuilder<ID, C> {
+    id: Option<ID>,
+    txn_id: Option<u32>,
+    revision: Option<u64>,
+    collection: C,
+}
+
+impl<ID, C> LocationBuilder<ID, C> {
+    pub fn new(collection: C) -> Self {
+        Self {
+            id: None,
+            txn_id: None,
+            revision: None,
+            collection,
+        }
+    }
+
+    pub fn id(mut self, id: ID) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn txn_id(mut self, txn_id: u32) -> Self {
+        self.txn_id = Some(txn_id);
+        self
+    }
+
+    pub fn revision(mut self, revision: u64) -> Self {
+        self.revision = Some(revision);
+        self
+    }
+
+    pub fn build(self) -> Location<ID, C> {
+        Location {
+            id: self.id,
+            txn_id: self.txn_id,
+            collection: self.collection,
+            revision: self.revision,
+        }
+    }
+}
+
+// builds an event field by field. the verb comes last, e.g.
+// `Event::builder().seq(3).upsert(location, record)`
+#[derive(Debug, Clone)]
+pub struct EventBuilder<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    seq: Option<u64>,
+    occurred_at: Option<u64>,
+    schema_version: Option<u32>,
+    scope: Option<Scope>,
+    idempotency_key: Option<String>,
+    expires_at: Option<u64>,
+    meta: Option<EventMeta>,
+    causality: Option<VectorClock>,
+    expected_revision: Option<u64>,
+    marker: PhantomData<Event<ID, T, C, P>>,
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> EventBuilder<ID, T, C, P> {
+    pub fn new() -> Self {
+        Self {
+            seq: None,
+            occurred_at: None,
+            schema_version: None,
+            scope: None,
+            idempotency_key: None,
+            expires_at: None,
+            meta: None,
+            causality: None,
+            expected_revision: None,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.seq = Some(seq);
+        self
+    }
+
+    pub fn occurred_at(mut self, occurred_at: u64) -> Self {
+        self.occurred_at = Some(occurred_at);
+        self
+    }
+
+    pub fn schema_version(mut self, schema_version: u32) -> Self {
+        self.schema_version = Some(schema_version);
+        self
+    }
+
+    pub fn scope(mut self, scope: impl Into<Scope>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn meta(mut self, meta: EventMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    pub fn causality(mut self, causality: VectorClock) -> Self {
+        self.causality = Some(causality);
+        self
+    }
+
+    // only kept by `update` and `upsert`
+    pub fn expected_revision(mut self, expected_revision: u64) -> Self {
+        self.expected_revision = Some(expected_revision);
+        self
+    }
+
+    pub fn insert(self, location: Location<ID, C>, data: T) -> Event<ID, T, C, P> {
+        self.verb(EventVerb::Insert(AppendableResource { location, data }))
+    }
+
+    pub fn update(self, location: Location<ID, C>, data: T) -> Event<ID, T, C, P> {
+        let resource = self.updatable(location, data);
+        self.verb(EventVerb::Update(resource))
+    }
+
+    pub fn upsert(self, location: Location<ID, C>, data: T) -> Event<ID, T, C, P> {
+        let resource = self.updatable(location, data);
+        self.verb(EventVerb::Upsert(resource))
+    }
+
+    pub fn patch(self, location: Location<ID, C>, patch: P) -> Event<ID, T, C, P> {
+        self.verb(EventVerb::Patch(PatchResource {
+            location,
+            data: patch,
+        }))
+    }
+
+    pub fn delete(self, location: Location<ID, C>) -> Event<ID, T, C, P> {
+        self.verb(EventVerb::Delete(DeletableResource {
+            location,
+            deleted_at: None,
+        }))
+    }
+
+    pub fn verb(self, verb: EventVerb<ID, T, C, P>) -> Event<ID, T, C, P> {
+        Event {
+            seq: self.seq,
+            occurred_at: self.occurred_at,
+            schema_version: self.schema_version,
+            scope: self.scope,
+            idempotency_key: self.idempotency_key,
+            expires_at: self.expires_at,
+            meta: self.meta,
+            causality: self.causality,
+            verb,
+        }
+    }
+
+    fn updatable(&self, location: Location<ID, C>, data: T) -> UpdatableResource<ID, T, C> {
+        UpdatableResource {
+            location,
+            data,
+            expected_revision: self.expected_revision,
+        }
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Default for EventBuilder<ID, T, C, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Event, EventVerb, Location, Syncable};
+
+    #[test]
+    fn built_events_can_be_inspected() {
+        let doggo = doggo(1);
+
+        let location = Location::builder(Collection::Dogs).id(1).txn_id(7).build();
+        let event: Event<u32, DoggoRecord, Collection> = Event::builder()
+            .seq(3)
+            .expected_revision(2)
+            .upsert(location, doggo.clone());
+        let json = event.clone().into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"seq":3,"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":7,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"},"expected_revision":2}}}}"###);
+
+        assert_eq!(
+            (event.seq(), event.id(), event.txn_id()),
+            (Some(3), Some(&1), Some(7))
+        );
+        assert_eq!(event.data().map(|doggo| doggo.name.as_str()), Some("Barky"));
+        let EventVerb::Upsert(resource) = event.into_verb() else {
+            panic!("expected an upsert");
+        };
+        assert_eq!(resource.expected_revision(), Some(2));
+        let (location, data) = resource.into_parts();
+        assert!(location.collection() == &Collection::Dogs);
+        assert_eq!(data.id(), doggo.id());
+
+        let deleted = doggo.to_delete_event();
+        let location = deleted.verb().location().unwrap();
+        assert_eq!((location.id(), location.txn_id()), (Some(&1), None));
+        assert!(deleted.data().is_none());
+    }
+}
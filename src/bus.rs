@@ -0,0 +1,357 @@
+// what the services on an external bus share: a runtime for the async bus
+// clients, listeners fed by a dedicated subscription, resuming from a store,
+// and telling those listeners the service is shutting down
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use futures_util::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use tokio::sync::watch;
+
+use crate::shutdown::{Drain, DEFAULT_RECONNECT_AFTER};
+use crate::{
+    buffered, BufferedListener, BufferedSender, Error, EventStore, GoingAway, Listener,
+    OverflowPolicy, Routable, Sequenced, Shutdown,
+};
+
+const LISTENER_CAPACITY: usize = 1024;
+// how often an idle subscription checks whether its listener is gone
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// the bus clients run on a runtime of their own, so the services work
+// whichever runtime their callers are on, if any
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("rsp-bus")
+            .enable_all()
+            .build()
+            .expect("failed to start the bus runtime")
+    })
+}
+
+// runs `future` on the bus runtime, blocking until it is done
+pub(crate) fn run<F>(future: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+    runtime().spawn(async move {
+        let _ = sender.send(future.await);
+    });
+    receiver.recv().expect("a bus task panicked")
+}
+
+// whether a bus service is shutting down, watched by each of its listeners'
+// subscriptions. dropping it closes them
+pub(crate) struct Going {
+    signal: watch::Sender<Option<GoingAway>>,
+    drain: Arc<Drain>,
+    pub(crate) reconnect_after: Duration,
+}
+
+impl Default for Going {
+    fn default() -> Self {
+        Self {
+            signal: watch::Sender::new(None),
+            drain: Arc::default(),
+            reconnect_after: DEFAULT_RECONNECT_AFTER,
+        }
+    }
+}
+
+impl Going {
+    // fails publishes once the service is shutting down
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        match *self.signal.borrow() {
+            Some(going_away) => Err(Error::GoingAway(going_away)),
+            None => Ok(()),
+        }
+    }
+
+    // ends every subscription, after which each listener fails with
+    // `Error::GoingAway` once it has delivered what had arrived
+    pub(crate) fn shutdown(&self, deadline: Duration) -> Shutdown {
+        let going_away = GoingAway::new(self.reconnect_after);
+        self.signal.send_if_modified(|signal| {
+            let first = signal.is_none();
+            signal.get_or_insert(going_away);
+            first
+        });
+        self.drain.wait(deadline)
+    }
+}
+
+// replays the store's backlog, then the live events of its subscription. the
+// subscription's task ends, and the listener closes, once either side is
+// gone, the server sends something that isn't an event or the service shuts
+// down, when it fails with `Error::GoingAway` instead
+pub(crate) struct BusListener<T> {
+    // replayed from the store, delivered before anything live
+    backlog: VecDeque<T>,
+    // live events older than this were already replayed
+    skip_below: u64,
+    inner: Option<BufferedListener<T>>,
+    failed: Option<Error>,
+    going: watch::Receiver<Option<GoingAway>>,
+    // until the listener has ended, for the service's shutdown to wait on
+    drain: Option<Arc<Drain>>,
+}
+
+impl<T> BusListener<T> {
+    // a listener on the payloads of the subscription `subscribe` opens, or
+    // one failing with the error opening it did. nothing is opened once the
+    // service is shutting down
+    pub(crate) fn spawn<S>(going: &Going, subscribe: impl FnOnce() -> Result<S, Error>) -> Self
+    where
+        S: Stream<Item = Vec<u8>> + Send + Unpin + 'static,
+        T: Routable + DeserializeOwned + Send + 'static,
+        T::Collection: PartialEq + Send,
+        T::Id: PartialEq + Send,
+    {
+        let mut listener = Self {
+            backlog: VecDeque::new(),
+            skip_below: 0,
+            inner: None,
+            failed: None,
+            going: going.signal.subscribe(),
+            drain: None,
+        };
+        if listener.going.borrow().is_some() {
+            return listener;
+        }
+        match subscribe() {
+            Ok(subscription) => {
+                let (sender, inner) = buffered(LISTENER_CAPACITY, OverflowPolicy::CloseConnection);
+                runtime().spawn(forward(subscription, sender, listener.going.clone()));
+                going.drain.add();
+                listener.inner = Some(inner);
+                listener.drain = Some(going.drain.clone());
+            }
+            Err(err) => listener.failed = Some(err),
+        }
+        listener
+    }
+
+    // `Error::GoingAway` if the subscription ended because the service is
+    // shutting down
+    fn ended(&mut self) -> Error {
+        if let Some(drain) = self.drain.take() {
+            drain.done();
+        }
+        match *self.going.borrow() {
+            Some(going_away) => Error::GoingAway(going_away),
+            None => Error::Closed,
+        }
+    }
+
+    // delivers the stored events from `seq` up to `head` first. subscribe
+    // before reading `head` so nothing falls in between. if the store fails
+    // the listener just starts at the head
+    pub(crate) fn replay(&mut self, store: Option<&Arc<dyn EventStore<T>>>, seq: u64, head: u64)
+    where
+        T: Sequenced,
+    {
+        let Some(store) = store.filter(|_| seq < head && self.going.borrow().is_none()) else {
+            return;
+        };
+        if let Ok(events) = store.replay(seq) {
+            self.backlog = events
+                .take_while(|event| event.seq().is_some_and(|seq| seq < head))
+                .collect();
+            self.skip_below = head;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Sequenced + Send> Listener for BusListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        if let Some(event) = self.backlog.pop_front() {
+            return Ok(event);
+        }
+        if let Some(err) = self.failed.take() {
+            return Err(err);
+        }
+        let Some(inner) = self.inner.as_mut() else {
+            return Err(self.ended());
+        };
+        loop {
+            match inner.recv().await {
+                Ok(event) if event.seq().is_some_and(|seq| seq < self.skip_below) => {}
+                Ok(event) => return Ok(event),
+                Err(Error::Closed) => return Err(self.ended()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl<T> Drop for BusListener<T> {
+    fn drop(&mut self) {
+        if let Some(drain) = self.drain.take() {
+            drain.done();
+        }
+    }
+}
+
+async fn forward<T, S>(
+    mut subscription: S,
+    sender: BufferedSender<T>,
+    mut going: watch::Receiver<Option<GoingAway>>,
+) where
+    S: Stream<Item = Vec<u8>> + Unpin,
+    T: Routable + DeserializeOwned,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    loop {
+        let next = tokio::select! {
+            next = tokio::time::timeout(POLL_INTERVAL, subscription.next()) => next,
+            // the service is shutting down, or gone
+            _ = going.wait_for(Option::is_some) => return,
+        };
+        let payload = match next {
+            Ok(Some(payload)) => payload,
+            Ok(None) => return,
+            Err(_) if sender.is_closed() => return,
+            Err(_) => continue,
+        };
+        let Ok(event) = serde_json::from_slice(&payload) else {
+            return;
+        };
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures_util::{stream, StreamExt};
+
+    use super::{BusListener, Going};
+    use crate::test::{block_on, Collection};
+    use crate::{Error, Event, EventStore, InMemoryEventStore, Listener, Sequenced};
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    fn event(seq: u64) -> DeleteEvent {
+        let mut event = DeleteEvent::new_delete_event(seq as u32, Collection::Dogs);
+        event.set_seq(seq);
+        event
+    }
+
+    // a listener on a subscription yielding `payloads`, then ending
+    fn live(
+        going: &Going,
+        payloads: impl IntoIterator<Item = Vec<u8>>,
+    ) -> BusListener<DeleteEvent> {
+        let payloads: Vec<_> = payloads.into_iter().collect();
+        BusListener::spawn(going, || Ok(stream::iter(payloads)))
+    }
+
+    // a listener on a subscription that stays open
+    fn idle(going: &Going) -> BusListener<DeleteEvent> {
+        BusListener::spawn(going, || Ok(stream::pending()))
+    }
+
+    fn json(seq: u64) -> Vec<u8> {
+        serde_json::to_vec(&event(seq)).unwrap()
+    }
+
+    #[test]
+    fn stored_events_are_replayed_before_live_ones() {
+        let store = InMemoryEventStore::new(16);
+        (0..4).for_each(|seq| store.append(&event(seq)).unwrap());
+        let store: Arc<dyn EventStore<DeleteEvent>> = Arc::new(store);
+        let going = Going::default();
+
+        // seq 3 was published between subscribing and reading the head
+        let mut listener = live(&going, [json(3), json(4)]);
+        listener.replay(Some(&store), 1, 4);
+        let seqs: Vec<_> = (0..4)
+            .map(|_| block_on(listener.recv()).unwrap().seq())
+            .collect();
+        assert_eq!(seqs, [Some(1), Some(2), Some(3), Some(4)]);
+        assert!(matches!(block_on(listener.recv()), Err(Error::Closed)));
+
+        // nothing to replay when resuming from the head, or without a store
+        let mut listener = live(&going, [json(4)]);
+        listener.replay(Some(&store), 4, 4);
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(4));
+        let mut listener = live(&going, [json(4)]);
+        listener.replay(None, 0, 4);
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(4));
+    }
+
+    #[test]
+    fn malformed_events_close_the_listener() {
+        let going = Going::default();
+        let mut listener = live(&going, [json(0), b"{\"data\":".to_vec(), json(1)]);
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+        assert!(matches!(block_on(listener.recv()), Err(Error::Closed)));
+
+        let mut unreachable = BusListener::<DeleteEvent>::spawn(&going, || {
+            Err::<stream::Empty<_>, _>(Error::Transport("connection refused".to_owned()))
+        });
+        assert!(matches!(
+            block_on(unreachable.recv()),
+            Err(Error::Transport(_))
+        ));
+        assert!(matches!(block_on(unreachable.recv()), Err(Error::Closed)));
+    }
+
+    #[test]
+    fn shutdowns_end_subscriptions() {
+        let going = Going {
+            reconnect_after: Duration::from_millis(1_500),
+            ..Going::default()
+        };
+        let mut listener = BusListener::<DeleteEvent>::spawn(&going, || {
+            Ok(stream::iter([json(0)]).chain(stream::pending()))
+        });
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+
+        let shutdown = going.shutdown(Duration::from_millis(500));
+        assert!(matches!(going.check(), Err(Error::GoingAway(_))));
+        let Err(Error::GoingAway(going_away)) = block_on(listener.recv()) else {
+            panic!("expected the service to be going away");
+        };
+        assert_eq!(going_away.reconnect_after(), Duration::from_millis(1_500));
+        block_on(shutdown).unwrap();
+
+        // listeners started afterwards don't subscribe at all
+        let mut late =
+            BusListener::<DeleteEvent>::spawn(&going, || -> Result<stream::Empty<_>, _> {
+                panic!("subscribed after the shutdown")
+            });
+        assert!(matches!(block_on(late.recv()), Err(Error::GoingAway(_))));
+
+        // listeners that are never told hold the shutdown up, dropped ones don't
+        let going = Going::default();
+        let _untold = idle(&going);
+        drop(idle(&going));
+        assert!(matches!(
+            block_on(going.shutdown(Duration::from_millis(20))),
+            Err(Error::Undrained(1))
+        ));
+
+        // dropping the service closes its listeners
+        let going = Going::default();
+        let mut orphaned = idle(&going);
+        drop(going);
+        assert!(matches!(block_on(orphaned.recv()), Err(Error::Closed)));
+    }
+}
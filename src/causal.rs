@@ -0,0 +1,331 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Event, Listener};
+
+// how many events each writer (a server, or a peer) had made when an event
+// was made. writers that made none are left out, so clocks stay small when
+// only a few of many writers touch a record
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct VectorClock(#[ts(type = "Record<string, number>")] BTreeMap<String, u64>);
+
+impl VectorClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or_default()
+    }
+
+    // counts an event made by `node`, returning its count. writers tick only
+    // for their own writes, so each write is the next of its writer
+    pub fn tick(&mut self, node: &str) -> u64 {
+        let count = self.0.entry(node.to_owned()).or_default();
+        *count += 1;
+        *count
+    }
+
+    pub(crate) fn add(&mut self, node: &str, count: u64) {
+        if count > 0 {
+            *self.0.entry(node.to_owned()).or_default() += count;
+        }
+    }
+
+    // everything either clock has seen
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (node, &count) in &other.0 {
+            let entry = self.0.entry(node.clone()).or_default();
+            *entry = (*entry).max(count);
+        }
+    }
+
+    // for a writer receiving an event: it has now seen `other`. receiving
+    // isn't a write, so it doesn't tick, or the writer's next write would
+    // look like it follows writes that were never made
+    pub fn observe(&mut self, other: &VectorClock) {
+        self.merge(other);
+    }
+
+    // neither clock happened before the other, i.e. the events are conflicting
+    // writes
+    pub fn is_concurrent(&self, other: &VectorClock) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.0.iter().map(|(node, &count)| (node.as_str(), count))
+    }
+
+    // whether an event with this clock comes right after everything in
+    // `delivered`: it is the next event of one writer, and everything else it
+    // depends on has been delivered
+    fn follows(&self, delivered: &VectorClock) -> bool {
+        let mut next = 0;
+        for (node, count) in self.iter() {
+            let seen = delivered.get(node);
+            if count == seen + 1 {
+                next += 1;
+            } else if count > seen {
+                return false;
+            }
+        }
+        next == 1
+    }
+}
+
+// `Less` if this clock happened before `other`, `None` if they are concurrent
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let mut nodes = self.0.keys().chain(other.0.keys());
+        nodes.try_fold(Ordering::Equal, |ordering, node| {
+            match (ordering, self.get(node).cmp(&other.get(node))) {
+                (ordering, Ordering::Equal) => Some(ordering),
+                (Ordering::Equal, next) => Some(next),
+                (ordering, next) if ordering == next => Some(ordering),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl FromIterator<(String, u64)> for VectorClock {
+    fn from_iter<I: IntoIterator<Item = (String, u64)>>(iter: I) -> Self {
+        Self(iter.into_iter().filter(|(_, count)| *count > 0).collect())
+    }
+}
+
+// implemented by anything that can carry causal metadata
+pub trait Causal {
+    fn causality(&self) -> Option<&VectorClock>;
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Causal for Event<ID, T, C, P> {
+    fn causality(&self) -> Option<&VectorClock> {
+        Event::causality(self)
+    }
+}
+
+// delivers events in causal order: an event is held back until every event
+// it depends on has been delivered, so a consumer never sees a reply before
+// what it replies to. events delivered already are dropped, and events
+// without causality are delivered as they come
+pub struct CausalOrderBuffer<T> {
+    delivered: VectorClock,
+    held: Vec<T>,
+    deliverable: VecDeque<T>,
+    duplicates: u64,
+}
+
+impl<T: Causal> CausalOrderBuffer<T> {
+    pub fn new() -> Self {
+        Self::starting_at(VectorClock::new())
+    }
+
+    // for a consumer that has already seen everything up to `delivered`,
+    // e.g. from a snapshot
+    pub fn starting_at(delivered: VectorClock) -> Self {
+        Self {
+            delivered,
+            held: Vec::new(),
+            deliverable: VecDeque::new(),
+            duplicates: 0,
+        }
+    }
+
+    pub fn push(&mut self, event: T) {
+        let Some(causality) = event.causality() else {
+            self.deliverable.push_back(event);
+            return;
+        };
+        if causality <= &self.delivered {
+            self.duplicates += 1;
+            return;
+        }
+        self.held.push(event);
+        // a delivered event can make held ones deliverable in turn
+        while let Some(index) = self.held.iter().position(|event| {
+            event
+                .causality()
+                .is_some_and(|causality| causality.follows(&self.delivered))
+        }) {
+            self.deliver(index);
+        }
+    }
+
+    // the next event in causal order
+    pub fn pop(&mut self) -> Option<T> {
+        self.deliverable.pop_front()
+    }
+
+    pub fn ready(&mut self) -> Vec<T> {
+        self.deliverable.drain(..).collect()
+    }
+
+    // gives up on the missing dependencies of the held events, e.g. once a
+    // writer is known to be gone, and delivers them in the order they came
+    pub fn flush(&mut self) -> Vec<T> {
+        while !self.held.is_empty() {
+            self.deliver(0);
+        }
+        self.ready()
+    }
+
+    // what has been delivered so far
+    pub fn clock(&self) -> &VectorClock {
+        &self.delivered
+    }
+
+    // events waiting for their dependencies
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    // events dropped because they had been delivered already
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    fn deliver(&mut self, index: usize) {
+        let event = self.held.remove(index);
+        if let Some(causality) = event.causality() {
+            self.delivered.merge(causality);
+        }
+        self.deliverable.push_back(event);
+    }
+}
+
+impl<T: Causal> Default for CausalOrderBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// a listener that yields the events of `inner` in causal order
+pub struct CausalListener<L: Listener> {
+    inner: L,
+    buffer: CausalOrderBuffer<L::Item>,
+}
+
+impl<L: Listener> CausalListener<L>
+where
+    L::Item: Causal,
+{
+    pub fn new(inner: L) -> Self {
+        Self::with_buffer(inner, CausalOrderBuffer::new())
+    }
+
+    pub fn with_buffer(inner: L, buffer: CausalOrderBuffer<L::Item>) -> Self {
+        Self { inner, buffer }
+    }
+
+    pub fn buffer(&self) -> &CausalOrderBuffer<L::Item> {
+        &self.buffer
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for CausalListener<L>
+where
+    L: Listener + Send,
+    L::Item: Causal + Send,
+{
+    type Error = L::Error;
+    type Item = L::Item;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        loop {
+            if let Some(event) = self.buffer.pop() {
+                return Ok(event);
+            }
+            let event = self.inner.recv().await?;
+            self.buffer.push(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, DoggoRecord};
+    use crate::{
+        BroadcastService, CausalListener, CausalOrderBuffer, Listener, Service, Syncable,
+        VectorClock,
+    };
+
+    #[test]
+    fn events_are_delivered_in_causal_order() {
+        let named = |name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(1)
+        };
+        let (mut a, mut b) = (VectorClock::new(), VectorClock::new());
+        a.tick("a");
+        let first = named("Barky").to_upsert_event().with_causality(a.clone());
+        b.observe(&a);
+        b.tick("b");
+        let reply = named("Woofy").to_upsert_event().with_causality(b.clone());
+        a.tick("a");
+        assert!(a.is_concurrent(&b));
+        assert!(first.causality() < reply.causality());
+
+        let json = reply.clone().into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"causality":{"a":1,"b":1},"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Woofy","breed":"Poodle"}}}}}"###);
+
+        let service = BroadcastService::new();
+        let mut listener = CausalListener::new(service.listener());
+        service.publish(reply).unwrap();
+        service.publish(first.clone()).unwrap();
+        service.publish(first).unwrap();
+        service.publish(named("Sparky").to_upsert_event()).unwrap();
+
+        block_on(async {
+            let mut next = Vec::new();
+            for _ in 0..3 {
+                next.push(listener.recv().await.unwrap().data().unwrap().name.clone());
+            }
+            assert_eq!(next, ["Barky", "Woofy", "Sparky"]);
+        });
+        assert_eq!(listener.buffer().duplicates(), 1);
+        assert_eq!(listener.buffer().clock(), &b);
+    }
+
+    #[test]
+    fn observing_leaves_no_gaps() {
+        let named = |name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(1)
+        };
+        let (mut a, mut b) = (VectorClock::new(), VectorClock::new());
+        let mut history = Vec::new();
+        for name in ["Barky", "Sir Barks"] {
+            a.tick("a");
+            history.push(named(name).to_upsert_event().with_causality(a.clone()));
+            b.observe(&a);
+            b.observe(&a);
+        }
+        assert_eq!(b.get("b"), 0);
+        b.tick("b");
+        let reply = named("Woofy").to_upsert_event().with_causality(b.clone());
+        b.tick("b");
+        let again = named("Woofier").to_upsert_event().with_causality(b.clone());
+
+        let mut buffer = CausalOrderBuffer::new();
+        for event in [again, reply].into_iter().chain(history) {
+            buffer.push(event);
+        }
+        // nothing waits for a flush
+        assert_eq!(buffer.held(), 0);
+        let names: Vec<_> = buffer
+            .ready()
+            .iter()
+            .map(|event| event.data().unwrap().name.clone())
+            .collect();
+        assert_eq!(names, ["Barky", "Sir Barks", "Woofy", "Woofier"]);
+        assert_eq!(buffer.clock(), &b);
+    }
+}
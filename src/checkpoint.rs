@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{Error, Listener, Sequenced, Service};
+
+// where consumers record how far they got, by name
+pub trait OffsetStore: Send + Sync {
+    // the seq of the last event `consumer` finished with
+    fn load(&self, consumer: &str) -> Result<Option<u64>, Error>;
+
+    fn save(&self, consumer: &str, seq: u64) -> Result<(), Error>;
+}
+
+impl<O: OffsetStore + ?Sized> OffsetStore for &O {
+    fn load(&self, consumer: &str) -> Result<Option<u64>, Error> {
+        (**self).load(consumer)
+    }
+
+    fn save(&self, consumer: &str, seq: u64) -> Result<(), Error> {
+        (**self).save(consumer, seq)
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryOffsetStore {
+    offsets: Mutex<HashMap<String, u64>>,
+}
+
+impl InMemoryOffsetStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, u64>> {
+        self.offsets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl OffsetStore for InMemoryOffsetStore {
+    fn load(&self, consumer: &str) -> Result<Option<u64>, Error> {
+        Ok(self.lock().get(consumer).copied())
+    }
+
+    fn save(&self, consumer: &str, seq: u64) -> Result<(), Error> {
+        self.lock().insert(consumer.to_owned(), seq);
+        Ok(())
+    }
+}
+
+// every consumer's offset in one json file, rewritten through a temporary
+// file and a rename so a crash leaves either the old offsets or the new ones
+pub struct FileOffsetStore {
+    path: PathBuf,
+    offsets: Mutex<BTreeMap<String, u64>>,
+}
+
+impl FileOffsetStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let offsets = match fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).map_err(Error::Decode)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(Error::Store(err.to_string())),
+        };
+        Ok(Self {
+            path,
+            offsets: Mutex::new(offsets),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, u64>> {
+        self.offsets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl OffsetStore for FileOffsetStore {
+    fn load(&self, consumer: &str) -> Result<Option<u64>, Error> {
+        Ok(self.lock().get(consumer).copied())
+    }
+
+    fn save(&self, consumer: &str, seq: u64) -> Result<(), Error> {
+        let mut offsets = self.lock();
+        offsets.insert(consumer.to_owned(), seq);
+        let json = serde_json::to_vec(&*offsets).map_err(Error::Encode)?;
+        let staged = self.path.with_extension("tmp");
+        let write = || {
+            let mut file = File::create(&staged)?;
+            file.write_all(&json)?;
+            file.sync_all()?;
+            fs::rename(&staged, &self.path)
+        };
+        write().map_err(|err| Error::Store(err.to_string()))
+    }
+}
+
+// processes a listener's events at most once each, recording the seq of
+// every event it finished with in an `OffsetStore`. after a crash it resumes
+// after the last one recorded, and events the listener delivers again are
+// skipped, so a handler whose effects are saved along with the offset (or
+// are idempotent) sees each event effectively once
+pub struct CheckpointedConsumer<L: Listener, O> {
+    listener: L,
+    offsets: O,
+    name: String,
+    last_seq: Option<u64>,
+    // an event whose handler failed, handed to the next one first
+    retry: Option<L::Item>,
+}
+
+impl<L, O> CheckpointedConsumer<L, O>
+where
+    L: Listener,
+    L::Item: Sequenced,
+    L::Error: From<Error>,
+    O: OffsetStore,
+{
+    pub fn new(listener: L, offsets: O, name: impl Into<String>) -> Result<Self, Error> {
+        let name = name.into();
+        let last_seq = offsets.load(&name)?;
+        Ok(Self {
+            listener,
+            offsets,
+            name,
+            last_seq,
+            retry: None,
+        })
+    }
+
+    // a consumer on `service` picking up after the last event `name`
+    // finished with, or at the start of the stream
+    pub fn resume<S>(service: &S, offsets: O, name: impl Into<String>) -> Result<Self, Error>
+    where
+        S: Service<L::Item, Listener = L>,
+    {
+        let name = name.into();
+        let from = offsets.load(&name)?.map_or(0, |seq| seq + 1);
+        Self::new(service.listener_from(from), offsets, name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn last_seq(&self) -> Option<u64> {
+        self.last_seq
+    }
+
+    // hands the next new event to `handler`, and records it as done if that
+    // succeeds. a failed event is handed over again on the next call
+    pub async fn process<F>(&mut self, handler: F) -> Result<(), L::Error>
+    where
+        F: FnOnce(&L::Item) -> Result<(), Error>,
+    {
+        let event = match self.retry.take() {
+            Some(event) => event,
+            None => self.next().await?,
+        };
+        if let Err(err) = handler(&event) {
+            self.retry = Some(event);
+            return Err(err.into());
+        }
+        if let Some(seq) = event.seq() {
+            self.offsets.save(&self.name, seq)?;
+            self.last_seq = Some(seq);
+        }
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<L::Item, L::Error> {
+        loop {
+            let event = self.listener.recv().await?;
+            let done =
+                matches!((event.seq(), self.last_seq), (Some(seq), Some(last)) if seq <= last);
+            if !done {
+                return Ok(event);
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> L {
+        self.listener
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use crate::test::{block_on, Collection};
+    use crate::{
+        BroadcastService, CheckpointedConsumer, Error, Event, FileOffsetStore, OffsetStore, Service,
+    };
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    #[test]
+    fn consumers_resume_after_a_crash() {
+        let path = std::env::temp_dir().join(format!("rsp-offsets-{}.json", std::process::id()));
+        let service = BroadcastService::<DeleteEvent>::new();
+        for id in 1..=4 {
+            service
+                .publish(Event::new_delete_event(id, Collection::Dogs))
+                .unwrap();
+        }
+        let seen = RefCell::new(Vec::new());
+        let record = |event: &DeleteEvent| {
+            seen.borrow_mut().push(*event.id().unwrap());
+            Ok(())
+        };
+
+        let offsets = FileOffsetStore::open(&path).unwrap();
+        let mut consumer = CheckpointedConsumer::resume(&service, offsets, "counts").unwrap();
+        block_on(async {
+            consumer.process(record).await.unwrap();
+            let failed = consumer
+                .process(|_| Err(Error::Store("disk full".to_owned())))
+                .await;
+            assert!(matches!(failed, Err(Error::Store(_))));
+            consumer.process(record).await.unwrap();
+        });
+        assert_eq!(consumer.last_seq(), Some(1));
+        drop(consumer);
+
+        // a listener from the start replays what was already done
+        let offsets = FileOffsetStore::open(&path).unwrap();
+        assert_eq!(offsets.load("counts").unwrap(), Some(1));
+        let mut consumer =
+            CheckpointedConsumer::new(service.listener_from(0), offsets, "counts").unwrap();
+        block_on(async {
+            consumer.process(record).await.unwrap();
+            consumer.process(record).await.unwrap();
+        });
+        assert_eq!(*seen.borrow(), [1, 2, 3, 4]);
+        assert_eq!(consumer.last_seq(), Some(3));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,440 @@
+// a typed client for the protocol. it connects over a websocket, says hello,
+// authenticates, subscribes and yields the events it receives as a
+// `Listener`. when the connection drops it reconnects with exponential
+// backoff and resubscribes from the seq after the last event it delivered,
+// so nothing is missed or delivered twice as long as the server can replay.
+// the protocol lives in `Session`, which never touches a socket: natively
+// `Client` runs it over tokio-tungstenite on a runtime of its own, on wasm32,
+// where there are no tcp sockets or threads, over the browser's `WebSocket`
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{
+    AuthResult, Authenticate, BufferedListener, Clock, Command, Error, ErrorMessage, Event,
+    GoingAway, Hello, HelloAck, Listener, NoPatch, Ping, Pong, Subscribe, SystemClock, WsBody,
+};
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+#[cfg(not(target_arch = "wasm32"))]
+mod sync;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use sync::SyncClient;
+
+const LISTENER_CAPACITY: usize = 1024;
+
+// what the client knows about its connection, shared with whatever runs it
+#[derive(Default)]
+struct Status {
+    last_seq: Option<u64>,
+    reconnects: u64,
+    // why the connection gave up, reported once the events before it are gone
+    failed: Option<Error>,
+}
+
+#[derive(Default)]
+struct Shared(Mutex<Status>);
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Status> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// a `Session` kept connected, see `Client::connect`
+pub struct Client<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    inner: BufferedListener<Event<ID, T, C, P>>,
+    shared: Arc<Shared>,
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Client<ID, T, C, P> {
+    // the seq of the last event received, where a reconnect resumes after
+    pub fn last_seq(&self) -> Option<u64> {
+        self.shared.lock().last_seq
+    }
+
+    pub fn reconnects(&self) -> u64 {
+        self.shared.lock().reconnects
+    }
+}
+
+#[async_trait::async_trait]
+impl<ID, T, C, P> Listener for Client<ID, T, C, P>
+where
+    ID: Send,
+    T: Serialize + TS + Send,
+    C: Send,
+    P: Serialize + TS + Send,
+{
+    type Error = Error;
+    type Item = Event<ID, T, C, P>;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        match self.inner.recv().await {
+            Err(Error::Closed) => Err(self.shared.lock().failed.take().unwrap_or(Error::Closed)),
+            received => received,
+        }
+    }
+}
+
+// how long to wait before each reconnect: `initial`, doubling every failed
+// attempt up to `max`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    // `attempt` counts from 0
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+pub struct ClientConfig<C> {
+    // `ws://host[:port]/path`, or `wss://` for tls
+    url: String,
+    token: Option<String>,
+    collections: Vec<C>,
+    backoff: Backoff,
+    // where the first subscribe starts. `None` for only new events
+    from_seq: Option<u64>,
+}
+
+impl<C> ClientConfig<C> {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            token: None,
+            collections: Vec::new(),
+            backoff: Backoff::default(),
+            from_seq: None,
+        }
+    }
+
+    // sent in an `Authenticate` on every connect
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_collections(mut self, collections: Vec<C>) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_from_seq(mut self, seq: u64) -> Self {
+        self.from_seq = Some(seq);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+}
+
+// whether reconnecting can't help after a connection failed with `err`
+pub fn is_fatal(err: &Error) -> bool {
+    match err {
+        Error::Unauthorized(_) | Error::UnsupportedVersion { .. } | Error::UnsupportedCodec(_) => {
+            true
+        }
+        Error::Server(message) => !message.retryable,
+        _ => false,
+    }
+}
+
+// what to do with a frame the session was handed
+pub enum Step<E> {
+    // send this frame back
+    Send(String),
+    Event(E),
+    // nothing, e.g. for frames this client doesn't know
+    Skip,
+}
+
+enum Stage {
+    Hello,
+    Authenticating,
+    Subscribed,
+}
+
+// one client's side of the protocol, without the socket: send what `start`
+// returns once connected, then hand every text frame to `receive` and do what
+// it says. call `start` again on each new connection; it resubscribes after
+// the last event received
+pub struct Session<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    config: ClientConfig<C>,
+    hello: Hello,
+    stage: Stage,
+    last_seq: Option<u64>,
+    clock: Box<dyn Clock + Send>,
+    marker: PhantomData<Event<ID, T, C, P>>,
+}
+
+// what the server sends once subscribed. anything else, e.g. acks for
+// commands this client never sends, is skipped
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Incoming<E> {
+    Event(E),
+    Error(ErrorMessage),
+    GoingAway(GoingAway),
+    // before `Ping`, which a pong would decode as too
+    Pong(Pong),
+    Ping(Ping),
+    Other(Value),
+}
+
+impl<ID, T, C, P> Session<ID, T, C, P>
+where
+    ID: Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned + TS,
+    C: Serialize + DeserializeOwned + Clone,
+    P: Serialize + DeserializeOwned + TS,
+{
+    pub fn new(config: ClientConfig<C>) -> Self {
+        Self {
+            config,
+            hello: Hello::default(),
+            stage: Stage::Hello,
+            last_seq: None,
+            clock: Box::new(SystemClock),
+            marker: PhantomData,
+        }
+    }
+
+    // what pongs are stamped with. wasm32 has no system time, so the browser
+    // `Client` reads `Date.now()`
+    pub fn with_clock(mut self, clock: impl Clock + Send + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn config(&self) -> &ClientConfig<C> {
+        &self.config
+    }
+
+    pub fn last_seq(&self) -> Option<u64> {
+        self.last_seq
+    }
+
+    // whether the handshake is done and events are on their way
+    pub fn is_subscribed(&self) -> bool {
+        matches!(self.stage, Stage::Subscribed)
+    }
+
+    // the first frame of a new connection
+    pub fn start(&mut self) -> Result<String, Error> {
+        self.stage = Stage::Hello;
+        WsBody::new(&self.hello).try_json()
+    }
+
+    // fails with an error `is_fatal` tells apart from ones worth
+    // reconnecting after
+    pub fn receive(&mut self, text: &str) -> Result<Step<Event<ID, T, C, P>>, Error> {
+        match self.stage {
+            Stage::Hello => {
+                reply::<HelloAck>(text)?.verify(&self.hello)?;
+                let Some(token) = &self.config.token else {
+                    return self.subscribe();
+                };
+                let authenticate = Command::<ID, T, C, P>::Authenticate(Authenticate {
+                    token: token.clone(),
+                });
+                self.stage = Stage::Authenticating;
+                Ok(Step::Send(authenticate.into_ws_body().try_json()?))
+            }
+            Stage::Authenticating => match reply::<AuthResult<C>>(text)? {
+                AuthResult::Accepted(_) => self.subscribe(),
+                AuthResult::Rejected(reason) => Err(Error::Unauthorized(reason)),
+            },
+            Stage::Subscribed => {
+                match WsBody::<Incoming<Event<ID, T, C, P>>>::from_json(text)?.into_data() {
+                    Incoming::Event(event) => {
+                        if let Some(seq) = event.seq() {
+                            self.last_seq = Some(seq);
+                        }
+                        Ok(Step::Event(event))
+                    }
+                    Incoming::Error(message) => Err(Error::Server(message)),
+                    Incoming::GoingAway(going_away) => Err(Error::GoingAway(going_away)),
+                    Incoming::Ping(ping) => {
+                        let pong = Command::<ID, T, C, P>::Pong(ping.pong(&|| self.clock.now()));
+                        Ok(Step::Send(pong.into_ws_body().try_json()?))
+                    }
+                    Incoming::Pong(_) | Incoming::Other(_) => Ok(Step::Skip),
+                }
+            }
+        }
+    }
+
+    fn subscribe(&mut self) -> Result<Step<Event<ID, T, C, P>>, Error> {
+        let subscribe = Command::<ID, T, C, P>::Subscribe(Subscribe {
+            collections: self.config.collections.clone(),
+            patterns: Vec::new(),
+            from_seq: self.last_seq.map(|seq| seq + 1).or(self.config.from_seq),
+        });
+        self.stage = Stage::Subscribed;
+        Ok(Step::Send(subscribe.into_ws_body().try_json()?))
+    }
+}
+
+// the server's answer during the handshake, or the `ErrorMessage` it sent
+// instead
+fn reply<R: Serialize + DeserializeOwned>(text: &str) -> Result<R, Error> {
+    match WsBody::<R>::from_json(text) {
+        Ok(body) => Ok(body.into_data()),
+        Err(err) => match WsBody::<ErrorMessage>::from_json(text) {
+            Ok(message) => Err(Error::Server(message.into_data())),
+            Err(_) => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+
+    use tokio_tungstenite::tungstenite::{self, Message, WebSocket};
+
+    use crate::client::{is_fatal, ClientConfig, Session, Step};
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{
+        AuthResult, Claims, Command, Error, ErrorCode, ErrorMessage, Hello, Ping, Sequenced,
+        Subscribe, Syncable, WsBody,
+    };
+
+    type DoggoCommand = Command<u32, DoggoRecord, Collection>;
+
+    pub(super) fn doggo_event(seq: u64) -> String {
+        let mut event = doggo(seq as u32).to_upsert_event();
+        event.set_seq(seq);
+        event.into_ws_body().try_json().unwrap()
+    }
+
+    // plays the server for one connection: the handshake, then `seqs`.
+    // returns where the client subscribed from
+    pub(super) fn serve(listener: &TcpListener, seqs: &[u64]) -> Option<u64> {
+        let (mut socket, from_seq) = handshake(listener);
+        for &seq in seqs {
+            socket.send(Message::text(doggo_event(seq))).unwrap();
+        }
+        from_seq
+    }
+
+    fn read_text(socket: &mut WebSocket<TcpStream>) -> String {
+        socket
+            .read()
+            .unwrap()
+            .into_text()
+            .unwrap()
+            .as_str()
+            .to_owned()
+    }
+
+    // accepts a connection and plays the server up to its subscribe
+    pub(super) fn handshake(listener: &TcpListener) -> (WebSocket<TcpStream>, Option<u64>) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut socket = tungstenite::accept(stream).unwrap();
+        let hello = WsBody::<Hello>::from_json(&read_text(&mut socket)).unwrap();
+        let ack = hello.into_data().negotiate(&[1], &["json"]).unwrap();
+        socket
+            .send(Message::text(WsBody::from(ack).try_json().unwrap()))
+            .unwrap();
+
+        let command = WsBody::<DoggoCommand>::from_json(&read_text(&mut socket)).unwrap();
+        let Command::Authenticate(authenticate) = command.into_data() else {
+            panic!("expected an authenticate");
+        };
+        assert_eq!(authenticate.token, "secret");
+        let accepted = AuthResult::Accepted(Claims::<Collection>::new("tester"));
+        socket
+            .send(Message::text(accepted.into_ws_body().try_json().unwrap()))
+            .unwrap();
+
+        let command = WsBody::<DoggoCommand>::from_json(&read_text(&mut socket)).unwrap();
+        let Command::Subscribe(Subscribe { from_seq, .. }) = command.into_data() else {
+            panic!("expected a subscribe");
+        };
+        (socket, from_seq)
+    }
+
+    #[test]
+    fn sessions_run_without_a_socket() {
+        let config =
+            ClientConfig::new("ws://localhost/rsp").with_collections(vec![Collection::Dogs]);
+        let mut session = Session::<u32, DoggoRecord, Collection>::new(config).with_clock(|| 20);
+        let hello = session.start().unwrap();
+        let ack = WsBody::<Hello>::from_json(&hello)
+            .unwrap()
+            .into_data()
+            .negotiate(&[1], &["json"])
+            .unwrap();
+        let Step::Send(subscribe) = session
+            .receive(&WsBody::from(ack.clone()).try_json().unwrap())
+            .unwrap()
+        else {
+            panic!("expected a subscribe");
+        };
+        insta::assert_snapshot!(subscribe, @r###"{"data":{"type":"subscribe","payload":{"collections":["Dogs"]}}}"###);
+
+        let Step::Event(event) = session.receive(&doggo_event(7)).unwrap() else {
+            panic!("expected an event");
+        };
+        assert_eq!(event.data().unwrap().name, "Barky");
+        let ping = Ping { sent_at: 10 }.into_ws_body().try_json().unwrap();
+        let Step::Send(pong) = session.receive(&ping).unwrap() else {
+            panic!("expected a pong");
+        };
+        insta::assert_snapshot!(pong, @r###"{"data":{"type":"pong","payload":{"ping_sent_at":10,"sent_at":20}}}"###);
+
+        // a new connection picks up after the last event
+        session.start().unwrap();
+        let Ok(Step::Send(subscribe)) = session.receive(&WsBody::from(ack).try_json().unwrap())
+        else {
+            panic!("expected a subscribe");
+        };
+        assert!(subscribe.contains(r#""from_seq":8"#));
+        let lagged = ErrorMessage::new(ErrorCode::Lagged, "too slow")
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        let Err(err) = session.receive(&lagged) else {
+            panic!("expected an error");
+        };
+        assert!(matches!(err, Error::Server(_)) && !is_fatal(&err));
+    }
+}
@@ -0,0 +1,259 @@
+// `Client` on the browser's `WebSocket`. there are no threads, so the
+// socket's handlers run the session on the page's event loop, and a timer
+// checks whether the client is gone the way the native thread does between
+// frames. it needs a `window`, so it doesn't run in workers
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+use ts_rs::TS;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CloseEvent, MessageEvent, WebSocket, Window};
+
+use super::{is_fatal, Client, ClientConfig, Session, Shared, Step, LISTENER_CAPACITY};
+use crate::{buffered, BufferedSender, Error, Event, OverflowPolicy};
+
+// how often the client is checked for being gone
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+impl<ID, T, C, P> Client<ID, T, C, P>
+where
+    ID: Serialize + DeserializeOwned + PartialEq + 'static,
+    T: Serialize + DeserializeOwned + TS + 'static,
+    C: Serialize + DeserializeOwned + PartialEq + Clone + 'static,
+    P: Serialize + DeserializeOwned + TS + 'static,
+{
+    // fails only if the browser refuses the url. unlike natively, a first
+    // connection that fails is retried like a dropped one: the client keeps
+    // reconnecting until it is dropped, unless the server refuses it for good,
+    // which `recv` then reports
+    pub fn connect(config: ClientConfig<C>) -> Result<Self, Error> {
+        let session = Session::new(config).with_clock(|| js_sys::Date::now() as u64);
+        let shared = Arc::new(Shared::default());
+        let (sender, inner) = buffered(LISTENER_CAPACITY, OverflowPolicy::CloseConnection);
+        let runner = Rc::new(RefCell::new(Runner {
+            session,
+            sender,
+            shared: shared.clone(),
+            window: web_sys::window().ok_or_else(|| Error::Transport("no window".to_owned()))?,
+            socket: None,
+            subscribed_before: false,
+            attempt: 0,
+            watchdog: None,
+            retry: None,
+        }));
+        Runner::open(&runner)?;
+        Runner::watch(&runner)?;
+        Ok(Self { inner, shared })
+    }
+}
+
+// the session and its socket. the handlers and timers hold it, so it lives
+// until `stop` drops them
+struct Runner<ID, T: Serialize + TS, C, P: Serialize + TS> {
+    session: Session<ID, T, C, P>,
+    sender: BufferedSender<Event<ID, T, C, P>>,
+    shared: Arc<Shared>,
+    window: Window,
+    socket: Option<(WebSocket, Handlers)>,
+    // the next subscribe is a reconnect
+    subscribed_before: bool,
+    // failed connections since the last subscribe
+    attempt: u32,
+    watchdog: Option<Timer>,
+    retry: Option<Timer>,
+}
+
+struct Handlers {
+    _open: Closure<dyn FnMut()>,
+    _message: Closure<dyn FnMut(MessageEvent)>,
+    _close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+struct Timer {
+    handle: i32,
+    _callback: Closure<dyn FnMut()>,
+}
+
+type Running<ID, T, C, P> = Rc<RefCell<Runner<ID, T, C, P>>>;
+
+impl<ID, T, C, P> Runner<ID, T, C, P>
+where
+    ID: Serialize + DeserializeOwned + PartialEq + 'static,
+    T: Serialize + DeserializeOwned + TS + 'static,
+    C: Serialize + DeserializeOwned + PartialEq + Clone + 'static,
+    P: Serialize + DeserializeOwned + TS + 'static,
+{
+    fn open(this: &Running<ID, T, C, P>) -> Result<(), Error> {
+        let mut runner = this.borrow_mut();
+        let socket = WebSocket::new(runner.session.config().url()).map_err(js_error)?;
+        let opened = this.clone();
+        let open = Closure::<dyn FnMut()>::new(move || {
+            let sent = {
+                let mut runner = opened.borrow_mut();
+                let hello = runner.session.start();
+                hello.and_then(|frame| runner.send(&frame))
+            };
+            if let Err(err) = sent {
+                Runner::failed(&opened, err);
+            }
+        });
+        let received = this.clone();
+        let message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            // the protocol is only ever sent as text
+            if let Some(text) = event.data().as_string() {
+                Runner::receive(&received, &text);
+            }
+        });
+        let closed = this.clone();
+        let close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+            let err = match event.code() {
+                1000 | 1001 => Error::Closed,
+                code => Error::Transport(format!("closed with code {code}")),
+            };
+            Runner::failed(&closed, err);
+        });
+        socket.set_onopen(Some(open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(message.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(close.as_ref().unchecked_ref()));
+        let handlers = Handlers {
+            _open: open,
+            _message: message,
+            _close: close,
+        };
+        runner.socket = Some((socket, handlers));
+        Ok(())
+    }
+
+    // stops once the client is gone
+    fn watch(this: &Running<ID, T, C, P>) -> Result<(), Error> {
+        let watched = this.clone();
+        let callback = Closure::<dyn FnMut()>::new(move || {
+            let mut runner = watched.borrow_mut();
+            if runner.sender.is_closed() {
+                runner.stop();
+            }
+        });
+        let mut runner = this.borrow_mut();
+        let handle = runner
+            .window
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                callback.as_ref().unchecked_ref(),
+                millis(POLL_INTERVAL),
+            )
+            .map_err(js_error)?;
+        runner.watchdog = Some(Timer {
+            handle,
+            _callback: callback,
+        });
+        Ok(())
+    }
+
+    fn send(&self, frame: &str) -> Result<(), Error> {
+        let (socket, _) = self.socket.as_ref().ok_or(Error::Closed)?;
+        socket.send_with_str(frame).map_err(js_error)
+    }
+
+    fn receive(this: &Running<ID, T, C, P>, text: &str) {
+        let mut runner = this.borrow_mut();
+        let subscribing = !runner.session.is_subscribed();
+        let sent = match runner.session.receive(text) {
+            Ok(Step::Event(event)) => {
+                runner.shared.lock().last_seq = runner.session.last_seq();
+                runner.sender.send(event)
+            }
+            Ok(Step::Send(frame)) => runner.send(&frame),
+            Ok(Step::Skip) => Ok(()),
+            Err(err) => Err(err),
+        };
+        if subscribing && runner.session.is_subscribed() {
+            runner.attempt = 0;
+            if std::mem::replace(&mut runner.subscribed_before, true) {
+                runner.shared.lock().reconnects += 1;
+            }
+        }
+        drop(runner);
+        if let Err(err) = sent {
+            Runner::failed(this, err);
+        }
+    }
+
+    // the connection failed with `err`: reconnect after the backoff, or stop
+    // if the client is gone or reconnecting can't help
+    fn failed(this: &Running<ID, T, C, P>, err: Error) {
+        let mut runner = this.borrow_mut();
+        runner.disconnect();
+        if runner.sender.is_closed() {
+            runner.stop();
+            return;
+        }
+        if is_fatal(&err) {
+            runner.shared.lock().failed = Some(err);
+            runner.stop();
+            return;
+        }
+        // a server going away says when to come back
+        let delay = match err {
+            Error::GoingAway(going_away) => going_away.reconnect_after(),
+            _ => runner.session.config().backoff().delay(runner.attempt),
+        };
+        runner.attempt += 1;
+        let retried = this.clone();
+        let callback = Closure::<dyn FnMut()>::new(move || {
+            retried.borrow_mut().retry = None;
+            if let Err(err) = Runner::open(&retried) {
+                Runner::failed(&retried, err);
+            }
+        });
+        let scheduled = runner
+            .window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                callback.as_ref().unchecked_ref(),
+                millis(delay),
+            );
+        match scheduled {
+            Ok(handle) => {
+                runner.retry = Some(Timer {
+                    handle,
+                    _callback: callback,
+                });
+            }
+            Err(err) => {
+                runner.shared.lock().failed = Some(js_error(err));
+                runner.stop();
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if let Some((socket, _)) = self.socket.take() {
+            socket.set_onopen(None);
+            socket.set_onmessage(None);
+            socket.set_onclose(None);
+            let _ = socket.close();
+        }
+    }
+
+    // drops everything holding the runner, and with it the sender, so the
+    // client's `recv` ends
+    fn stop(&mut self) {
+        self.disconnect();
+        if let Some(watchdog) = self.watchdog.take() {
+            self.window.clear_interval_with_handle(watchdog.handle);
+        }
+        if let Some(retry) = self.retry.take() {
+            self.window.clear_timeout_with_handle(retry.handle);
+        }
+    }
+}
+
+fn millis(duration: Duration) -> i32 {
+    i32::try_from(duration.as_millis()).unwrap_or(i32::MAX)
+}
+
+fn js_error(err: JsValue) -> Error {
+    Error::Transport(err.as_string().unwrap_or_else(|| format!("{err:?}")))
+}
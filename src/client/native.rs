@@ -0,0 +1,363 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use ts_rs::TS;
+
+use super::{is_fatal, Client, ClientConfig, Session, Shared, Step, LISTENER_CAPACITY};
+use crate::{buffered, BufferedSender, Error, Event, OverflowPolicy};
+
+// how often an idle connection, or one waiting to reconnect, checks whether
+// its client is gone
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// the clients run on a runtime of their own, so they work whichever runtime
+// their callers are on, if any
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("rsp-client")
+            .enable_all()
+            .build()
+            .expect("failed to start the client runtime")
+    })
+}
+
+// runs a `Session` over a tokio-tungstenite websocket, `ws://` or `wss://`
+impl<ID, T, C, P> Client<ID, T, C, P>
+where
+    ID: Serialize + DeserializeOwned + PartialEq + Send + 'static,
+    T: Serialize + DeserializeOwned + TS + Send + 'static,
+    C: Serialize + DeserializeOwned + PartialEq + Clone + Send + 'static,
+    P: Serialize + DeserializeOwned + TS + Send + 'static,
+{
+    // fails if the first connection does. after that the client keeps
+    // reconnecting until it is dropped, unless the server refuses it for good
+    // (a rejected token, no common protocol version, a non-retryable
+    // `ErrorMessage`), which `recv` then reports
+    pub fn connect(config: ClientConfig<C>) -> Result<Self, Error> {
+        let mut session = Session::new(config);
+        let shared = Arc::new(Shared::default());
+        let (sender, inner) = buffered(LISTENER_CAPACITY, OverflowPolicy::CloseConnection);
+        let (opened, first) = std::sync::mpsc::sync_channel(1);
+        let task_shared = shared.clone();
+        runtime().spawn(async move {
+            match open(&mut session).await {
+                Ok(socket) => {
+                    let _ = opened.send(Ok(()));
+                    run(session, socket, sender, &task_shared).await;
+                }
+                Err(err) => {
+                    let _ = opened.send(Err(err));
+                }
+            }
+        });
+        first.recv().expect("the client task panicked")?;
+        Ok(Self { inner, shared })
+    }
+}
+
+fn transport(err: tungstenite::Error) -> Error {
+    match err {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => Error::Closed,
+        err => Error::Transport(err.to_string()),
+    }
+}
+
+async fn send_text(socket: &mut Socket, text: String) -> Result<(), Error> {
+    socket.send(Message::text(text)).await.map_err(transport)
+}
+
+// the next message, with the pings before it answered by tungstenite
+async fn read_text(socket: &mut Socket) -> Result<String, Error> {
+    while let Some(message) = socket.next().await {
+        match message.map_err(transport)? {
+            Message::Text(text) => return Ok(text.as_str().to_owned()),
+            Message::Binary(bytes) => {
+                return String::from_utf8(bytes.to_vec())
+                    .map_err(|_| Error::InvalidFrame("message is not utf-8".to_owned()))
+            }
+            Message::Close(_) => return Err(Error::Closed),
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+        }
+    }
+    Err(Error::Closed)
+}
+
+// connects and runs the session up to its subscribe
+async fn open<ID, T, C, P>(session: &mut Session<ID, T, C, P>) -> Result<Socket, Error>
+where
+    ID: Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned + TS,
+    C: Serialize + DeserializeOwned + Clone,
+    P: Serialize + DeserializeOwned + TS,
+{
+    let (mut socket, _) = connect_async(session.config().url())
+        .await
+        .map_err(transport)?;
+    send_text(&mut socket, session.start()?).await?;
+    while !session.is_subscribed() {
+        if let Step::Send(frame) = session.receive(&read_text(&mut socket).await?)? {
+            send_text(&mut socket, frame).await?;
+        }
+    }
+    Ok(socket)
+}
+
+async fn run<ID, T, C, P>(
+    mut session: Session<ID, T, C, P>,
+    mut socket: Socket,
+    sender: BufferedSender<Event<ID, T, C, P>>,
+    shared: &Shared,
+) where
+    ID: Serialize + DeserializeOwned + PartialEq,
+    T: Serialize + DeserializeOwned + TS,
+    C: Serialize + DeserializeOwned + PartialEq + Clone,
+    P: Serialize + DeserializeOwned + TS,
+{
+    loop {
+        let err = receive(&mut session, &mut socket, &sender, shared).await;
+        if sender.is_closed() {
+            let _ = socket.close(None).await;
+            return;
+        }
+        if is_fatal(&err) {
+            shared.lock().failed = Some(err);
+            return;
+        }
+        // a server going away says when to come back
+        let after = match err {
+            Error::GoingAway(going_away) => Some(going_away.reconnect_after()),
+            _ => None,
+        };
+        match reconnect(&mut session, &sender, shared, after).await {
+            Some(reconnected) => socket = reconnected,
+            None => return,
+        }
+    }
+}
+
+// `None` once the client is gone or the server refused it for good. the
+// first attempt waits `after` instead of the backoff, if given
+async fn reconnect<ID, T, C, P>(
+    session: &mut Session<ID, T, C, P>,
+    sender: &BufferedSender<Event<ID, T, C, P>>,
+    shared: &Shared,
+    after: Option<Duration>,
+) -> Option<Socket>
+where
+    ID: Serialize + DeserializeOwned + PartialEq,
+    T: Serialize + DeserializeOwned + TS,
+    C: Serialize + DeserializeOwned + PartialEq + Clone,
+    P: Serialize + DeserializeOwned + TS,
+{
+    for attempt in 0.. {
+        let mut waited = Duration::ZERO;
+        let delay = match (attempt, after) {
+            (0, Some(after)) => after,
+            _ => session.config().backoff().delay(attempt),
+        };
+        while waited < delay {
+            if sender.is_closed() {
+                return None;
+            }
+            let step = POLL_INTERVAL.min(delay - waited);
+            tokio::time::sleep(step).await;
+            waited += step;
+        }
+        match open(session).await {
+            Ok(socket) => {
+                shared.lock().reconnects += 1;
+                return Some(socket);
+            }
+            Err(err) if is_fatal(&err) => {
+                shared.lock().failed = Some(err);
+                return None;
+            }
+            Err(_) => {}
+        }
+    }
+    None
+}
+
+// moves events into `sender` until the connection fails, returning why
+async fn receive<ID, T, C, P>(
+    session: &mut Session<ID, T, C, P>,
+    socket: &mut Socket,
+    sender: &BufferedSender<Event<ID, T, C, P>>,
+    shared: &Shared,
+) -> Error
+where
+    ID: Serialize + DeserializeOwned + PartialEq,
+    T: Serialize + DeserializeOwned + TS,
+    C: Serialize + DeserializeOwned + PartialEq + Clone,
+    P: Serialize + DeserializeOwned + TS,
+{
+    loop {
+        let text = match tokio::time::timeout(POLL_INTERVAL, read_text(socket)).await {
+            Ok(text) => text,
+            Err(_) if sender.is_closed() => return Error::Closed,
+            Err(_) => continue,
+        };
+        let sent = match text.and_then(|text| session.receive(&text)) {
+            Ok(Step::Event(event)) => {
+                shared.lock().last_seq = session.last_seq();
+                sender.send(event)
+            }
+            Ok(Step::Send(frame)) => send_text(socket, frame).await,
+            Ok(Step::Skip) => Ok(()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sent {
+            return err;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+    use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::client::test::{doggo_event, handshake, serve};
+    use crate::client::{Backoff, Client, ClientConfig};
+    use crate::test::{block_on, Collection, DoggoRecord};
+    use crate::{Error, Event, Listener};
+
+    #[test]
+    fn resumes_after_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/rsp", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let first = serve(&listener, &[0, 1]);
+            // the first connection is dropped here
+            let second = serve(&listener, &[2]);
+            (first, second)
+        });
+
+        let config = ClientConfig::new(url)
+            .with_token("secret")
+            .with_collections(vec![Collection::Dogs])
+            .with_backoff(Backoff::new(
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+            ));
+        let mut client = Client::<u32, DoggoRecord, Collection>::connect(config).unwrap();
+        let seqs: Vec<_> = block_on(async {
+            let mut seqs = Vec::new();
+            for _ in 0..3 {
+                let event: Event<u32, DoggoRecord, Collection> = client.recv().await.unwrap();
+                seqs.push(event.seq().unwrap());
+            }
+            seqs
+        });
+        assert_eq!(seqs, vec![0, 1, 2]);
+        assert_eq!(server.join().unwrap(), (None, Some(2)));
+        assert_eq!((client.last_seq(), client.reconnects()), (Some(2), 1));
+        assert_eq!(
+            Backoff::default().delay(20),
+            Duration::from_secs(30),
+            "the delay is capped"
+        );
+    }
+
+    #[test]
+    fn reconnects_resume_after_the_last_whole_event() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/rsp", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut socket, first) = handshake(&listener);
+            socket.send(Message::text(doggo_event(0))).unwrap();
+            // the connection drops halfway through the second event
+            let event = doggo_event(1);
+            let (half, _) = event.as_bytes().split_at(event.len() / 2);
+            let fragment = Frame::message(half.to_vec(), OpCode::Data(Data::Text), false);
+            socket.send(Message::Frame(fragment)).unwrap();
+            drop(socket);
+            let (mut socket, second) = handshake(&listener);
+            for seq in [1, 2] {
+                socket.send(Message::text(doggo_event(seq))).unwrap();
+            }
+            (first, second)
+        });
+
+        let config = ClientConfig::new(url)
+            .with_token("secret")
+            .with_collections(vec![Collection::Dogs])
+            .with_backoff(Backoff::new(
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+            ));
+        let mut client = Client::<u32, DoggoRecord, Collection>::connect(config).unwrap();
+        let seqs: Vec<_> = (0..3)
+            .map(|_| block_on(client.recv()).unwrap().seq())
+            .collect();
+        assert_eq!(seqs, [Some(0), Some(1), Some(2)]);
+        assert_eq!(server.join().unwrap(), (None, Some(1)));
+        assert_eq!((client.last_seq(), client.reconnects()), (Some(2), 1));
+    }
+
+    #[test]
+    fn refused_upgrades_fail_the_first_connect() {
+        // answers the upgrade request with `response`
+        let refusing = |response: &'static str| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            });
+            addr
+        };
+        let connect = |url: String| {
+            Client::<u32, DoggoRecord, Collection>::connect(ClientConfig::new(url))
+                .err()
+                .unwrap()
+        };
+
+        let addr = refusing("HTTP/1.1 400 Bad Request\r\n\r\n");
+        insta::assert_snapshot!(connect(format!("ws://{addr}/rsp")).to_string(), @"transport failed: HTTP error: 400 Bad Request");
+        let addr = refusing("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n");
+        insta::assert_snapshot!(connect(format!("ws://{addr}/rsp")).to_string(), @r###"transport failed: WebSocket protocol error: Key mismatch in "Sec-WebSocket-Accept" header"###);
+        // `wss://` starts with a tls client hello
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut record = [0; 3];
+            listener
+                .accept()
+                .unwrap()
+                .0
+                .read_exact(&mut record)
+                .unwrap();
+            record
+        });
+        let err = connect(format!("wss://localhost:{port}/rsp"));
+        assert!(matches!(err, Error::Transport(_)), "{err}");
+        assert_eq!(
+            server.join().unwrap(),
+            [0x16, 0x03, 0x01],
+            "a handshake record"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{de::DeserializeOwned, Serialize};
+use ts_rs::TS;
+
+use super::{Client, ClientConfig};
+use crate::testing::block_on;
+use crate::{Error, Event, Listener, NoPatch};
+
+// the client for programs without an async runtime, e.g. cli tools. `recv`
+// blocks the calling thread, and the events can be iterated until the client
+// gives up
+pub struct SyncClient<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    inner: Client<ID, T, C, P>,
+}
+
+impl<ID, T, C, P> SyncClient<ID, T, C, P>
+where
+    ID: Serialize + DeserializeOwned + PartialEq + Send + 'static,
+    T: Serialize + DeserializeOwned + TS + Send + 'static,
+    C: Serialize + DeserializeOwned + PartialEq + Clone + Send + 'static,
+    P: Serialize + DeserializeOwned + TS + Send + 'static,
+{
+    pub fn connect(config: ClientConfig<C>) -> Result<Self, Error> {
+        Client::connect(config).map(|inner| Self { inner })
+    }
+}
+
+impl<ID, T, C, P> SyncClient<ID, T, C, P>
+where
+    ID: Send,
+    T: Serialize + TS + Send,
+    C: Send,
+    P: Serialize + TS + Send,
+{
+    pub fn recv(&mut self) -> Result<Event<ID, T, C, P>, Error> {
+        block_on(self.inner.recv())
+    }
+
+    pub fn last_seq(&self) -> Option<u64> {
+        self.inner.last_seq()
+    }
+
+    pub fn reconnects(&self) -> u64 {
+        self.inner.reconnects()
+    }
+
+    pub fn into_inner(self) -> Client<ID, T, C, P> {
+        self.inner
+    }
+}
+
+// ends once the client is closed, after yielding the error it failed with if
+// there was one
+impl<ID, T, C, P> Iterator for SyncClient<ID, T, C, P>
+where
+    ID: Send,
+    T: Serialize + TS + Send,
+    C: Send,
+    P: Serialize + TS + Send,
+{
+    type Item = Result<Event<ID, T, C, P>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.recv() {
+            Err(Error::Closed) => None,
+            received => Some(received),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::client::test::serve;
+    use crate::client::{ClientConfig, SyncClient};
+    use crate::test::{Collection, DoggoRecord};
+
+    #[test]
+    fn events_can_be_iterated_without_a_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve(&listener, &[4, 5]));
+
+        let config = ClientConfig::new(url)
+            .with_token("secret")
+            .with_collections(vec![Collection::Dogs])
+            .with_from_seq(4);
+        let client = SyncClient::<u32, DoggoRecord, Collection>::connect(config).unwrap();
+        let seqs: Vec<_> = client
+            .take(2)
+            .map(|event| event.unwrap().seq().unwrap())
+            .collect();
+        assert_eq!(seqs, vec![4, 5]);
+        assert_eq!(server.join().unwrap(), Some(4));
+    }
+}
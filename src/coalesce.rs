@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Clock, Event, EventVerb, NoPatch, SystemClock};
+
+// holds events back for `window` so chatty producers send less: an update,
+// upsert or patch is replaced by a later update or upsert of the same record,
+// and dropped altogether if the record is deleted. the rest (inserts and
+// anything stamped with a transaction) passes through untouched, in order
+pub struct Coalescer<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch, K = SystemClock> {
+    window: Duration,
+    clock: K,
+    pending: VecDeque<Pending<ID, T, C, P>>,
+    collapsed: u64,
+}
+
+struct Pending<ID, T: Serialize + TS, C, P: Serialize + TS> {
+    event: Event<ID, T, C, P>,
+    since: u64,
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Coalescer<ID, T, C, P> {
+    pub fn new(window: Duration) -> Self {
+        Self::with_clock(window, SystemClock)
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS, K> Coalescer<ID, T, C, P, K> {
+    pub fn with_clock(window: Duration, clock: K) -> Self {
+        Self {
+            window,
+            clock,
+            pending: VecDeque::new(),
+            collapsed: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // events that were replaced or dropped so far
+    pub fn collapsed(&self) -> u64 {
+        self.collapsed
+    }
+}
+
+impl<ID, T, C, P, K> Coalescer<ID, T, C, P, K>
+where
+    ID: PartialEq,
+    T: Serialize + TS,
+    C: PartialEq,
+    P: Serialize + TS,
+    K: Clock,
+{
+    pub fn push(&mut self, event: Event<ID, T, C, P>) {
+        if event.txn_id().is_some() || event.id().is_none() {
+            return self.append(event);
+        }
+        let replaces = match event.verb {
+            EventVerb::Update(_) | EventVerb::Upsert(_) => true,
+            EventVerb::Delete(_) => false,
+            _ => return self.append(event),
+        };
+
+        let superseded: Vec<_> = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, pending)| is_update_of(&pending.event, &event))
+            .map(|(index, _)| index)
+            .collect();
+        self.collapsed += superseded.len() as u64;
+        match superseded.split_first() {
+            // an update takes the place of the first update it supersedes, so
+            // it goes out no later than that one would have
+            Some((&slot, rest)) if replaces => {
+                for &index in rest.iter().rev() {
+                    self.pending.remove(index);
+                }
+                self.pending[slot].event = event;
+            }
+            _ => {
+                for &index in superseded.iter().rev() {
+                    self.pending.remove(index);
+                }
+                self.append(event);
+            }
+        }
+    }
+
+    // the events that have been held back for the whole window, in order
+    pub fn ready(&mut self) -> Vec<Event<ID, T, C, P>> {
+        let now = self.clock.now();
+        let window = self.window.as_millis() as u64;
+        let ready = self
+            .pending
+            .iter()
+            .take_while(|pending| pending.since.saturating_add(window) <= now)
+            .count();
+        self.pending
+            .drain(..ready)
+            .map(|pending| pending.event)
+            .collect()
+    }
+
+    // the oldest pending event, whether its window has passed or not
+    pub fn pop(&mut self) -> Option<Event<ID, T, C, P>> {
+        self.pending.pop_front().map(|pending| pending.event)
+    }
+
+    // every pending event, whether its window has passed or not
+    pub fn flush(&mut self) -> Vec<Event<ID, T, C, P>> {
+        self.pending
+            .drain(..)
+            .map(|pending| pending.event)
+            .collect()
+    }
+
+    fn append(&mut self, event: Event<ID, T, C, P>) {
+        let since = self.clock.now();
+        self.pending.push_back(Pending { event, since });
+    }
+}
+
+// whether `pending` is an update `event` may replace or drop
+fn is_update_of<ID, T, C, P>(pending: &Event<ID, T, C, P>, event: &Event<ID, T, C, P>) -> bool
+where
+    ID: PartialEq,
+    T: Serialize + TS,
+    C: PartialEq,
+    P: Serialize + TS,
+{
+    matches!(
+        pending.verb,
+        EventVerb::Update(_) | EventVerb::Upsert(_) | EventVerb::Patch(_)
+    ) && pending.txn_id().is_none()
+        && pending.id() == event.id()
+        && pending.collection() == event.collection()
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use crate::test::{doggo, Collection, DoggoPatch, DoggoRecord};
+    use crate::{Coalescer, Event, Patchable, Syncable};
+
+    #[test]
+    fn updates_collapse_within_the_window() {
+        let named = |id, name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(id)
+        };
+        let now = Cell::new(0);
+        let mut coalescer = Coalescer::with_clock(Duration::from_millis(10), || now.get());
+
+        coalescer.push(named(1, "a").to_update_event().into_patchable());
+        coalescer.push(named(1, "a").to_patch_event(DoggoPatch { name: None }));
+        coalescer.push(named(2, "b").to_upsert_event().into_patchable());
+        now.set(5);
+        coalescer.push(named(1, "c").to_upsert_event().into_patchable());
+        coalescer.push(named(2, "d").to_update_event().into_patchable());
+        coalescer.push(Event::new_delete_event(2, Collection::Dogs));
+        assert_eq!(coalescer.len(), 2);
+        assert_eq!(coalescer.collapsed(), 4);
+
+        now.set(10);
+        let ready = coalescer.ready();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].data().unwrap().name, "c");
+        assert!(coalescer.ready().is_empty());
+        let flushed = coalescer.flush();
+        assert_eq!(flushed[0].id(), Some(&2));
+        assert!(flushed[0].data().is_none());
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::Error;
+
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "compression")]
+mod compress;
+mod limits;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod style;
+
+#[cfg(feature = "cbor")]
+pub use cbor::CborCodec;
+#[cfg(feature = "compression")]
+pub use compress::{Compressed, Compression, Compressor, Envelope, DEFAULT_THRESHOLD};
+pub use limits::{Limit, LimitedCodec, Limits};
+#[cfg(feature = "msgpack")]
+pub use msgpack::MessagePackCodec;
+pub use style::{StyledJsonCodec, WireStyle};
+
+// how deep arrays and maps may nest in a decoded message, as deep as
+// serde_json allows, so a frame of nothing but openings can't overflow the
+// stack of the binary codecs' readers
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub(crate) const MAX_DEPTH: usize = 128;
+
+// how a message is turned into the bytes of a websocket frame
+pub trait WireCodec {
+    fn name(&self) -> &'static str;
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl WireCodec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(Error::Encode)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Decode)
+    }
+}
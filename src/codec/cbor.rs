@@ -0,0 +1,88 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use super::{WireCodec, MAX_DEPTH};
+use crate::Error;
+
+// CBOR (RFC 8949) encoding of the JSON data model, see `MessagePackCodec`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+impl WireCodec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let value = serde_json::to_value(value).map_err(Error::Encode)?;
+        let mut out = Vec::new();
+        ciborium::into_writer(&value, &mut out).map_err(|err| invalid(&err.to_string()))?;
+        Ok(out)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        let mut rest = bytes;
+        let value: Value = ciborium::de::from_reader_with_recursion_limit(&mut rest, MAX_DEPTH)
+            .map_err(|err| match err {
+                ciborium::de::Error::RecursionLimitExceeded => invalid("nested too deeply"),
+                err => invalid(&err.to_string()),
+            })?;
+        if !rest.is_empty() {
+            return Err(invalid("trailing bytes after message"));
+        }
+        serde_json::from_value(value).map_err(Error::Decode)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidFrame(format!("cbor: {reason}"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::codec::{CborCodec, WireCodec};
+    use crate::test::{doggo, Collection, DoggoPatch, DoggoRecord};
+    use crate::{Event, Patchable, Syncable, Txn, WsBody};
+
+    #[test]
+    fn cbor_round_trips_every_verb() {
+        let doggo = doggo(1);
+        let patch = DoggoPatch {
+            name: Some("Woofy".to_string()),
+        };
+        let txn = || Txn::builder().txn_id(7).build();
+
+        let events: Vec<Event<u32, DoggoRecord, Collection, DoggoPatch>> = vec![
+            Event::new_insert_event(doggo.clone(), Collection::Dogs),
+            doggo.clone().to_update_event().into_patchable(),
+            doggo.clone().to_upsert_event().into_patchable(),
+            doggo.to_patch_event(patch),
+            doggo.to_delete_event().into_patchable(),
+            txn().begin_event(),
+            txn().commit_event(),
+            txn().abort_event(),
+        ];
+
+        for event in events {
+            let body = event.into_ws_body();
+            let bytes = body.to_bytes(&CborCodec).unwrap();
+            let decoded: WsBody<Event<u32, DoggoRecord, Collection, DoggoPatch>> =
+                WsBody::from_bytes(&bytes, &CborCodec).unwrap();
+            assert_eq!(decoded.try_json().unwrap(), body.try_json().unwrap());
+        }
+
+        let numbers = serde_json::json!([0, 23, 24, -1, -25, 70000, i64::MIN, u64::MAX, -0.5]);
+        let bytes = CborCodec.encode(&numbers).unwrap();
+        assert_eq!(
+            CborCodec.decode::<serde_json::Value>(&bytes).unwrap(),
+            numbers
+        );
+        // half precision 1.5
+        assert_eq!(
+            CborCodec
+                .decode::<serde_json::Value>(&[0xf9, 0x3e, 0x00])
+                .unwrap(),
+            serde_json::json!(1.5)
+        );
+    }
+}
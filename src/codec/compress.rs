@@ -0,0 +1,344 @@
+use std::io::{Read, Write};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::limits::exceeds;
+use super::Limit;
+use crate::{base64, Error};
+
+// below this many bytes of json a message goes out as it is
+pub const DEFAULT_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    // zlib, what http and `CompressionStream` call "deflate"
+    Deflate,
+    Gzip,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Deflate => "deflate",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "deflate" => Ok(Compression::Deflate),
+            "gzip" => Ok(Compression::Gzip),
+            _ => Err(Error::UnsupportedCodec(vec![name.to_owned()])),
+        }
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> Vec<u8> {
+        let level = flate2::Compression::default();
+        // writing into a vec can't fail
+        match self {
+            Compression::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(bytes).expect("writing into a vec");
+                encoder.finish().expect("writing into a vec")
+            }
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(bytes).expect("writing into a vec");
+                encoder.finish().expect("writing into a vec")
+            }
+        }
+    }
+
+    // fails with `Error::LimitExceeded` once the output would grow past
+    // `max_output` bytes
+    pub fn decompress(&self, bytes: &[u8], max_output: usize) -> Result<Vec<u8>, Error> {
+        let decoder: Box<dyn Read + '_> = match self {
+            Compression::Deflate => Box::new(ZlibDecoder::new(bytes)),
+            Compression::Gzip => Box::new(GzDecoder::new(bytes)),
+        };
+        let mut out = Vec::new();
+        // a byte past the limit tells a stream that fits exactly from one
+        // that doesn't, without inflating the rest of it
+        decoder
+            .take(max_output as u64 + 1)
+            .read_to_end(&mut out)
+            .map_err(|err| Error::InvalidFrame(format!("{}: {err}", self.name())))?;
+        exceeds(Limit::PayloadBytes, max_output, out.len())?;
+        Ok(out)
+    }
+}
+
+// the json of a message, compressed with `codec` and base64 encoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Compressed {
+    pub codec: String,
+    pub data: String,
+}
+
+impl Compressed {
+    // the json of the message
+    pub(crate) fn inflate(&self, max_output: usize) -> Result<Vec<u8>, Error> {
+        let compression = Compression::from_name(&self.codec)?;
+        let bytes = base64::decode(&self.data)
+            .ok_or_else(|| Error::InvalidFrame("data is not valid base64".to_owned()))?;
+        compression.decompress(&bytes, max_output)
+    }
+}
+
+// a message as a `Compressor` sends it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+#[ts(export)]
+pub enum Envelope<T> {
+    Plain(T),
+    Compressed(Compressed),
+}
+
+// compresses messages (typically batches and snapshots) whose json is at
+// least `threshold` bytes, if that makes them smaller. once the websocket has
+// negotiated permessage-deflate the transport compresses every frame anyway,
+// so compressing again only costs time and everything goes out plain
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    compression: Compression,
+    threshold: usize,
+    permessage_deflate: bool,
+}
+
+impl Compressor {
+    pub fn new(compression: Compression) -> Self {
+        Self {
+            compression,
+            threshold: DEFAULT_THRESHOLD,
+            permessage_deflate: false,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    // whether the connection negotiated the permessage-deflate extension
+    pub fn with_permessage_deflate(mut self, negotiated: bool) -> Self {
+        self.permessage_deflate = negotiated;
+        self
+    }
+
+    pub fn seal<T: Serialize>(&self, message: T) -> Result<Envelope<T>, Error> {
+        if self.permessage_deflate {
+            return Ok(Envelope::Plain(message));
+        }
+        let json = serde_json::to_vec(&message).map_err(Error::Encode)?;
+        if json.len() < self.threshold {
+            return Ok(Envelope::Plain(message));
+        }
+        let data = base64::encode(&self.compression.compress(&json));
+        if data.len() >= json.len() {
+            return Ok(Envelope::Plain(message));
+        }
+        Ok(Envelope::Compressed(Compressed {
+            codec: self.compression.name().to_owned(),
+            data,
+        }))
+    }
+}
+
+impl<T> Envelope<T> {
+    // the message, whichever codec the sender compressed it with. its json
+    // may inflate to no more than `max_output` bytes, typically
+    // `Limits::max_payload_bytes`
+    pub fn open(self, max_output: usize) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            Envelope::Plain(message) => Ok(message),
+            Envelope::Compressed(compressed) => {
+                let json = compressed.inflate(max_output)?;
+                serde_json::from_slice(&json).map_err(Error::Decode)
+            }
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, Envelope::Compressed(_))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::codec::{Compression, Compressor, Envelope, Limit};
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Error, Snapshot, SnapshotEntry, WsBody};
+
+    type DoggoSnapshot = Snapshot<u32, DoggoRecord, Collection>;
+
+    fn zlib_compress(bytes: &[u8]) -> Vec<u8> {
+        Compression::Deflate.compress(bytes)
+    }
+
+    fn zlib_decompress(bytes: &[u8], max_output: usize) -> Result<Vec<u8>, Error> {
+        Compression::Deflate.decompress(bytes, max_output)
+    }
+
+    fn gzip_compress(bytes: &[u8]) -> Vec<u8> {
+        Compression::Gzip.compress(bytes)
+    }
+
+    fn gzip_decompress(bytes: &[u8], max_output: usize) -> Result<Vec<u8>, Error> {
+        Compression::Gzip.decompress(bytes, max_output)
+    }
+
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn over_limit(result: Result<Vec<u8>, Error>) -> usize {
+        match result {
+            Err(Error::LimitExceeded {
+                limit: Limit::PayloadBytes,
+                max,
+                ..
+            }) => max,
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn every_block_type_inflates() {
+        // zlib's level 0: a stored block, then an empty final stored block
+        let stored = hex("7801000300fcff616263000000ffff010000ffff024d0127");
+        assert_eq!(zlib_decompress(&stored, 3).unwrap(), b"abc");
+
+        // what flate2 writes
+        let input = b"abcabcabcabcabcabcabc, barky barky barky".repeat(20);
+        let compressed = zlib_compress(&input);
+        assert!(compressed.len() < input.len() / 4);
+        assert_eq!(zlib_decompress(&compressed, input.len()).unwrap(), input);
+        assert_eq!(
+            gzip_decompress(&gzip_compress(&input), input.len()).unwrap(),
+            input
+        );
+
+        // zlib's own dynamic huffman block
+        let dynamic = hex("78da2d8ac911003000016b75f45f436c120fcc2227d134b36e2b75d0c4bc409390f7b2fe7d63eab2187e00d5cb1e8f");
+        assert_eq!(dynamic[2] & 0b111, 0b101);
+        assert_eq!(
+            zlib_decompress(&dynamic, 1024).unwrap(),
+            b"abcccaaaacaabacaaaadcaabccabaabcabadaaaabbadabaababacaabaaabacaadaacdbdbaabbcaab"
+        );
+    }
+
+    #[test]
+    fn corrupt_streams_are_refused() {
+        let input = b"barky barky barky woofy".repeat(10);
+        let compressed = zlib_compress(&input);
+        for len in [1, 2, 5, compressed.len() - 5, compressed.len() - 1] {
+            assert!(
+                matches!(
+                    zlib_decompress(&compressed[..len], input.len()),
+                    Err(Error::InvalidFrame(_))
+                ),
+                "truncated to {len} bytes"
+            );
+        }
+
+        let mut bad_adler = compressed.clone();
+        *bad_adler.last_mut().unwrap() ^= 1;
+        let Err(Error::InvalidFrame(reason)) = zlib_decompress(&bad_adler, input.len()) else {
+            panic!("a bad checksum passed");
+        };
+        assert_eq!(reason, "deflate: corrupt deflate stream");
+
+        let mut bad_crc = gzip_compress(&input);
+        let crc = bad_crc.len() - 8;
+        bad_crc[crc] ^= 1;
+        assert!(gzip_decompress(&bad_crc, input.len()).is_err());
+    }
+
+    #[test]
+    fn output_stops_at_the_limit() {
+        // a megabyte of zeros is a couple of kilobytes of long matches
+        let bomb = zlib_compress(&vec![0; 1024 * 1024]);
+        assert!(bomb.len() < 8 * 1024);
+        assert_eq!(over_limit(zlib_decompress(&bomb, 64 * 1024)), 64 * 1024);
+        assert_eq!(
+            over_limit(gzip_decompress(&gzip_compress(&vec![0; 1024 * 1024]), 1000)),
+            1000
+        );
+        assert_eq!(
+            zlib_decompress(&bomb, 1024 * 1024).unwrap().len(),
+            1024 * 1024
+        );
+
+        // stored blocks and literals count too
+        let stored = hex("7801000300fcff616263000000ffff010000ffff024d0127");
+        assert_eq!(over_limit(zlib_decompress(&stored, 2)), 2);
+        assert_eq!(over_limit(zlib_decompress(&zlib_compress(b"ab"), 1)), 1);
+    }
+
+    #[test]
+    fn large_snapshots_are_compressed() {
+        let records = (0..200)
+            .map(|id| {
+                let doggo = DoggoRecord {
+                    name: format!("Barky {id}"),
+                    ..doggo(id)
+                };
+                SnapshotEntry::new(id, doggo)
+            })
+            .collect();
+        let snapshot = DoggoSnapshot::new(Collection::Dogs, 7, records);
+        let plain = WsBody::new(&snapshot).try_json().unwrap();
+
+        for compression in [Compression::Deflate, Compression::Gzip] {
+            let envelope = Compressor::new(compression).seal(&snapshot).unwrap();
+            assert!(envelope.is_compressed());
+            let json = WsBody::new(envelope).try_json().unwrap();
+            assert!(json.len() * 3 < plain.len(), "{} bytes", json.len());
+
+            let body = WsBody::<Envelope<DoggoSnapshot>>::from_json(&json).unwrap();
+            let opened = body.into_data().open(plain.len()).unwrap();
+            assert_eq!(WsBody::new(&opened).try_json().unwrap(), plain);
+        }
+
+        // streams the way zlib writes them: stored blocks, and a dynamic
+        // huffman block
+        let compression = Compression::Deflate;
+        let stored = [
+            0x78, 0x01, 0x00, 0x03, 0x00, 0xfc, 0xff, 0x61, 0x62, 0x63, 0x00, 0x00, 0x00, 0xff,
+            0xff, 0x01, 0x00, 0x00, 0xff, 0xff, 0x02, 0x4d, 0x01, 0x27,
+        ];
+        assert_eq!(compression.decompress(&stored, 3).unwrap(), b"abc");
+        let hex = "78da2d8ac911003000016b75f45f436c120fcc2227d134b36e2b75d0c4bc409390f7b2fe7d63eab2187e00d5cb1e8f";
+        let dynamic: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+            .collect();
+        assert_eq!(
+            compression.decompress(&dynamic, 1024).unwrap(),
+            b"abcccaaaacaabacaaaadcaabccabaabcabadaaaabbadabaababacaabaaabacaadaacdbdbaabbcaab"
+        );
+
+        let small = Compressor::new(compression).seal(Collection::Cats).unwrap();
+        assert!(matches!(small, Envelope::Plain(Collection::Cats)));
+        let negotiated = Compressor::new(compression)
+            .with_permessage_deflate(true)
+            .seal(&snapshot)
+            .unwrap();
+        assert!(!negotiated.is_compressed());
+        let unknown = WsBody::<Envelope<DoggoSnapshot>>::from_json(
+            r#"{"data":{"type":"compressed","payload":{"codec":"br","data":""}}}"#,
+        )
+        .unwrap();
+        assert!(unknown.into_data().open(1024).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+#[cfg(feature = "compression")]
+use crate::codec::Envelope;
+use crate::codec::WireCodec;
+use crate::{Error, MAX_ENVELOPE_LEN};
+
+// which of the `Limits` a message went over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    PayloadBytes,
+    BatchSize,
+    StringLength,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::PayloadBytes => "payload bytes",
+            Limit::BatchSize => "batch size",
+            Limit::StringLength => "string length",
+        })
+    }
+}
+
+// bounds on a single message, so one frame can't make the server allocate
+// without end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    // of the encoded message
+    pub max_payload_bytes: usize,
+    // elements of any one array: the events of a batch, the records of a
+    // snapshot or query result, the ids of a query and so on
+    pub max_batch_size: usize,
+    // bytes of any one string or object key
+    pub max_string_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_payload_bytes: MAX_ENVELOPE_LEN,
+            max_batch_size: 1024,
+            max_string_len: 64 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    pub fn with_max_payload_bytes(mut self, max_payload_bytes: usize) -> Self {
+        self.max_payload_bytes = max_payload_bytes;
+        self
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    pub fn with_max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = max_string_len;
+        self
+    }
+
+    pub fn check_payload(&self, len: usize) -> Result<(), Error> {
+        exceeds(Limit::PayloadBytes, self.max_payload_bytes, len)
+    }
+
+    // checks the arrays and strings of a message, at any depth
+    pub fn check(&self, value: &Value) -> Result<(), Error> {
+        match value {
+            Value::String(string) => {
+                exceeds(Limit::StringLength, self.max_string_len, string.len())
+            }
+            Value::Array(values) => {
+                exceeds(Limit::BatchSize, self.max_batch_size, values.len())?;
+                values.iter().try_for_each(|value| self.check(value))
+            }
+            Value::Object(object) => object.iter().try_for_each(|(key, value)| {
+                exceeds(Limit::StringLength, self.max_string_len, key.len())?;
+                self.check(value)
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(super) fn exceeds(limit: Limit, max: usize, found: usize) -> Result<(), Error> {
+    match found > max {
+        true => Err(Error::LimitExceeded { limit, max, found }),
+        false => Ok(()),
+    }
+}
+
+// enforces `Limits` around another codec: messages over them are neither
+// sent nor decoded. an oversized frame is refused before the inner codec
+// reads a byte of it
+#[derive(Debug, Clone, Default)]
+pub struct LimitedCodec<W> {
+    inner: W,
+    limits: Limits,
+}
+
+impl<W: WireCodec> LimitedCodec<W> {
+    pub fn new(inner: W, limits: Limits) -> Self {
+        Self { inner, limits }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    // the message of an envelope this codec decoded. a compressed one is held
+    // to the same limits once inflated as a frame would be, so a small frame
+    // can't smuggle in a message that's over them
+    #[cfg(feature = "compression")]
+    pub fn open<T: DeserializeOwned>(&self, envelope: Envelope<T>) -> Result<T, Error> {
+        match envelope {
+            Envelope::Plain(message) => Ok(message),
+            Envelope::Compressed(compressed) => {
+                let json = compressed.inflate(self.limits.max_payload_bytes)?;
+                let value: Value = serde_json::from_slice(&json).map_err(Error::Decode)?;
+                self.limits.check(&value)?;
+                serde_json::from_value(value).map_err(Error::Decode)
+            }
+        }
+    }
+}
+
+impl<W: WireCodec> WireCodec for LimitedCodec<W> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let value = serde_json::to_value(value).map_err(Error::Encode)?;
+        self.limits.check(&value)?;
+        let bytes = self.inner.encode(&value)?;
+        self.limits.check_payload(bytes.len())?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        self.limits.check_payload(bytes.len())?;
+        let value: Value = self.inner.decode(bytes)?;
+        self.limits.check(&value)?;
+        serde_json::from_value(value).map_err(Error::Decode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::codec::{JsonCodec, Limit, LimitedCodec, Limits, WireCodec};
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Error, Event, EventBatch, Syncable, WsBody};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn messages_over_the_limits_are_refused() {
+        let limits = Limits::default()
+            .with_max_payload_bytes(512)
+            .with_max_batch_size(2)
+            .with_max_string_len(16);
+        let codec = LimitedCodec::new(JsonCodec, limits);
+        let named = |name: &str| DoggoRecord {
+            name: name.to_owned(),
+            ..doggo(1)
+        };
+        let mut batch = EventBatch::new(0);
+        batch.push(named("Barky").to_upsert_event());
+        batch.push(named("Woofy").to_upsert_event());
+        let bytes = codec.encode(&batch.clone().into_ws_body()).unwrap();
+        let decoded: WsBody<EventBatch<u32, DoggoRecord, Collection>> =
+            codec.decode(&bytes).unwrap();
+        assert_eq!(decoded.data().len(), 2);
+
+        batch.push(named("Fluffy").to_upsert_event());
+        let err = codec.encode(&batch.into_ws_body()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::LimitExceeded {
+                limit: Limit::BatchSize,
+                max: 2,
+                found: 3
+            }
+        ));
+        let long = named("Sir Barksalot the Third").to_upsert_event();
+        let err = codec.encode(&long.clone().into_ws_body()).unwrap_err();
+        insta::assert_snapshot!(err.to_string(), @r###"string length of 23 is over the limit of 16"###);
+
+        // what an unlimited peer sends is checked on the way in too
+        let bytes = long.into_ws_body().to_bytes(&JsonCodec).unwrap();
+        assert!(matches!(
+            codec.decode::<WsBody<DoggoEvent>>(&bytes),
+            Err(Error::LimitExceeded {
+                limit: Limit::StringLength,
+                ..
+            })
+        ));
+        let small = LimitedCodec::new(JsonCodec, limits.with_max_payload_bytes(8));
+        assert!(matches!(
+            small.decode::<WsBody<DoggoEvent>>(&[b' '; 9]),
+            Err(Error::LimitExceeded {
+                limit: Limit::PayloadBytes,
+                max: 8,
+                found: 9
+            })
+        ));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn inflated_messages_are_held_to_the_limits() {
+        use crate::base64;
+        use crate::codec::{Compressed, Compression, Compressor, Envelope};
+
+        // a compressed message in a json frame
+        fn frame<T: serde::Serialize>(message: T) -> Vec<u8> {
+            let compressor = Compressor::new(Compression::Deflate).with_threshold(0);
+            let envelope = compressor.seal(message).unwrap();
+            assert!(envelope.is_compressed());
+            JsonCodec.encode(&WsBody::new(envelope)).unwrap()
+        }
+
+        let limits = Limits::default()
+            .with_max_payload_bytes(16 * 1024)
+            .with_max_string_len(4096);
+        let codec = LimitedCodec::new(JsonCodec, limits);
+        let open = |bytes: &[u8]| {
+            let body: WsBody<Envelope<DoggoEvent>> = codec.decode(bytes).unwrap();
+            codec.open(body.into_data())
+        };
+
+        // a quarter megabyte name fits a frame well under the payload limit
+        let huge = DoggoRecord {
+            name: "a".repeat(256 * 1024),
+            ..doggo(1)
+        };
+        let bytes = frame(huge.to_upsert_event());
+        assert!(bytes.len() < 4096, "{} bytes", bytes.len());
+        assert!(matches!(
+            open(&bytes),
+            Err(Error::LimitExceeded {
+                limit: Limit::PayloadBytes,
+                max: 16384,
+                ..
+            })
+        ));
+
+        // under the payload limit once inflated, but not the others
+        let long = DoggoRecord {
+            name: "a".repeat(8000),
+            ..doggo(1)
+        };
+        assert!(matches!(
+            open(&frame(long.to_upsert_event())),
+            Err(Error::LimitExceeded {
+                limit: Limit::StringLength,
+                max: 4096,
+                found: 8000
+            })
+        ));
+        let body: WsBody<Envelope<Vec<u32>>> = codec.decode(&frame(vec![7u32; 2000])).unwrap();
+        assert!(matches!(
+            codec.open(body.into_data()),
+            Err(Error::LimitExceeded {
+                limit: Limit::BatchSize,
+                ..
+            })
+        ));
+        // nesting is as limited as it is in a frame
+        let deep = format!("{}{}", "[".repeat(5_000), "]".repeat(5_000));
+        let deep = Envelope::<serde_json::Value>::Compressed(Compressed {
+            codec: "deflate".to_owned(),
+            data: base64::encode(&Compression::Deflate.compress(deep.as_bytes())),
+        });
+        assert!(matches!(codec.open(deep), Err(Error::Decode(_))));
+
+        let fine = DoggoRecord {
+            name: "a".repeat(4000),
+            ..doggo(1)
+        };
+        let opened = open(&frame(fine.to_upsert_event())).unwrap();
+        assert_eq!(opened.data().unwrap().name.len(), 4000);
+    }
+}
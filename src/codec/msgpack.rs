@@ -0,0 +1,69 @@
+use std::io::Cursor;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{WireCodec, MAX_DEPTH};
+use crate::Error;
+
+// MessagePack encoding of the JSON data model: messages are serialized to a
+// `serde_json::Value` first, so anything that round-trips through JSON
+// round-trips through this codec
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+impl WireCodec for MessagePackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let value = serde_json::to_value(value).map_err(Error::Encode)?;
+        rmp_serde::to_vec_named(&value).map_err(|err| invalid(&err.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        let mut deserializer = rmp_serde::Deserializer::new(Cursor::new(bytes));
+        deserializer.set_max_depth(MAX_DEPTH);
+        let value = Value::deserialize(&mut deserializer).map_err(|err| match err {
+            rmp_serde::decode::Error::DepthLimitExceeded => invalid("nested too deeply"),
+            err => invalid(&err.to_string()),
+        })?;
+        if deserializer.position() != bytes.len() as u64 {
+            return Err(invalid("trailing bytes after message"));
+        }
+        serde_json::from_value(value).map_err(Error::Decode)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidFrame(format!("msgpack: {reason}"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::codec::{MessagePackCodec, WireCodec};
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Event, Syncable, WsBody};
+
+    #[test]
+    fn msgpack_round_trips() {
+        let doggo = doggo(300);
+
+        let body = doggo.to_upsert_event().into_ws_body();
+        let bytes = body.to_bytes(&MessagePackCodec).unwrap();
+        assert!(bytes.len() < body.try_json().unwrap().len());
+
+        let decoded: WsBody<Event<u32, DoggoRecord, Collection>> =
+            WsBody::from_bytes(&bytes, &MessagePackCodec).unwrap();
+        assert_eq!(decoded.try_json().unwrap(), body.try_json().unwrap());
+
+        let numbers = serde_json::json!([0, 127, 128, -1, -33, -200, 70000, -70000, u64::MAX, 1.5]);
+        let bytes = MessagePackCodec.encode(&numbers).unwrap();
+        let decoded: serde_json::Value = MessagePackCodec.decode(&bytes).unwrap();
+        assert_eq!(decoded, numbers);
+        assert!(MessagePackCodec
+            .decode::<serde_json::Value>(&bytes[..bytes.len() - 1])
+            .is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use crate::codec::WireCodec;
+use crate::Error;
+
+// the protocol's field names that change with the style, i.e. those of more
+// than one word. names of one word are the same in every style
+const FIELDS: &[&str] = &[
+    "actor_id",
+    "assigned_id",
+    "causation_id",
+    "correlation_id",
+    "current_revision",
+    "deleted_at",
+    "expected_revision",
+    "expires_at",
+    "from_seq",
+    "idempotency_key",
+    "key_id",
+    "live_seq",
+    "next_cursor",
+    "occurred_at",
+    "ping_sent_at",
+    "protocol_version",
+    "protocol_versions",
+    "query_id",
+    "reconnect_after",
+    "related_seq",
+    "request_id",
+    "schema_version",
+    "sent_at",
+    "txn_id",
+];
+
+// and the enum tags, found under `type` or, for an `ErrorCode`, `code`
+const TAGS: &[&str] = &[
+    "bad_command",
+    "entered_result_set",
+    "left_result_set",
+    "live_query",
+    "not_found",
+    "presence_heartbeat",
+    "txn_abort",
+    "txn_begin",
+    "txn_commit",
+];
+
+// how multi-word names are spelled on the wire. the crate's types are
+// snake_case; a `StyledJsonCodec` renames them on the way out and back on the
+// way in, so a deployment can match the conventions of an existing api.
+// application records (anything under a nested `data` or a patch's `value`)
+// are left as their own serde attributes have them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WireStyle {
+    #[default]
+    SnakeCase,
+    CamelCase,
+}
+
+impl WireStyle {
+    // how this style spells a snake_case protocol name
+    pub fn spell(self, name: &str) -> String {
+        match self {
+            WireStyle::SnakeCase => name.to_owned(),
+            WireStyle::CamelCase => {
+                let mut words = name.split('_');
+                let mut camel = words.next().unwrap_or_default().to_owned();
+                for word in words {
+                    let mut chars = word.chars();
+                    if let Some(first) = chars.next() {
+                        camel.push(first.to_ascii_uppercase());
+                        camel.push_str(chars.as_str());
+                    }
+                }
+                camel
+            }
+        }
+    }
+
+    // renames the protocol's names in a snake_case message to this style
+    pub fn restyle(self, value: &mut Value) {
+        if self != WireStyle::SnakeCase {
+            rename(value, true, &|name| {
+                is_protocol_name(name).then(|| self.spell(name))
+            });
+        }
+    }
+
+    // and back, before the message is decoded
+    pub fn unstyle(self, value: &mut Value) {
+        if self != WireStyle::SnakeCase {
+            let snake = self.snake_names();
+            rename(value, true, &|name| {
+                snake.get(name).map(|name| name.to_string())
+            });
+        }
+    }
+
+    // renames the protocol's names in typescript, e.g. the bindings ts-rs
+    // exports or a generated client, to match what goes over the wire
+    pub fn restyle_ts(self, ts: &str) -> String {
+        if self == WireStyle::SnakeCase {
+            return ts.to_owned();
+        }
+        let mut out = String::with_capacity(ts.len());
+        let mut word = String::new();
+        for c in ts.chars().chain(['\0']) {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            match is_protocol_name(&word) {
+                true => out.push_str(&self.spell(&word)),
+                false => out.push_str(&word),
+            }
+            word.clear();
+            if c != '\0' {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    fn snake_names(self) -> &'static HashMap<String, &'static str> {
+        static CAMEL: OnceLock<HashMap<String, &'static str>> = OnceLock::new();
+        CAMEL.get_or_init(|| {
+            FIELDS
+                .iter()
+                .chain(TAGS)
+                .map(|name| (WireStyle::CamelCase.spell(name), *name))
+                .collect()
+        })
+    }
+}
+
+fn is_protocol_name(name: &str) -> bool {
+    FIELDS.contains(&name) || TAGS.contains(&name)
+}
+
+// renames keys and tags through `renamed`, which returns `None` for names
+// that stay. `root` is the body itself, whose `data` is the message
+fn rename(value: &mut Value, root: bool, renamed: &dyn Fn(&str) -> Option<String>) {
+    match value {
+        Value::Object(object) => {
+            let mut styled = Map::with_capacity(object.len());
+            for (key, mut value) in std::mem::take(object) {
+                let application = (key == "data" && !root) || key == "value";
+                if !application {
+                    match (&mut value, key.as_str()) {
+                        (Value::String(tag), "type" | "code") => {
+                            if let Some(renamed) = renamed(tag) {
+                                *tag = renamed;
+                            }
+                        }
+                        (value, _) => rename(value, false, renamed),
+                    }
+                }
+                let key = renamed(&key).unwrap_or(key);
+                styled.insert(key, value);
+            }
+            *object = styled;
+        }
+        Value::Array(values) => {
+            for value in values {
+                rename(value, false, renamed);
+            }
+        }
+        _ => {}
+    }
+}
+
+// json in a `WireStyle`. it negotiates as `json`, the style being a setting
+// of the deployment rather than something each client picks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StyledJsonCodec {
+    style: WireStyle,
+}
+
+impl StyledJsonCodec {
+    pub fn new(style: WireStyle) -> Self {
+        Self { style }
+    }
+
+    pub fn style(&self) -> WireStyle {
+        self.style
+    }
+}
+
+impl WireCodec for StyledJsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let mut value = serde_json::to_value(value).map_err(Error::Encode)?;
+        self.style.restyle(&mut value);
+        serde_json::to_vec(&value).map_err(Error::Encode)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        let mut value: Value = serde_json::from_slice(bytes).map_err(Error::Decode)?;
+        self.style.unstyle(&mut value);
+        serde_json::from_value(value).map_err(Error::Decode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use crate::codec::{StyledJsonCodec, WireCodec, WireStyle};
+    use crate::test::Collection;
+    use crate::{Event, Txn, WsBody};
+
+    use super::{FIELDS, TAGS};
+
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ts_rs::TS)]
+    struct Booking {
+        booking_id: u32,
+        txn_id: String,
+    }
+
+    type BookingEvent = Event<u32, Booking, Collection>;
+
+    #[test]
+    fn camel_case_renames_the_protocol_only() {
+        let codec = StyledJsonCodec::new(WireStyle::CamelCase);
+        let booking = Booking {
+            booking_id: 1,
+            txn_id: "card-7".to_owned(),
+        };
+        let txn = Txn::builder().txn_id(3).build();
+        let event = txn
+            .stamp(BookingEvent::new_insert_event(booking, Collection::Dogs).with_occurred_at(10));
+        let bytes = codec.encode(&event.into_ws_body()).unwrap();
+        insta::assert_snapshot!(String::from_utf8(bytes.clone()).unwrap(), @r###"{"data":{"occurredAt":10,"verb":{"payload":{"data":{"booking_id":1,"txn_id":"card-7"},"location":{"collection":"Dogs","id":null,"txnId":3}},"type":"insert"}}}"###);
+        let decoded: WsBody<BookingEvent> = codec.decode(&bytes).unwrap();
+        assert_eq!(decoded.data().occurred_at(), Some(10));
+
+        let begin = codec
+            .encode(&txn.begin_event::<u32, Booking, Collection, ()>())
+            .unwrap();
+        assert!(String::from_utf8(begin)
+            .unwrap()
+            .contains(r#""type":"txnBegin""#));
+        assert_eq!(
+            WireStyle::CamelCase.restyle_ts("{ from_seq?: number, booking_id: number }"),
+            "{ fromSeq?: number, booking_id: number }"
+        );
+
+        // every multi-word name in the bindings is renamed
+        let mut names = BTreeSet::new();
+        for entry in std::fs::read_dir("bindings").unwrap() {
+            let ts = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            for word in ts.split(|c: char| !c.is_ascii_alphanumeric() && c != '_') {
+                if word.contains('_') && word.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+                    names.insert(word.to_owned());
+                }
+            }
+        }
+        let known: BTreeSet<_> = FIELDS
+            .iter()
+            .chain(TAGS)
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(
+            names.difference(&known).collect::<Vec<_>>(),
+            Vec::<&String>::new()
+        );
+    }
+}
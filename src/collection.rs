@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Command, Error, WsBody};
+
+// a collection with a stable name, used on the wire instead of however the
+// collection value happens to serialize
+pub trait Collection {
+    const NAME: &'static str;
+}
+
+// every collection a server knows, so names can be checked once at startup and
+// looked up when a client refers to one
+pub struct CollectionRegistry<C> {
+    collections: BTreeMap<&'static str, C>,
+}
+
+impl<C> Default for CollectionRegistry<C> {
+    fn default() -> Self {
+        Self {
+            collections: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C> CollectionRegistry<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // registers `collection` under `K::NAME`, failing if the name is taken
+    pub fn register<K: Collection>(mut self, collection: C) -> Result<Self, Error> {
+        if self.collections.contains_key(K::NAME) {
+            return Err(Error::InvalidCollection(format!(
+                "{} is registered twice",
+                K::NAME
+            )));
+        }
+        self.collections.insert(K::NAME, collection);
+        Ok(self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&C> {
+        self.collections.get(name)
+    }
+
+    pub fn name_of(&self, collection: &C) -> Option<&'static str>
+    where
+        C: PartialEq,
+    {
+        self.collections
+            .iter()
+            .find(|(_, other)| *other == collection)
+            .map(|(name, _)| *name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.collections.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.collections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collections.is_empty()
+    }
+
+    // decodes a command that refers to collections by name
+    pub fn decode_command<ID, T, P>(
+        &self,
+        json: &str,
+    ) -> Result<WsBody<Command<ID, T, C, P>>, Error>
+    where
+        ID: Serialize + DeserializeOwned,
+        T: Serialize + TS + DeserializeOwned,
+        C: Serialize + DeserializeOwned,
+        P: Serialize + TS + DeserializeOwned,
+    {
+        let mut body: Value = serde_json::from_str(json).map_err(Error::Decode)?;
+        let kind = body.pointer("/data/type").and_then(Value::as_str);
+        let path = match kind {
+            Some("subscribe" | "unsubscribe") => "/data/payload/collections",
+            Some("query" | "live_query" | "ephemeral") => "/data/payload/collection",
+            Some("mutate") => "/data/payload/event/verb/payload/location/collection",
+            _ => "",
+        };
+        match body.pointer_mut(path) {
+            Some(Value::Array(names)) => {
+                names.iter_mut().try_for_each(|name| self.resolve(name))?
+            }
+            Some(name) if !path.is_empty() => self.resolve(name)?,
+            _ => {}
+        }
+        serde_json::from_value(body).map_err(Error::Decode)
+    }
+
+    // replaces a collection name with the collection's own serialization
+    fn resolve(&self, name: &mut Value) -> Result<(), Error>
+    where
+        C: Serialize,
+    {
+        let collection = name
+            .as_str()
+            .and_then(|name| self.get(name))
+            .ok_or_else(|| Error::InvalidCollection(format!("unknown collection {name}")))?;
+        *name = serde_json::to_value(collection).map_err(Error::Encode)?;
+        Ok(())
+    }
+}
+
+// matches collections by their hierarchical name, as the collection
+// serializes, with segments separated by `/`. `*` matches any one segment and
+// a trailing `**` any number of them, none included: `orders/eu/*` matches
+// `orders/eu/paris` but not `orders/eu` or `orders/eu/paris/returns`, which
+// `orders/eu/**` matches both of
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct CollectionPattern(String);
+
+impl CollectionPattern {
+    pub fn new(pattern: impl Into<String>) -> Result<Self, Error> {
+        let pattern = pattern.into();
+        let segments: Vec<_> = pattern.split('/').collect();
+        for (index, segment) in segments.iter().enumerate() {
+            let invalid = match *segment {
+                "" => Some("an empty segment"),
+                "**" if index + 1 < segments.len() => Some("`**` before its last segment"),
+                segment if segment != "*" && segment != "**" && segment.contains('*') => {
+                    Some("`*` inside a segment")
+                }
+                _ => None,
+            };
+            if let Some(invalid) = invalid {
+                return Err(Error::InvalidCollection(format!(
+                    "pattern {pattern} has {invalid}"
+                )));
+            }
+        }
+        Ok(Self(pattern))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let mut names = name.split('/');
+        for segment in self.0.split('/') {
+            match (segment, names.next()) {
+                ("**", _) => return true,
+                (_, None) => return false,
+                ("*", Some(_)) => {}
+                (segment, Some(name)) if segment == name => {}
+                _ => return false,
+            }
+        }
+        names.next().is_none()
+    }
+
+    // false for collections that don't serialize to a string
+    pub fn matches_collection<C: Serialize>(&self, collection: &C) -> bool {
+        match serde_json::to_value(collection) {
+            Ok(Value::String(name)) => self.matches(&name),
+            _ => false,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CollectionPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for CollectionPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{Collection, DoggoRecord};
+    use crate::{CollectionPattern, CollectionRegistry, Command, Error};
+
+    struct Dogs;
+    struct Cats;
+
+    impl crate::Collection for Dogs {
+        const NAME: &'static str = "dogs";
+    }
+
+    impl crate::Collection for Cats {
+        const NAME: &'static str = "cats";
+    }
+
+    #[test]
+    fn commands_refer_to_collections_by_name() {
+        let registry = CollectionRegistry::new()
+            .register::<Dogs>(Collection::Dogs)
+            .unwrap()
+            .register::<Cats>(Collection::Cats)
+            .unwrap();
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["cats", "dogs"]);
+        assert_eq!(registry.name_of(&Collection::Dogs), Some("dogs"));
+        let twice = CollectionRegistry::new()
+            .register::<Dogs>(Collection::Dogs)
+            .and_then(|registry| registry.register::<Dogs>(Collection::Cats));
+        assert!(matches!(twice, Err(Error::InvalidCollection(_))));
+
+        let json = r#"{"data":{"type":"subscribe","payload":{"collections":["dogs","cats"]}}}"#;
+        let command = registry
+            .decode_command::<u32, DoggoRecord, ()>(json)
+            .unwrap()
+            .into_data();
+        let Command::Subscribe(subscribe) = command else {
+            panic!("expected a subscribe");
+        };
+        assert!(subscribe.collections == [Collection::Dogs, Collection::Cats]);
+
+        let json = r#"{"data":{"type":"query","payload":{"request_id":1,"collection":"birds"}}}"#;
+        let unknown = registry.decode_command::<u32, DoggoRecord, ()>(json);
+        assert!(matches!(unknown, Err(Error::InvalidCollection(_))));
+    }
+
+    #[test]
+    fn patterns_match_whole_segments() {
+        let pattern = |pattern: &str| CollectionPattern::new(pattern).unwrap();
+        let eu = pattern("orders/eu/*");
+        assert!(eu.matches("orders/eu/paris"));
+        assert!(!eu.matches("orders/eu"));
+        assert!(!eu.matches("orders/eu/paris/returns"));
+        assert!(!eu.matches("orders/us/austin"));
+        assert!(!eu.matches("orders/europe/paris"));
+        let everything = pattern("orders/**");
+        assert!(everything.matches("orders"));
+        assert!(everything.matches("orders/eu/paris/returns"));
+        assert!(!everything.matches("ordersx/eu"));
+        assert!(pattern("*/eu/*").matches("invoices/eu/rome"));
+        assert!(pattern("Dogs").matches_collection(&Collection::Dogs));
+        assert!(!pattern("*/**").matches_collection(&1));
+
+        for invalid in ["", "orders//eu", "orders/**/eu", "orders/e*"] {
+            assert!(CollectionPattern::new(invalid).is_err(), "{invalid}");
+        }
+        let decoded: CollectionPattern = serde_json::from_str(r#""orders/*""#).unwrap();
+        assert_eq!(decoded, pattern("orders/*"));
+        assert!(serde_json::from_str::<CollectionPattern>(r#""orders/**/eu""#).is_err());
+    }
+}
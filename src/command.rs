@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::presence::{Join, Leave, PresenceHeartbeat};
+use crate::{
+    Acknowledgement, Authenticate, CollectionPattern, Ephemeral, Event, LiveQuery, NoPatch, Ping,
+    Pong, WsBody,
+};
+
+// start receiving events for `collections`, and for every collection
+// matching one of `patterns`. with `from_seq` the server replays everything
+// since then first, as after a reconnect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Subscribe<C> {
+    pub collections: Vec<C>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<CollectionPattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    pub from_seq: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Unsubscribe<C> {
+    pub collections: Vec<C>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<CollectionPattern>,
+}
+
+// asks for the current records of `collection`, or only those with `ids` or
+// whose fields equal those in `filter`. with a `limit` the answer is a page,
+// and the next one is asked for with the `next_cursor` of the last. answered
+// with a `QueryResult`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Query<ID, C> {
+    pub request_id: u32,
+    pub collection: C,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<ID>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "Record<string, unknown>")]
+    pub filter: Option<BTreeMap<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+impl<ID, C> Query<ID, C> {
+    pub fn new(request_id: u32, collection: C) -> Self {
+        Self {
+            request_id,
+            collection,
+            ids: None,
+            filter: None,
+            cursor: None,
+            limit: None,
+        }
+    }
+
+    pub fn with_ids(mut self, ids: Vec<ID>) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    pub fn with_filter(mut self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filter
+            .get_or_insert_with(BTreeMap::new)
+            .insert(field.into(), value.into());
+        self
+    }
+
+    pub fn with_cursor(mut self, cursor: impl Into<String>) -> Self {
+        self.cursor = Some(cursor.into());
+        self
+    }
+
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+// a write the client wants the server to apply and publish, which it may
+// have already applied optimistically. answered with a `MutationResult`
+// carrying the same `request_id`, so the client can keep or roll back its
+// local change
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Mutate<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    pub request_id: u32,
+    pub event: Event<ID, T, C, P>,
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Mutate<ID, T, C, P> {
+    pub fn new(request_id: u32, event: Event<ID, T, C, P>) -> Self {
+        Self { request_id, event }
+    }
+}
+
+// everything a client sends to the server. mutations carry a whole event,
+// but commands are decoded and handled one at a time, so they aren't boxed
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+#[ts(export)]
+pub enum Command<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    Subscribe(Subscribe<C>),
+    Unsubscribe(Unsubscribe<C>),
+    Query(Query<ID, C>),
+    LiveQuery(LiveQuery<C>),
+    Mutate(Mutate<ID, T, C, P>),
+    Ack(Acknowledgement),
+    Ping(Ping),
+    Pong(Pong),
+    Authenticate(Authenticate),
+    Join(Join),
+    Leave(Leave),
+    PresenceHeartbeat(PresenceHeartbeat),
+    Ephemeral(Ephemeral<ID, C>),
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Command<ID, T, C, P> {
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Ack, Acknowledgement, Command, Mutate, Query, Subscribe, Syncable, WsBody};
+
+    type DoggoCommand = Command<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn commands_round_trip() {
+        let body = WsBody::<DoggoCommand>::from_json(
+            r#"{"data":{"type":"subscribe","payload":{"collections":["Dogs"],"from_seq":4}}}"#,
+        )
+        .unwrap();
+        let Command::Subscribe(subscribe) = body.into_data() else {
+            panic!("expected a subscribe");
+        };
+        assert_eq!(subscribe.from_seq, Some(4));
+
+        let doggo = doggo(1);
+        let commands: [DoggoCommand; 4] = [
+            Command::Subscribe(Subscribe {
+                collections: vec![Collection::Cats],
+                patterns: Vec::new(),
+                from_seq: None,
+            }),
+            Command::Query(Query::new(1, Collection::Dogs).with_ids(vec![1])),
+            Command::Mutate(Mutate {
+                request_id: 2,
+                event: doggo.to_update_event().with_expected_revision(3),
+            }),
+            Command::Ack(Acknowledgement::Ack(Ack { seq: 5 })),
+        ];
+        let json: Vec<_> = commands
+            .into_iter()
+            .map(|command| command.into_ws_body().try_json().unwrap())
+            .collect();
+        insta::assert_snapshot!(json.join("\n"), @r###"
+        {"data":{"type":"subscribe","payload":{"collections":["Cats"]}}}
+        {"data":{"type":"query","payload":{"request_id":1,"collection":"Dogs","ids":[1]}}}
+        {"data":{"type":"mutate","payload":{"request_id":2,"event":{"verb":{"type":"update","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"},"expected_revision":3}}}}}}
+        {"data":{"type":"ack","payload":{"type":"ack","payload":{"seq":5}}}}
+        "###);
+        for json in json {
+            let body = WsBody::<DoggoCommand>::from_json(&json).unwrap();
+            assert_eq!(body.try_json().unwrap(), json);
+        }
+    }
+}
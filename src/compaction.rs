@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    ApplyPatch, Error, Event, EventStore, EventVerb, Location, Materializer, Replay, Snapshot,
+    SnapshotEntry, UpdatableResource, UpsertPolicy,
+};
+
+// folds a log into the state it leaves behind: an upsert of each live
+// record's latest data and the tombstone of each deleted one, at the seq of
+// the record's last event and in that order. replaying the result leaves a
+// `Materializer` with the same records as replaying the history did, so
+// patches that wouldn't apply are skipped here too. upserts are folded as
+// `UpsertPolicy::InsertOrReplace` would apply them. a transaction's events
+// are folded once it commits and dropped if it aborts, and its markers go,
+// as nothing is left for them to group. transactions still open at the end
+// of the log are left as they were after the folded events, so they can
+// still commit or abort
+pub fn compact_history<ID, T, C, P>(
+    events: impl IntoIterator<Item = Event<ID, T, C, P>>,
+) -> Vec<Event<ID, T, C, P>>
+where
+    ID: Eq + Hash + Clone,
+    T: Serialize + TS + Clone,
+    C: Eq + Hash + Clone,
+    P: Serialize + TS + ApplyPatch<T>,
+{
+    let mut folded: HashMap<(C, ID), Folded<ID, T, C, P>> = HashMap::new();
+    // the events of transactions that haven't committed or aborted yet
+    let mut open: HashMap<u32, Vec<Positioned<ID, T, C, P>>> = HashMap::new();
+    for (position, event) in events.into_iter().enumerate() {
+        match event.verb {
+            EventVerb::TxnBegin(txn_id) => open.entry(txn_id).or_default().push((position, event)),
+            EventVerb::TxnCommit(txn_id) => {
+                for (position, event) in open.remove(&txn_id).into_iter().flatten() {
+                    fold(&mut folded, position, event);
+                }
+            }
+            EventVerb::TxnAbort(txn_id) => {
+                open.remove(&txn_id);
+            }
+            _ => match event.txn_id() {
+                Some(txn_id) => open.entry(txn_id).or_default().push((position, event)),
+                None => fold(&mut folded, position, event),
+            },
+        }
+    }
+
+    let mut folded: Vec<_> = folded.into_values().collect();
+    folded.sort_by_key(|(_, _, position)| *position);
+    let mut compacted: Vec<_> = folded
+        .into_iter()
+        .map(|(data, mut event, _)| {
+            event.verb = match (event.verb, data) {
+                (EventVerb::Delete(mut resource), _) => {
+                    resource.location.txn_id = None;
+                    EventVerb::Delete(resource)
+                }
+                (verb, Some(data)) => {
+                    let location = verb.location().expect("folded events have a location");
+                    EventVerb::Upsert(UpdatableResource {
+                        location: Location {
+                            id: location.id.clone(),
+                            txn_id: None,
+                            collection: location.collection.clone(),
+                            revision: location.revision,
+                        },
+                        data,
+                        expected_revision: None,
+                    })
+                }
+                (verb, None) => verb,
+            };
+            event
+        })
+        .collect();
+    let mut open: Vec<_> = open.into_values().flatten().collect();
+    open.sort_by_key(|(position, _)| *position);
+    compacted.extend(open.into_iter().map(|(_, event)| event));
+    compacted
+}
+
+// folds `event` into the state of its record, leaving markers and patches
+// that don't apply out
+fn fold<ID, T, C, P>(
+    folded: &mut HashMap<(C, ID), Folded<ID, T, C, P>>,
+    position: usize,
+    event: Event<ID, T, C, P>,
+) where
+    ID: Eq + Hash + Clone,
+    T: Serialize + TS + Clone,
+    C: Eq + Hash + Clone,
+    P: Serialize + TS + ApplyPatch<T>,
+{
+    let Some(location) = event.verb.location() else {
+        return;
+    };
+    let Some(id) = location.id.clone() else {
+        return;
+    };
+    let key = (location.collection.clone(), id);
+    let data = match &event.verb {
+        EventVerb::Insert(resource) => Some(resource.data.clone()),
+        EventVerb::Update(resource) | EventVerb::Upsert(resource) => Some(resource.data.clone()),
+        EventVerb::Patch(resource) => {
+            let patched = folded
+                .get(&key)
+                .and_then(|(data, _, _)| data.as_ref())
+                .and_then(|data| resource.data.apply_patch(data).ok());
+            match patched {
+                Some(patched) => Some(patched),
+                None => return,
+            }
+        }
+        EventVerb::Delete(_) => None,
+        EventVerb::TxnBegin(_) | EventVerb::TxnCommit(_) | EventVerb::TxnAbort(_) => return,
+    };
+    folded.insert(key, (data, event, position));
+}
+
+// a record's data (`None` once deleted), its last event and where that came
+// in the log
+type Folded<ID, T, C, P> = (Option<T>, Event<ID, T, C, P>, usize);
+
+// an event and where it came in the log
+type Positioned<ID, T, C, P> = (usize, Event<ID, T, C, P>);
+
+// keeps a snapshot of every collection fresh, so a new client starts from
+// one and replays only what came after it, O(state) rather than O(history).
+// every `interval` events applied, each collection's records are captured
+// as of the seq after the last event
+pub struct Snapshotter<ID, T: TS, C> {
+    interval: u64,
+    collections: HashMap<C, Materializer<ID, T>>,
+    upsert: UpsertPolicy<T>,
+    snapshots: HashMap<C, Snapshot<ID, T, C>>,
+    since_snapshot: u64,
+    next_seq: u64,
+    // the seq each open transaction began at. snapshots leave its events
+    // out, so they're taken as of the oldest of these
+    open: HashMap<u32, u64>,
+}
+
+impl<ID, T, C> Snapshotter<ID, T, C>
+where
+    ID: Eq + Hash + Clone,
+    T: Serialize + TS + Clone,
+    C: Eq + Hash + Clone,
+{
+    pub fn new(interval: u64) -> Self {
+        assert!(interval > 0, "snapshot interval must be greater than zero");
+        Self {
+            interval,
+            collections: HashMap::new(),
+            upsert: UpsertPolicy::default(),
+            snapshots: HashMap::new(),
+            since_snapshot: 0,
+            next_seq: 0,
+            open: HashMap::new(),
+        }
+    }
+
+    // how the records of every collection take upserts
+    pub fn with_upsert_policy(mut self, upsert: UpsertPolicy<T>) -> Self {
+        self.upsert = upsert;
+        self
+    }
+
+    // true if it took new snapshots
+    pub fn apply<P>(&mut self, event: Event<ID, T, C, P>) -> bool
+    where
+        ID: Send + 'static,
+        T: Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+    {
+        let seq = event.seq().unwrap_or(self.next_seq);
+        self.next_seq = self.next_seq.max(seq + 1);
+        // a transaction's markers concern every collection it touched
+        match *event.verb() {
+            EventVerb::TxnBegin(txn_id) => {
+                self.open.entry(txn_id).or_insert(seq);
+            }
+            EventVerb::TxnCommit(txn_id) => {
+                self.open.remove(&txn_id);
+                self.collections
+                    .values_mut()
+                    .for_each(|records| records.commit(txn_id));
+            }
+            EventVerb::TxnAbort(txn_id) => {
+                self.open.remove(&txn_id);
+                self.collections
+                    .values_mut()
+                    .for_each(|records| records.abort(txn_id));
+            }
+            _ => {
+                if let Some(txn_id) = event.txn_id() {
+                    self.open.entry(txn_id).or_insert(seq);
+                }
+                if let Some(collection) = event.collection() {
+                    let upsert = self.upsert;
+                    self.collections
+                        .entry(collection.clone())
+                        .or_insert_with(|| Materializer::new().with_upsert_policy(upsert))
+                        .apply(event);
+                }
+            }
+        }
+        self.since_snapshot += 1;
+        if self.since_snapshot < self.interval {
+            return false;
+        }
+        self.take_snapshots();
+        true
+    }
+
+    // snapshots every collection now, whatever the interval
+    pub fn take_snapshots(&mut self) {
+        self.since_snapshot = 0;
+        let seq = self.open.values().copied().min().unwrap_or(self.next_seq);
+        for (collection, records) in &self.collections {
+            let entries = records
+                .records()
+                .iter()
+                .map(|(id, data)| SnapshotEntry::new(id.clone(), data.clone()))
+                .collect();
+            let snapshot = Snapshot::new(collection.clone(), seq, entries);
+            self.snapshots.insert(collection.clone(), snapshot);
+        }
+    }
+
+    pub fn snapshot(&self, collection: &C) -> Option<&Snapshot<ID, T, C>> {
+        self.snapshots.get(collection)
+    }
+
+    // what a new client of `collection` needs: the latest snapshot, if one
+    // has been taken, and the stored events after it
+    pub fn catch_up<'a, P, S>(
+        &self,
+        store: &'a S,
+        collection: &'a C,
+    ) -> Result<CatchUp<'a, ID, T, C, P>, Error>
+    where
+        P: Serialize + TS + 'a,
+        S: EventStore<Event<ID, T, C, P>>,
+        ID: 'a,
+        T: 'a,
+    {
+        let snapshot = self.snapshot(collection).cloned();
+        let from_seq = snapshot.as_ref().map_or(0, Snapshot::seq);
+        Ok((snapshot, store.replay_collection(collection, from_seq)?))
+    }
+}
+
+pub type CatchUp<'a, ID, T, C, P> = (Option<Snapshot<ID, T, C>>, Replay<'a, Event<ID, T, C, P>>);
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{
+        compact_history, Event, EventStore, InMemoryEventStore, JsonPatch, Materializer, Sequenced,
+        Snapshotter, Syncable, Txn,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection, JsonPatch>;
+
+    fn named(id: u32, name: &str) -> DoggoRecord {
+        DoggoRecord {
+            name: name.to_owned(),
+            ..doggo(id)
+        }
+    }
+
+    #[test]
+    fn history_folds_to_state() {
+        let history: Vec<DoggoEvent> = vec![
+            named(1, "Barky").to_upsert_event().into_patchable(),
+            named(2, "Woofy").to_upsert_event().into_patchable(),
+            named(1, "Sir Barks")
+                .to_json_patch_event(&named(1, "Barky"))
+                .unwrap(),
+            Event::new_delete_event(2, Collection::Dogs),
+            named(3, "Fluffy").to_upsert_event().into_patchable(),
+            named(3, "Fluffier").to_update_event().into_patchable(),
+        ];
+        let compacted = compact_history(history.clone());
+        let summary: Vec<_> = compacted
+            .iter()
+            .map(|event| format!("{} {}", event.verb().name(), event.id().unwrap()))
+            .collect();
+        assert_eq!(summary, ["upsert 1", "delete 2", "upsert 3"]);
+        let replayed = |events: Vec<DoggoEvent>| {
+            let mut records = Materializer::new();
+            events.into_iter().for_each(|event| records.apply(event));
+            let mut names: Vec<_> = records
+                .into_records()
+                .into_values()
+                .map(|doggo| doggo.name)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(replayed(compacted), ["Fluffier", "Sir Barks"]);
+
+        let mut snapshotter = Snapshotter::new(4);
+        let stored = InMemoryEventStore::new(16);
+        for (seq, mut event) in history.into_iter().enumerate() {
+            event.set_seq(seq as u64);
+            stored.append(&event).unwrap();
+            snapshotter.apply(event);
+        }
+        let (snapshot, rest) = snapshotter.catch_up(&stored, &Collection::Dogs).unwrap();
+        let snapshot = snapshot.unwrap();
+        assert_eq!((snapshot.seq(), snapshot.records().len()), (4, 1));
+        let rest: Vec<_> = rest.map(|event| event.seq().unwrap()).collect();
+        assert_eq!(rest, [4, 5]);
+
+        assert_eq!(stored.compact_history(), 3);
+        let seqs: Vec<_> = stored
+            .replay(0)
+            .unwrap()
+            .map(|event| event.seq().unwrap())
+            .collect();
+        assert_eq!(seqs, [2, 3, 5]);
+    }
+
+    #[test]
+    fn only_committed_transactions_are_folded() {
+        let txn = |txn_id| Txn::builder().txn_id(txn_id).build();
+        let (committed, aborted, open) = (txn(1), txn(2), txn(3));
+        let history: Vec<DoggoEvent> = vec![
+            named(1, "Barky").to_upsert_event().into_patchable(),
+            committed.begin_event(),
+            aborted.begin_event(),
+            committed.stamp(named(1, "Sir Barks").to_update_event().into_patchable()),
+            aborted.stamp(named(1, "Lord Barks").to_update_event().into_patchable()),
+            aborted.stamp(named(2, "Woofy").to_upsert_event().into_patchable()),
+            open.begin_event(),
+            open.stamp(Event::new_delete_event(1, Collection::Dogs)),
+            committed.commit_event(),
+            aborted.abort_event(),
+        ];
+        let summary: Vec<_> = compact_history(history.clone())
+            .iter()
+            .map(|event| {
+                let name = event.data().map(|doggo| doggo.name.as_str());
+                format!("{} {:?} {:?}", event.verb().name(), event.txn_id(), name)
+            })
+            .collect();
+        // the open transaction is left to finish
+        assert_eq!(
+            summary,
+            [
+                "upsert None Some(\"Sir Barks\")",
+                "txn_begin None None",
+                "delete Some(3) None",
+            ]
+        );
+
+        // snapshots leave the open transaction to the events after them
+        let mut snapshotter = Snapshotter::new(100);
+        for (seq, mut event) in history.into_iter().enumerate() {
+            event.set_seq(seq as u64);
+            snapshotter.apply(event);
+        }
+        snapshotter.take_snapshots();
+        let snapshot = snapshotter.snapshot(&Collection::Dogs).unwrap().clone();
+        let seq = snapshot.seq();
+        let names: Vec<_> = snapshot
+            .into_records()
+            .into_iter()
+            .map(|entry| entry.into_parts().1.name)
+            .collect();
+        assert_eq!((seq, names), (6, vec!["Sir Barks".to_owned()]));
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Event, WsBody};
+
+// server -> client rejection of a write whose `expected_revision` no longer
+// matches the record. clients refetch (or rebase) and retry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS, thiserror::Error)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[error("expected revision {expected_revision}, found {current_revision:?}")]
+#[ts(export)]
+pub struct ConflictError<ID, C> {
+    collection: C,
+    id: Option<ID>,
+    #[ts(type = "number")]
+    expected_revision: u64,
+    // `None` if the record doesn't exist
+    #[ts(type = "number | null")]
+    current_revision: Option<u64>,
+}
+
+impl<ID, C> ConflictError<ID, C> {
+    pub fn new(
+        collection: C,
+        id: Option<ID>,
+        expected_revision: u64,
+        current_revision: Option<u64>,
+    ) -> Self {
+        Self {
+            collection,
+            id,
+            expected_revision,
+            current_revision,
+        }
+    }
+
+    pub fn collection(&self) -> &C {
+        &self.collection
+    }
+
+    pub fn id(&self) -> Option<&ID> {
+        self.id.as_ref()
+    }
+
+    pub fn expected_revision(&self) -> u64 {
+        self.expected_revision
+    }
+
+    pub fn current_revision(&self) -> Option<u64> {
+        self.current_revision
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+impl<ID: Clone, T: Serialize + TS, C: Clone, P: Serialize + TS> Event<ID, T, C, P> {
+    // for the server, given the revision the record is currently at. writes
+    // without an expected revision always pass
+    pub fn check_revision(&self, current: Option<u64>) -> Result<(), ConflictError<ID, C>> {
+        let Some(expected) = self.expected_revision() else {
+            return Ok(());
+        };
+        if current == Some(expected) {
+            return Ok(());
+        }
+        let collection = self
+            .collection()
+            .cloned()
+            .expect("updates and upserts always have a collection");
+        Err(ConflictError::new(
+            collection,
+            self.id().cloned(),
+            expected,
+            current,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{ConflictError, Event, Syncable, WsBody};
+
+    #[test]
+    fn stale_writes_conflict() {
+        let doggo = doggo(1);
+
+        let event = doggo.clone().to_upsert_event().with_expected_revision(3);
+        let json = event.clone().into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"},"expected_revision":3}}}}"###);
+        let body = WsBody::<Event<u32, DoggoRecord, Collection>>::from_json(&json).unwrap();
+        assert_eq!(body.into_data().expected_revision(), Some(3));
+
+        assert!(event.check_revision(Some(3)).is_ok());
+        assert!(doggo.to_delete_event().check_revision(Some(7)).is_ok());
+
+        let conflict = event.check_revision(Some(4)).unwrap_err();
+        assert_eq!(conflict.current_revision(), Some(4));
+        assert_eq!(conflict.to_string(), "expected revision 3, found Some(4)");
+        let json = conflict.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"collection":"Dogs","id":1,"expected_revision":3,"current_revision":4}}"###);
+        assert!(WsBody::<ConflictError<u32, Collection>>::from_json(&json).is_ok());
+
+        let published =
+            Event::<u32, DoggoRecord, Collection>::new_delete_event(1, Collection::Dogs)
+                .with_revision(5);
+        assert_eq!(published.revision(), Some(5));
+    }
+}
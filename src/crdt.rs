@@ -0,0 +1,295 @@
+// payloads that merge without conflicts, for apps whose clients edit offline.
+// each one is a record (it implements `Syncable`) whose state can be merged
+// with any other replica of it: merging is commutative, associative and
+// idempotent, so replicas that have seen the same writes end up the same no
+// matter the order they saw them in. publish them like any other record and
+// fold incoming events in with `Materializer::apply_merging`
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Appendable, Clock, Syncable, VectorClock};
+
+pub trait Merge {
+    // folds `other` into `self`
+    fn merge(&mut self, other: &Self);
+}
+
+// a single value, where the latest write wins. ties (writes in the same
+// millisecond) go to the higher node name so every replica picks the same one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LwwRegister<ID, V, C> {
+    id: ID,
+    collection: C,
+    value: V,
+    #[ts(type = "number")]
+    timestamp: u64,
+    node: String,
+}
+
+impl<ID, V, C> LwwRegister<ID, V, C> {
+    pub fn new(id: ID, collection: C, value: V, node: &str, clock: &impl Clock) -> Self {
+        Self {
+            id,
+            collection,
+            value,
+            timestamp: clock.now(),
+            node: node.to_owned(),
+        }
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    // a write always wins over the ones this replica has seen, even if the
+    // clock is behind them
+    pub fn set(&mut self, value: V, node: &str, clock: &impl Clock) {
+        self.value = value;
+        self.timestamp = clock.now().max(self.timestamp + 1);
+        self.node = node.to_owned();
+    }
+}
+
+impl<ID, V: Clone, C> Merge for LwwRegister<ID, V, C> {
+    fn merge(&mut self, other: &Self) {
+        if (other.timestamp, &other.node) > (self.timestamp, &self.node) {
+            self.value = other.value.clone();
+            self.timestamp = other.timestamp;
+            self.node = other.node.clone();
+        }
+    }
+}
+
+// a counter that goes up and down, as the increments and decrements of every
+// node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Counter<ID, C> {
+    id: ID,
+    collection: C,
+    increments: VectorClock,
+    decrements: VectorClock,
+}
+
+impl<ID, C> Counter<ID, C> {
+    pub fn new(id: ID, collection: C) -> Self {
+        Self {
+            id,
+            collection,
+            increments: VectorClock::new(),
+            decrements: VectorClock::new(),
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        let sum = |clock: &VectorClock| clock.iter().map(|(_, count)| count as i64).sum::<i64>();
+        sum(&self.increments) - sum(&self.decrements)
+    }
+
+    pub fn increment(&mut self, node: &str, by: u64) {
+        self.increments.add(node, by);
+    }
+
+    pub fn decrement(&mut self, node: &str, by: u64) {
+        self.decrements.add(node, by);
+    }
+}
+
+impl<ID, C> Merge for Counter<ID, C> {
+    fn merge(&mut self, other: &Self) {
+        self.increments.merge(&other.increments);
+        self.decrements.merge(&other.decrements);
+    }
+}
+
+// identifies one add to an `OrSet`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Dot {
+    node: String,
+    #[ts(type = "number")]
+    counter: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OrSetEntry<V> {
+    value: V,
+    dot: Dot,
+}
+
+// a set where an add wins over a concurrent remove: a remove only removes the
+// adds its replica had seen. the dots of removed adds are kept, so the set
+// grows with the number of removes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OrSet<ID, V, C> {
+    id: ID,
+    collection: C,
+    // sorted, so equal sets serialize the same
+    entries: Vec<OrSetEntry<V>>,
+    removed: Vec<Dot>,
+    clock: VectorClock,
+}
+
+impl<ID, V: Ord, C> OrSet<ID, V, C> {
+    pub fn new(id: ID, collection: C) -> Self {
+        Self {
+            id,
+            collection,
+            entries: Vec::new(),
+            removed: Vec::new(),
+            clock: VectorClock::new(),
+        }
+    }
+
+    pub fn add(&mut self, value: V, node: &str) {
+        let dot = Dot {
+            node: node.to_owned(),
+            counter: self.clock.tick(node),
+        };
+        let entry = OrSetEntry { value, dot };
+        let index = self.entries.partition_point(|other| other < &entry);
+        self.entries.insert(index, entry);
+    }
+
+    pub fn remove(&mut self, value: &V) {
+        let (removed, kept) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition::<Vec<_>, _>(|entry| &entry.value == value);
+        self.entries = kept;
+        self.removed
+            .extend(removed.into_iter().map(|entry| entry.dot));
+        self.removed.sort();
+    }
+
+    pub fn contains(&self, value: &V) -> bool {
+        self.entries.iter().any(|entry| &entry.value == value)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        let mut previous = None;
+        self.entries
+            .iter()
+            .map(|entry| &entry.value)
+            .filter(move |value| previous.replace(*value) != Some(*value))
+    }
+}
+
+impl<ID, V: Ord + Clone, C> Merge for OrSet<ID, V, C> {
+    fn merge(&mut self, other: &Self) {
+        self.clock.merge(&other.clock);
+        self.removed.extend(other.removed.iter().cloned());
+        self.removed.sort();
+        self.removed.dedup();
+        self.entries.extend(other.entries.iter().cloned());
+        self.entries.sort();
+        self.entries.dedup();
+        let removed = &self.removed;
+        self.entries
+            .retain(|entry| removed.binary_search(&entry.dot).is_err());
+    }
+}
+
+// they are records like any other, with their own id and collection
+macro_rules! impl_syncable {
+    ($name:ident $(, $param:ident)*) => {
+        impl<ID, $($param,)* C> Appendable for $name<ID, $($param,)* C>
+        where
+            Self: Serialize + TS,
+            C: Clone,
+        {
+            type Collection = C;
+
+            fn collection(&self) -> C {
+                self.collection.clone()
+            }
+        }
+
+        impl<ID, $($param,)* C> Syncable for $name<ID, $($param,)* C>
+        where
+            Self: Serialize + TS,
+            ID: Clone,
+            C: Clone,
+        {
+            type Id = ID;
+
+            fn id(&self) -> ID {
+                self.id.clone()
+            }
+        }
+    };
+}
+
+impl_syncable!(LwwRegister, V);
+impl_syncable!(Counter);
+impl_syncable!(OrSet, V);
+
+#[cfg(test)]
+mod test {
+    use crate::crdt::{Counter, LwwRegister, Merge, OrSet};
+    use crate::test::Collection;
+    use crate::{Event, Materializer, Syncable, WsBody};
+
+    #[test]
+    fn replicas_converge() {
+        let clock = || 1_000;
+        let mut a = LwwRegister::new(1u32, Collection::Dogs, "Barky", "a", &clock);
+        let mut b = a.clone();
+        a.set("Woofy", "a", &clock);
+        b.set("Sparky", "b", &clock);
+        let (mut ab, mut ba) = (a.clone(), b.clone());
+        ab.merge(&b);
+        ba.merge(&a);
+        assert!(ab == ba);
+        assert_eq!(*ab.value(), "Sparky");
+        assert_eq!(ab.timestamp(), 1_001);
+
+        let mut a = Counter::new(1u32, Collection::Dogs);
+        let mut b = a.clone();
+        a.increment("a", 3);
+        b.increment("b", 2);
+        b.decrement("b", 1);
+        a.merge(&b);
+        a.merge(&b);
+        assert_eq!(a.value(), 4);
+
+        let trick = |name: &str| name.to_string();
+        let mut a = OrSet::new(1u32, Collection::Dogs);
+        a.add(trick("sit"), "a");
+        a.add(trick("stay"), "a");
+        let mut b = a.clone();
+        // the add wins over the remove it is concurrent with
+        b.remove(&trick("sit"));
+        b.remove(&trick("stay"));
+        a.add(trick("sit"), "a");
+        let (mut ab, mut ba) = (a.clone(), b.clone());
+        ab.merge(&b);
+        ba.merge(&a);
+        assert!(ab == ba);
+        assert_eq!(ab.values().collect::<Vec<_>>(), [&trick("sit")]);
+        assert!(!ab.contains(&trick("stay")));
+
+        let json = ab
+            .clone()
+            .to_upsert_event()
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"collection":"Dogs","entries":[{"value":"sit","dot":{"node":"a","counter":3}}],"removed":[{"node":"a","counter":1},{"node":"a","counter":2}],"clock":{"a":3}}}}}}"###);
+        let body =
+            WsBody::<Event<u32, OrSet<u32, String, Collection>, Collection>>::from_json(&json)
+                .unwrap();
+
+        let mut materializer = Materializer::new();
+        materializer.apply_merging(b.to_upsert_event());
+        materializer.apply_merging(body.into_data());
+        materializer.apply_merging(a.to_upsert_event());
+        assert!(materializer.get(&1) == Some(&ab));
+    }
+}
@@ -0,0 +1,365 @@
+// signs the json of messages, so receivers can tell a message came from a
+// holder of the key and was not changed on the way, e.g. when events pass
+// through a relay or a queue the server doesn't trust. hmac-sha256 when both
+// ends share a secret, ed25519 when receivers should only be able to verify.
+// `CollectionKeys` goes further and encrypts the records of collections the
+// server should not be able to read at all
+use std::collections::HashMap;
+
+use ed25519_dalek::Signer as _;
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use ts_rs::TS;
+
+use crate::{base64, Error, WsBody};
+
+mod encrypt;
+
+pub use encrypt::{CollectionKeys, EncryptedEvent, EncryptedPayload};
+
+// a message as it goes out signed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SignedBody {
+    // the `WsBody` json, signed byte for byte as it is
+    pub payload: String,
+    // the base64 signature of `payload`
+    pub sig: String,
+    // which key signed it, so keys can be rotated
+    pub key_id: String,
+}
+
+impl SignedBody {
+    pub fn try_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(Error::Encode)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(Error::Decode)
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+enum SigningKey {
+    Hmac(Vec<u8>),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+// signs outgoing messages with one key
+pub struct Signer {
+    key_id: String,
+    key: SigningKey,
+}
+
+impl Signer {
+    pub fn hmac(key_id: impl Into<String>, secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key_id: key_id.into(),
+            key: SigningKey::Hmac(secret.into()),
+        }
+    }
+
+    // `seed` is the 32 byte private key of rfc 8032
+    pub fn ed25519(key_id: impl Into<String>, seed: [u8; 32]) -> Self {
+        Self {
+            key_id: key_id.into(),
+            key: SigningKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&seed)),
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    // what receivers need to verify this signer's messages
+    pub fn verifying_key(&self) -> VerifyingKey {
+        match &self.key {
+            SigningKey::Hmac(secret) => VerifyingKey::Hmac(secret.clone()),
+            SigningKey::Ed25519(key) => VerifyingKey::Ed25519(key.verifying_key().to_bytes()),
+        }
+    }
+
+    pub fn sign<T: Serialize>(&self, body: &WsBody<T>) -> Result<SignedBody, Error> {
+        Ok(self.sign_json(body.try_json()?))
+    }
+
+    // for bodies that were encoded already, e.g. `Serialized` events
+    pub fn sign_json(&self, json: impl Into<String>) -> SignedBody {
+        let payload = json.into();
+        SignedBody {
+            sig: base64::encode(&self.sign_bytes(payload.as_bytes())),
+            payload,
+            key_id: self.key_id.clone(),
+        }
+    }
+
+    fn sign_bytes(&self, message: &[u8]) -> Vec<u8> {
+        match &self.key {
+            SigningKey::Hmac(secret) => {
+                let mut mac =
+                    HmacSha256::new_from_slice(secret).expect("hmac takes keys of any length");
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            SigningKey::Ed25519(key) => key.sign(message).to_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum VerifyingKey {
+    // the shared secret
+    Hmac(Vec<u8>),
+    // the public key
+    Ed25519([u8; 32]),
+}
+
+impl VerifyingKey {
+    fn verify(&self, message: &[u8], sig: &[u8]) -> bool {
+        match self {
+            // in constant time, so the time taken doesn't tell how much of a
+            // forged signature was right
+            VerifyingKey::Hmac(secret) => {
+                let mut mac =
+                    HmacSha256::new_from_slice(secret).expect("hmac takes keys of any length");
+                mac.update(message);
+                mac.verify_slice(sig).is_ok()
+            }
+            // strictly, refusing weak keys and non-canonical signatures, so a
+            // signature can't be changed into another that still verifies
+            VerifyingKey::Ed25519(public) => {
+                let Ok(key) = ed25519_dalek::VerifyingKey::from_bytes(public) else {
+                    return false;
+                };
+                ed25519_dalek::Signature::from_slice(sig)
+                    .is_ok_and(|sig| key.verify_strict(message, &sig).is_ok())
+            }
+        }
+    }
+}
+
+// checks incoming messages against the keys of their senders. a message signed
+// by an unknown key, or whose signature doesn't match, is an
+// `Error::InvalidSignature`
+#[derive(Default)]
+pub struct Verifier {
+    keys: HashMap<String, VerifyingKey>,
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // keep the old key around while senders move to a new one
+    pub fn with_key(mut self, key_id: impl Into<String>, key: VerifyingKey) -> Self {
+        self.keys.insert(key_id.into(), key);
+        self
+    }
+
+    // the signed json, if the signature is good
+    pub fn verify<'a>(&self, body: &'a SignedBody) -> Result<&'a str, Error> {
+        let key = self
+            .keys
+            .get(&body.key_id)
+            .ok_or_else(|| Error::InvalidSignature(format!("unknown key {:?}", body.key_id)))?;
+        let sig = base64::decode(&body.sig)
+            .ok_or_else(|| Error::InvalidSignature("sig is not valid base64".to_owned()))?;
+        if !key.verify(body.payload.as_bytes(), &sig) {
+            return Err(Error::InvalidSignature(format!(
+                "payload does not match the signature of key {:?}",
+                body.key_id
+            )));
+        }
+        Ok(&body.payload)
+    }
+
+    // the body of a `SignedBody` frame as it came off the socket
+    pub fn open<T>(&self, json: &str) -> Result<WsBody<T>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let signed = SignedBody::from_json(json)?;
+        WsBody::from_json(self.verify(&signed)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crypto::{SignedBody, Signer, Verifier, VerifyingKey};
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{base64, Error, Event, Syncable};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len() / 2)
+            .map(|index| u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn signed_messages_verify() {
+        let doggo = doggo(1);
+        let body = doggo.to_upsert_event().into_ws_body();
+        let ed25519 = Signer::ed25519("server-1", [7; 32]);
+        let hmac = Signer::hmac("shared", "shared secret");
+        let verifier = Verifier::new()
+            .with_key(ed25519.key_id(), ed25519.verifying_key())
+            .with_key(hmac.key_id(), hmac.verifying_key());
+
+        let json = ed25519.sign(&body).unwrap().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"payload":"{\"data\":{\"verb\":{\"type\":\"upsert\",\"payload\":{\"location\":{\"id\":1,\"txn_id\":null,\"collection\":\"Dogs\"},\"data\":{\"id\":1,\"name\":\"Barky\",\"breed\":\"Poodle\"}}}}}","sig":"PY4Yiish4eyvEoZ+040KVXKagSYQt1bDjXq5XCsuR+s+UbwvalKJyOS2S85XkPar65gpsYgrRYKpY7O5RLTnDQ==","key_id":"server-1"}"###);
+        let opened = verifier.open::<DoggoEvent>(&json).unwrap();
+        assert_eq!(opened.into_data().data().unwrap().name, "Barky");
+
+        let signed = hmac.sign(&body).unwrap();
+        assert_eq!(signed.sig, "vEuX/MikySDKD4aHb7+c9wIJ85rN4pxg3/MBE+UaXdU=");
+        assert!(verifier.verify(&signed).is_ok());
+
+        let tampered = SignedBody {
+            payload: signed.payload.replace("Barky", "Woofy"),
+            ..signed.clone()
+        };
+        assert!(matches!(
+            verifier.verify(&tampered),
+            Err(Error::InvalidSignature(_))
+        ));
+        let unknown =
+            Verifier::new().with_key("shared", Signer::hmac("shared", "guess").verifying_key());
+        assert!(unknown.verify(&signed).is_err());
+        let rotated = SignedBody {
+            key_id: "server-2".to_owned(),
+            ..signed
+        };
+        assert!(verifier
+            .open::<DoggoEvent>(&rotated.try_json().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn signatures_match_the_rfc_vectors() {
+        // rfc 8032, section 7.1, tests 1 to 3: (secret key, public key,
+        // message, signature)
+        let ed25519 = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ];
+        for (secret, public, message, signature) in ed25519 {
+            let signer = Signer::ed25519("rfc", hex(secret).try_into().unwrap());
+            assert!(
+                signer.verifying_key() == VerifyingKey::Ed25519(hex(public).try_into().unwrap())
+            );
+            assert_eq!(signer.sign_bytes(&hex(message)), hex(signature));
+            assert!(signer
+                .verifying_key()
+                .verify(&hex(message), &hex(signature)));
+        }
+
+        // rfc 4231, test cases 1, 2, 6 and 7: (key, data, hmac-sha256)
+        let block_key = "aa".repeat(131);
+        let hmac = [
+            ("0b".repeat(20), "Hi There", "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+            (
+                "4a656665".to_owned(),
+                "what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                block_key.clone(),
+                "Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                block_key,
+                "This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, mac) in hmac {
+            let signed = Signer::hmac("rfc", hex(&key)).sign_json(data);
+            assert_eq!(base64::decode(&signed.sig).unwrap(), hex(mac));
+            let verifier = Verifier::new().with_key("rfc", VerifyingKey::Hmac(hex(&key)));
+            assert_eq!(verifier.verify(&signed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn forged_signatures_are_refused() {
+        let signer = Signer::ed25519("server-1", [7; 32]);
+        let key = signer.verifying_key();
+        let message = b"{\"data\":1}";
+        let sig = signer.sign_bytes(message);
+        assert!(key.verify(message, &sig));
+
+        let mut flipped = sig.clone();
+        flipped[0] ^= 1;
+        assert!(!key.verify(message, &flipped));
+        assert!(!key.verify(b"{\"data\":2}", &sig));
+        assert!(!key.verify(message, &sig[..63]));
+        assert!(!Signer::ed25519("server-2", [8; 32])
+            .verifying_key()
+            .verify(message, &sig));
+
+        // s + L verifies under lax checks but is not the canonical encoding
+        const L: [u8; 32] = [
+            0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9,
+            0xde, 0x14, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+        ];
+        let mut malleated = sig.clone();
+        let mut carry = 0;
+        for (byte, l) in malleated[32..].iter_mut().zip(L) {
+            let sum = *byte as u16 + l as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!key.verify(message, &malleated));
+
+        // the identity is a key of small order: with r the identity and s
+        // zero, a lax check accepts the signature for every message
+        let mut identity = [0; 32];
+        identity[0] = 1;
+        let mut weak = vec![0; 64];
+        weak[0] = 1;
+        assert!(!VerifyingKey::Ed25519(identity).verify(message, &weak));
+        // nor is every 32 bytes a point
+        assert!(!VerifyingKey::Ed25519([0xff; 32]).verify(message, &sig));
+
+        let hmac = Signer::hmac("shared", "shared secret");
+        let key = hmac.verifying_key();
+        let mac = hmac.sign_bytes(message);
+        assert!(key.verify(message, &mac));
+        assert!(!key.verify(message, &mac[..31]));
+        assert!(!key.verify(message, &[]));
+        let mut flipped = mac.clone();
+        flipped[31] ^= 0x80;
+        assert!(!key.verify(message, &flipped));
+
+        let verifier = Verifier::new().with_key("server-1", signer.verifying_key());
+        let refused = |signed: &SignedBody| match verifier.verify(signed) {
+            Err(Error::InvalidSignature(reason)) => reason,
+            other => panic!("{other:?}"),
+        };
+        let signed = signer.sign_json("{}");
+        insta::assert_snapshot!(refused(&SignedBody { sig: "not base64!".to_owned(), ..signed.clone() }), @"sig is not valid base64");
+        insta::assert_snapshot!(refused(&SignedBody { key_id: "server-3".to_owned(), ..signed }), @r###"unknown key "server-3""###);
+    }
+}
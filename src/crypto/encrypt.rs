@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    base64, AppendableResource, Error, Event, EventVerb, PatchResource, UpdatableResource,
+};
+
+// a record or patch encrypted with the key of its collection, in place of its
+// json. the server routes and stores it like any other, but only clients
+// holding the key can read it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EncryptedPayload {
+    pub key_id: String,
+    // base64, 12 random bytes
+    pub nonce: String,
+    // base64, the chacha20-poly1305 ciphertext of the json followed by its tag
+    pub ciphertext: String,
+}
+
+// an event whose records and patches are encrypted
+pub type EncryptedEvent<ID, C> = Event<ID, EncryptedPayload, C, EncryptedPayload>;
+
+// the keys of end-to-end encrypted collections. the newest key of a
+// collection encrypts, older ones are kept to decrypt what was written before
+// a rotation
+pub struct CollectionKeys<C> {
+    current: Vec<(C, String)>,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl<C: PartialEq + Serialize> CollectionKeys<C> {
+    pub fn new() -> Self {
+        Self {
+            current: Vec::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn with_key(mut self, collection: C, key_id: impl Into<String>, key: [u8; 32]) -> Self {
+        let key_id = key_id.into();
+        self.keys.insert(key_id.clone(), key);
+        match self
+            .current
+            .iter_mut()
+            .find(|(other, _)| *other == collection)
+        {
+            Some((_, current)) => *current = key_id,
+            None => self.current.push((collection, key_id)),
+        }
+        self
+    }
+
+    pub fn encrypt<T: Serialize>(
+        &self,
+        collection: &C,
+        data: &T,
+    ) -> Result<EncryptedPayload, Error> {
+        let Some((_, key_id)) = self.current.iter().find(|(other, _)| other == collection) else {
+            return Err(Error::Encryption(format!(
+                "no key for collection {}",
+                collection_json(collection)?
+            )));
+        };
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.keys[key_id]));
+        let json = serde_json::to_vec(data).map_err(Error::Encode)?;
+        let aad = aad(key_id, collection)?;
+        // random nonces of 96 bits only collide after billions of messages
+        // under one key, so rotate keys well before that
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &json,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| Error::Encryption("payload is too long to encrypt".to_owned()))?;
+        Ok(EncryptedPayload {
+            key_id: key_id.clone(),
+            nonce: base64::encode(&nonce),
+            ciphertext: base64::encode(&ciphertext),
+        })
+    }
+
+    // fails if the payload was changed, was moved from another collection or
+    // its key is unknown
+    pub fn decrypt<T: DeserializeOwned>(
+        &self,
+        collection: &C,
+        payload: &EncryptedPayload,
+    ) -> Result<T, Error> {
+        let key = self
+            .keys
+            .get(&payload.key_id)
+            .ok_or_else(|| Error::Encryption(format!("unknown key {:?}", payload.key_id)))?;
+        let nonce = base64::decode(&payload.nonce)
+            .and_then(|nonce| <[u8; 12]>::try_from(nonce).ok())
+            .ok_or_else(|| Error::Encryption("nonce is not 12 bytes of base64".to_owned()))?;
+        let ciphertext = base64::decode(&payload.ciphertext)
+            .ok_or_else(|| Error::Encryption("ciphertext is not valid base64".to_owned()))?;
+        let json = ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &aad(&payload.key_id, collection)?,
+                },
+            )
+            .map_err(|_| {
+                Error::Encryption(format!(
+                    "payload does not decrypt with key {:?}",
+                    payload.key_id
+                ))
+            })?;
+        serde_json::from_slice(&json).map_err(Error::Decode)
+    }
+
+    // encrypts the record or patch of `event`, leaving what the server needs
+    // to route it (its location, seq and meta) readable
+    pub fn encrypt_event<ID, T, P>(
+        &self,
+        event: Event<ID, T, C, P>,
+    ) -> Result<EncryptedEvent<ID, C>, Error>
+    where
+        T: Serialize + TS,
+        P: Serialize + TS,
+    {
+        map_event(
+            event,
+            |collection, data| self.encrypt(collection, &data),
+            |collection, patch| self.encrypt(collection, &patch),
+        )
+    }
+
+    pub fn decrypt_event<ID, T, P>(
+        &self,
+        event: EncryptedEvent<ID, C>,
+    ) -> Result<Event<ID, T, C, P>, Error>
+    where
+        T: Serialize + DeserializeOwned + TS,
+        P: Serialize + DeserializeOwned + TS,
+    {
+        map_event(
+            event,
+            |collection, data| self.decrypt(collection, &data),
+            |collection, patch| self.decrypt(collection, &patch),
+        )
+    }
+}
+
+impl<C: PartialEq + Serialize> Default for CollectionKeys<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn collection_json(collection: &impl Serialize) -> Result<String, Error> {
+    serde_json::to_string(collection).map_err(Error::Encode)
+}
+
+// ties a ciphertext to its key and collection, so the server can't pass one
+// collection's records off as another's
+fn aad(key_id: &str, collection: &impl Serialize) -> Result<Vec<u8>, Error> {
+    Ok(format!("{key_id}\n{}", collection_json(collection)?).into_bytes())
+}
+
+fn map_event<ID, C, T, P, U, Q>(
+    event: Event<ID, T, C, P>,
+    mut data: impl FnMut(&C, T) -> Result<U, Error>,
+    mut patch: impl FnMut(&C, P) -> Result<Q, Error>,
+) -> Result<Event<ID, U, C, Q>, Error>
+where
+    T: Serialize + TS,
+    P: Serialize + TS,
+    U: Serialize + TS,
+    Q: Serialize + TS,
+{
+    let verb = match event.verb {
+        EventVerb::Insert(resource) => EventVerb::Insert(AppendableResource {
+            data: data(&resource.location.collection, resource.data)?,
+            location: resource.location,
+        }),
+        EventVerb::Update(resource) => EventVerb::Update(UpdatableResource {
+            data: data(&resource.location.collection, resource.data)?,
+            location: resource.location,
+            expected_revision: resource.expected_revision,
+        }),
+        EventVerb::Upsert(resource) => EventVerb::Upsert(UpdatableResource {
+            data: data(&resource.location.collection, resource.data)?,
+            location: resource.location,
+            expected_revision: resource.expected_revision,
+        }),
+        EventVerb::Patch(resource) => EventVerb::Patch(PatchResource {
+            data: patch(&resource.location.collection, resource.data)?,
+            location: resource.location,
+        }),
+        EventVerb::Delete(resource) => EventVerb::Delete(resource),
+        EventVerb::TxnBegin(txn_id) => EventVerb::TxnBegin(txn_id),
+        EventVerb::TxnCommit(txn_id) => EventVerb::TxnCommit(txn_id),
+        EventVerb::TxnAbort(txn_id) => EventVerb::TxnAbort(txn_id),
+    };
+    Ok(Event {
+        seq: event.seq,
+        occurred_at: event.occurred_at,
+        schema_version: event.schema_version,
+        scope: event.scope,
+        idempotency_key: event.idempotency_key,
+        expires_at: event.expires_at,
+        meta: event.meta,
+        causality: event.causality,
+        verb,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crypto::{CollectionKeys, EncryptedEvent, EncryptedPayload};
+    use crate::test::{doggo, Collection, DoggoPatch, DoggoRecord};
+    use crate::{Error, Event, Patchable, Syncable, WsBody};
+
+    #[test]
+    fn encrypted_collections_round_trip() {
+        let keys = CollectionKeys::new()
+            .with_key(Collection::Dogs, "dogs-1", [3; 32])
+            .with_key(Collection::Dogs, "dogs-2", [4; 32]);
+        let doggo = doggo(1);
+        let event = keys
+            .encrypt_event(
+                doggo
+                    .clone()
+                    .to_upsert_event()
+                    .into_patchable::<DoggoPatch>(),
+            )
+            .unwrap();
+        let json = event.clone().into_ws_body().try_json().unwrap();
+        assert!(!json.contains("Barky"));
+        let body = WsBody::<EncryptedEvent<u32, Collection>>::from_json(&json).unwrap();
+        let payload = body.into_data().data().unwrap().clone();
+        assert_eq!(payload.key_id, "dogs-2");
+
+        let decrypted: Event<u32, DoggoRecord, Collection, DoggoPatch> =
+            keys.decrypt_event(event).unwrap();
+        assert_eq!(decrypted.data().unwrap().name, "Barky");
+        let patch = keys
+            .encrypt_event(doggo.to_patch_event(DoggoPatch {
+                name: Some("Woofy".to_string()),
+            }))
+            .unwrap();
+        let patch: Event<u32, DoggoRecord, Collection, DoggoPatch> =
+            keys.decrypt_event(patch).unwrap();
+        assert!(patch.verb().name() == "patch");
+
+        // the same record never encrypts to the same nonce or ciphertext
+        let again = keys.encrypt(&Collection::Dogs, &doggo).unwrap();
+        let twice = keys.encrypt(&Collection::Dogs, &doggo).unwrap();
+        assert_ne!(again.nonce, twice.nonce);
+        assert_ne!(again.ciphertext, twice.ciphertext);
+
+        // a record moved to another collection no longer decrypts
+        let keys = keys.with_key(Collection::Cats, "cats-1", [4; 32]);
+        let refused = |payload: &EncryptedPayload| {
+            matches!(
+                keys.decrypt::<DoggoRecord>(&Collection::Dogs, payload),
+                Err(Error::Encryption(_))
+            )
+        };
+        assert!(matches!(
+            keys.decrypt::<DoggoRecord>(&Collection::Cats, &payload),
+            Err(Error::Encryption(_))
+        ));
+        // nor does one that was changed on the way, or that names a key or
+        // nonce the client doesn't have
+        let mut tampered = crate::base64::decode(&payload.ciphertext).unwrap();
+        tampered[0] ^= 1;
+        assert!(refused(&EncryptedPayload {
+            ciphertext: crate::base64::encode(&tampered),
+            ..payload.clone()
+        }));
+        assert!(refused(&EncryptedPayload {
+            key_id: "dogs-1".to_owned(),
+            ..payload.clone()
+        }));
+        assert!(refused(&EncryptedPayload {
+            key_id: "dogs-3".to_owned(),
+            ..payload.clone()
+        }));
+        assert!(refused(&EncryptedPayload {
+            nonce: crate::base64::encode(&[0; 8]),
+            ..payload.clone()
+        }));
+        assert!(keys
+            .decrypt::<DoggoRecord>(&Collection::Dogs, &payload)
+            .is_ok());
+    }
+}
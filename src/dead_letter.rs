@@ -0,0 +1,238 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{Clock, Error, SystemClock};
+
+// where an event was given up on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterStage {
+    // a `Middleware` rejected it
+    Rejected,
+    // its json couldn't be encoded
+    Serialization,
+    // it was queued for, or on its way to, a listener closed on overflow
+    Overflow,
+}
+
+// an event that could not be delivered, with why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter<T> {
+    pub event: T,
+    pub stage: DeadLetterStage,
+    pub error: String,
+    // milliseconds since the unix epoch
+    pub failed_at: u64,
+}
+
+impl<T> DeadLetter<T> {
+    pub fn new(event: T, stage: DeadLetterStage, error: &Error) -> Self {
+        Self {
+            event,
+            stage,
+            error: error.to_string(),
+            failed_at: SystemClock.now(),
+        }
+    }
+}
+
+// keeps events that would otherwise be dropped, so they can be inspected and
+// replayed. called on the publishing thread, so it shouldn't block for long
+pub trait DeadLetterSink<T>: Send + Sync {
+    fn dead_letter(&self, letter: DeadLetter<T>);
+}
+
+impl<T, F: Fn(DeadLetter<T>) + Send + Sync> DeadLetterSink<T> for F {
+    fn dead_letter(&self, letter: DeadLetter<T>) {
+        self(letter)
+    }
+}
+
+impl<T, S: DeadLetterSink<T> + ?Sized> DeadLetterSink<T> for Arc<S> {
+    fn dead_letter(&self, letter: DeadLetter<T>) {
+        (**self).dead_letter(letter)
+    }
+}
+
+// for tests, and for servers that expose their dead letters over an admin api
+pub struct InMemoryDeadLetters<T> {
+    letters: Mutex<Vec<DeadLetter<T>>>,
+}
+
+impl<T> InMemoryDeadLetters<T> {
+    pub fn new() -> Self {
+        Self {
+            letters: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<DeadLetter<T>>> {
+        self.letters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn letters(&self) -> Vec<DeadLetter<T>>
+    where
+        T: Clone,
+    {
+        self.lock().clone()
+    }
+
+    // takes the letters out, e.g. to republish them
+    pub fn drain(&self) -> Vec<DeadLetter<T>> {
+        std::mem::take(&mut *self.lock())
+    }
+}
+
+impl<T> Default for InMemoryDeadLetters<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send> DeadLetterSink<T> for InMemoryDeadLetters<T> {
+    fn dead_letter(&self, letter: DeadLetter<T>) {
+        self.lock().push(letter);
+    }
+}
+
+// appends letters to a file as json lines. a letter that can't be encoded or
+// written is lost, as there is nowhere left to put it
+pub struct FileDeadLetters {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl FileDeadLetters {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| Error::Store(err.to_string()))?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // every letter written to `path` so far
+    pub fn read<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Vec<DeadLetter<T>>, Error> {
+        let file = File::open(path).map_err(|err| Error::Store(err.to_string()))?;
+        BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let line = line.map_err(|err| Error::Store(err.to_string()))?;
+                serde_json::from_str(&line).map_err(Error::Decode)
+            })
+            .collect()
+    }
+}
+
+impl<T: Serialize> DeadLetterSink<T> for FileDeadLetters {
+    fn dead_letter(&self, letter: DeadLetter<T>) {
+        let Ok(mut line) = serde_json::to_vec(&letter) else {
+            return;
+        };
+        line.push(b'\n');
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _ = file.write_all(&line);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, DeadLetter, DeadLetterSink, DeadLetterStage, Error, Event,
+        FileDeadLetters, InMemoryDeadLetters, Listener, Outcome, OverflowPolicy, Service,
+        Subscribe, SubscriptionManager, Syncable,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    fn named(id: u32, name: &str) -> DoggoEvent {
+        DoggoRecord {
+            name: name.to_string(),
+            ..doggo(id)
+        }
+        .to_upsert_event()
+    }
+
+    #[test]
+    fn undeliverable_events_are_kept() {
+        let letters = Arc::new(InMemoryDeadLetters::<DoggoEvent>::new());
+        let service = BroadcastService::<DoggoEvent>::new()
+            .with_middleware(|event: DoggoEvent| match event.data() {
+                Some(doggo) if doggo.name.is_empty() => Outcome::Reject("unnamed".to_owned()),
+                _ => Outcome::Continue(event),
+            })
+            .with_dead_letters(letters.clone());
+        assert!(service.publish(named(1, "")).is_err());
+
+        let manager = SubscriptionManager::<DoggoEvent>::new().with_dead_letters(letters.clone());
+        let mut listener = manager.connect_buffered(1, OverflowPolicy::CloseConnection);
+        manager.handle_subscribe(
+            listener.connection(),
+            &Subscribe {
+                collections: vec![Collection::Dogs],
+                patterns: Vec::new(),
+                from_seq: None,
+            },
+        );
+        manager.publish(named(2, "Barky"));
+        manager.publish(named(3, "Woofy"));
+        assert!(matches!(
+            block_on(listener.recv()),
+            Err(Error::Overflowed(1))
+        ));
+
+        let stages: Vec<_> = letters
+            .letters()
+            .iter()
+            .map(|letter| (letter.stage, *letter.event.id().unwrap()))
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                (DeadLetterStage::Rejected, 1),
+                (DeadLetterStage::Overflow, 2),
+                (DeadLetterStage::Overflow, 3)
+            ]
+        );
+        assert_eq!(letters.letters()[0].error, "event rejected: unnamed");
+
+        let path = std::env::temp_dir().join(format!("rsp-dead-letters-{}", std::process::id()));
+        let file = FileDeadLetters::open(&path).unwrap();
+        for letter in letters.drain() {
+            file.dead_letter(letter);
+        }
+        let read: Vec<DeadLetter<DoggoEvent>> = FileDeadLetters::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), 3);
+        assert!(letters.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use ts_rs::TS;
+
+use crate::{Error, Event, EventMeta, Routable, Scope, Sequenced};
+
+// an event of any record, for routers, stores and relays that move mixed
+// streams along without knowing their types. it encodes exactly like the
+// `Event` it was made from, so a relay can decode any event frame as one and
+// pass it on unchanged. the collection and id it's routed by are read from
+// the verb's location; a collection that isn't encoded as a string is named
+// by its json
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynEvent {
+    verb: String,
+    // what the verb carries: the location and record of a write, the id of
+    // a transaction marker
+    payload: Value,
+    collection: Option<String>,
+    id: Option<Value>,
+    seq: Option<u64>,
+    scope: Option<Scope>,
+    meta: Option<EventMeta>,
+    // the rest of the envelope, as it was encoded
+    rest: Map<String, Value>,
+}
+
+impl DynEvent {
+    pub fn from_event<ID, T, C, P>(event: &Event<ID, T, C, P>) -> Result<Self, Error>
+    where
+        ID: Serialize,
+        T: Serialize + TS,
+        C: Serialize,
+        P: Serialize + TS,
+    {
+        match serde_json::to_value(event).map_err(Error::Encode)? {
+            Value::Object(object) => Self::from_object(object),
+            _ => Err(Error::InvalidFrame("an event must be an object".to_owned())),
+        }
+    }
+
+    // the typed event back, failing as `Decode` if it's of another record
+    pub fn into_event<ID, T, C, P>(self) -> Result<Event<ID, T, C, P>, Error>
+    where
+        ID: DeserializeOwned,
+        T: Serialize + TS + DeserializeOwned,
+        C: DeserializeOwned,
+        P: Serialize + TS + DeserializeOwned,
+    {
+        serde_json::from_value(Value::Object(self.into_object())).map_err(Error::Decode)
+    }
+
+    // the `type` of the verb, as `EventVerb::name` gives it
+    pub fn verb(&self) -> &str {
+        &self.verb
+    }
+
+    pub fn payload(&self) -> &Value {
+        &self.payload
+    }
+
+    // the record carried by inserts, updates and upserts, the operations of
+    // a patch
+    pub fn data(&self) -> Option<&Value> {
+        self.payload.get("data")
+    }
+
+    pub fn collection(&self) -> Option<&str> {
+        self.collection.as_deref()
+    }
+
+    pub fn id(&self) -> Option<&Value> {
+        self.id.as_ref()
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    pub fn scope(&self) -> Option<&Scope> {
+        self.scope.as_ref()
+    }
+
+    pub fn meta(&self) -> Option<&EventMeta> {
+        self.meta.as_ref()
+    }
+
+    fn from_object(mut object: Map<String, Value>) -> Result<Self, Error> {
+        let Some(Value::Object(mut verb)) = object.remove("verb") else {
+            return Err(Error::InvalidFrame("an event must have a verb".to_owned()));
+        };
+        let Some(Value::String(kind)) = verb.remove("type") else {
+            return Err(Error::InvalidFrame("a verb must have a type".to_owned()));
+        };
+        let payload = verb.remove("payload").unwrap_or(Value::Null);
+        let collection =
+            payload
+                .pointer("/location/collection")
+                .map(|collection| match collection {
+                    Value::String(name) => name.clone(),
+                    other => other.to_string(),
+                });
+        let id = payload
+            .pointer("/location/id")
+            .filter(|id| !id.is_null())
+            .cloned();
+        let mut take = |key: &str| object.remove(key).unwrap_or(Value::Null);
+        Ok(Self {
+            verb: kind,
+            payload,
+            collection,
+            id,
+            seq: serde_json::from_value(take("seq")).map_err(Error::Decode)?,
+            scope: serde_json::from_value(take("scope")).map_err(Error::Decode)?,
+            meta: serde_json::from_value(take("meta")).map_err(Error::Decode)?,
+            rest: object,
+        })
+    }
+
+    fn into_object(self) -> Map<String, Value> {
+        let mut object = self.rest;
+        if let Some(seq) = self.seq {
+            object.insert("seq".to_owned(), seq.into());
+        }
+        // neither can fail to encode
+        if let Some(scope) = self.scope {
+            object.insert("scope".to_owned(), serde_json::to_value(scope).unwrap());
+        }
+        if let Some(meta) = self.meta {
+            object.insert("meta".to_owned(), serde_json::to_value(meta).unwrap());
+        }
+        let mut verb = Map::new();
+        verb.insert("type".to_owned(), self.verb.into());
+        verb.insert("payload".to_owned(), self.payload);
+        object.insert("verb".to_owned(), Value::Object(verb));
+        object
+    }
+}
+
+impl Serialize for DynEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.clone().into_object().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DynEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let object = Map::deserialize(deserializer)?;
+        Self::from_object(object).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Sequenced for DynEvent {
+    fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    fn set_seq(&mut self, seq: u64) {
+        self.seq = Some(seq);
+    }
+}
+
+impl Routable for DynEvent {
+    type Collection = String;
+    type Id = Value;
+
+    fn collection(&self) -> Option<&String> {
+        self.collection.as_ref()
+    }
+
+    fn id(&self) -> Option<&Value> {
+        self.id.as_ref()
+    }
+
+    fn origin(&self) -> Option<&str> {
+        self.meta.as_ref().and_then(EventMeta::origin)
+    }
+
+    fn scope(&self) -> Option<&Scope> {
+        self.scope.as_ref()
+    }
+}
+
+impl<ID, T, C, P> TryFrom<&Event<ID, T, C, P>> for DynEvent
+where
+    ID: Serialize,
+    T: Serialize + TS,
+    C: Serialize,
+    P: Serialize + TS,
+{
+    type Error = Error;
+
+    fn try_from(event: &Event<ID, T, C, P>) -> Result<Self, Error> {
+        Self::from_event(event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, DynEvent, Error, Event, EventMeta, Listener, NoPatch, Service, Syncable,
+        Txn,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn typed_events_survive_erasure() {
+        let doggo = doggo(1);
+        let event = doggo
+            .to_upsert_event()
+            .with_occurred_at(1_000)
+            .with_scope("tenant-1")
+            .with_meta(EventMeta::new().with_origin("web"));
+        let erased = DynEvent::from_event(&event).unwrap();
+        assert_eq!(
+            (erased.verb(), erased.collection(), erased.id()),
+            ("upsert", Some("Dogs"), Some(&json!(1)))
+        );
+        assert_eq!(erased.data().unwrap()["name"], "Barky");
+        assert_eq!(
+            serde_json::to_value(&erased).unwrap(),
+            serde_json::to_value(&event).unwrap()
+        );
+
+        // mixed streams go through services untyped
+        let service = BroadcastService::new();
+        let mut listener = service.listener();
+        service.publish(erased).unwrap();
+        let marker: DoggoEvent = Txn::builder().txn_id(7).build().begin_event();
+        service
+            .publish(DynEvent::from_event(&marker).unwrap())
+            .unwrap();
+        let received = block_on(listener.recv()).unwrap();
+        assert_eq!(received.seq(), Some(0));
+        let event: DoggoEvent = received.into_event().unwrap();
+        assert_eq!(event.data().unwrap().name, "Barky");
+        assert_eq!(event.meta().unwrap().origin(), Some("web"));
+        let marker = block_on(listener.recv()).unwrap();
+        assert_eq!((marker.verb(), marker.collection()), ("txn_begin", None));
+        assert!(marker
+            .into_event::<u32, DoggoRecord, Collection, NoPatch>()
+            .is_ok());
+
+        let json = r#"{"verb":{"type":"insert","payload":{"location":{"id":null,"txn_id":null,"collection":"Dogs"},"data":{"id":1}}}}"#;
+        let erased: DynEvent = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            erased.into_event::<u32, DoggoRecord, Collection, NoPatch>(),
+            Err(Error::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::codec::{Limits, WireCodec};
+use crate::{Command, Error, Hello, NoPatch, WsBody};
+
+// client frames bigger than this are refused before they're decoded, unless
+// `Limits` say otherwise
+pub const MAX_ENVELOPE_LEN: usize = 1024 * 1024;
+
+// the `type` of every `Command`
+const COMMANDS: &[&str] = &[
+    "subscribe",
+    "unsubscribe",
+    "query",
+    "live_query",
+    "mutate",
+    "ack",
+    "ping",
+    "pong",
+    "authenticate",
+    "join",
+    "leave",
+    "presence_heartbeat",
+    "ephemeral",
+];
+
+// anything a client may send the server: the `Hello` opening the handshake,
+// then commands. like commands, they're handled one at a time, so the command
+// isn't boxed
+#[allow(clippy::large_enum_variant)]
+pub enum AnyMessage<ID: Serialize, T: Serialize + TS, C: Serialize, P: Serialize + TS = NoPatch> {
+    Hello(Hello),
+    Command(WsBody<Command<ID, T, C, P>>),
+}
+
+// decodes a frame from a client, which may be anything, within the default
+// `Limits`. frames over them fail as `LimitExceeded`; frames nested deeper
+// than the codecs allow, that aren't objects or whose command `type` is
+// unknown are refused as `InvalidFrame` before serde sees them; what's left
+// fails as `Decode` if it doesn't fit
+pub fn decode_envelope<ID, T, C, P>(
+    bytes: &[u8],
+    codec: &impl WireCodec,
+) -> Result<AnyMessage<ID, T, C, P>, Error>
+where
+    ID: Serialize + DeserializeOwned,
+    T: Serialize + TS + DeserializeOwned,
+    C: Serialize + DeserializeOwned,
+    P: Serialize + TS + DeserializeOwned,
+{
+    decode_envelope_with(bytes, codec, &Limits::default())
+}
+
+pub fn decode_envelope_with<ID, T, C, P>(
+    bytes: &[u8],
+    codec: &impl WireCodec,
+    limits: &Limits,
+) -> Result<AnyMessage<ID, T, C, P>, Error>
+where
+    ID: Serialize + DeserializeOwned,
+    T: Serialize + TS + DeserializeOwned,
+    C: Serialize + DeserializeOwned,
+    P: Serialize + TS + DeserializeOwned,
+{
+    limits.check_payload(bytes.len())?;
+    let frame: Value = codec.decode(bytes)?;
+    limits.check(&frame)?;
+    let Value::Object(object) = &frame else {
+        return Err(Error::InvalidFrame("a frame must be an object".to_owned()));
+    };
+    if !object.contains_key("data") && object.contains_key("protocol_versions") {
+        return serde_json::from_value(frame)
+            .map(AnyMessage::Hello)
+            .map_err(Error::Decode);
+    }
+    match frame.pointer("/data/type") {
+        Some(Value::String(kind)) if COMMANDS.contains(&kind.as_str()) => {}
+        Some(Value::String(kind)) => {
+            return Err(Error::InvalidFrame(format!(
+                "unknown command type {kind:?}"
+            )))
+        }
+        _ => {
+            return Err(Error::InvalidFrame(
+                "a frame must be a hello or carry a command with a type".to_owned(),
+            ))
+        }
+    }
+    serde_json::from_value(frame)
+        .map(AnyMessage::Command)
+        .map_err(Error::Decode)
+}
+
+#[cfg(test)]
+mod test {
+    use ts_rs::TS;
+
+    #[cfg(feature = "cbor")]
+    use crate::codec::CborCodec;
+    #[cfg(feature = "msgpack")]
+    use crate::codec::MessagePackCodec;
+    use crate::codec::{JsonCodec, Limit};
+    use crate::test::{Collection, DoggoRecord};
+    use crate::{decode_envelope, AnyMessage, Command, Error, Hello, Ping, MAX_ENVELOPE_LEN};
+
+    use super::COMMANDS;
+
+    type Message = AnyMessage<u32, DoggoRecord, Collection>;
+
+    fn decode(bytes: &[u8]) -> Result<Message, Error> {
+        decode_envelope(bytes, &JsonCodec)
+    }
+
+    #[test]
+    fn malformed_frames_are_refused() {
+        let hello = serde_json::to_vec(&Hello::default()).unwrap();
+        assert!(matches!(decode(&hello), Ok(AnyMessage::Hello(_))));
+        let ping = Command::<u32, DoggoRecord, Collection>::Ping(Ping { sent_at: 1 });
+        let ping = ping.into_ws_body().try_json().unwrap();
+        assert!(matches!(
+            decode(ping.as_bytes()),
+            Ok(AnyMessage::Command(_))
+        ));
+
+        let invalid = |result: Result<Message, Error>| match result {
+            Err(Error::InvalidFrame(reason)) => reason,
+            Err(err) => panic!("{err:?}"),
+            Ok(_) => panic!("a malformed frame decoded"),
+        };
+        insta::assert_snapshot!(invalid(decode(br#"{"data":{"type":"shutdown"}}"#)), @r###"unknown command type "shutdown""###);
+        insta::assert_snapshot!(invalid(decode(b"[1]")), @r###"a frame must be an object"###);
+        assert!(matches!(
+            decode(&vec![b' '; MAX_ENVELOPE_LEN + 1]),
+            Err(Error::LimitExceeded {
+                limit: Limit::PayloadBytes,
+                ..
+            })
+        ));
+        assert!(matches!(
+            decode(br#"{"data":{"type":"ping","payload":{"sent_at":"soon"}}}"#),
+            Err(Error::Decode(_))
+        ));
+        assert!(matches!(decode(b"{\"data\":"), Err(Error::Decode(_))));
+
+        // deep nesting fails before the stack runs out, in every codec
+        let deep_json = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        assert!(matches!(
+            decode(deep_json.as_bytes()),
+            Err(Error::Decode(_))
+        ));
+        #[cfg(feature = "msgpack")]
+        {
+            let mut deep_msgpack = vec![0x91; 10_000];
+            deep_msgpack.push(0xc0);
+            insta::assert_snapshot!(invalid(decode_envelope(&deep_msgpack, &MessagePackCodec)), @r###"msgpack: nested too deeply"###);
+        }
+        #[cfg(feature = "cbor")]
+        {
+            let mut deep_cbor = vec![0x81; 10_000];
+            deep_cbor.push(0xf6);
+            insta::assert_snapshot!(invalid(decode_envelope(&deep_cbor, &CborCodec)), @r###"cbor: nested too deeply"###);
+        }
+    }
+
+    #[test]
+    fn every_command_type_is_known() {
+        // the bindings name every variant as serde tags it
+        let decl = Command::<u32, DoggoRecord, Collection>::decl();
+        let types: Vec<_> = decl
+            .split(r#""type": ""#)
+            .skip(1)
+            .map(|rest| &rest[..rest.find('"').unwrap()])
+            .collect();
+        assert_eq!(types, COMMANDS);
+    }
+}
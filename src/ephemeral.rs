@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Routable, Scope, Scoped, WsBody};
+
+// a message routed like the events of `collection`, but never stored or given
+// a seq, for state that is only interesting while it is fresh, like cursor
+// positions or typing indicators. a subscriber that connects later, or
+// resumes from a seq, never sees it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Ephemeral<ID, C> {
+    collection: C,
+    // the record it is about, for subscriptions to single records
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ID>,
+    #[ts(type = "unknown")]
+    data: Value,
+    // the client it came from, so it isn't echoed back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<Scope>,
+}
+
+impl<ID, C> Ephemeral<ID, C> {
+    pub fn new(collection: C, data: impl Into<Value>) -> Self {
+        Self {
+            collection,
+            id: None,
+            data: data.into(),
+            origin: None,
+            scope: None,
+        }
+    }
+
+    pub fn with_id(mut self, id: ID) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    pub fn with_scope(mut self, scope: impl Into<Scope>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    pub fn data(&self) -> &Value {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Value {
+        self.data
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+impl<ID, C> Routable for Ephemeral<ID, C> {
+    type Collection = C;
+    type Id = ID;
+
+    fn collection(&self) -> Option<&C> {
+        Some(&self.collection)
+    }
+
+    fn id(&self) -> Option<&ID> {
+        self.id.as_ref()
+    }
+
+    fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    fn scope(&self) -> Option<&Scope> {
+        self.scope.as_ref()
+    }
+}
+
+impl<ID, C> Scoped for Ephemeral<ID, C> {
+    fn set_scope(&mut self, scope: Scope) {
+        self.scope = Some(scope);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{Ephemeral, Event, Listener, SubscriptionManager, Syncable};
+
+    #[test]
+    fn ephemeral_messages_skip_the_stream() {
+        let manager = SubscriptionManager::<Event<u32, DoggoRecord, Collection>>::new();
+        let phone = manager.connect();
+        let mut laptop = manager.connect();
+        let cats = manager.connect();
+        for connection in [phone.connection(), laptop.connection()] {
+            manager.subscribe(connection, Collection::Dogs, None);
+        }
+        manager.subscribe(cats.connection(), Collection::Cats, None);
+        manager.suppress_origin(phone.connection(), "phone");
+
+        let typing = Ephemeral::new(Collection::Dogs, json!({ "typing": true }))
+            .with_id(1)
+            .with_origin("phone");
+        assert_eq!(manager.publish_ephemeral(typing), 1);
+        let barky = doggo(1);
+        manager.publish(barky.to_upsert_event());
+
+        let mut ephemeral = manager.ephemeral_listener(laptop.connection());
+        let message = block_on(ephemeral.recv()).unwrap();
+        insta::assert_snapshot!(message.into_ws_body().try_json().unwrap(), @r###"{"data":{"collection":"Dogs","id":1,"data":{"typing":true},"origin":"phone"}}"###);
+        let event = block_on(laptop.recv()).unwrap();
+        assert_eq!(event.seq(), Some(0), "ephemeral messages take no seq");
+        assert_eq!(manager.metrics().published, 1);
+    }
+}
@@ -0,0 +1,78 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("could not encode message: {0}")]
+    Encode(#[source] serde_json::Error),
+    #[error("could not decode message: {0}")]
+    Decode(#[source] serde_json::Error),
+    #[error("invalid frame: {0}")]
+    InvalidFrame(String),
+    #[error("could not apply patch: {0}")]
+    Patch(String),
+    #[error("expected protocol version {expected}, found {found}")]
+    VersionMismatch { expected: u32, found: u32 },
+    #[error("no common protocol version, offered {offered:?} but supported {supported:?}")]
+    UnsupportedVersion {
+        offered: Vec<u32>,
+        supported: Vec<u32>,
+    },
+    #[error("no common codec, offered {0:?}")]
+    UnsupportedCodec(Vec<String>),
+    #[error("invalid collection: {0}")]
+    InvalidCollection(String),
+    #[error("could not generate code: {0}")]
+    Codegen(String),
+    #[error("event store failed: {0}")]
+    Store(String),
+    #[error("transport failed: {0}")]
+    Transport(String),
+    #[error("listener fell behind and missed {0} events")]
+    Lagged(u64),
+    #[error("connection timed out after {0}ms without a heartbeat")]
+    ConnectionTimedOut(u64),
+    #[error("listener buffer overflowed at {0} events")]
+    Overflowed(usize),
+    #[error("publish at revision {found} is out of order, the record is at revision {current}")]
+    OutOfOrder { current: u64, found: u64 },
+    // by a `Middleware`
+    #[error("event rejected: {0}")]
+    Rejected(String),
+    // by `Validated`
+    #[error("invalid record: {0}")]
+    Validation(crate::ValidationErrors),
+    // no `Upcasters` path from the version a payload was written at
+    #[error("cannot upcast schema version {found} to {current}")]
+    SchemaVersion { found: u32, current: u32 },
+    #[error("not authorized: {0}")]
+    Unauthorized(String),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("encryption failed: {0}")]
+    Encryption(String),
+    // an `ErrorMessage` the server sent, as the client sees it
+    #[error("server answered {:?}: {}", .0.code, .0.message)]
+    Server(crate::ErrorMessage),
+    #[error("service was closed")]
+    Closed,
+    // the service shut down, see `Service::shutdown`
+    #[error("service is going away, reconnect after {}ms", .0.reconnect_after)]
+    GoingAway(crate::GoingAway),
+    #[error("{0} listeners had not drained by the shutdown deadline")]
+    Undrained(usize),
+    // a message over one of a `LimitedCodec`'s `Limits`
+    #[error("{limit} of {found} is over the limit of {max}")]
+    LimitExceeded {
+        limit: crate::codec::Limit,
+        max: usize,
+        found: usize,
+    },
+    // messages that no longer match their `WireCompat` fixtures
+    #[error("wire format changed:\n{0}")]
+    WireFormat(String),
+}
+
+// an io error on a byte stream, e.g. under a tokio-util `Framed`
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Transport(err.to_string())
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{ConflictError, Error, WsBody};
+
+// what went wrong, stable across releases so clients can match on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ErrorCode {
+    // the frame didn't parse or asked for something that doesn't exist
+    BadCommand,
+    Unauthorized,
+    // a write raced another one
+    Conflict,
+    // a protocol version or codec the server doesn't speak
+    Unsupported,
+    // the client fell behind the stream and has to resubscribe
+    Lagged,
+    // the server is shedding load
+    Overloaded,
+    // a store or transport the server depends on is down
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    // whether sending the same frame again later can succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Lagged | ErrorCode::Overloaded | ErrorCode::Unavailable
+        )
+    }
+}
+
+// server -> client report of a frame it could not act on, instead of dropping
+// the frame without a word
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct ErrorMessage {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+    // the event the error is about, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    pub related_seq: Option<u64>,
+}
+
+impl ErrorMessage {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retryable: code.is_retryable(),
+            related_seq: None,
+        }
+    }
+
+    pub fn with_related_seq(mut self, seq: u64) -> Self {
+        self.related_seq = Some(seq);
+        self
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+impl From<&Error> for ErrorMessage {
+    fn from(err: &Error) -> Self {
+        let code = match err {
+            Error::Decode(_)
+            | Error::InvalidFrame(_)
+            | Error::Patch(_)
+            | Error::InvalidCollection(_)
+            | Error::LimitExceeded { .. }
+            | Error::Rejected(_)
+            | Error::Validation(_) => ErrorCode::BadCommand,
+            Error::Unauthorized(_) | Error::InvalidSignature(_) => ErrorCode::Unauthorized,
+            Error::OutOfOrder { .. } => ErrorCode::Conflict,
+            Error::VersionMismatch { .. }
+            | Error::UnsupportedVersion { .. }
+            | Error::UnsupportedCodec(_)
+            | Error::SchemaVersion { .. } => ErrorCode::Unsupported,
+            Error::Lagged(_) => ErrorCode::Lagged,
+            Error::Overflowed(_) => ErrorCode::Overloaded,
+            Error::Store(_)
+            | Error::Transport(_)
+            | Error::ConnectionTimedOut(_)
+            | Error::Closed
+            | Error::GoingAway(_)
+            | Error::Undrained(_) => ErrorCode::Unavailable,
+            Error::Encode(_) | Error::Codegen(_) | Error::Encryption(_) | Error::WireFormat(_) => {
+                ErrorCode::Internal
+            }
+            // relayed as it came
+            Error::Server(message) => return message.clone(),
+        };
+        Self::new(code, err.to_string())
+    }
+}
+
+impl From<Error> for ErrorMessage {
+    fn from(err: Error) -> Self {
+        Self::from(&err)
+    }
+}
+
+impl<ID, C> From<&ConflictError<ID, C>> for ErrorMessage {
+    fn from(conflict: &ConflictError<ID, C>) -> Self {
+        Self::new(ErrorCode::Conflict, conflict.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::Collection;
+    use crate::{ConflictError, Error, ErrorCode, ErrorMessage, WsBody};
+
+    #[test]
+    fn failures_go_out_as_messages() {
+        let json = r#"{"data":{"type":"subscribe""#;
+        let Err(err) = WsBody::<ErrorMessage>::from_json(json) else {
+            panic!("expected a decode error");
+        };
+        let message = ErrorMessage::from(&err);
+        assert_eq!(message.code, ErrorCode::BadCommand);
+        assert!(!message.retryable);
+
+        let message = ErrorMessage::from(Error::Lagged(3)).with_related_seq(41);
+        let json = message.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"code":"lagged","message":"listener fell behind and missed 3 events","retryable":true,"related_seq":41}}"###);
+        let message = WsBody::<ErrorMessage>::from_json(&json)
+            .unwrap()
+            .into_data();
+        assert!(message.retryable && message.related_seq == Some(41));
+
+        let conflict = ConflictError::new(Collection::Dogs, Some(1), 2, Some(3));
+        assert_eq!(ErrorMessage::from(&conflict).code, ErrorCode::Conflict);
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::timer::sleep;
+use crate::{Clock, Event, EventVerb, Routable, Scope, Service, Shutdown, SystemClock};
+
+// a record is its scope, collection and id
+type Record<T> = (
+    Option<Scope>,
+    <T as Routable>::Collection,
+    <T as Routable>::Id,
+);
+
+// deletes records once their `expires_at` has passed, for short-lived ones
+// like locks or temporary shares. it remembers the expiry of every record
+// published through it: inserts, updates and upserts replace it (and clear
+// it if they have none), patches only replace it if they set one, and deletes
+// clear it. `sweep` publishes a tombstone for every record that has expired
+pub struct ExpiringService<S, T: Routable> {
+    inner: S,
+    clock: Box<dyn Clock + Send + Sync>,
+    expiries: Mutex<HashMap<Record<T>, u64>>,
+}
+
+impl<S, T: Routable> ExpiringService<S, T> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            clock: Box::new(SystemClock),
+            expiries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // records waiting to expire
+    pub fn expiring(&self) -> usize {
+        self.lock().len()
+    }
+
+    // when the next record expires
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.lock().values().min().copied()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Record<T>, u64>> {
+        self.expiries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<S, ID, T, C, P> ExpiringService<S, Event<ID, T, C, P>>
+where
+    S: Service<Event<ID, T, C, P>>,
+    ID: Eq + Hash + Clone,
+    T: Serialize + TS,
+    C: Eq + Hash + Clone,
+    P: Serialize + TS,
+{
+    // publishes the delete of every expired record, stopping at the first
+    // that fails, and returns how many went out
+    pub fn sweep(&self) -> Result<usize, S::Error> {
+        let mut expiries = self.lock();
+        let now = self.clock.now();
+        let expired: Vec<_> = expiries
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(record, _)| record.clone())
+            .collect();
+        for record in &expired {
+            let (scope, collection, id) = record.clone();
+            let mut delete = Event::new_delete_event(id, collection).with_deleted_at(now);
+            delete.scope = scope;
+            self.inner.publish(delete)?;
+            expiries.remove(record);
+        }
+        Ok(expired.len())
+    }
+
+    // sweeps every `interval` until a sweep fails, and returns why
+    pub async fn run(&self, interval: Duration) -> S::Error {
+        loop {
+            if let Err(err) = self.sweep() {
+                return err;
+            }
+            sleep(interval).await;
+        }
+    }
+}
+
+impl<S, ID, T, C, P> Service<Event<ID, T, C, P>> for ExpiringService<S, Event<ID, T, C, P>>
+where
+    S: Service<Event<ID, T, C, P>>,
+    ID: Eq + Hash + Clone,
+    T: Serialize + TS,
+    C: Eq + Hash + Clone,
+    P: Serialize + TS,
+{
+    type Listener = S::Listener;
+    type Error = S::Error;
+
+    fn publish(&self, event: Event<ID, T, C, P>) -> Result<(), Self::Error> {
+        let record = match (event.collection(), event.id()) {
+            (Some(collection), Some(id)) => {
+                Some((event.scope.clone(), collection.clone(), id.clone()))
+            }
+            _ => None,
+        };
+        let Some(record) = record else {
+            return self.inner.publish(event);
+        };
+        let expires_at = event.expires_at;
+        let replaces = match &event.verb {
+            EventVerb::Patch(_) => expires_at.is_some(),
+            _ => true,
+        };
+        // held across the publish so a sweep can't delete the record in
+        // between and then lose the new expiry
+        let mut expiries = self.lock();
+        self.inner.publish(event)?;
+        match expires_at {
+            Some(at) if replaces => {
+                expiries.insert(record, at);
+            }
+            None if replaces => {
+                expiries.remove(&record);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.inner.listener()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        self.inner.listener_from(seq)
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Event, Listener, Service, Syncable};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn expired_records_are_deleted() {
+        let now = Arc::new(AtomicU64::new(0));
+        let clock = now.clone();
+        let service = BroadcastService::<DoggoEvent>::new()
+            .with_expiry()
+            .with_clock(move || clock.load(Ordering::SeqCst));
+        let mut listener = service.listener();
+
+        service
+            .publish(doggo(1).to_upsert_event().with_expires_at(1_000))
+            .unwrap();
+        service
+            .publish(doggo(2).to_upsert_event().with_expires_at(1_000))
+            .unwrap();
+        service
+            .publish(doggo(3).to_upsert_event().with_expires_at(5_000))
+            .unwrap();
+        // kept for good
+        service.publish(doggo(2).to_update_event()).unwrap();
+        assert_eq!(service.expiring(), 2);
+        assert_eq!(service.next_expiry(), Some(1_000));
+
+        assert_eq!(service.sweep().unwrap(), 0);
+        now.store(1_000, Ordering::SeqCst);
+        assert_eq!(service.sweep().unwrap(), 1);
+        assert_eq!(service.sweep().unwrap(), 0);
+        assert_eq!(service.expiring(), 1);
+
+        let deleted = block_on(async {
+            for _ in 0..4 {
+                listener.recv().await.unwrap();
+            }
+            listener.recv().await.unwrap()
+        });
+        assert_eq!(deleted.verb().name(), "delete");
+        assert_eq!(
+            (deleted.id(), deleted.deleted_at()),
+            (Some(&1), Some(1_000))
+        );
+
+        let clock = || 1_000;
+        let ttl = |ttl| doggo(4).to_upsert_event().with_ttl(ttl, &clock);
+        assert_eq!(ttl(Duration::from_millis(500)).expires_at(), Some(1_500));
+        // ttls past the end of time never expire instead of overflowing
+        assert_eq!(ttl(Duration::MAX).expires_at(), Some(u64::MAX));
+        assert_eq!(
+            ttl(Duration::from_millis(u64::MAX)).expires_at(),
+            Some(u64::MAX)
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Event, EventMeta, Listener, Scope, Scoped};
+
+// anything a listener can route by collection. events that don't belong to a
+// collection (e.g. transaction markers) return `None` and are never filtered out
+pub trait Routable {
+    type Collection;
+    type Id;
+
+    fn collection(&self) -> Option<&Self::Collection>;
+
+    // the record the item is about, if any
+    fn id(&self) -> Option<&Self::Id>;
+
+    // the client the item came from, so it isn't echoed back to it
+    fn origin(&self) -> Option<&str> {
+        None
+    }
+
+    // the sync domain the item belongs to, if the server has several
+    fn scope(&self) -> Option<&Scope> {
+        None
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Routable for Event<ID, T, C, P> {
+    type Collection = C;
+    type Id = ID;
+
+    fn collection(&self) -> Option<&C> {
+        Event::collection(self)
+    }
+
+    fn id(&self) -> Option<&ID> {
+        Event::id(self)
+    }
+
+    fn origin(&self) -> Option<&str> {
+        self.meta().and_then(EventMeta::origin)
+    }
+
+    fn scope(&self) -> Option<&Scope> {
+        Event::scope(self)
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Scoped for Event<ID, T, C, P> {
+    fn set_scope(&mut self, scope: Scope) {
+        self.scope = Some(scope);
+    }
+}
+
+pub type Predicate<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+// only yields the items of the inner listener that match `predicate`
+pub struct FilteredListener<L, F> {
+    inner: L,
+    predicate: F,
+}
+
+impl<L, F> FilteredListener<L, F> {
+    pub fn new(inner: L, predicate: F) -> Self {
+        Self { inner, predicate }
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<L, F> Listener for FilteredListener<L, F>
+where
+    L: Listener + Send,
+    L::Item: Send,
+    F: Fn(&L::Item) -> bool + Send,
+{
+    type Error = L::Error;
+    type Item = L::Item;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        loop {
+            let item = self.inner.recv().await?;
+            if (self.predicate)(&item) {
+                return Ok(item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection};
+    use crate::{BroadcastService, Event, Listener, Service, Syncable};
+
+    #[test]
+    fn listener_only_receives_its_collections() {
+        let doggo = doggo(1);
+
+        let service = BroadcastService::new();
+        let mut dogs = service.listener_for_collections([Collection::Dogs]);
+        let mut cats = service.listener_for_collections([Collection::Cats]);
+
+        service
+            .publish(Event::new_delete_event(2, Collection::Cats))
+            .unwrap();
+        service.publish(doggo.to_upsert_event()).unwrap();
+
+        block_on(async {
+            assert!(dogs.recv().await.unwrap().collection() == Some(&Collection::Dogs));
+            assert!(cats.recv().await.unwrap().collection() == Some(&Collection::Cats));
+        });
+    }
+}
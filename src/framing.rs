@@ -0,0 +1,350 @@
+// the protocol over byte streams that, unlike websockets, don't keep message
+// boundaries: raw tcp, unix sockets, quic streams. every message is a frame of
+// a 4 byte big endian length, then a byte naming the codec of the rest, then
+// the encoded body. the length counts the codec byte and the body. with the
+// `tokio` feature `FrameCodec` is a tokio-util `Encoder`/`Decoder`, so it can
+// go straight into a `Framed`
+use std::io::{self, Read, Write};
+
+use bytes::{Buf, BufMut, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "tokio")]
+use tokio_util::codec::{Decoder, Encoder};
+
+#[cfg(feature = "cbor")]
+use crate::codec::CborCodec;
+#[cfg(feature = "msgpack")]
+use crate::codec::MessagePackCodec;
+use crate::codec::{JsonCodec, WireCodec};
+use crate::{flush, write_all, Error};
+
+// frames longer than this are refused unless the codec is told otherwise,
+// so a corrupt length can't make the reader allocate gigabytes
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const HEADER_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecByte {
+    Json = 0,
+    MessagePack = 1,
+    Cbor = 2,
+}
+
+impl CodecByte {
+    pub fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(CodecByte::Json),
+            1 => Ok(CodecByte::MessagePack),
+            2 => Ok(CodecByte::Cbor),
+            _ => Err(Error::InvalidFrame(format!("unknown codec byte {byte}"))),
+        }
+    }
+
+    // the byte of a `WireCodec`, by its name
+    pub fn of(codec: &impl WireCodec) -> Result<Self, Error> {
+        match codec.name() {
+            "json" => Ok(CodecByte::Json),
+            "msgpack" => Ok(CodecByte::MessagePack),
+            "cbor" => Ok(CodecByte::Cbor),
+            name => Err(Error::UnsupportedCodec(vec![name.to_owned()])),
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        self as u8
+    }
+
+    // the name of its `WireCodec`
+    pub fn name(self) -> &'static str {
+        match self {
+            CodecByte::Json => "json",
+            CodecByte::MessagePack => "msgpack",
+            CodecByte::Cbor => "cbor",
+        }
+    }
+}
+
+// one message, still encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    codec: CodecByte,
+    body: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(codec: CodecByte, body: Vec<u8>) -> Self {
+        Self { codec, body }
+    }
+
+    pub fn encode<T: Serialize>(value: &T, codec: &impl WireCodec) -> Result<Self, Error> {
+        Ok(Self::new(CodecByte::of(codec)?, codec.encode(value)?))
+    }
+
+    // with whichever codec the frame names
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        match self.codec {
+            CodecByte::Json => JsonCodec.decode(&self.body),
+            #[cfg(feature = "msgpack")]
+            CodecByte::MessagePack => MessagePackCodec.decode(&self.body),
+            #[cfg(feature = "cbor")]
+            CodecByte::Cbor => CborCodec.decode(&self.body),
+            // a codec this build was compiled without
+            #[allow(unreachable_patterns)]
+            codec => Err(Error::UnsupportedCodec(vec![codec.name().to_owned()])),
+        }
+    }
+
+    pub fn codec(&self) -> CodecByte {
+        self.codec
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+// splits a byte stream into frames and back
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCodec {
+    max_frame_len: usize,
+}
+
+impl FrameCodec {
+    pub fn new() -> Self {
+        Self {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+
+    // the most bytes a frame's codec byte and body may add up to
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    pub fn encode_frame(&self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        let len = self.check_len(frame.body.len() + 1)?;
+        dst.reserve(HEADER_LEN + len as usize);
+        dst.put_u32(len);
+        dst.put_u8(frame.codec.byte());
+        dst.put_slice(&frame.body);
+        Ok(())
+    }
+
+    // takes the first frame off `src`, or `None` until all of it has arrived
+    pub fn decode_frame(&self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        let Some(header) = src.first_chunk::<HEADER_LEN>() else {
+            return Ok(None);
+        };
+        let len = self.check_len(u32::from_be_bytes(*header) as usize)? as usize;
+        if src.len() < HEADER_LEN + len {
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+        src.advance(HEADER_LEN);
+        let mut frame = src.split_to(len);
+        let codec = CodecByte::from_byte(frame.get_u8())?;
+        Ok(Some(Frame::new(codec, frame.to_vec())))
+    }
+
+    pub fn write_frame(&self, frame: &Frame, mut writer: impl Write) -> Result<(), Error> {
+        let mut bytes = BytesMut::new();
+        self.encode_frame(frame, &mut bytes)?;
+        Ok(writer.write_all(&bytes)?)
+    }
+
+    pub async fn write_frame_async<W>(&self, frame: &Frame, writer: &mut W) -> Result<(), Error>
+    where
+        W: futures_io::AsyncWrite + Unpin + ?Sized,
+    {
+        let mut bytes = BytesMut::new();
+        self.encode_frame(frame, &mut bytes)?;
+        write_all(writer, &bytes).await?;
+        flush(writer).await
+    }
+
+    // blocks until a whole frame is read. `None` if the stream ended cleanly
+    // between frames
+    pub fn read_frame(&self, mut reader: impl Read) -> Result<Option<Frame>, Error> {
+        let mut header = [0; HEADER_LEN];
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            read => read?,
+        }
+        let len = self.check_len(u32::from_be_bytes(header) as usize)? as usize;
+        let mut frame = vec![0; len];
+        reader.read_exact(&mut frame)?;
+        let codec = CodecByte::from_byte(frame.remove(0))?;
+        Ok(Some(Frame::new(codec, frame)))
+    }
+
+    fn check_len(&self, len: usize) -> Result<u32, Error> {
+        match len {
+            0 => Err(Error::InvalidFrame("frame without a codec byte".to_owned())),
+            len if len > self.max_frame_len => Err(Error::InvalidFrame(format!(
+                "frame of {len} bytes is over the limit of {}",
+                self.max_frame_len
+            ))),
+            len => u32::try_from(len)
+                .map_err(|_| Error::InvalidFrame(format!("frame of {len} bytes is too long"))),
+        }
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Encoder<&Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        self.encode_frame(frame, dst)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Encoder<Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        self.encode_frame(&frame, dst)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        self.decode_frame(src)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use bytes::BytesMut;
+
+    use crate::codec::JsonCodec;
+    use crate::framing::{Frame, FrameCodec};
+    use crate::test::Collection;
+    use crate::{Error, Event, WsBody};
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    fn frame(id: u32) -> Frame {
+        let event = DeleteEvent::new_delete_event(id, Collection::Dogs).into_ws_body();
+        Frame::encode(&event, &JsonCodec).unwrap()
+    }
+
+    fn id(frame: &Frame) -> Option<u32> {
+        let body: WsBody<DeleteEvent> = frame.decode().unwrap();
+        body.into_data().id().copied()
+    }
+
+    #[test]
+    fn frames_survive_a_byte_stream() {
+        let codec = FrameCodec::new();
+        let mut stream = BytesMut::new();
+        for frame in [frame(1), frame(2)] {
+            codec.encode_frame(&frame, &mut stream).unwrap();
+        }
+        assert_eq!(&stream[..5], &[0, 0, 0, 102, 0]);
+
+        // arriving a few bytes at a time
+        let mut received = BytesMut::new();
+        let mut frames = Vec::new();
+        for chunk in stream.chunks(7) {
+            received.extend_from_slice(chunk);
+            while let Some(frame) = codec.decode_frame(&mut received).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert!(received.is_empty());
+        let ids: Vec<_> = frames.iter().map(id).collect();
+        assert_eq!(ids, [Some(1), Some(2)]);
+
+        let mut reader = Cursor::new(stream.to_vec());
+        assert_eq!(
+            codec.read_frame(&mut reader).unwrap().as_ref(),
+            frames.first()
+        );
+        assert_eq!(
+            codec.read_frame(&mut reader).unwrap().as_ref(),
+            frames.get(1)
+        );
+        assert_eq!(codec.read_frame(&mut reader).unwrap(), None);
+
+        let small = FrameCodec::new().with_max_frame_len(8);
+        let mut oversized = BytesMut::from(&[0, 0, 0, 9][..]);
+        assert!(matches!(
+            small.decode_frame(&mut oversized),
+            Err(Error::InvalidFrame(_))
+        ));
+        assert!(small
+            .encode_frame(&frames[0], &mut BytesMut::new())
+            .is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn frames_name_their_codec() {
+        use crate::codec::CborCodec;
+        use crate::framing::CodecByte;
+
+        let event = DeleteEvent::new_delete_event(1, Collection::Dogs).into_ws_body();
+        let codec = FrameCodec::new();
+        let mut stream = BytesMut::new();
+        codec.encode_frame(&frame(1), &mut stream).unwrap();
+        let cbor = Frame::encode(&event, &CborCodec).unwrap();
+        codec.encode_frame(&cbor, &mut stream).unwrap();
+
+        let mut frames = Vec::new();
+        while let Some(frame) = codec.decode_frame(&mut stream).unwrap() {
+            frames.push(frame);
+        }
+        let codecs: Vec<_> = frames.iter().map(Frame::codec).collect();
+        assert_eq!(codecs, [CodecByte::Json, CodecByte::Cbor]);
+        let ids: Vec<_> = frames.iter().map(id).collect();
+        assert_eq!(ids, [Some(1), Some(1)]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn frames_go_through_tokio_util_framed() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_util::codec::{FramedRead, FramedWrite};
+
+        use crate::test::block_on;
+
+        let frames = block_on(async {
+            let mut writer = FramedWrite::new(Vec::new(), FrameCodec::new());
+            writer.send(frame(1)).await.unwrap();
+            writer.send(&frame(2)).await.unwrap();
+            let stream = writer.into_inner();
+            FramedRead::new(&stream[..], FrameCodec::new())
+                .collect::<Vec<_>>()
+                .await
+        });
+        let ids: Vec<_> = frames
+            .iter()
+            .map(|frame| id(frame.as_ref().unwrap()))
+            .collect();
+        assert_eq!(ids, [Some(1), Some(2)]);
+    }
+}
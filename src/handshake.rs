@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::Error;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// sent by the client when it connects, listing what it can speak in order of
+// preference
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Hello {
+    protocol_versions: Vec<u32>,
+    codecs: Vec<String>,
+}
+
+// the server's pick out of a `Hello`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct HelloAck {
+    protocol_version: u32,
+    codec: String,
+}
+
+impl Hello {
+    pub fn new(protocol_versions: Vec<u32>, codecs: Vec<String>) -> Self {
+        Self {
+            protocol_versions,
+            codecs,
+        }
+    }
+
+    pub fn protocol_versions(&self) -> &[u32] {
+        &self.protocol_versions
+    }
+
+    pub fn codecs(&self) -> &[String] {
+        &self.codecs
+    }
+
+    // picks the newest version both sides speak and the client's most
+    // preferred codec the server supports
+    pub fn negotiate(&self, versions: &[u32], codecs: &[&str]) -> Result<HelloAck, Error> {
+        let protocol_version = self
+            .protocol_versions
+            .iter()
+            .filter(|version| versions.contains(version))
+            .max()
+            .copied()
+            .ok_or_else(|| Error::UnsupportedVersion {
+                offered: self.protocol_versions.clone(),
+                supported: versions.to_vec(),
+            })?;
+        let codec = self
+            .codecs
+            .iter()
+            .find(|codec| codecs.contains(&codec.as_str()))
+            .cloned()
+            .ok_or_else(|| Error::UnsupportedCodec(self.codecs.clone()))?;
+        Ok(HelloAck {
+            protocol_version,
+            codec,
+        })
+    }
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self::new(vec![PROTOCOL_VERSION], vec!["json".to_owned()])
+    }
+}
+
+impl HelloAck {
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
+
+    pub fn codec(&self) -> &str {
+        &self.codec
+    }
+
+    // for the client, to reject a server that picked something it never offered
+    pub fn verify(&self, hello: &Hello) -> Result<(), Error> {
+        if !hello.protocol_versions.contains(&self.protocol_version) {
+            return Err(Error::UnsupportedVersion {
+                offered: hello.protocol_versions.clone(),
+                supported: vec![self.protocol_version],
+            });
+        }
+        if !hello.codecs.contains(&self.codec) {
+            return Err(Error::UnsupportedCodec(hello.codecs.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::codec::{JsonCodec, WireCodec};
+    use crate::{Error, Hello, WsBody};
+
+    #[test]
+    fn negotiates_version_and_codec() {
+        let hello = Hello::new(vec![1, 2], vec!["cbor".to_owned(), "json".to_owned()]);
+        let ack = hello.negotiate(&[1], &[JsonCodec.name(), "cbor"]).unwrap();
+        assert_eq!((ack.protocol_version(), ack.codec()), (1, "cbor"));
+        assert!(ack.verify(&hello).is_ok());
+
+        assert!(matches!(
+            hello.negotiate(&[3], &["json"]),
+            Err(Error::UnsupportedVersion { .. })
+        ));
+
+        let body = WsBody::from(ack).with_protocol_version(1);
+        let json = body.try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"protocol_version":1,"data":{"protocol_version":1,"codec":"cbor"}}"###);
+        assert!(matches!(
+            body.check_protocol_version(2),
+            Err(Error::VersionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Clock, Error, Listener, SystemClock, WsBody};
+
+// either side may ping, the other answers with a pong echoing `sent_at`.
+// times are milliseconds since the unix epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Ping {
+    #[ts(type = "number")]
+    pub sent_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Pong {
+    #[ts(type = "number")]
+    pub ping_sent_at: u64,
+    #[ts(type = "number")]
+    pub sent_at: u64,
+}
+
+impl Ping {
+    pub fn pong(&self, clock: &impl Clock) -> Pong {
+        Pong {
+            ping_sent_at: self.sent_at,
+            sent_at: clock.now(),
+        }
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+impl Pong {
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+// keeps one connection alive: `tick` it every so often (at least once per
+// `interval`) and send the pings it returns. a connection nothing was heard
+// from for `timeout` is stale, which fails `tick` and every listener made with
+// `listener` with `Error::ConnectionTimedOut`
+pub struct Heartbeat<K = SystemClock> {
+    interval: u64,
+    timeout: u64,
+    clock: K,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    last_seen: u64,
+    last_ping: Option<u64>,
+    rtt: Option<u64>,
+    // how long the connection had been silent when it timed out
+    timed_out: Option<u64>,
+    wakers: Vec<Waker>,
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Heartbeat {
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self::with_clock(interval, timeout, SystemClock)
+    }
+}
+
+impl<K: Clock> Heartbeat<K> {
+    pub fn with_clock(interval: Duration, timeout: Duration, clock: K) -> Self {
+        let state = State {
+            last_seen: clock.now(),
+            last_ping: None,
+            rtt: None,
+            timed_out: None,
+            wakers: Vec::new(),
+        };
+        Self {
+            interval: interval.as_millis() as u64,
+            timeout: timeout.as_millis() as u64,
+            clock,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    // the ping to send, if one is due
+    pub fn tick(&self) -> Result<Option<Ping>, Error> {
+        let now = self.clock.now();
+        let mut state = lock(&self.state);
+        if let Some(idle) = state.timed_out {
+            return Err(Error::ConnectionTimedOut(idle));
+        }
+        let idle = now.saturating_sub(state.last_seen);
+        if idle >= self.timeout {
+            state.timed_out = Some(idle);
+            state.wakers.drain(..).for_each(Waker::wake);
+            return Err(Error::ConnectionTimedOut(idle));
+        }
+        if state
+            .last_ping
+            .is_some_and(|sent_at| now.saturating_sub(sent_at) < self.interval)
+        {
+            return Ok(None);
+        }
+        state.last_ping = Some(now);
+        Ok(Some(Ping { sent_at: now }))
+    }
+
+    // anything received from the peer shows it's alive, not just pongs
+    pub fn record_activity(&self) {
+        lock(&self.state).last_seen = self.clock.now();
+    }
+
+    pub fn handle_pong(&self, pong: &Pong) {
+        let now = self.clock.now();
+        let mut state = lock(&self.state);
+        state.last_seen = now;
+        state.rtt = Some(now.saturating_sub(pong.ping_sent_at));
+    }
+
+    // round trip time of the last answered ping, in milliseconds
+    pub fn rtt(&self) -> Option<u64> {
+        lock(&self.state).rtt
+    }
+
+    pub fn is_timed_out(&self) -> bool {
+        lock(&self.state).timed_out.is_some()
+    }
+
+    pub fn listener<L>(&self, inner: L) -> HeartbeatListener<L> {
+        HeartbeatListener {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+// fails with `Error::ConnectionTimedOut` as soon as its heartbeat finds the
+// connection stale, even while waiting for the inner listener
+pub struct HeartbeatListener<L> {
+    inner: L,
+    state: Arc<Mutex<State>>,
+}
+
+impl<L> HeartbeatListener<L> {
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for HeartbeatListener<L>
+where
+    L: Listener<Error = Error> + Send,
+    L::Item: Send,
+{
+    type Error = Error;
+    type Item = L::Item;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        let state = &self.state;
+        let mut inner = self.inner.recv();
+        poll_fn(|cx| {
+            let mut state = lock(state);
+            if let Some(idle) = state.timed_out {
+                return Poll::Ready(Err(Error::ConnectionTimedOut(idle)));
+            }
+            if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            drop(state);
+            inner.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::test::{block_on, Collection};
+    use crate::{BroadcastService, Error, Event, Heartbeat, Listener, Ping, Service, WsBody};
+
+    #[test]
+    fn silent_connections_time_out() {
+        let now = Arc::new(AtomicU64::new(0));
+        let clock = {
+            let now = now.clone();
+            move || now.load(Ordering::Relaxed)
+        };
+        let heartbeat =
+            Heartbeat::with_clock(Duration::from_millis(10), Duration::from_millis(30), clock);
+        let service = BroadcastService::<Event<u32, (), Collection>>::new();
+        let mut listener = heartbeat.listener(service.listener());
+
+        let ping = heartbeat.tick().unwrap().unwrap();
+        now.store(5, Ordering::Relaxed);
+        assert_eq!(heartbeat.tick().unwrap(), None);
+
+        let json = ping.into_ws_body().try_json().unwrap();
+        let ping = WsBody::<Ping>::from_json(&json).unwrap().into_data();
+        heartbeat.handle_pong(&ping.pong(&|| 4));
+        assert_eq!(heartbeat.rtt(), Some(5));
+
+        now.store(20, Ordering::Relaxed);
+        assert!(heartbeat.tick().unwrap().is_some());
+        now.store(35, Ordering::Relaxed);
+        assert!(matches!(
+            heartbeat.tick(),
+            Err(Error::ConnectionTimedOut(30))
+        ));
+        block_on(async {
+            assert!(matches!(
+                listener.recv().await,
+                Err(Error::ConnectionTimedOut(30))
+            ));
+        });
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Event, EventVerb, ResourceIdentifier, Routable, Service, Shutdown};
+
+// hands out ids for records inserted without one
+pub trait IdAssigner<ID>: Send + Sync {
+    fn next_id(&self) -> ID;
+}
+
+impl<ID, F: Fn() -> ID + Send + Sync> IdAssigner<ID> for F {
+    fn next_id(&self) -> ID {
+        self()
+    }
+}
+
+// counts up from `start`. only unique within the process, so a restarted
+// server has to start after the largest id it handed out
+pub struct SequentialIds {
+    next: AtomicU64,
+}
+
+impl SequentialIds {
+    pub fn new(start: u64) -> Self {
+        Self {
+            next: AtomicU64::new(start),
+        }
+    }
+}
+
+impl IdAssigner<u64> for SequentialIds {
+    fn next_id(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl IdAssigner<ResourceIdentifier> for SequentialIds {
+    fn next_id(&self) -> ResourceIdentifier {
+        ResourceIdentifier::U64(self.next_id())
+    }
+}
+
+// implemented by anything that can be given an id when it is published
+pub trait AssignId: Routable {
+    // gives an insert without an id the next one from `assigner`, returning it
+    fn assign_id(&mut self, assigner: &impl IdAssigner<Self::Id>) -> Option<&Self::Id>;
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> AssignId for Event<ID, T, C, P> {
+    fn assign_id(&mut self, assigner: &impl IdAssigner<ID>) -> Option<&ID> {
+        let EventVerb::Insert(resource) = &mut self.verb else {
+            return None;
+        };
+        if resource.location.id.is_some() {
+            return None;
+        }
+        Some(resource.location.id.insert(assigner.next_id()))
+    }
+}
+
+// gives every insert published through it an id before it reaches `inner`,
+// so subscribers (including the client that created the record) learn its
+// canonical id from the echoed insert
+pub struct IdAssigningService<S, A> {
+    inner: S,
+    assigner: A,
+}
+
+impl<S, A> IdAssigningService<S, A> {
+    pub fn new(inner: S, assigner: A) -> Self {
+        Self { inner, assigner }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // like `publish`, returning the id the event was given, e.g. for a
+    // `MutationResult`
+    pub fn publish_assigned<T>(&self, mut event: T) -> Result<Option<T::Id>, S::Error>
+    where
+        S: Service<T>,
+        T: AssignId,
+        T::Id: Clone,
+        A: IdAssigner<T::Id>,
+    {
+        let id = event.assign_id(&self.assigner).cloned();
+        self.inner.publish(event)?;
+        Ok(id)
+    }
+}
+
+impl<T, S, A> Service<T> for IdAssigningService<S, A>
+where
+    T: AssignId,
+    S: Service<T>,
+    A: IdAssigner<T::Id>,
+{
+    type Listener = S::Listener;
+    type Error = S::Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        event.assign_id(&self.assigner);
+        self.inner.publish(event)
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.inner.listener()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        self.inner.listener_from(seq)
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection};
+    use crate::{BroadcastService, Event, IdAssigningService, Listener, SequentialIds, Service};
+
+    #[test]
+    fn inserts_get_ids_when_published() {
+        let doggo = doggo(0);
+        let insert = || Event::<u64, _, _>::new_insert_event(doggo.clone(), Collection::Dogs);
+
+        let service = IdAssigningService::new(BroadcastService::new(), SequentialIds::new(100));
+        let mut listener = service.listener();
+        assert_eq!(service.publish_assigned(insert()).unwrap(), Some(100));
+        service.publish(insert()).unwrap();
+        let delete = Event::new_delete_event(100, Collection::Dogs);
+        assert_eq!(service.publish_assigned(delete).unwrap(), None);
+
+        block_on(async {
+            assert_eq!(listener.recv().await.unwrap().id(), Some(&100));
+            assert_eq!(listener.recv().await.unwrap().id(), Some(&101));
+            assert_eq!(listener.recv().await.unwrap().id(), Some(&100));
+        });
+    }
+}
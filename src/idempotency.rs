@@ -0,0 +1,179 @@
+use std::collections::{HashSet, VecDeque};
+use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Clock, Event, Service, Shutdown, SystemClock};
+
+// an event that can carry the key it's published under
+pub trait Idempotent {
+    fn idempotency_key(&self) -> Option<&str>;
+    fn set_idempotency_key(&mut self, key: String);
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Idempotent for Event<ID, T, C, P> {
+    fn idempotency_key(&self) -> Option<&str> {
+        Event::idempotency_key(self)
+    }
+
+    fn set_idempotency_key(&mut self, key: String) {
+        self.idempotency_key = Some(key);
+    }
+}
+
+// drops an event whose idempotency key was published within the window, so
+// a producer retrying after a timeout (or an outbox relay after a crash)
+// doesn't put the event on the stream twice. the retry succeeds, as the
+// event is out there. events without a key always go through. keys are only
+// remembered once `inner` took the event, so a failed publish can be retried
+pub struct DedupService<S, T> {
+    inner: S,
+    window: u64,
+    clock: Box<dyn Clock + Send + Sync>,
+    seen: Mutex<Seen>,
+    marker: PhantomData<fn(T)>,
+}
+
+#[derive(Default)]
+struct Seen {
+    keys: HashSet<String>,
+    // when each key was published, oldest first
+    order: VecDeque<(u64, String)>,
+}
+
+impl Seen {
+    fn expire(&mut self, before: u64) {
+        while let Some((at, _)) = self.order.front() {
+            if *at >= before {
+                break;
+            }
+            if let Some((_, key)) = self.order.pop_front() {
+                self.keys.remove(&key);
+            }
+        }
+    }
+}
+
+impl<S, T> DedupService<S, T> {
+    pub fn new(inner: S, window: Duration) -> Self {
+        Self {
+            inner,
+            window: window.as_millis() as u64,
+            clock: Box::new(SystemClock),
+            seen: Mutex::new(Seen::default()),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // keys still in the window
+    pub fn remembered(&self) -> usize {
+        let mut seen = self.lock();
+        seen.expire(self.clock.now().saturating_sub(self.window));
+        seen.keys.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Seen> {
+        self.seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T, S> Service<T> for DedupService<S, T>
+where
+    T: Idempotent,
+    S: Service<T>,
+{
+    type Listener = S::Listener;
+    type Error = S::Error;
+
+    fn publish(&self, event: T) -> Result<(), Self::Error> {
+        let Some(key) = event.idempotency_key().map(str::to_owned) else {
+            return self.inner.publish(event);
+        };
+        // held across the publish, so two retries racing each other can't
+        // both get through
+        let mut seen = self.lock();
+        let now = self.clock.now();
+        seen.expire(now.saturating_sub(self.window));
+        if seen.keys.contains(&key) {
+            return Ok(());
+        }
+        self.inner.publish(event)?;
+        seen.keys.insert(key.clone());
+        seen.order.push_back((now, key));
+        Ok(())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.inner.listener()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        self.inner.listener_from(seq)
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::test::Collection;
+    use crate::{BroadcastService, Event, Service};
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    #[test]
+    fn retries_within_the_window_are_dropped() {
+        let now = Arc::new(AtomicU64::new(0));
+        let clock = now.clone();
+        let service = BroadcastService::<DeleteEvent>::new()
+            .with_dedup(Duration::from_secs(60))
+            .with_clock(move || clock.load(Ordering::SeqCst));
+        let delete = |id| Event::new_delete_event(id, Collection::Dogs);
+
+        service.publish_idempotent("a", delete(1)).unwrap();
+        service.publish_idempotent("a", delete(1)).unwrap();
+        service.publish_idempotent("b", delete(2)).unwrap();
+        service.publish(delete(3)).unwrap();
+        service.publish(delete(3)).unwrap();
+        assert_eq!(service.head_seq(), 4);
+        assert_eq!(service.remembered(), 2);
+
+        now.store(30_000, Ordering::SeqCst);
+        service.publish_idempotent("b", delete(2)).unwrap();
+        assert_eq!(service.head_seq(), 4);
+
+        // "a" has left the window, so it's a new event again
+        now.store(61_000, Ordering::SeqCst);
+        assert_eq!(service.remembered(), 0);
+        service.publish_idempotent("a", delete(1)).unwrap();
+        assert_eq!(service.head_seq(), 5);
+    }
+}
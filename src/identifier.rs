@@ -0,0 +1,228 @@
+use std::fmt;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ts_rs::{Dependency, TS};
+
+use crate::ResourceId;
+
+// an id that can be numeric, a uuid or any string, for collections that don't
+// share one id type. numbers serialize as json numbers and everything else as
+// a string, so typescript sees `number | string`. with the `uuid` feature,
+// strings in the canonical uuid format deserialize as `Uuid`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceIdentifier {
+    U64(u64),
+    #[cfg(feature = "uuid")]
+    Uuid(u128),
+    String(String),
+}
+
+impl ResourceIdentifier {
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::U64(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    pub fn as_uuid(&self) -> Option<u128> {
+        match self {
+            Self::Uuid(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    // `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`, in either case
+    #[cfg(feature = "uuid")]
+    pub fn parse_uuid(text: &str) -> Option<Self> {
+        let hyphens = [8, 13, 18, 23];
+        let valid = text.len() == 36
+            && text
+                .char_indices()
+                .all(|(at, c)| match hyphens.contains(&at) {
+                    true => c == '-',
+                    false => c.is_ascii_hexdigit(),
+                });
+        let digits: String = text.chars().filter(|c| *c != '-').collect();
+        valid
+            .then(|| u128::from_str_radix(&digits, 16).ok())
+            .flatten()
+            .map(Self::Uuid)
+    }
+}
+
+impl fmt::Display for ResourceIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::U64(id) => write!(f, "{id}"),
+            #[cfg(feature = "uuid")]
+            Self::Uuid(id) => {
+                let hex = format!("{id:032x}");
+                let groups = [
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..],
+                ];
+                f.write_str(&groups.join("-"))
+            }
+            Self::String(id) => f.write_str(id),
+        }
+    }
+}
+
+impl From<u64> for ResourceIdentifier {
+    fn from(id: u64) -> Self {
+        Self::U64(id)
+    }
+}
+
+impl From<u32> for ResourceIdentifier {
+    fn from(id: u32) -> Self {
+        Self::U64(id.into())
+    }
+}
+
+impl From<String> for ResourceIdentifier {
+    fn from(id: String) -> Self {
+        Self::String(id)
+    }
+}
+
+impl From<&str> for ResourceIdentifier {
+    fn from(id: &str) -> Self {
+        Self::String(id.to_owned())
+    }
+}
+
+impl From<ResourceId> for ResourceIdentifier {
+    fn from(id: ResourceId) -> Self {
+        Self::U64(id.0.into())
+    }
+}
+
+impl Serialize for ResourceIdentifier {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::U64(id) => serializer.serialize_u64(*id),
+            Self::String(id) => serializer.serialize_str(id),
+            #[cfg(feature = "uuid")]
+            Self::Uuid(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ResourceIdentifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IdVisitor;
+
+        impl Visitor<'_> for IdVisitor {
+            type Value = ResourceIdentifier;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a non-negative integer or a string")
+            }
+
+            fn visit_u64<E: de::Error>(self, id: u64) -> Result<Self::Value, E> {
+                Ok(ResourceIdentifier::U64(id))
+            }
+
+            fn visit_i64<E: de::Error>(self, id: i64) -> Result<Self::Value, E> {
+                u64::try_from(id)
+                    .map(ResourceIdentifier::U64)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(id), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, id: &str) -> Result<Self::Value, E> {
+                #[cfg(feature = "uuid")]
+                if let Some(uuid) = ResourceIdentifier::parse_uuid(id) {
+                    return Ok(uuid);
+                }
+                Ok(ResourceIdentifier::String(id.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_any(IdVisitor)
+    }
+}
+
+impl TS for ResourceIdentifier {
+    const EXPORT_TO: Option<&'static str> = Some("bindings/ResourceIdentifier.ts");
+
+    fn decl() -> String {
+        format!("type {} = {};", Self::name(), Self::inline())
+    }
+
+    fn name() -> String {
+        "ResourceIdentifier".to_owned()
+    }
+
+    fn inline() -> String {
+        "number | string".to_owned()
+    }
+
+    fn dependencies() -> Vec<Dependency> {
+        Vec::new()
+    }
+
+    fn transparent() -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "json-schema")]
+impl schemars::JsonSchema for ResourceIdentifier {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "ResourceIdentifier".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [{ "type": "integer", "minimum": 0 }, { "type": "string" }]
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ts_rs::TS;
+
+    use crate::test::Collection;
+    use crate::{Event, ResourceIdentifier};
+
+    #[test]
+    fn ids_serialize_as_numbers_or_strings() {
+        ResourceIdentifier::export().unwrap();
+
+        let ids: Vec<ResourceIdentifier> = vec![7u64.into(), "barky".into()];
+        let json = serde_json::to_string(&ids).unwrap();
+        assert_eq!(json, r#"[7,"barky"]"#);
+        assert_eq!(
+            serde_json::from_str::<Vec<ResourceIdentifier>>(&json).unwrap(),
+            ids
+        );
+
+        let delete =
+            Event::<ResourceIdentifier, (), _>::new_delete_event("barky".into(), Collection::Dogs);
+        let json = serde_json::to_string(&delete).unwrap();
+        insta::assert_snapshot!(json, @r###"{"verb":{"type":"delete","payload":{"location":{"id":"barky","txn_id":null,"collection":"Dogs"}}}}"###);
+
+        #[cfg(feature = "uuid")]
+        {
+            let text = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+            let uuid: ResourceIdentifier = serde_json::from_value(text.into()).unwrap();
+            assert_eq!(uuid.as_uuid(), Some(0x67e5504410b1426f9247bb680e5fe0c8));
+            assert_eq!(uuid.to_string(), text);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Appendable, Error, Event, Syncable};
+
+// a single RFC 6902 operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "op", rename_all = "lowercase")]
+#[ts(export)]
+pub enum PatchOperation {
+    Add {
+        path: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    Test {
+        path: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+}
+
+// an RFC 6902 JSON Patch document, usable as the payload of a `Patch` verb
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct JsonPatch(Vec<PatchOperation>);
+
+impl JsonPatch {
+    pub fn new(operations: Vec<PatchOperation>) -> Self {
+        Self(operations)
+    }
+
+    // the operations that turn `old` into `new`
+    pub fn diff(old: &impl Serialize, new: &impl Serialize) -> Result<Self, Error> {
+        let old = serde_json::to_value(old).map_err(Error::Encode)?;
+        let new = serde_json::to_value(new).map_err(Error::Encode)?;
+        let mut operations = Vec::new();
+        diff_values(&mut String::new(), &old, &new, &mut operations);
+        Ok(Self(operations))
+    }
+
+    pub fn operations(&self) -> &[PatchOperation] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn apply(&self, target: &mut Value) -> Result<(), Error> {
+        // operations are applied to a copy so a failing patch leaves `target` untouched
+        let mut patched = target.clone();
+        for operation in &self.0 {
+            apply_operation(&mut patched, operation)?;
+        }
+        *target = patched;
+        Ok(())
+    }
+
+    pub fn apply_to<T: Serialize + DeserializeOwned>(&self, record: &T) -> Result<T, Error> {
+        let mut value = serde_json::to_value(record).map_err(Error::Encode)?;
+        self.apply(&mut value)?;
+        serde_json::from_value(value).map_err(Error::Decode)
+    }
+}
+
+impl From<Vec<PatchOperation>> for JsonPatch {
+    fn from(operations: Vec<PatchOperation>) -> Self {
+        Self(operations)
+    }
+}
+
+// which event `diff_to_event` makes of a record that changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffPolicy {
+    // the whole new record, for consumers that can't apply patches
+    Update,
+    // only the operations, however many there are
+    Patch,
+    // whichever of the two encodes to less json
+    #[default]
+    Smallest,
+}
+
+type DiffEvent<R> = Event<<R as Syncable>::Id, R, <R as Appendable>::Collection, JsonPatch>;
+
+// the event taking a record from `old` to `new`, for producers that only
+// have the two snapshots. `None` if nothing changed
+pub fn diff_to_event<R>(old: &R, new: &R) -> Result<Option<DiffEvent<R>>, Error>
+where
+    R: Syncable + Clone,
+{
+    diff_to_event_with(old, new, DiffPolicy::default())
+}
+
+pub fn diff_to_event_with<R>(
+    old: &R,
+    new: &R,
+    policy: DiffPolicy,
+) -> Result<Option<DiffEvent<R>>, Error>
+where
+    R: Syncable + Clone,
+{
+    let patch = JsonPatch::diff(old, new)?;
+    if patch.is_empty() {
+        return Ok(None);
+    }
+    let patch_smaller = || -> Result<bool, Error> {
+        let patch_len = serde_json::to_vec(&patch).map_err(Error::Encode)?.len();
+        let record_len = serde_json::to_vec(new).map_err(Error::Encode)?.len();
+        Ok(patch_len < record_len)
+    };
+    let as_patch = match policy {
+        DiffPolicy::Update => false,
+        DiffPolicy::Patch => true,
+        DiffPolicy::Smallest => patch_smaller()?,
+    };
+    Ok(Some(match as_patch {
+        true => Event::new_patch_event(new.id(), patch, new.collection()),
+        false => new.clone().to_update_event().into_patchable(),
+    }))
+}
+
+fn diff_values(path: &mut String, old: &Value, new: &Value, operations: &mut Vec<PatchOperation>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let len = path.len();
+                push_token(path, key);
+                match new.get(key) {
+                    Some(new_value) => diff_values(path, old_value, new_value, operations),
+                    None => operations.push(PatchOperation::Remove { path: path.clone() }),
+                }
+                path.truncate(len);
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    let len = path.len();
+                    push_token(path, key);
+                    operations.push(PatchOperation::Add {
+                        path: path.clone(),
+                        value: new_value.clone(),
+                    });
+                    path.truncate(len);
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items))
+            if old_items.len() == new_items.len() =>
+        {
+            for (idx, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+                let len = path.len();
+                push_token(path, &idx.to_string());
+                diff_values(path, old_item, new_item, operations);
+                path.truncate(len);
+            }
+        }
+        (old, new) if old != new => operations.push(PatchOperation::Replace {
+            path: path.clone(),
+            value: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn push_token(path: &mut String, token: &str) {
+    path.push('/');
+    path.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+fn parse_pointer(path: &str) -> Result<Vec<String>, Error> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = path.strip_prefix('/') else {
+        return Err(Error::Patch(format!("invalid JSON pointer `{path}`")));
+    };
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn array_index(token: &str, len: usize, path: &str) -> Result<usize, Error> {
+    // RFC 6901 indexes are plain decimals: no sign, no leading zeros
+    let digits = !token.is_empty() && token.bytes().all(|byte| byte.is_ascii_digit());
+    if !digits || (token.starts_with('0') && token != "0") {
+        return Err(Error::Patch(format!("`{path}` has an invalid array index")));
+    }
+    match token.parse::<usize>() {
+        Ok(idx) if idx < len => Ok(idx),
+        _ => Err(Error::Patch(format!("`{path}` is out of bounds"))),
+    }
+}
+
+fn parent<'a>(target: &'a mut Value, path: &str) -> Result<(&'a mut Value, Option<String>), Error> {
+    let mut tokens = parse_pointer(path)?;
+    let last = tokens.pop();
+    let mut current = target;
+    for token in &tokens {
+        current = match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => {
+                let idx = array_index(token, items.len(), path)?;
+                items.get_mut(idx)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| Error::Patch(format!("`{path}` does not exist")))?;
+    }
+    Ok((current, last))
+}
+
+fn get(target: &Value, path: &str) -> Result<Value, Error> {
+    target
+        .pointer(path)
+        .cloned()
+        .ok_or_else(|| Error::Patch(format!("`{path}` does not exist")))
+}
+
+fn add(target: &mut Value, path: &str, value: Value) -> Result<(), Error> {
+    let (parent, last) = parent(target, path)?;
+    let Some(last) = last else {
+        *parent = value;
+        return Ok(());
+    };
+    match parent {
+        Value::Object(map) => {
+            map.insert(last, value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => {
+            let idx = array_index(&last, items.len() + 1, path)?;
+            items.insert(idx, value);
+        }
+        _ => return Err(Error::Patch(format!("`{path}` has no parent container"))),
+    }
+    Ok(())
+}
+
+fn remove(target: &mut Value, path: &str) -> Result<Value, Error> {
+    let (parent, last) = parent(target, path)?;
+    let Some(last) = last else {
+        return Err(Error::Patch("cannot remove the document root".to_owned()));
+    };
+    match parent {
+        Value::Object(map) => map.remove(&last),
+        Value::Array(items) => {
+            let idx = array_index(&last, items.len(), path)?;
+            Some(items.remove(idx))
+        }
+        _ => None,
+    }
+    .ok_or_else(|| Error::Patch(format!("`{path}` does not exist")))
+}
+
+fn apply_operation(target: &mut Value, operation: &PatchOperation) -> Result<(), Error> {
+    match operation {
+        PatchOperation::Add { path, value } => add(target, path, value.clone()),
+        PatchOperation::Remove { path } => remove(target, path).map(drop),
+        PatchOperation::Replace { path, value } if path.is_empty() => {
+            *target = value.clone();
+            Ok(())
+        }
+        PatchOperation::Replace { path, value } => {
+            remove(target, path)?;
+            add(target, path, value.clone())
+        }
+        PatchOperation::Move { from, path } => {
+            let value = remove(target, from)?;
+            add(target, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = get(target, from)?;
+            add(target, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            if get(target, path)? == *value {
+                Ok(())
+            } else {
+                Err(Error::Patch(format!("test failed at `{path}`")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::test::{doggo, DoggoRecord};
+    use crate::{
+        diff_to_event, diff_to_event_with, DiffPolicy, Error, EventVerb, JsonPatch, Syncable,
+    };
+
+    // `document` after `operations`, or why they failed
+    fn patched(document: &Value, operations: Value) -> Result<Value, String> {
+        let patch: JsonPatch = serde_json::from_value(operations).unwrap();
+        let mut patched = document.clone();
+        match patch.apply(&mut patched) {
+            Ok(()) => Ok(patched),
+            Err(Error::Patch(reason)) => {
+                // a failed patch changes nothing
+                assert_eq!(&patched, document);
+                Err(reason)
+            }
+            Err(err) => panic!("unexpected error {err}"),
+        }
+    }
+
+    #[test]
+    fn operations_follow_rfc_6902() {
+        let document = json!({
+            "name": "Barky",
+            "tags": ["good", "fluffy"],
+            "a/b": 1,
+            "m~n": 2,
+        });
+        let ok = |operations| patched(&document, operations).unwrap();
+
+        let added = ok(json!([
+            { "op": "add", "path": "/breed", "value": "Poodle" },
+            { "op": "add", "path": "/tags/1", "value": "loud" },
+            { "op": "add", "path": "/tags/-", "value": "last" },
+        ]));
+        assert_eq!(added["breed"], "Poodle");
+        assert_eq!(added["tags"], json!(["good", "loud", "fluffy", "last"]));
+
+        let removed = ok(json!([
+            { "op": "remove", "path": "/name" },
+            { "op": "remove", "path": "/tags/0" },
+        ]));
+        assert_eq!(removed.get("name"), None);
+        assert_eq!(removed["tags"], json!(["fluffy"]));
+
+        let moved = ok(json!([
+            { "op": "move", "from": "/name", "path": "/nickname" },
+            { "op": "copy", "from": "/tags/1", "path": "/tags/0" },
+        ]));
+        assert_eq!(
+            (moved.get("name"), &moved["nickname"]),
+            (None, &json!("Barky"))
+        );
+        assert_eq!(moved["tags"], json!(["fluffy", "good", "fluffy"]));
+
+        // `~1` is `/` and `~0` is `~`
+        let escaped = ok(json!([
+            { "op": "test", "path": "/a~1b", "value": 1 },
+            { "op": "replace", "path": "/m~0n", "value": 3 },
+        ]));
+        assert_eq!(escaped["m~n"], 3);
+
+        let failed = |operations| patched(&document, operations).unwrap_err();
+        insta::assert_snapshot!(failed(json!([
+            { "op": "add", "path": "/breed", "value": "Poodle" },
+            { "op": "test", "path": "/name", "value": "Woofy" },
+        ])), @"test failed at `/name`");
+        insta::assert_snapshot!(failed(json!([
+            { "op": "remove", "path": "/name" },
+            { "op": "remove", "path": "/missing" },
+        ])), @"`/missing` does not exist");
+        insta::assert_snapshot!(failed(json!([
+            { "op": "add", "path": "/tags/3", "value": "far" },
+        ])), @"`/tags/3` is out of bounds");
+        for index in ["+1", "01", "-1", "1.0", ""] {
+            let path = format!("/tags/{index}");
+            assert_eq!(
+                failed(json!([{ "op": "remove", "path": path }])),
+                format!("`{path}` has an invalid array index")
+            );
+        }
+    }
+
+    #[test]
+    fn diff_round_trips() {
+        let old = doggo(1);
+        let new = DoggoRecord {
+            name: "Woofy".to_string(),
+            ..old.clone()
+        };
+
+        let patch = JsonPatch::diff(&old, &new).unwrap();
+        let patched = patch.apply_to(&old).unwrap();
+        assert_eq!(patched.name, "Woofy");
+
+        let json = new
+            .to_json_patch_event(&old)
+            .unwrap()
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"patch","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":[{"op":"replace","path":"/name","value":"Woofy"}]}}}}"###);
+    }
+
+    #[test]
+    fn diffs_become_the_smaller_event() {
+        let old = DoggoRecord {
+            breed: "Standard Poodle, apricot, from a long line of show dogs".to_string(),
+            ..doggo(1)
+        };
+        assert!(diff_to_event(&old, &old.clone()).unwrap().is_none());
+
+        let renamed = DoggoRecord {
+            name: "Woofy".to_string(),
+            ..old.clone()
+        };
+        let event = diff_to_event(&old, &renamed).unwrap().unwrap();
+        assert!(matches!(event.verb(), EventVerb::Patch(_)));
+        let event = diff_to_event_with(&old, &renamed, DiffPolicy::Update)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.data().unwrap().name, "Woofy");
+
+        // replacing every field takes more json than the record itself
+        let replaced = DoggoRecord {
+            name: "Woofy".to_string(),
+            breed: "Toy Poodle, white, the first of its line to live in town".to_string(),
+            ..doggo(1)
+        };
+        let event = diff_to_event(&old, &replaced).unwrap().unwrap();
+        assert!(matches!(event.verb(), EventVerb::Update(_)));
+        let event = diff_to_event_with(&old, &replaced, DiffPolicy::Patch)
+            .unwrap()
+            .unwrap();
+        assert!(
+            matches!(event.verb(), EventVerb::Patch(resource) if resource.data.operations().len() == 2)
+        );
+    }
+}
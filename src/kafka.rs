@@ -0,0 +1,260 @@
+use std::marker::PhantomData;
+use std::thread;
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    buffered, BufferedListener, BufferedSender, Error, Listener, OverflowPolicy, Routable,
+    Sequenced,
+};
+
+const LISTENER_CAPACITY: usize = 1024;
+// how long a poll waits, and so how often an idle consumer checks whether its
+// listener is gone
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// a record read back from a topic. compaction can leave records without a
+// payload (tombstones), which are skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaRecord {
+    pub offset: u64,
+    pub key: Option<Vec<u8>>,
+    pub payload: Option<Vec<u8>>,
+}
+
+// the two halves of a kafka client (e.g. an rdkafka producer and consumer
+// assigned to a single partition), left to the application so this crate
+// doesn't pick one
+pub trait KafkaProducer: Send + Sync {
+    // appends a record to `topic`, returning its offset
+    fn produce(&self, topic: &str, key: &[u8], payload: &[u8]) -> Result<u64, Error>;
+}
+
+pub trait KafkaConsumer: Send {
+    // the next `poll` returns the record at `offset` onwards
+    fn seek(&mut self, topic: &str, offset: u64) -> Result<(), Error>;
+
+    // the next record, or `None` if nothing arrived within `timeout`
+    fn poll(&mut self, timeout: Duration) -> Result<Option<KafkaRecord>, Error>;
+}
+
+// writes events to a topic as json, keyed by collection and id so a compacted
+// topic keeps the latest event of every record. deletes are written as
+// events too, so consumers see them; compaction drops them only once a
+// tombstone is produced by other means
+pub struct KafkaSink<T, P> {
+    producer: P,
+    topic: String,
+    marker: PhantomData<fn(T)>,
+}
+
+impl<T, P> KafkaSink<T, P>
+where
+    T: Routable + Serialize,
+    T::Collection: Serialize,
+    T::Id: Serialize,
+    P: KafkaProducer,
+{
+    pub fn new(producer: P, topic: impl Into<String>) -> Self {
+        Self {
+            producer,
+            topic: topic.into(),
+            marker: PhantomData,
+        }
+    }
+
+    // returns the offset, which a `KafkaListener` hands out as the seq
+    pub fn send(&self, event: &T) -> Result<u64, Error> {
+        let key = serde_json::to_vec(&(event.collection(), event.id())).map_err(Error::Encode)?;
+        let payload = serde_json::to_vec(event).map_err(Error::Encode)?;
+        self.producer.produce(&self.topic, &key, &payload)
+    }
+
+    // sends everything `listener` receives until it fails
+    pub async fn forward<L>(&self, listener: &mut L) -> Error
+    where
+        L: Listener<Item = T>,
+        L::Error: Into<Error>,
+    {
+        loop {
+            let result = match listener.recv().await {
+                Ok(event) => self.send(&event).map(drop),
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = result {
+                return err;
+            }
+        }
+    }
+}
+
+// reads events back from a topic. every event is stamped with its offset as
+// its seq, so a client resuming from seq `n` is served by a listener created
+// with `from_seq(.., n)`; on a compacted topic seqs just have gaps
+pub struct KafkaListener<T> {
+    inner: Option<BufferedListener<T>>,
+    failed: Option<Error>,
+}
+
+impl<T> KafkaListener<T>
+where
+    T: Routable + Sequenced + DeserializeOwned + Send + 'static,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    // starts wherever `consumer` is positioned
+    pub fn new(consumer: impl KafkaConsumer + 'static) -> Self {
+        Self::spawn(consumer)
+    }
+
+    pub fn from_seq(mut consumer: impl KafkaConsumer + 'static, topic: &str, seq: u64) -> Self {
+        match consumer.seek(topic, seq) {
+            Ok(()) => Self::spawn(consumer),
+            Err(err) => Self {
+                inner: None,
+                failed: Some(err),
+            },
+        }
+    }
+
+    fn spawn(consumer: impl KafkaConsumer + 'static) -> Self {
+        let (sender, listener) = buffered(LISTENER_CAPACITY, OverflowPolicy::CloseConnection);
+        thread::spawn(move || forward_records(consumer, sender));
+        Self {
+            inner: Some(listener),
+            failed: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Send> Listener for KafkaListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        if let Some(err) = self.failed.take() {
+            return Err(err);
+        }
+        self.inner.as_mut().ok_or(Error::Closed)?.recv().await
+    }
+}
+
+fn forward_records<T>(mut consumer: impl KafkaConsumer, sender: BufferedSender<T>)
+where
+    T: Routable + Sequenced + DeserializeOwned,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    loop {
+        let record = match consumer.poll(POLL_INTERVAL) {
+            Ok(Some(record)) => record,
+            Ok(None) if sender.is_closed() => return,
+            Ok(None) => continue,
+            Err(_) => return,
+        };
+        let Some(payload) = record.payload else {
+            continue;
+        };
+        let Ok(mut event) = serde_json::from_slice::<T>(&payload) else {
+            return;
+        };
+        event.set_seq(record.offset);
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::test::{block_on, Collection};
+    use crate::{
+        Error, Event, KafkaConsumer, KafkaListener, KafkaProducer, KafkaRecord, KafkaSink, Listener,
+    };
+
+    type DoggoEvent = Event<u32, (), Collection>;
+
+    #[derive(Clone, Default)]
+    struct Topic(Arc<Mutex<Vec<KafkaRecord>>>);
+
+    struct Consumer {
+        topic: Topic,
+        position: u64,
+    }
+
+    impl KafkaProducer for Topic {
+        fn produce(&self, topic: &str, key: &[u8], payload: &[u8]) -> Result<u64, Error> {
+            assert_eq!(topic, "doggos");
+            let mut records = self.0.lock().unwrap();
+            let offset = records.len() as u64;
+            records.push(KafkaRecord {
+                offset,
+                key: Some(key.to_vec()),
+                payload: Some(payload.to_vec()),
+            });
+            Ok(offset)
+        }
+    }
+
+    impl KafkaConsumer for Consumer {
+        fn seek(&mut self, _topic: &str, offset: u64) -> Result<(), Error> {
+            self.position = offset;
+            Ok(())
+        }
+
+        fn poll(&mut self, timeout: Duration) -> Result<Option<KafkaRecord>, Error> {
+            let record = self
+                .topic
+                .0
+                .lock()
+                .unwrap()
+                .get(self.position as usize)
+                .cloned();
+            match record {
+                Some(record) => {
+                    self.position += 1;
+                    Ok(Some(record))
+                }
+                None => {
+                    std::thread::sleep(timeout);
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_become_seqs() {
+        let topic = Topic::default();
+        let sink = KafkaSink::new(topic.clone(), "doggos");
+        for id in 1..=3 {
+            let offset = sink
+                .send(&DoggoEvent::new_delete_event(id, Collection::Dogs))
+                .unwrap();
+            assert_eq!(offset, id as u64 - 1);
+        }
+        let key = topic.0.lock().unwrap()[0].key.clone().unwrap();
+        assert_eq!(key, br#"["Dogs",1]"#);
+        // compaction left a tombstone behind
+        topic.0.lock().unwrap()[2].payload = None;
+        sink.send(&DoggoEvent::new_delete_event(4, Collection::Cats))
+            .unwrap();
+
+        let consumer = Consumer {
+            topic: topic.clone(),
+            position: 0,
+        };
+        let mut listener = KafkaListener::<DoggoEvent>::from_seq(consumer, "doggos", 1);
+        block_on(async {
+            let event = listener.recv().await.unwrap();
+            assert_eq!((event.seq(), event.id()), (Some(1), Some(&2)));
+            let event = listener.recv().await.unwrap();
+            assert_eq!((event.seq(), event.id()), (Some(3), Some(&4)));
+        });
+    }
+}
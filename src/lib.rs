@@ -1,16 +1,206 @@
 // use rsb_derive::Builder;
-use serde::Serialize;
+use std::io;
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use ts_rs::TS;
 
-#[derive(Debug, Clone, Serialize, TS)]
+mod ack;
+mod auth;
+mod backpressure;
+#[cfg(any(feature = "compression", feature = "crypto", feature = "nats"))]
+mod base64;
+mod batch;
+mod broadcast;
+mod builder;
+#[cfg(any(feature = "nats", feature = "redis"))]
+mod bus;
+mod causal;
+mod checkpoint;
+#[cfg(feature = "client")]
+pub mod client;
+mod coalesce;
+pub mod codec;
+mod collection;
+mod command;
+mod compaction;
+mod conflict;
+mod crc32;
+pub mod crdt;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+mod dead_letter;
+mod dyn_event;
+mod envelope;
+mod ephemeral;
+mod error;
+mod error_message;
+mod expiry;
+mod filter;
+pub mod framing;
+mod handshake;
+mod heartbeat;
+mod id;
+mod idempotency;
+mod identifier;
+mod json_patch;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod lifecycle;
+mod live_query;
+mod lww;
+mod materialize;
+mod meta;
+pub mod metrics;
+mod middleware;
+mod mutation;
+mod mux;
+#[cfg(feature = "nats")]
+mod nats;
+mod outbox;
+mod pacing;
+#[cfg(feature = "postgres")]
+mod postgres;
+pub mod presence;
+mod projection;
+#[cfg(feature = "grpc")]
+pub mod proto;
+mod protocol;
+mod query;
+#[cfg(feature = "reactive")]
+pub mod reactive;
+mod redact;
+#[cfg(feature = "redis")]
+mod redis;
+mod schema;
+mod scope;
+mod sequencer;
+mod serialized;
+mod shutdown;
+mod sink;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod sse;
+mod store;
+mod stream;
+mod subscription;
+pub mod testing;
+mod throttle;
+mod timer;
+#[cfg(feature = "tokio")]
+mod tokio_broadcast;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod tsgen;
+mod txn;
+mod upcast;
+mod validate;
+mod visibility;
+mod wal;
+#[cfg(feature = "webtransport")]
+pub mod webtransport;
+pub mod wire_compat;
+pub mod zodgen;
+
+use codec::WireCodec;
+
+#[cfg(feature = "postgres")]
+pub use self::postgres::{json_decoder, Notification, PgListenerSource};
+#[cfg(feature = "redis")]
+pub use self::redis::{RedisListener, RedisService};
+pub use ack::{Ack, AckListener, Acknowledgement, Nack};
+pub use auth::{AuthResult, Authenticate, Authenticator, Claims};
+pub use backpressure::{buffered, BufferedListener, BufferedSender, OverflowPolicy};
+pub use batch::EventBatch;
+pub use broadcast::{BroadcastListener, BroadcastService};
+pub use builder::{EventBuilder, LocationBuilder};
+pub use bytes::Bytes;
+pub use causal::{Causal, CausalListener, CausalOrderBuffer, VectorClock};
+pub use checkpoint::{CheckpointedConsumer, FileOffsetStore, InMemoryOffsetStore, OffsetStore};
+pub use coalesce::Coalescer;
+pub use collection::{Collection, CollectionPattern, CollectionRegistry};
+pub use command::{Command, Mutate, Query, Subscribe, Unsubscribe};
+pub use compaction::{compact_history, CatchUp, Snapshotter};
+pub use conflict::ConflictError;
+pub use dead_letter::{
+    DeadLetter, DeadLetterSink, DeadLetterStage, FileDeadLetters, InMemoryDeadLetters,
+};
+pub use dyn_event::DynEvent;
+pub use envelope::{decode_envelope, decode_envelope_with, AnyMessage, MAX_ENVELOPE_LEN};
+pub use ephemeral::Ephemeral;
+pub use error::Error;
+pub use error_message::{ErrorCode, ErrorMessage};
+pub use expiry::ExpiringService;
+pub use filter::{FilteredListener, Predicate, Routable};
+pub use handshake::{Hello, HelloAck, PROTOCOL_VERSION};
+pub use heartbeat::{Heartbeat, HeartbeatListener, Ping, Pong};
+pub use id::{AssignId, IdAssigner, IdAssigningService, SequentialIds};
+pub use idempotency::{DedupService, Idempotent};
+pub use identifier::ResourceIdentifier;
+pub use json_patch::{diff_to_event, diff_to_event_with, DiffPolicy, JsonPatch, PatchOperation};
+#[cfg(feature = "kafka")]
+pub use kafka::{KafkaConsumer, KafkaListener, KafkaProducer, KafkaRecord, KafkaSink};
+pub use lifecycle::{ConnectionEvent, DisconnectReason};
+pub use live_query::{Filter, LiveQuery, LiveQueryChange, LiveQueryTracker, LiveQueryUpdate};
+pub use lww::{merge, Clock, SystemClock};
+pub use materialize::{ApplyPatch, Conflict, Materializer, UpsertPolicy};
+pub use meta::EventMeta;
+pub use middleware::{Middleware, MiddlewareService, Outcome};
+pub use mutation::{MutationResult, MutationStatus, Rejection};
+pub use mux::Mux;
+#[cfg(feature = "nats")]
+pub use nats::{connect_nats, JetStreamStore, NatsListener, NatsService};
+pub use outbox::{InMemoryOutbox, InMemoryTxn, Outbox, OutboxEntry, OutboxStore};
+pub use pacing::{CatchUpItem, CaughtUp, PacedCatchUp};
+pub use projection::{Projection, ProjectionRunner};
+pub use protocol::Handle;
+pub use query::{QueryResult, Queryable};
+pub use redact::{Redact, RedactionPolicy};
+pub use schema::{schema_hash, SchemaEntry, SchemaManifest};
+pub use scope::{Scope, Scoped};
+pub use sequencer::{KeyedSequencer, OrderPolicy, Versioned};
+pub use serialized::{PublishSerialized, Serialized};
+pub use shutdown::{GoingAway, Shutdown};
+pub use sink::ServiceSink;
+pub use snapshot::{
+    Snapshot, SnapshotAssembler, SnapshotChunk, SnapshotChunks, SnapshotEntry, Snapshottable,
+};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{SqliteEventStore, SqliteOffsetStore};
+pub use store::{EventStore, InMemoryEventStore, Replay, Tombstone};
+pub use stream::{Batched, EventStreamExt, FilterCollection, FilterItem, ListenerStream, MapData};
+pub use subscription::{
+    ConnectionEvents, ConnectionId, EphemeralListener, FanOutMetrics, PresenceListener,
+    SubscriptionListener, SubscriptionManager,
+};
+pub use throttle::{RateLimit, Throttle};
+#[cfg(feature = "tokio")]
+pub use tokio_broadcast::{TokioBroadcastListener, TokioBroadcastService};
+pub use txn::{Txn, TxnBuilder};
+pub use upcast::{SchemaVersion, Upcasters};
+pub use validate::{FieldError, Validate, Validated, ValidationErrors};
+pub use visibility::{Visibility, VisibleListener};
+pub use wal::{FileWal, Wal, WalStore, DEFAULT_SEGMENT_SIZE};
+
+#[cfg(feature = "derive")]
+pub use rsp_derive::{Appendable, Syncable, TsDocs};
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[ts(export)]
 pub struct Location<ID, C> {
     id: Option<ID>,
     txn_id: Option<u32>,
     collection: C,
+    // the record's revision after this event, for optimistic concurrency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    revision: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[ts(export)]
 pub struct UpdatableResource<ID, T, C>
 where
@@ -18,9 +208,15 @@ where
 {
     location: Location<ID, C>,
     data: T,
+    // the revision the writer last saw. servers reject the write with a
+    // `ConflictError` when the record has moved on since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    expected_revision: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[ts(export)]
 pub struct AppendableResource<ID, T, C>
 where
@@ -30,30 +226,271 @@ where
     data: T,
 }
 
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct PatchResource<ID, P, C>
+where
+    P: TS,
+{
+    location: Location<ID, C>,
+    data: P,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct DeletableResource<ID, C> {
+    location: Location<ID, C>,
+    // when the record was deleted, so stores know how long to keep the tombstone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    deleted_at: Option<u64>,
+}
+
+// superseded by `ResourceIdentifier`, which isn't limited to u32s
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct ResourceId(u32);
 
-#[derive(Debug, Clone, Serialize, TS)]
+impl<ID, C> Location<ID, C> {
+    pub fn builder(collection: C) -> LocationBuilder<ID, C> {
+        LocationBuilder::new(collection)
+    }
+
+    pub fn id(&self) -> Option<&ID> {
+        self.id.as_ref()
+    }
+
+    pub fn txn_id(&self) -> Option<u32> {
+        self.txn_id
+    }
+
+    pub fn collection(&self) -> &C {
+        &self.collection
+    }
+
+    pub fn revision(&self) -> Option<u64> {
+        self.revision
+    }
+
+    pub fn into_parts(self) -> (Option<ID>, C) {
+        (self.id, self.collection)
+    }
+
+    pub(crate) fn as_ref(&self) -> Location<&ID, &C> {
+        Location {
+            id: self.id.as_ref(),
+            txn_id: self.txn_id,
+            collection: &self.collection,
+            revision: self.revision,
+        }
+    }
+}
+
+impl<ID, T: TS, C> UpdatableResource<ID, T, C> {
+    pub fn location(&self) -> &Location<ID, C> {
+        &self.location
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    pub fn expected_revision(&self) -> Option<u64> {
+        self.expected_revision
+    }
+
+    pub fn into_parts(self) -> (Location<ID, C>, T) {
+        (self.location, self.data)
+    }
+}
+
+impl<ID, T: TS, C> AppendableResource<ID, T, C> {
+    pub fn location(&self) -> &Location<ID, C> {
+        &self.location
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    pub fn into_parts(self) -> (Location<ID, C>, T) {
+        (self.location, self.data)
+    }
+}
+
+impl<ID, P: TS, C> PatchResource<ID, P, C> {
+    pub fn location(&self) -> &Location<ID, C> {
+        &self.location
+    }
+
+    pub fn data(&self) -> &P {
+        &self.data
+    }
+
+    pub fn into_parts(self) -> (Location<ID, C>, P) {
+        (self.location, self.data)
+    }
+}
+
+impl<ID, C> DeletableResource<ID, C> {
+    pub fn location(&self) -> &Location<ID, C> {
+        &self.location
+    }
+
+    pub fn into_location(self) -> Location<ID, C> {
+        self.location
+    }
+
+    pub fn deleted_at(&self) -> Option<u64> {
+        self.deleted_at
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 // this produces a json object with a "type" field and a "payload" field
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 #[ts(export)]
-pub enum EventVerb<ID, T: Serialize + TS, C> {
+pub enum EventVerb<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
     Insert(AppendableResource<ID, T, C>),
     Update(UpdatableResource<ID, T, C>),
     Upsert(UpdatableResource<ID, T, C>),
-    Delete(ResourceId),
+    Patch(PatchResource<ID, P, C>),
+    Delete(DeletableResource<ID, C>),
+    TxnBegin(u32),
+    TxnCommit(u32),
+    TxnAbort(u32),
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> EventVerb<ID, T, C, P> {
+    // `None` for transaction markers
+    pub fn location(&self) -> Option<&Location<ID, C>> {
+        match self {
+            EventVerb::Insert(resource) => Some(&resource.location),
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => Some(&resource.location),
+            EventVerb::Patch(resource) => Some(&resource.location),
+            EventVerb::Delete(resource) => Some(&resource.location),
+            EventVerb::TxnBegin(_) | EventVerb::TxnCommit(_) | EventVerb::TxnAbort(_) => None,
+        }
+    }
+
+    // the `type` tag the verb is serialized with
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventVerb::Insert(_) => "insert",
+            EventVerb::Update(_) => "update",
+            EventVerb::Upsert(_) => "upsert",
+            EventVerb::Patch(_) => "patch",
+            EventVerb::Delete(_) => "delete",
+            EventVerb::TxnBegin(_) => "txn_begin",
+            EventVerb::TxnCommit(_) => "txn_commit",
+            EventVerb::TxnAbort(_) => "txn_abort",
+        }
+    }
+
+    pub(crate) fn location_mut(&mut self) -> Option<&mut Location<ID, C>> {
+        match self {
+            EventVerb::Insert(resource) => Some(&mut resource.location),
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => {
+                Some(&mut resource.location)
+            }
+            EventVerb::Patch(resource) => Some(&mut resource.location),
+            EventVerb::Delete(resource) => Some(&mut resource.location),
+            EventVerb::TxnBegin(_) | EventVerb::TxnCommit(_) | EventVerb::TxnAbort(_) => None,
+        }
+    }
+}
+
+// patch payload of events for records that aren't `Patchable`. it has no
+// values, so such events can never carry a `Patch` verb
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoPatch {}
+
+impl TS for NoPatch {
+    fn name() -> String {
+        "never".to_owned()
+    }
+
+    fn inline() -> String {
+        Self::name()
+    }
+
+    fn dependencies() -> Vec<ts_rs::Dependency> {
+        Vec::new()
+    }
+
+    fn transparent() -> bool {
+        false
+    }
+}
+
+// nothing validates against it, like typescript's `never`
+#[cfg(feature = "json-schema")]
+impl schemars::JsonSchema for NoPatch {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "NoPatch".into()
+    }
+
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "not": {} })
+    }
 }
 
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[ts(export)]
-pub struct Event<ID, T: Serialize + TS, C> {
-    verb: EventVerb<ID, T, C>,
+pub struct Event<ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch> {
+    // assigned by the service when the event is published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    seq: Option<u64>,
+    // milliseconds since the unix epoch, set by the producer for last-write-wins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    occurred_at: Option<u64>,
+    // the version of `T`'s shape the payload was written with, so an
+    // `Upcasters` can migrate events persisted before the struct changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u32>,
+    // the sync domain the event belongs to, for servers hosting many
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<Scope>,
+    // the producer's name for the event, the same on every retry of it, so
+    // a republish can be told apart from a new event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idempotency_key: Option<String>,
+    // milliseconds since the unix epoch after which the record is deleted,
+    // see `ExpiringService`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<EventMeta>,
+    // for multi-writer setups, what its writer had seen when it was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    causality: Option<VectorClock>,
+    verb: EventVerb<ID, T, C, P>,
 }
 
-impl<ID, T: Serialize + TS, C> Event<ID, T, C> {
-    pub fn new(verb: EventVerb<ID, T, C>) -> Self {
-        Self { verb }
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Event<ID, T, C, P> {
+    pub fn new(verb: EventVerb<ID, T, C, P>) -> Self {
+        Self {
+            seq: None,
+            occurred_at: None,
+            schema_version: None,
+            scope: None,
+            idempotency_key: None,
+            expires_at: None,
+            meta: None,
+            causality: None,
+            verb,
+        }
     }
 
     pub fn new_insert_event(data: T, collection: C) -> Self {
@@ -61,11 +498,212 @@ impl<ID, T: Serialize + TS, C> Event<ID, T, C> {
             id: None,
             txn_id: None,
             collection,
+            revision: None,
         };
         let verb = EventVerb::Insert(AppendableResource { location, data });
         Self::new(verb)
     }
 
+    pub fn new_delete_event(id: ID, collection: C) -> Self {
+        let location = Location {
+            id: Some(id),
+            txn_id: None,
+            collection,
+            revision: None,
+        };
+        let verb = EventVerb::Delete(DeletableResource {
+            location,
+            deleted_at: None,
+        });
+        Self::new(verb)
+    }
+
+    pub fn new_patch_event(id: ID, patch: P, collection: C) -> Self {
+        let location = Location {
+            id: Some(id),
+            txn_id: None,
+            collection,
+            revision: None,
+        };
+        let verb = EventVerb::Patch(PatchResource {
+            location,
+            data: patch,
+        });
+        Self::new(verb)
+    }
+
+    pub fn builder() -> EventBuilder<ID, T, C, P> {
+        EventBuilder::new()
+    }
+
+    pub fn verb(&self) -> &EventVerb<ID, T, C, P> {
+        &self.verb
+    }
+
+    pub fn into_verb(self) -> EventVerb<ID, T, C, P> {
+        self.verb
+    }
+
+    // the record carried by inserts, updates and upserts
+    pub fn data(&self) -> Option<&T> {
+        match &self.verb {
+            EventVerb::Insert(resource) => Some(&resource.data),
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => Some(&resource.data),
+            _ => None,
+        }
+    }
+
+    pub fn collection(&self) -> Option<&C> {
+        self.verb.location().map(|location| &location.collection)
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    pub fn id(&self) -> Option<&ID> {
+        self.verb
+            .location()
+            .and_then(|location| location.id.as_ref())
+    }
+
+    pub fn txn_id(&self) -> Option<u32> {
+        self.verb.location().and_then(|location| location.txn_id)
+    }
+
+    pub fn occurred_at(&self) -> Option<u64> {
+        self.occurred_at
+    }
+
+    pub fn with_occurred_at(mut self, occurred_at: u64) -> Self {
+        self.occurred_at = Some(occurred_at);
+        self
+    }
+
+    pub fn stamped(self, clock: &impl Clock) -> Self {
+        self.with_occurred_at(clock.now())
+    }
+
+    pub fn schema_version(&self) -> Option<u32> {
+        self.schema_version
+    }
+
+    pub fn with_schema_version(mut self, schema_version: u32) -> Self {
+        self.schema_version = Some(schema_version);
+        self
+    }
+
+    pub fn scope(&self) -> Option<&Scope> {
+        self.scope.as_ref()
+    }
+
+    pub fn with_scope(mut self, scope: impl Into<Scope>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    pub fn idempotency_key(&self) -> Option<&str> {
+        self.idempotency_key.as_deref()
+    }
+
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    pub fn with_expires_at(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    // expires `ttl` from now by `clock`, or never if that's past `u64::MAX`
+    pub fn with_ttl(self, ttl: Duration, clock: &impl Clock) -> Self {
+        let now = clock.now();
+        let expires_at = u64::try_from(ttl.as_millis())
+            .ok()
+            .and_then(|ttl| now.checked_add(ttl))
+            .unwrap_or(u64::MAX);
+        self.with_expires_at(expires_at)
+    }
+
+    // stamps the payload with `T`'s current schema version
+    pub fn versioned(self) -> Self
+    where
+        T: SchemaVersion,
+    {
+        self.with_schema_version(T::SCHEMA_VERSION)
+    }
+
+    // when the record was deleted, for delete events
+    pub fn deleted_at(&self) -> Option<u64> {
+        match &self.verb {
+            EventVerb::Delete(resource) => resource.deleted_at,
+            _ => None,
+        }
+    }
+
+    // marks a delete as a tombstone deleted at `deleted_at`. other verbs are
+    // left as they are
+    pub fn with_deleted_at(mut self, deleted_at: u64) -> Self {
+        if let EventVerb::Delete(resource) = &mut self.verb {
+            resource.deleted_at = Some(deleted_at);
+        }
+        self
+    }
+
+    pub fn meta(&self) -> Option<&EventMeta> {
+        self.meta.as_ref()
+    }
+
+    pub fn meta_mut(&mut self) -> &mut EventMeta {
+        self.meta.get_or_insert_with(EventMeta::default)
+    }
+
+    pub fn with_meta(mut self, meta: EventMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    pub fn causality(&self) -> Option<&VectorClock> {
+        self.causality.as_ref()
+    }
+
+    pub fn with_causality(mut self, causality: VectorClock) -> Self {
+        self.causality = Some(causality);
+        self
+    }
+
+    pub fn revision(&self) -> Option<u64> {
+        self.verb.location().and_then(|location| location.revision)
+    }
+
+    // stamps the record's revision after this event, set by the server
+    pub fn with_revision(mut self, revision: u64) -> Self {
+        if let Some(location) = self.verb.location_mut() {
+            location.revision = Some(revision);
+        }
+        self
+    }
+
+    pub fn expected_revision(&self) -> Option<u64> {
+        match &self.verb {
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => resource.expected_revision,
+            _ => None,
+        }
+    }
+
+    // only updates and upserts carry an expected revision
+    pub fn with_expected_revision(mut self, revision: u64) -> Self {
+        if let EventVerb::Update(resource) | EventVerb::Upsert(resource) = &mut self.verb {
+            resource.expected_revision = Some(revision);
+        }
+        self
+    }
+
     pub fn into_ws_body(self) -> WsBody<Self>
     where
         Self: Serialize,
@@ -74,19 +712,189 @@ impl<ID, T: Serialize + TS, C> Event<ID, T, C> {
     }
 }
 
-#[derive(Serialize)]
+impl<ID, T: Serialize + TS, C> Event<ID, T, C> {
+    // lets full-document events share a stream with the patches of a
+    // `Patchable` record
+    pub fn into_patchable<P: Serialize + TS>(self) -> Event<ID, T, C, P> {
+        let verb = match self.verb {
+            EventVerb::Insert(resource) => EventVerb::Insert(resource),
+            EventVerb::Update(resource) => EventVerb::Update(resource),
+            EventVerb::Upsert(resource) => EventVerb::Upsert(resource),
+            EventVerb::Patch(resource) => match resource.data {},
+            EventVerb::Delete(resource) => EventVerb::Delete(resource),
+            EventVerb::TxnBegin(txn_id) => EventVerb::TxnBegin(txn_id),
+            EventVerb::TxnCommit(txn_id) => EventVerb::TxnCommit(txn_id),
+            EventVerb::TxnAbort(txn_id) => EventVerb::TxnAbort(txn_id),
+        };
+        Event {
+            seq: self.seq,
+            occurred_at: self.occurred_at,
+            schema_version: self.schema_version,
+            scope: self.scope,
+            idempotency_key: self.idempotency_key,
+            expires_at: self.expires_at,
+            meta: self.meta,
+            causality: self.causality,
+            verb,
+        }
+    }
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Event<ID, T, C, P> {
+    // the same event with its record passed through `f`, e.g. to project it
+    // down to what a view needs. patches pass as they are
+    pub fn map_data<U: Serialize + TS>(self, f: impl FnOnce(T) -> U) -> Event<ID, U, C, P> {
+        let verb = match self.verb {
+            EventVerb::Insert(resource) => EventVerb::Insert(AppendableResource {
+                location: resource.location,
+                data: f(resource.data),
+            }),
+            EventVerb::Update(resource) => EventVerb::Update(resource.map_data(f)),
+            EventVerb::Upsert(resource) => EventVerb::Upsert(resource.map_data(f)),
+            EventVerb::Patch(resource) => EventVerb::Patch(resource),
+            EventVerb::Delete(resource) => EventVerb::Delete(resource),
+            EventVerb::TxnBegin(txn_id) => EventVerb::TxnBegin(txn_id),
+            EventVerb::TxnCommit(txn_id) => EventVerb::TxnCommit(txn_id),
+            EventVerb::TxnAbort(txn_id) => EventVerb::TxnAbort(txn_id),
+        };
+        Event {
+            seq: self.seq,
+            occurred_at: self.occurred_at,
+            schema_version: self.schema_version,
+            scope: self.scope,
+            idempotency_key: self.idempotency_key,
+            expires_at: self.expires_at,
+            meta: self.meta,
+            causality: self.causality,
+            verb,
+        }
+    }
+}
+
+impl<ID, T: TS, C> UpdatableResource<ID, T, C> {
+    fn map_data<U: TS>(self, f: impl FnOnce(T) -> U) -> UpdatableResource<ID, U, C> {
+        UpdatableResource {
+            location: self.location,
+            data: f(self.data),
+            expected_revision: self.expected_revision,
+        }
+    }
+}
+
+// implemented by anything a service can stamp with its position in the stream
+pub trait Sequenced {
+    fn seq(&self) -> Option<u64>;
+    fn set_seq(&mut self, seq: u64);
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Sequenced for Event<ID, T, C, P> {
+    fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    fn set_seq(&mut self, seq: u64) {
+        self.seq = Some(seq);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct WsBody<T: Serialize> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol_version: Option<u32>,
+    // which of a connection's multiplexed channels the body belongs to, see
+    // `Mux`. bodies without one are on the connection itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channel: Option<u32>,
     data: T,
 }
 
 impl<T: Serialize> WsBody<T> {
     fn new(data: T) -> Self {
-        Self { data }
+        Self {
+            protocol_version: None,
+            channel: None,
+            data,
+        }
+    }
+
+    pub fn with_protocol_version(mut self, protocol_version: u32) -> Self {
+        self.protocol_version = Some(protocol_version);
+        self
+    }
+
+    pub fn protocol_version(&self) -> Option<u32> {
+        self.protocol_version
+    }
+
+    pub fn with_channel(mut self, channel: u32) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn channel(&self) -> Option<u32> {
+        self.channel
+    }
+
+    // bodies without a version are assumed to speak the negotiated one
+    pub fn check_protocol_version(&self, expected: u32) -> Result<(), Error> {
+        match self.protocol_version {
+            Some(found) if found != expected => Err(Error::VersionMismatch { expected, found }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn try_json(&self) -> Result<String, Error> {
+        serde_json::to_string(&self).map_err(Error::Encode)
     }
 
-    // TODO: this should return a result type
+    // writes the json as it is produced instead of building it up in memory
+    pub fn write_json_to(&self, writer: impl io::Write) -> Result<(), Error> {
+        serde_json::to_writer(writer, self).map_err(Error::Encode)
+    }
+
+    // serde can't pause halfway through a value, so the json is encoded up
+    // front and then written. a snapshot streams a record at a time with
+    // `WsBody::<Snapshot>::stream_json_to`, or goes out as chunks
+    // (`Snapshot::into_chunks`)
+    pub async fn write_json_to_async<W>(&self, writer: &mut W) -> Result<(), Error>
+    where
+        W: futures_io::AsyncWrite + Unpin + ?Sized,
+    {
+        let json = serde_json::to_vec(self).map_err(Error::Encode)?;
+        write_all(writer, &json).await?;
+        flush(writer).await
+    }
+
+    #[deprecated(note = "use `try_json` instead")]
     pub fn json(&self) -> String {
-        serde_json::to_string(&self).expect("Could not serialize WsBody<T> to JSON")
+        self.try_json()
+            .expect("Could not serialize WsBody<T> to JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_str(json).map_err(Error::Decode)
+    }
+
+    pub fn to_bytes(&self, codec: &impl WireCodec) -> Result<Vec<u8>, Error> {
+        codec.encode(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8], codec: &impl WireCodec) -> Result<Self, Error>
+    where
+        T: DeserializeOwned,
+    {
+        codec.decode(bytes)
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    pub fn into_data(self) -> T {
+        self.data
     }
 }
 
@@ -108,6 +916,10 @@ pub trait Appendable: Serialize + Sized + TS {
         <Self as Appendable>::to_event(self, EventVerb::Insert)
     }
 
+    fn to_insert_event_at(self, clock: &impl Clock) -> Event<(), Self, Self::Collection> {
+        self.to_insert_event().stamped(clock)
+    }
+
     fn to_event(
         self,
         build_verb: impl FnOnce(
@@ -118,6 +930,7 @@ pub trait Appendable: Serialize + Sized + TS {
             id: None,
             txn_id: None,
             collection: self.collection(),
+            revision: None,
         };
         let verb = build_verb(AppendableResource {
             location,
@@ -140,6 +953,35 @@ pub trait Syncable: Appendable {
         <Self as Syncable>::to_event(self, EventVerb::Update)
     }
 
+    fn to_delete_event(&self) -> Event<Self::Id, Self, Self::Collection> {
+        Event::new_delete_event(self.id(), self.collection())
+    }
+
+    // the same events stamped with `clock`, for last-write-wins merging
+    fn to_upsert_event_at(self, clock: &impl Clock) -> Event<Self::Id, Self, Self::Collection> {
+        self.to_upsert_event().stamped(clock)
+    }
+
+    fn to_update_event_at(self, clock: &impl Clock) -> Event<Self::Id, Self, Self::Collection> {
+        self.to_update_event().stamped(clock)
+    }
+
+    fn to_delete_event_at(&self, clock: &impl Clock) -> Event<Self::Id, Self, Self::Collection> {
+        let now = clock.now();
+        self.to_delete_event()
+            .with_occurred_at(now)
+            .with_deleted_at(now)
+    }
+
+    // a patch event carrying the RFC 6902 operations from `previous` to `self`
+    fn to_json_patch_event(
+        &self,
+        previous: &Self,
+    ) -> Result<Event<Self::Id, Self, Self::Collection, JsonPatch>, Error> {
+        let patch = JsonPatch::diff(previous, self)?;
+        Ok(Event::new_patch_event(self.id(), patch, self.collection()))
+    }
+
     fn to_event(
         self,
         build_verb: impl FnOnce(
@@ -150,31 +992,296 @@ pub trait Syncable: Appendable {
             id: Some(self.id()),
             txn_id: None,
             collection: self.collection(),
+            revision: None,
         };
         let verb = build_verb(UpdatableResource {
             location,
             data: self,
+            expected_revision: None,
         });
         Event::new(verb)
     }
 }
 
+pub trait Patchable: Syncable {
+    type Patch: Serialize + TS;
+
+    fn to_patch_event(
+        &self,
+        patch: Self::Patch,
+    ) -> Event<Self::Id, Self, Self::Collection, Self::Patch> {
+        Event::new_patch_event(self.id(), patch, self.collection())
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Listener {
+    type Error;
+    type Item;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error>;
+
+    // the items as a `Stream`, to compose with combinators instead of
+    // looping over `recv`
+    fn into_stream(self) -> ListenerStream<Self>
+    where
+        Self: Sized + Send + 'static,
+    {
+        ListenerStream::new(self)
+    }
+}
+
+// writes all of `bytes` to a `futures_io::AsyncWrite`. tokio's streams are
+// one through tokio-util's `compat`
+pub(crate) async fn write_all<W>(writer: &mut W, mut bytes: &[u8]) -> Result<(), Error>
+where
+    W: futures_io::AsyncWrite + Unpin + ?Sized,
+{
+    while !bytes.is_empty() {
+        let written =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut *writer).poll_write(cx, bytes))
+                .await?;
+        if written == 0 {
+            return Err(io::Error::from(io::ErrorKind::WriteZero).into());
+        }
+        bytes = &bytes[written..];
+    }
+    Ok(())
+}
+
+pub(crate) async fn flush<W>(writer: &mut W) -> Result<(), Error>
+where
+    W: futures_io::AsyncWrite + Unpin + ?Sized,
+{
+    std::future::poll_fn(|cx| std::pin::Pin::new(&mut *writer).poll_flush(cx)).await?;
+    Ok(())
+}
+
+pub trait Service<T> {
+    type Listener: Listener<Item = T>;
+    type Error;
+
+    fn publish(&self, event: T) -> Result<(), Self::Error>;
+    fn listener(&self) -> Self::Listener;
+    // a listener that starts at `seq` rather than at the next published event,
+    // so a reconnecting client can pass its last seen seq + 1
+    fn listener_from(&self, seq: u64) -> Self::Listener;
+    // the seq the next published event will get
+    fn head_seq(&self) -> u64;
+
+    // stops taking publishes and lets every listener deliver what was
+    // published before, after which it fails with `Error::GoingAway` for the
+    // connection to send on to its client and close. resolves once every
+    // listener has, failing with `Error::Undrained` if some haven't by
+    // `deadline`. services with no listeners of their own resolve at once
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        let _ = deadline;
+        Shutdown::drained()
+    }
+
+    // the current records of `collection`, valid at the returned snapshot's
+    // seq. the seq is taken before the records are read, so resuming from it
+    // with `listener_from` may replay a few events already in the snapshot
+    // but never misses one
+    fn snapshot<S: Snapshottable>(
+        &self,
+        source: &S,
+        collection: S::Collection,
+    ) -> Snapshot<S::Id, S::Record, S::Collection> {
+        let seq = self.head_seq();
+        let records = source.records(&collection);
+        Snapshot::new(collection, seq, records)
+    }
+
+    // a listener that keeps every delivered event until the client acks it,
+    // redelivering nacked ones for at-least-once delivery
+    fn acked_listener(&self) -> AckListener<Self::Listener> {
+        AckListener::new(self.listener())
+    }
+
+    // runs every event published through the returned service by
+    // `middleware` first. more can be chained onto it
+    fn with_middleware(self, middleware: impl Middleware<T> + 'static) -> MiddlewareService<Self, T>
+    where
+        Self: Sized,
+    {
+        MiddlewareService::new(self).with_middleware(middleware)
+    }
+
+    // a `futures::Sink` publishing what's sent to it, for `forward`ing a
+    // producer's stream in with backpressure instead of a publish loop
+    fn sink(&self) -> ServiceSink<'_, Self, T>
+    where
+        Self: Sized,
+    {
+        ServiceSink::new(self)
+    }
+
+    fn listener_filtered<F>(&self, predicate: F) -> FilteredListener<Self::Listener, F>
+    where
+        F: Fn(&T) -> bool,
+    {
+        FilteredListener::new(self.listener(), predicate)
+    }
+
+    // a publish a retry of which, under the same key, is dropped by a
+    // `DedupService` instead of producing the event twice
+    fn publish_idempotent(&self, key: impl Into<String>, mut event: T) -> Result<(), Self::Error>
+    where
+        T: Idempotent,
+    {
+        event.set_idempotency_key(key.into());
+        self.publish(event)
+    }
+
+    // drops events whose idempotency key was already published within
+    // `window`
+    fn with_dedup(self, window: Duration) -> DedupService<Self, T>
+    where
+        Self: Sized,
+    {
+        DedupService::new(self, window)
+    }
+
+    // tracks the `expires_at` of published records, for `sweep` to delete
+    // them once it has passed
+    fn with_expiry(self) -> ExpiringService<Self, T>
+    where
+        T: Routable,
+        Self: Sized,
+    {
+        ExpiringService::new(self)
+    }
+
+    // puts `event` into `scope` before publishing it
+    fn publish_scoped(&self, scope: impl Into<Scope>, mut event: T) -> Result<(), Self::Error>
+    where
+        T: Scoped,
+    {
+        event.set_scope(scope.into());
+        self.publish(event)
+    }
+
+    // a listener for the events of `scope` only, leaving out unscoped ones
+    fn listener_for_scope(
+        &self,
+        scope: impl Into<Scope>,
+    ) -> FilteredListener<Self::Listener, Predicate<T>>
+    where
+        T: Routable + 'static,
+    {
+        let scope = scope.into();
+        self.listener_filtered(Box::new(move |event: &T| event.scope() == Some(&scope)))
+    }
+
+    fn listener_for_collections(
+        &self,
+        collections: impl IntoIterator<Item = T::Collection>,
+    ) -> FilteredListener<Self::Listener, Predicate<T>>
+    where
+        T: Routable + 'static,
+        T::Collection: PartialEq + Send + Sync + 'static,
+    {
+        let collections: Vec<_> = collections.into_iter().collect();
+        self.listener_filtered(Box::new(move |event: &T| {
+            event
+                .collection()
+                .is_none_or(|collection| collections.contains(collection))
+        }))
+    }
+
+    // a listener that only yields the events `visibility` lets `context` see,
+    // e.g. with the connection's tenant as the context
+    fn listener_visible_to<V, X>(
+        &self,
+        visibility: V,
+        context: X,
+    ) -> VisibleListener<Self::Listener, V, X>
+    where
+        V: Visibility<T, X>,
+    {
+        VisibleListener::new(self.listener(), visibility, context)
+    }
+
+    // a listener resuming from `seq` that replays what it missed no faster
+    // than `limit`, with live events in between, see `PacedCatchUp`
+    fn listener_paced(&self, seq: u64, limit: RateLimit) -> PacedCatchUp<Self::Listener>
+    where
+        Self: Sized,
+    {
+        PacedCatchUp::new::<T, Self>(self, seq, limit)
+    }
+
+    // a listener that skips events from `origin`, like
+    // `SubscriptionManager::suppress_origin`
+    fn listener_suppressing_origin(
+        &self,
+        origin: impl Into<String>,
+    ) -> FilteredListener<Self::Listener, Predicate<T>>
+    where
+        T: Routable + 'static,
+    {
+        let origin = origin.into();
+        self.listener_filtered(Box::new(move |event: &T| {
+            event.origin() != Some(origin.as_str())
+        }))
+    }
+}
+
+#[cfg(test)]
 mod test {
-    use serde::Serialize;
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::Thread;
+
+    use serde::{Deserialize, Serialize};
     use ts_rs::TS;
 
-    use crate::{Appendable, Syncable};
+    use crate::{Appendable, Patchable, Syncable};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // minimal executor so the async listeners can be driven without a runtime
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[derive(Clone, Serialize, Deserialize, TS)]
+    #[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+    pub(crate) struct DoggoRecord {
+        pub(crate) id: u32,
+        pub(crate) name: String,
+        pub(crate) breed: String,
+    }
 
-    #[derive(Serialize, TS)]
-    struct DoggoRecord {
-        id: u32,
-        name: String,
-        breed: String,
+    // the doggo the tests publish, told apart by its id
+    pub(crate) fn doggo(id: u32) -> DoggoRecord {
+        DoggoRecord {
+            id,
+            name: "Barky".to_string(),
+            breed: "Poodle".to_string(),
+        }
     }
 
     #[allow(dead_code)]
-    #[derive(Serialize, TS)]
-    enum Collection {
+    #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+    #[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+    pub(crate) enum Collection {
         Dogs,
         Cats,
     }
@@ -195,44 +1302,83 @@ mod test {
         }
     }
 
+    #[derive(Clone, Serialize, Deserialize, TS)]
+    #[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+    pub(crate) struct DoggoPatch {
+        pub(crate) name: Option<String>,
+    }
+
+    impl Patchable for DoggoRecord {
+        type Patch = DoggoPatch;
+    }
+
     #[test]
     fn conversion_works() {
         use super::*;
 
-        let doggo = DoggoRecord {
-            id: 1,
-            name: "Barky".to_string(),
-            breed: "Poodle".to_string(),
-        };
-
-        let event = doggo.to_insert_event();
-        let json = WsBody::new(event).json();
+        let event = doggo(1).to_insert_event();
+        let json = WsBody::new(event).try_json().unwrap();
         insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"insert","payload":{"location":{"id":null,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}}}"###);
 
-        let doggo = DoggoRecord {
-            id: 1,
-            name: "Barky".to_string(),
-            breed: "Poodle".to_string(),
-        };
-        let event = doggo.to_upsert_event();
-        let json = WsBody::new(event).json();
+        let event = doggo(1).to_upsert_event();
+        let json = WsBody::new(event).try_json().unwrap();
 
         insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}}}"###);
+
+        let event = doggo(1).to_delete_event();
+        let json = WsBody::new(event).try_json().unwrap();
+
+        insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"delete","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"}}}}}"###);
     }
-}
 
-#[async_trait::async_trait]
-pub trait Listener {
-    type Error;
-    type Item;
+    #[test]
+    fn round_trip_works() {
+        use super::*;
+        use crate::test::Collection;
 
-    async fn recv(&mut self) -> Result<Self::Item, Self::Error>;
-}
+        let json = doggo(1)
+            .to_insert_event()
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        let body = WsBody::<Event<(), DoggoRecord, Collection>>::from_json(&json).unwrap();
+        assert!(matches!(body.data.verb, EventVerb::Insert(_)));
+        assert_eq!(body.try_json().unwrap(), json);
 
-pub trait Service<T> {
-    type Listener: Listener<Item = T>;
-    type Error;
+        for json in [
+            doggo(1)
+                .to_upsert_event()
+                .into_ws_body()
+                .try_json()
+                .unwrap(),
+            doggo(1)
+                .to_update_event()
+                .into_ws_body()
+                .try_json()
+                .unwrap(),
+            doggo(1)
+                .to_delete_event()
+                .into_ws_body()
+                .try_json()
+                .unwrap(),
+        ] {
+            let body = WsBody::<Event<u32, DoggoRecord, Collection>>::from_json(&json).unwrap();
+            assert_eq!(body.try_json().unwrap(), json);
+        }
+    }
 
-    fn publish(&self, event: T) -> Result<(), Self::Error>;
-    fn listener(&self) -> Self::Listener;
+    #[test]
+    fn patch_events_share_a_batch() {
+        use super::*;
+
+        let doggo = doggo(1);
+
+        let mut batch = EventBatch::new(1);
+        batch.push(doggo.to_patch_event(DoggoPatch {
+            name: Some("Woofy".to_string()),
+        }));
+        batch.push(doggo.to_delete_event().into_patchable());
+        let json = batch.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"seq":1,"txn_id":null,"events":[{"verb":{"type":"patch","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"name":"Woofy"}}}},{"verb":{"type":"delete","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"}}}}]}}"###);
+    }
 }
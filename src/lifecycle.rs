@@ -0,0 +1,136 @@
+use crate::{CollectionPattern, ConnectionId, Error};
+
+// what happened to a connection of a `SubscriptionManager`, as its
+// `connection_events` listener reports it, e.g. for an audit log
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent<C> {
+    Connected {
+        connection: ConnectionId,
+    },
+    // with the subject of the claims its token was accepted with
+    Authenticated {
+        connection: ConnectionId,
+        subject: String,
+    },
+    // what one subscribe added
+    Subscribed {
+        connection: ConnectionId,
+        collections: Vec<C>,
+        patterns: Vec<CollectionPattern>,
+    },
+    // its buffer started dropping events. reported again only once it has
+    // caught up in between. `dropped` counts every event it has lost so far
+    Lagging {
+        connection: ConnectionId,
+        dropped: u64,
+    },
+    Disconnected {
+        connection: ConnectionId,
+        reason: DisconnectReason,
+    },
+}
+
+impl<C> ConnectionEvent<C> {
+    pub fn connection(&self) -> ConnectionId {
+        match self {
+            ConnectionEvent::Connected { connection }
+            | ConnectionEvent::Authenticated { connection, .. }
+            | ConnectionEvent::Subscribed { connection, .. }
+            | ConnectionEvent::Lagging { connection, .. }
+            | ConnectionEvent::Disconnected { connection, .. } => *connection,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    // `disconnect` was called or the connection's listener was dropped
+    Closed,
+    // its buffer overflowed under `OverflowPolicy::CloseConnection`
+    Overflowed,
+    // the transport gave up on it, e.g. when its heartbeat timed out
+    Failed(String),
+}
+
+impl From<&Error> for DisconnectReason {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Closed => DisconnectReason::Closed,
+            Error::Overflowed(_) => DisconnectReason::Overflowed,
+            err => DisconnectReason::Failed(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        Authenticate, Claims, ConnectionEvent, DisconnectReason, Error, Event, Listener,
+        OverflowPolicy, Subscribe, SubscriptionManager, Syncable,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn connections_report_their_lifecycle() {
+        let upsert = |id| doggo(id).to_upsert_event();
+        let manager = SubscriptionManager::<DoggoEvent>::new()
+            .with_authenticator(|_: &str| Ok(Claims::new("walker")));
+        let mut events = manager.connection_events();
+        let mut client = manager.connect_buffered(1, OverflowPolicy::DropOldest);
+        let connection = client.connection();
+        manager.handle_authenticate(
+            connection,
+            &Authenticate {
+                token: "secret".to_owned(),
+            },
+        );
+        manager.handle_subscribe(
+            connection,
+            &Subscribe {
+                collections: vec![Collection::Dogs, Collection::Cats],
+                patterns: Vec::new(),
+                from_seq: None,
+            },
+        );
+        // the second and third each push an older one out, but it's lagging
+        // from the first
+        for id in 1..=3 {
+            manager.publish(upsert(id));
+        }
+        assert_eq!(block_on(client.recv()).unwrap().seq(), Some(2));
+        manager.disconnect_with(connection, &Error::ConnectionTimedOut(30_000));
+        drop(client);
+
+        let lifecycle: Vec<_> = (0..5).map(|_| block_on(events.recv()).unwrap()).collect();
+        assert_eq!(lifecycle[3].connection(), connection);
+        assert!(
+            lifecycle
+                == [
+                    ConnectionEvent::Connected { connection },
+                    ConnectionEvent::Authenticated {
+                        connection,
+                        subject: "walker".to_owned()
+                    },
+                    ConnectionEvent::Subscribed {
+                        connection,
+                        collections: vec![Collection::Dogs, Collection::Cats],
+                        patterns: Vec::new()
+                    },
+                    ConnectionEvent::Lagging {
+                        connection,
+                        dropped: 1
+                    },
+                    ConnectionEvent::Disconnected {
+                        connection,
+                        reason: DisconnectReason::Failed(
+                            "connection timed out after 30000ms without a heartbeat".to_owned()
+                        )
+                    },
+                ]
+        );
+        drop(manager);
+        assert!(matches!(block_on(events.recv()), Err(Error::Closed)));
+    }
+}
@@ -0,0 +1,381 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Event, EventVerb, Materializer, SnapshotEntry, WsBody};
+
+// a condition on the fields of a record, evaluated against its json. values
+// of different types never compare, so `gt` on a string field with a number
+// is false
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "op", rename_all = "snake_case")]
+#[ts(export)]
+pub enum Filter {
+    Eq {
+        field: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Ne {
+        field: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Lt {
+        field: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Lte {
+        field: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Gt {
+        field: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    Gte {
+        field: String,
+        #[ts(type = "unknown")]
+        value: Value,
+    },
+    In {
+        field: String,
+        #[ts(type = "Array<unknown>")]
+        values: Vec<Value>,
+    },
+    And {
+        filters: Vec<Filter>,
+    },
+    Or {
+        filters: Vec<Filter>,
+    },
+    Not {
+        filter: Box<Filter>,
+    },
+}
+
+impl Filter {
+    pub fn eq(field: impl Into<String>, value: impl Into<Value>) -> Self {
+        Filter::Eq {
+            field: field.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn gt(field: impl Into<String>, value: impl Into<Value>) -> Self {
+        Filter::Gt {
+            field: field.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn lt(field: impl Into<String>, value: impl Into<Value>) -> Self {
+        Filter::Lt {
+            field: field.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And { mut filters } => {
+                filters.push(other);
+                Filter::And { filters }
+            }
+            this => Filter::And {
+                filters: vec![this, other],
+            },
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or { mut filters } => {
+                filters.push(other);
+                Filter::Or { filters }
+            }
+            this => Filter::Or {
+                filters: vec![this, other],
+            },
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        Filter::Not {
+            filter: Box::new(self),
+        }
+    }
+
+    pub fn matches(&self, record: &Value) -> bool {
+        let compare = |field: &str, value: &Value| compare(record.get(field)?, value);
+        match self {
+            Filter::Eq { field, value } => record.get(field) == Some(value),
+            Filter::Ne { field, value } => record.get(field) != Some(value),
+            Filter::Lt { field, value } => compare(field, value) == Some(Ordering::Less),
+            Filter::Lte { field, value } => {
+                matches!(
+                    compare(field, value),
+                    Some(Ordering::Less | Ordering::Equal)
+                )
+            }
+            Filter::Gt { field, value } => compare(field, value) == Some(Ordering::Greater),
+            Filter::Gte { field, value } => matches!(
+                compare(field, value),
+                Some(Ordering::Greater | Ordering::Equal)
+            ),
+            Filter::In { field, values } => record
+                .get(field)
+                .is_some_and(|found| values.contains(found)),
+            Filter::And { filters } => filters.iter().all(|filter| filter.matches(record)),
+            Filter::Or { filters } => filters.iter().any(|filter| filter.matches(record)),
+            Filter::Not { filter } => !filter.matches(record),
+        }
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        (Value::Bool(left), Value::Bool(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+// keeps the client up to date with the records of `collection` matching
+// `filter`. sending another with the same `query_id` replaces it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct LiveQuery<C> {
+    pub query_id: u32,
+    pub collection: C,
+    pub filter: Filter,
+}
+
+// how an event changed a live query's result set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum LiveQueryChange<ID, T: TS> {
+    // a new record that matches
+    Added { id: ID, data: T },
+    // a record in the set changed, and still matches
+    Updated { id: ID, data: T },
+    // a record in the set was deleted
+    Removed { id: ID },
+    // an existing record changed to match
+    EnteredResultSet { id: ID, data: T },
+    // a record in the set changed to no longer match
+    LeftResultSet { id: ID },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct LiveQueryUpdate<ID, T: TS> {
+    pub(crate) query_id: u32,
+    // the seq of the event behind the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    pub(crate) seq: Option<u64>,
+    pub(crate) change: LiveQueryChange<ID, T>,
+}
+
+impl<ID, T: TS> LiveQueryUpdate<ID, T> {
+    pub fn query_id(&self) -> u32 {
+        self.query_id
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    pub fn change(&self) -> &LiveQueryChange<ID, T> {
+        &self.change
+    }
+
+    pub fn into_change(self) -> LiveQueryChange<ID, T> {
+        self.change
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+// the server's side of a `LiveQuery`: which records are in its result set,
+// so events can be turned into changes to it. it reads records out of a
+// `Materializer` the server applies every event to first, so patches are
+// judged by the whole record they leave behind
+pub struct LiveQueryTracker<ID, C> {
+    query: LiveQuery<C>,
+    members: HashSet<ID>,
+}
+
+impl<ID: Eq + Hash + Clone, C: PartialEq> LiveQueryTracker<ID, C> {
+    pub fn new<T: Serialize>(query: LiveQuery<C>, records: &Materializer<ID, T>) -> Self {
+        let members = records
+            .records()
+            .iter()
+            .filter(|(_, record)| matches(&query.filter, record))
+            .map(|(id, _)| id.clone())
+            .collect();
+        Self { query, members }
+    }
+
+    pub fn query(&self) -> &LiveQuery<C> {
+        &self.query
+    }
+
+    pub fn contains(&self, id: &ID) -> bool {
+        self.members.contains(id)
+    }
+
+    // the result set as of now, for the client to start from
+    pub fn results<T: Serialize + TS + Clone>(
+        &self,
+        records: &Materializer<ID, T>,
+    ) -> Vec<SnapshotEntry<ID, T>> {
+        self.members
+            .iter()
+            .filter_map(|id| Some(SnapshotEntry::new(id.clone(), records.get(id)?.clone())))
+            .collect()
+    }
+
+    // `records` must already have `event` applied. `None` if the event
+    // leaves the result set as it was
+    pub fn apply<T, P>(
+        &mut self,
+        event: &Event<ID, T, C, P>,
+        records: &Materializer<ID, T>,
+    ) -> Option<LiveQueryUpdate<ID, T>>
+    where
+        T: Serialize + TS + Clone,
+        P: Serialize + TS,
+    {
+        if event.collection() != Some(&self.query.collection) {
+            return None;
+        }
+        let id = event.id()?.clone();
+        let record = records.get(&id);
+        let matching = record.is_some_and(|record| matches(&self.query.filter, record));
+        let was_member = self.members.contains(&id);
+        let change = match (was_member, record, matching) {
+            (true, None, _) => LiveQueryChange::Removed { id: id.clone() },
+            (true, Some(record), true) => LiveQueryChange::Updated {
+                id: id.clone(),
+                data: record.clone(),
+            },
+            (true, Some(_), false) => LiveQueryChange::LeftResultSet { id: id.clone() },
+            // an upsert can't tell whether it made the record, so only
+            // inserts count as adding one
+            (false, Some(record), true) => match event.verb() {
+                EventVerb::Insert(_) => LiveQueryChange::Added {
+                    id: id.clone(),
+                    data: record.clone(),
+                },
+                _ => LiveQueryChange::EnteredResultSet {
+                    id: id.clone(),
+                    data: record.clone(),
+                },
+            },
+            (false, _, _) => return None,
+        };
+        match matching {
+            true => self.members.insert(id),
+            false => self.members.remove(&id),
+        };
+        Some(LiveQueryUpdate {
+            query_id: self.query.query_id,
+            seq: event.seq(),
+            change,
+        })
+    }
+}
+
+fn matches<T: Serialize>(filter: &Filter, record: &T) -> bool {
+    serde_json::to_value(record).is_ok_and(|record| filter.matches(&record))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{
+        Event, Filter, LiveQuery, LiveQueryChange, LiveQueryTracker, Materializer, Sequenced,
+        Syncable,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn changes_follow_the_result_set() {
+        let of_breed = |id, breed: &str| DoggoRecord {
+            name: format!("dog {id}"),
+            breed: breed.to_string(),
+            ..doggo(id)
+        };
+        let mut records = Materializer::new();
+        records.apply(of_breed(1, "Poodle").to_upsert_event());
+        records.apply(of_breed(2, "Beagle").to_upsert_event());
+
+        let filter = Filter::eq("breed", "Poodle").and(Filter::lt("id", 10));
+        let query = LiveQuery {
+            query_id: 3,
+            collection: Collection::Dogs,
+            filter,
+        };
+        let mut tracker = LiveQueryTracker::new(query, &records);
+        assert!(tracker.contains(&1) && !tracker.contains(&2));
+
+        let events: Vec<DoggoEvent> = vec![
+            of_breed(2, "Poodle").to_update_event(),
+            of_breed(1, "Husky").to_update_event(),
+            of_breed(3, "Beagle").to_upsert_event(),
+            of_breed(2, "Poodle").to_update_event(),
+            Event::new_delete_event(2, Collection::Dogs),
+            of_breed(11, "Poodle").to_upsert_event(),
+        ];
+        let mut changes = Vec::new();
+        for (seq, mut event) in events.into_iter().enumerate() {
+            event.set_seq(seq as u64);
+            records.apply(event.clone());
+            if let Some(update) = tracker.apply(&event, &records) {
+                assert_eq!((update.query_id(), update.seq()), (3, Some(seq as u64)));
+                changes.push(update.into_change());
+            }
+        }
+        let ids: Vec<_> = changes
+            .iter()
+            .map(|change| match change {
+                LiveQueryChange::EnteredResultSet { id, .. } => format!("entered {id}"),
+                LiveQueryChange::LeftResultSet { id } => format!("left {id}"),
+                LiveQueryChange::Updated { id, .. } => format!("updated {id}"),
+                LiveQueryChange::Removed { id } => format!("removed {id}"),
+                LiveQueryChange::Added { id, .. } => format!("added {id}"),
+            })
+            .collect();
+        assert_eq!(ids, ["entered 2", "left 1", "updated 2", "removed 2"]);
+
+        let json =
+            serde_json::to_string(&Filter::gt("id", 1).or(Filter::eq("name", "Barky").negate()))
+                .unwrap();
+        insta::assert_snapshot!(json, @r###"{"op":"or","filters":[{"op":"gt","field":"id","value":1},{"op":"not","filter":{"op":"eq","field":"name","value":"Barky"}}]}"###);
+        assert_eq!(
+            serde_json::from_str::<Filter>(&json).unwrap(),
+            Filter::gt("id", 1).or(Filter::eq("name", "Barky").negate())
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::Event;
+
+// where `occurred_at` timestamps come from, in milliseconds since the unix
+// epoch. any `Fn() -> u64` is a clock, which makes tests deterministic
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+// resolves two events for the same record with last-write-wins: the later
+// `occurred_at` wins, unstamped events lose to stamped ones and ties go to
+// the higher seq, then to `incoming`. the caller is expected to only merge
+// events with the same collection and id
+pub fn merge<ID, T: Serialize + TS, C, P: Serialize + TS>(
+    current: Event<ID, T, C, P>,
+    incoming: Event<ID, T, C, P>,
+) -> Event<ID, T, C, P> {
+    let key = |event: &Event<ID, T, C, P>| (event.occurred_at(), event.seq());
+    if key(&current) > key(&incoming) {
+        current
+    } else {
+        incoming
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{merge, Clock, Event, EventVerb, Syncable, SystemClock, WsBody};
+
+    #[test]
+    fn later_writes_win() {
+        let doggo = doggo(1);
+        let now = Cell::new(1_000);
+        let clock = || now.replace(now.get() + 1);
+
+        let renamed = DoggoRecord {
+            name: "Woofy".to_string(),
+            ..doggo.clone()
+        }
+        .to_update_event_at(&clock);
+        let deleted = doggo.to_delete_event_at(&clock);
+        assert_eq!(renamed.occurred_at(), Some(1_000));
+
+        let json = renamed.clone().into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"occurred_at":1000,"verb":{"type":"update","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Woofy","breed":"Poodle"}}}}}"###);
+        let body = WsBody::<Event<u32, DoggoRecord, Collection>>::from_json(&json).unwrap();
+        assert_eq!(body.into_data().occurred_at(), Some(1_000));
+
+        // arrival order doesn't matter
+        let merged = merge(deleted.clone(), renamed.clone());
+        assert!(matches!(merged.verb, EventVerb::Delete(_)));
+        let merged = merge(renamed, deleted);
+        assert!(matches!(merged.verb, EventVerb::Delete(_)));
+
+        let stamped = doggo.clone().to_upsert_event().stamped(&SystemClock);
+        assert!(stamped.occurred_at() <= Some(SystemClock.now()));
+        let unstamped = doggo.to_upsert_event();
+        assert!(merge(stamped, unstamped).occurred_at().is_some());
+    }
+}
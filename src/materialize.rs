@@ -0,0 +1,510 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use serde::{de::DeserializeOwned, Serialize};
+use ts_rs::TS;
+
+use crate::crdt::Merge;
+use crate::{Error, Event, EventVerb, JsonPatch, Listener, NoPatch, Snapshot};
+
+// how the payload of a `Patch` verb is applied to a record
+pub trait ApplyPatch<T> {
+    // leaves `record` as is and returns the patched copy
+    fn apply_patch(&self, record: &T) -> Result<T, Error>;
+}
+
+impl<T: Serialize + DeserializeOwned> ApplyPatch<T> for JsonPatch {
+    fn apply_patch(&self, record: &T) -> Result<T, Error> {
+        self.apply_to(record)
+    }
+}
+
+impl<T> ApplyPatch<T> for NoPatch {
+    fn apply_patch(&self, _: &T) -> Result<T, Error> {
+        match *self {}
+    }
+}
+
+// an event that didn't fit the materialized state. the materializer resolves
+// each one the same way, and reports it to the `on_conflict` hook
+#[derive(Debug)]
+pub enum Conflict<ID> {
+    // an event that needs an id arrived without one. it is dropped
+    MissingId,
+    // the inserted record replaces the existing one
+    InsertExisting(ID),
+    // the updated record is inserted
+    UpdateMissing(ID),
+    // the patch is dropped
+    PatchMissing(ID),
+    // the record is left as it was
+    PatchFailed(ID, Error),
+    // nothing to delete
+    DeleteMissing(ID),
+    // under `UpsertPolicy::InsertOrError`, the record is left as it was
+    UpsertExisting(ID),
+}
+
+// what an upsert of a record that already exists does. whatever the policy,
+// an upsert of a missing record inserts it
+#[derive(Default)]
+pub enum UpsertPolicy<T> {
+    // the upserted record replaces it
+    #[default]
+    InsertOrReplace,
+    // the upserted record is folded into it, see `UpsertPolicy::merging`
+    InsertOrMerge(fn(&mut T, &T)),
+    // it's left as it was and the upsert reported as a conflict
+    InsertOrError,
+}
+
+impl<T: Merge> UpsertPolicy<T> {
+    // merges with the record's `Merge`
+    pub fn merging() -> Self {
+        Self::InsertOrMerge(T::merge)
+    }
+}
+
+impl<T> Clone for UpsertPolicy<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UpsertPolicy<T> {}
+
+impl<T> fmt::Debug for UpsertPolicy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UpsertPolicy::InsertOrReplace => "InsertOrReplace",
+            UpsertPolicy::InsertOrMerge(_) => "InsertOrMerge",
+            UpsertPolicy::InsertOrError => "InsertOrError",
+        })
+    }
+}
+
+// turns an event stream into the current records of one collection, keyed by
+// id. events are applied as they arrive, so feed it a listener for a single
+// collection. the events of a transaction are held back until it commits,
+// and dropped if it aborts
+pub struct Materializer<ID, T> {
+    records: HashMap<ID, T>,
+    upsert: UpsertPolicy<T>,
+    on_conflict: Option<Box<dyn FnMut(Conflict<ID>) + Send>>,
+    // the events of open transactions, by txn id
+    pending: HashMap<u32, Vec<Deferred<ID, T>>>,
+}
+
+// an event held back until its transaction commits
+type Deferred<ID, T> = Box<dyn FnOnce(&mut Materializer<ID, T>) + Send>;
+
+impl<ID: Eq + Hash + Clone, T> Materializer<ID, T> {
+    pub fn new() -> Self {
+        Self {
+            records: HashMap::new(),
+            upsert: UpsertPolicy::default(),
+            on_conflict: None,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn with_upsert_policy(mut self, upsert: UpsertPolicy<T>) -> Self {
+        self.upsert = upsert;
+        self
+    }
+
+    pub fn upsert_policy(&self) -> UpsertPolicy<T> {
+        self.upsert
+    }
+
+    pub fn on_conflict(mut self, hook: impl FnMut(Conflict<ID>) + Send + 'static) -> Self {
+        self.on_conflict = Some(Box::new(hook));
+        self
+    }
+
+    pub fn get(&self, id: &ID) -> Option<&T> {
+        self.records.get(id)
+    }
+
+    pub fn records(&self) -> &HashMap<ID, T> {
+        &self.records
+    }
+
+    pub fn into_records(self) -> HashMap<ID, T> {
+        self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // replaces everything with the snapshot's records. the event stream is
+    // then applied from the snapshot's seq
+    pub fn load_snapshot<C>(&mut self, snapshot: Snapshot<ID, T, C>)
+    where
+        T: Serialize + TS,
+    {
+        self.records = snapshot
+            .into_records()
+            .into_iter()
+            .map(|entry| entry.into_parts())
+            .collect();
+    }
+
+    pub fn apply<C, P>(&mut self, event: Event<ID, T, C, P>)
+    where
+        ID: Send + 'static,
+        T: Serialize + TS + Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+    {
+        if let Some(event) = self.hold(event, Self::apply_committed) {
+            self.apply_committed(event);
+        }
+    }
+
+    // applies the held back events of the transaction
+    pub fn commit(&mut self, txn_id: u32) {
+        for deferred in self.pending.remove(&txn_id).into_iter().flatten() {
+            deferred(self);
+        }
+    }
+
+    // drops the held back events of the transaction
+    pub fn abort(&mut self, txn_id: u32) {
+        self.pending.remove(&txn_id);
+    }
+
+    // events of transactions that haven't committed or aborted yet
+    pub fn pending(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    // ends transactions on their markers and holds back their events, to be
+    // applied with `then` on commit. returns the events to apply now
+    fn hold<C, P>(
+        &mut self,
+        event: Event<ID, T, C, P>,
+        then: fn(&mut Self, Event<ID, T, C, P>),
+    ) -> Option<Event<ID, T, C, P>>
+    where
+        ID: Send + 'static,
+        T: Serialize + TS + Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + Send + 'static,
+    {
+        match event.verb {
+            EventVerb::TxnBegin(txn_id) => {
+                self.pending.entry(txn_id).or_default();
+                None
+            }
+            EventVerb::TxnCommit(txn_id) => {
+                self.commit(txn_id);
+                None
+            }
+            EventVerb::TxnAbort(txn_id) => {
+                self.abort(txn_id);
+                None
+            }
+            _ => match event.txn_id() {
+                Some(txn_id) => {
+                    let deferred: Deferred<ID, T> = Box::new(move |records| then(records, event));
+                    self.pending.entry(txn_id).or_default().push(deferred);
+                    None
+                }
+                None => Some(event),
+            },
+        }
+    }
+
+    fn apply_committed<C, P>(&mut self, event: Event<ID, T, C, P>)
+    where
+        T: Serialize + TS,
+        P: Serialize + TS + ApplyPatch<T>,
+    {
+        match event.verb {
+            EventVerb::Insert(resource) => match resource.location.id {
+                Some(id) => {
+                    if self.records.insert(id.clone(), resource.data).is_some() {
+                        self.conflict(Conflict::InsertExisting(id));
+                    }
+                }
+                None => self.conflict(Conflict::MissingId),
+            },
+            EventVerb::Update(resource) => match resource.location.id {
+                Some(id) => {
+                    if self.records.insert(id.clone(), resource.data).is_none() {
+                        self.conflict(Conflict::UpdateMissing(id));
+                    }
+                }
+                None => self.conflict(Conflict::MissingId),
+            },
+            EventVerb::Upsert(resource) => match resource.location.id {
+                Some(id) => match (self.records.entry(id), self.upsert) {
+                    (Entry::Vacant(entry), _) => {
+                        entry.insert(resource.data);
+                    }
+                    (Entry::Occupied(mut entry), UpsertPolicy::InsertOrReplace) => {
+                        entry.insert(resource.data);
+                    }
+                    (Entry::Occupied(mut entry), UpsertPolicy::InsertOrMerge(merge)) => {
+                        merge(entry.get_mut(), &resource.data)
+                    }
+                    (Entry::Occupied(entry), UpsertPolicy::InsertOrError) => {
+                        let id = entry.key().clone();
+                        self.conflict(Conflict::UpsertExisting(id));
+                    }
+                },
+                None => self.conflict(Conflict::MissingId),
+            },
+            EventVerb::Patch(resource) => match resource.location.id {
+                Some(id) => match self.records.get_mut(&id) {
+                    Some(record) => match resource.data.apply_patch(record) {
+                        Ok(patched) => *record = patched,
+                        Err(err) => self.conflict(Conflict::PatchFailed(id, err)),
+                    },
+                    None => self.conflict(Conflict::PatchMissing(id)),
+                },
+                None => self.conflict(Conflict::MissingId),
+            },
+            EventVerb::Delete(resource) => match resource.location.id {
+                Some(id) => {
+                    if self.records.remove(&id).is_none() {
+                        self.conflict(Conflict::DeleteMissing(id));
+                    }
+                }
+                None => self.conflict(Conflict::MissingId),
+            },
+            EventVerb::TxnBegin(_) | EventVerb::TxnCommit(_) | EventVerb::TxnAbort(_) => {}
+        }
+    }
+
+    // receives one event from `listener` and applies it
+    pub async fn apply_next<L, C, P>(&mut self, listener: &mut L) -> Result<(), L::Error>
+    where
+        L: Listener<Item = Event<ID, T, C, P>>,
+        ID: Send + 'static,
+        T: Serialize + TS + Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+    {
+        let event = listener.recv().await?;
+        self.apply(event);
+        Ok(())
+    }
+
+    fn conflict(&mut self, conflict: Conflict<ID>) {
+        if let Some(hook) = &mut self.on_conflict {
+            hook(conflict);
+        }
+    }
+}
+
+impl<ID: Eq + Hash + Clone, T: Merge> Materializer<ID, T> {
+    // like `apply`, but inserts, updates and upserts are merged into the
+    // record instead of replacing it, so replicas of `crdt` payloads converge
+    // whatever order their events arrive in
+    pub fn apply_merging<C, P>(&mut self, event: Event<ID, T, C, P>)
+    where
+        ID: Send + 'static,
+        T: Serialize + TS + Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+    {
+        if let Some(event) = self.hold(event, Self::merge_committed) {
+            self.merge_committed(event);
+        }
+    }
+
+    fn merge_committed<C, P>(&mut self, event: Event<ID, T, C, P>)
+    where
+        T: Serialize + TS,
+        P: Serialize + TS + ApplyPatch<T>,
+    {
+        let (id, data) = match event.verb {
+            EventVerb::Insert(resource) => (resource.location.id, resource.data),
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => {
+                (resource.location.id, resource.data)
+            }
+            verb => return self.apply_committed(Event::new(verb)),
+        };
+        let Some(id) = id else {
+            return self.conflict(Conflict::MissingId);
+        };
+        match self.records.entry(id) {
+            Entry::Occupied(mut entry) => entry.get_mut().merge(&data),
+            Entry::Vacant(entry) => {
+                entry.insert(data);
+            }
+        }
+    }
+}
+
+impl<ID: Eq + Hash + Clone, T> Default for Materializer<ID, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ID, T, C, P> Extend<Event<ID, T, C, P>> for Materializer<ID, T>
+where
+    ID: Eq + Hash + Clone + Send + 'static,
+    T: Serialize + TS + Send + 'static,
+    C: Send + 'static,
+    P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+{
+    fn extend<I: IntoIterator<Item = Event<ID, T, C, P>>>(&mut self, events: I) {
+        events.into_iter().for_each(|event| self.apply(event));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::crdt::Merge;
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, Event, JsonPatch, Materializer, Service, Snapshot, SnapshotEntry,
+        Syncable, Txn, UpsertPolicy,
+    };
+
+    #[test]
+    fn events_are_materialized_in_order() {
+        let named = |name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(1)
+        };
+        let conflicts = Arc::new(Mutex::new(Vec::new()));
+        let seen = conflicts.clone();
+        let mut materializer = Materializer::new().on_conflict(move |conflict| {
+            seen.lock().unwrap().push(format!("{conflict:?}"));
+        });
+
+        materializer.load_snapshot(Snapshot::new(
+            Collection::Dogs,
+            0,
+            vec![SnapshotEntry::new(2, named("Fluffy"))],
+        ));
+
+        let service = BroadcastService::new();
+        let mut listener = service.listener();
+        let patch = named("Woofy").to_json_patch_event(&named("Barky")).unwrap();
+        service
+            .publish(named("Barky").to_upsert_event().into_patchable())
+            .unwrap();
+        service.publish(patch).unwrap();
+        service
+            .publish(
+                Event::<u32, DoggoRecord, Collection, JsonPatch>::new_delete_event(
+                    2,
+                    Collection::Dogs,
+                ),
+            )
+            .unwrap();
+        service
+            .publish(Event::new_delete_event(3, Collection::Dogs))
+            .unwrap();
+
+        block_on(async {
+            for _ in 0..4 {
+                materializer.apply_next(&mut listener).await.unwrap();
+            }
+        });
+        assert_eq!(materializer.len(), 1);
+        assert_eq!(materializer.get(&1).unwrap().name, "Woofy");
+        assert_eq!(*conflicts.lock().unwrap(), ["DeleteMissing(3)"]);
+
+        let stray = DoggoRecord {
+            id: 4,
+            ..named("Stray")
+        };
+        materializer.extend([named("Barky").to_update_event(), stray.to_update_event()]);
+        assert_eq!(materializer.get(&1).unwrap().name, "Barky");
+        assert_eq!(materializer.len(), 2);
+        assert_eq!(
+            conflicts.lock().unwrap().last().unwrap(),
+            "UpdateMissing(4)"
+        );
+    }
+
+    impl Merge for DoggoRecord {
+        // keeps the first name a doggo was given
+        fn merge(&mut self, other: &Self) {
+            if self.name.is_empty() {
+                self.name = other.name.clone();
+            }
+            self.breed = other.breed.clone();
+        }
+    }
+
+    #[test]
+    fn upserts_follow_the_policy() {
+        let named = |name: &str, breed: &str| DoggoRecord {
+            name: name.to_string(),
+            breed: breed.to_string(),
+            ..doggo(1)
+        };
+        let upserts = || {
+            [
+                named("Barky", "Poodle").to_upsert_event(),
+                named("Woofy", "Beagle").to_upsert_event(),
+            ]
+        };
+        let mut replacing = Materializer::new();
+        replacing.extend(upserts());
+        assert_eq!(replacing.get(&1).unwrap().name, "Woofy");
+
+        let mut merging = Materializer::new().with_upsert_policy(UpsertPolicy::merging());
+        merging.extend(upserts());
+        let merged = merging.get(&1).unwrap();
+        assert_eq!((&*merged.name, &*merged.breed), ("Barky", "Beagle"));
+
+        let conflicts = Arc::new(Mutex::new(Vec::new()));
+        let seen = conflicts.clone();
+        let mut strict = Materializer::new()
+            .with_upsert_policy(UpsertPolicy::InsertOrError)
+            .on_conflict(move |conflict| seen.lock().unwrap().push(format!("{conflict:?}")));
+        strict.extend(upserts());
+        assert_eq!(strict.get(&1).unwrap().name, "Barky");
+        assert_eq!(*conflicts.lock().unwrap(), ["UpsertExisting(1)"]);
+    }
+
+    #[test]
+    fn transactions_apply_on_commit_only() {
+        let named = |id, name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(id)
+        };
+        let (committed, aborted) = (
+            Txn::builder().txn_id(1).build(),
+            Txn::builder().txn_id(2).build(),
+        );
+        let mut records = Materializer::new();
+        records.apply(named(1, "Barky").to_upsert_event());
+        records.extend([
+            committed.begin_event(),
+            aborted.begin_event(),
+            committed.stamp(named(1, "Sir Barks").to_update_event()),
+            aborted.stamp(named(1, "Lord Barks").to_update_event()),
+            aborted.stamp(named(2, "Woofy").to_upsert_event()),
+        ]);
+        assert_eq!(records.pending(), 3);
+        assert_eq!(records.get(&1).unwrap().name, "Barky");
+
+        let commit: Event<u32, DoggoRecord, Collection> = committed.commit_event();
+        records.apply(commit);
+        assert_eq!(records.get(&1).unwrap().name, "Sir Barks");
+        let abort: Event<u32, DoggoRecord, Collection> = aborted.abort_event();
+        records.apply(abort);
+        assert_eq!(records.pending(), 0);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records.get(&1).unwrap().name, "Sir Barks");
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+// who made a change and why, so apps can attribute and trace events. `extra`
+// carries anything app specific
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct EventMeta {
+    // the user the change was made by or on behalf of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    actor_id: Option<String>,
+    // the client or device the change came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    // shared by every event that belongs to the same request or workflow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    // the event or command this one is a consequence of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    causation_id: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[ts(type = "Record<string, unknown>")]
+    extra: HashMap<String, Value>,
+}
+
+impl EventMeta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_actor_id(mut self, actor_id: impl Into<String>) -> Self {
+        self.actor_id = Some(actor_id.into());
+        self
+    }
+
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+
+    pub fn with_causation_id(mut self, causation_id: impl Into<String>) -> Self {
+        self.causation_id = Some(causation_id.into());
+        self
+    }
+
+    pub fn with_extra(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn actor_id(&self) -> Option<&str> {
+        self.actor_id.as_deref()
+    }
+
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    pub fn causation_id(&self) -> Option<&str> {
+        self.causation_id.as_deref()
+    }
+
+    pub fn extra(&self) -> &HashMap<String, Value> {
+        &self.extra
+    }
+
+    pub fn extra_mut(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.extra
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Event, EventMeta, Syncable, WsBody};
+
+    #[test]
+    fn meta_travels_with_the_event() {
+        let doggo = doggo(1);
+        let meta = EventMeta::new()
+            .with_actor_id("user-7")
+            .with_correlation_id("req-1")
+            .with_extra("reason", "rename");
+        let json = doggo
+            .to_update_event()
+            .with_meta(meta.clone())
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"meta":{"actor_id":"user-7","correlation_id":"req-1","extra":{"reason":"rename"}},"verb":{"type":"update","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}}}"###);
+
+        let body = WsBody::<Event<u32, DoggoRecord, Collection>>::from_json(&json).unwrap();
+        assert_eq!(body.into_data().meta(), Some(&meta));
+    }
+}
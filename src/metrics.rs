@@ -0,0 +1,173 @@
+// counters, gauges and histograms of what services do, reported to a
+// `Recorder`. nothing is recorded until one is installed, globally or for the
+// current thread, so uninstrumented apps pay for a thread-local lookup only
+use std::cell::RefCell;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusRecorder;
+
+// events published, whether or not anything received them
+pub const EVENTS_PUBLISHED: &str = "rsp_events_published_total";
+// one per listener an event was handed to
+pub const EVENTS_DELIVERED: &str = "rsp_events_delivered_total";
+// events a full listener buffer didn't take
+pub const EVENTS_DROPPED: &str = "rsp_events_dropped_total";
+// how long encoding an event's json took
+pub const SERIALIZATION_SECONDS: &str = "rsp_serialization_seconds";
+// how many connections each published event was routed to
+pub const FAN_OUT: &str = "rsp_fan_out";
+// the events queued for the slowest connection
+pub const BUFFER_DEPTH: &str = "rsp_buffer_depth";
+
+pub trait Recorder: Send + Sync {
+    fn increment_counter(&self, name: &'static str, by: u64);
+    fn set_gauge(&self, name: &'static str, value: f64);
+    fn record_histogram(&self, name: &'static str, value: f64);
+}
+
+impl<R: Recorder + ?Sized> Recorder for Arc<R> {
+    fn increment_counter(&self, name: &'static str, by: u64) {
+        (**self).increment_counter(name, by)
+    }
+
+    fn set_gauge(&self, name: &'static str, value: f64) {
+        (**self).set_gauge(name, value)
+    }
+
+    fn record_histogram(&self, name: &'static str, value: f64) {
+        (**self).record_histogram(name, value)
+    }
+}
+
+static GLOBAL: OnceLock<Box<dyn Recorder>> = OnceLock::new();
+
+thread_local! {
+    static SCOPED: RefCell<Option<Arc<dyn Recorder>>> = const { RefCell::new(None) };
+}
+
+// the recorder of every thread without one of its own. false if one was set
+// already
+pub fn set_global_recorder(recorder: impl Recorder + 'static) -> bool {
+    GLOBAL.set(Box::new(recorder)).is_ok()
+}
+
+// runs `f` with `recorder` receiving the metrics of this thread
+pub fn with_recorder<R>(recorder: impl Recorder + 'static, f: impl FnOnce() -> R) -> R {
+    let recorder: Arc<dyn Recorder> = Arc::new(recorder);
+    let previous = SCOPED.with(|scoped| scoped.replace(Some(recorder)));
+    let out = f();
+    SCOPED.with(|scoped| *scoped.borrow_mut() = previous);
+    out
+}
+
+fn with_current(f: impl FnOnce(&dyn Recorder)) {
+    SCOPED.with(|scoped| match &*scoped.borrow() {
+        Some(recorder) => f(recorder.as_ref()),
+        None => {
+            if let Some(recorder) = GLOBAL.get() {
+                f(recorder.as_ref())
+            }
+        }
+    });
+}
+
+pub(crate) fn counter(name: &'static str, by: u64) {
+    with_current(|recorder| recorder.increment_counter(name, by));
+}
+
+pub(crate) fn gauge(name: &'static str, value: f64) {
+    with_current(|recorder| recorder.set_gauge(name, value));
+}
+
+pub(crate) fn histogram(name: &'static str, value: f64) {
+    with_current(|recorder| recorder.record_histogram(name, value));
+}
+
+pub(crate) fn seconds(name: &'static str, elapsed: Duration) {
+    histogram(name, elapsed.as_secs_f64());
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::metrics::{self, Recorder};
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, Event, Listener, OverflowPolicy, PublishSerialized, Service, Subscribe,
+        SubscriptionManager, Syncable,
+    };
+
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<String>>);
+
+    impl Recorder for Recorded {
+        fn increment_counter(&self, name: &'static str, by: u64) {
+            self.0.lock().unwrap().push(format!("{name} +{by}"));
+        }
+
+        fn set_gauge(&self, name: &'static str, value: f64) {
+            self.0.lock().unwrap().push(format!("{name} = {value}"));
+        }
+
+        fn record_histogram(&self, name: &'static str, value: f64) {
+            // latencies vary from run to run
+            let value = match name {
+                metrics::SERIALIZATION_SECONDS => "..".to_owned(),
+                _ => value.to_string(),
+            };
+            self.0.lock().unwrap().push(format!("{name} <- {value}"));
+        }
+    }
+
+    #[test]
+    fn services_report_to_the_recorder() {
+        let recorded = Arc::new(Recorded::default());
+        let doggo = doggo(1);
+
+        metrics::with_recorder(recorded.clone(), || {
+            let service = BroadcastService::new();
+            let mut listener = service.listener();
+            service.publish_serialized(doggo.to_upsert_event()).unwrap();
+            block_on(async {
+                listener.recv().await.unwrap().json().unwrap();
+            });
+
+            let manager = SubscriptionManager::<Event<u32, DoggoRecord, Collection>>::new();
+            let subscribe = Subscribe {
+                collections: vec![Collection::Dogs],
+                patterns: Vec::new(),
+                from_seq: None,
+            };
+            let fast = manager.connect();
+            let slow = manager.connect_buffered(1, OverflowPolicy::DropNewest);
+            manager.handle_subscribe(fast.connection(), &subscribe);
+            manager.handle_subscribe(slow.connection(), &subscribe);
+            for id in 1..=2 {
+                manager.publish(Event::new_delete_event(id, Collection::Dogs));
+            }
+        });
+
+        let recorded = recorded.0.lock().unwrap().join("\n");
+        insta::assert_snapshot!(recorded, @r###"
+        rsp_events_published_total +1
+        rsp_events_delivered_total +1
+        rsp_serialization_seconds <- ..
+        rsp_events_published_total +1
+        rsp_events_delivered_total +2
+        rsp_events_dropped_total +0
+        rsp_fan_out <- 2
+        rsp_buffer_depth = 1
+        rsp_events_published_total +1
+        rsp_events_delivered_total +1
+        rsp_events_dropped_total +1
+        rsp_fan_out <- 1
+        rsp_buffer_depth = 2
+        "###);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, MutexGuard};
+
+use super::{Recorder, FAN_OUT};
+
+// the upper bounds of the buckets of histograms, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+const FAN_OUT_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0];
+
+// keeps every metric in memory and renders them in the prometheus text
+// format, for an http handler to serve on `/metrics`
+pub struct PrometheusRecorder {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    counters: BTreeMap<&'static str, u64>,
+    gauges: BTreeMap<&'static str, f64>,
+    histograms: BTreeMap<&'static str, Histogram>,
+}
+
+struct Histogram {
+    bounds: &'static [f64],
+    // not cumulative, one more than `bounds` for +Inf
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl PrometheusRecorder {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn render(&self) -> String {
+        let state = self.lock();
+        let mut out = String::new();
+        for (name, value) in &state.counters {
+            let _ = writeln!(out, "# TYPE {name} counter\n{name} {value}");
+        }
+        for (name, value) in &state.gauges {
+            let _ = writeln!(out, "# TYPE {name} gauge\n{name} {value}");
+        }
+        for (name, histogram) in &state.histograms {
+            let _ = writeln!(out, "# TYPE {name} histogram");
+            let mut count = 0;
+            for (index, bucket) in histogram.counts.iter().enumerate() {
+                count += bucket;
+                let le = match histogram.bounds.get(index) {
+                    Some(bound) => bound.to_string(),
+                    None => "+Inf".to_owned(),
+                };
+                let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {count}");
+            }
+            let _ = writeln!(out, "{name}_sum {}\n{name}_count {count}", histogram.sum);
+        }
+        out
+    }
+}
+
+impl Default for PrometheusRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder for PrometheusRecorder {
+    fn increment_counter(&self, name: &'static str, by: u64) {
+        *self.lock().counters.entry(name).or_default() += by;
+    }
+
+    fn set_gauge(&self, name: &'static str, value: f64) {
+        self.lock().gauges.insert(name, value);
+    }
+
+    fn record_histogram(&self, name: &'static str, value: f64) {
+        let mut state = self.lock();
+        let histogram = state.histograms.entry(name).or_insert_with(|| {
+            let bounds = match name {
+                FAN_OUT => FAN_OUT_BUCKETS,
+                _ => LATENCY_BUCKETS,
+            };
+            Histogram {
+                bounds,
+                counts: vec![0; bounds.len() + 1],
+                sum: 0.0,
+            }
+        });
+        let index = histogram.bounds.partition_point(|bound| *bound < value);
+        histogram.counts[index] += 1;
+        histogram.sum += value;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::metrics::{PrometheusRecorder, Recorder, BUFFER_DEPTH, EVENTS_PUBLISHED, FAN_OUT};
+
+    #[test]
+    fn renders_the_text_format() {
+        let recorder = PrometheusRecorder::new();
+        recorder.increment_counter(EVENTS_PUBLISHED, 2);
+        recorder.increment_counter(EVENTS_PUBLISHED, 1);
+        recorder.set_gauge(BUFFER_DEPTH, 4.0);
+        for fan_out in [0.0, 3.0, 3.0, 2000.0] {
+            recorder.record_histogram(FAN_OUT, fan_out);
+        }
+        insta::assert_snapshot!(recorder.render(), @r###"
+        # TYPE rsp_events_published_total counter
+        rsp_events_published_total 3
+        # TYPE rsp_buffer_depth gauge
+        rsp_buffer_depth 4
+        # TYPE rsp_fan_out histogram
+        rsp_fan_out_bucket{le="0"} 1
+        rsp_fan_out_bucket{le="1"} 1
+        rsp_fan_out_bucket{le="2"} 1
+        rsp_fan_out_bucket{le="5"} 3
+        rsp_fan_out_bucket{le="10"} 3
+        rsp_fan_out_bucket{le="50"} 3
+        rsp_fan_out_bucket{le="100"} 3
+        rsp_fan_out_bucket{le="500"} 3
+        rsp_fan_out_bucket{le="1000"} 3
+        rsp_fan_out_bucket{le="+Inf"} 4
+        rsp_fan_out_sum 2006
+        rsp_fan_out_count 4
+        "###);
+    }
+}
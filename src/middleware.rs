@@ -0,0 +1,158 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    DeadLetter, DeadLetterSink, DeadLetterStage, Error, Service, Shutdown, ValidationErrors,
+};
+
+// what a middleware decided about an event
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome<T> {
+    // hands the event, changed or not, to the next middleware
+    Continue(T),
+    // stops the publish, failing it with `Error::Rejected`
+    Reject(String),
+    // stops the publish, failing it with `Error::Validation`
+    Invalid(ValidationErrors),
+}
+
+// looks at every event before it is published, e.g. to validate it, enrich
+// its meta or write it to an audit log
+pub trait Middleware<T>: Send + Sync {
+    fn on_publish(&self, event: T) -> Outcome<T>;
+}
+
+impl<T, F: Fn(T) -> Outcome<T> + Send + Sync> Middleware<T> for F {
+    fn on_publish(&self, event: T) -> Outcome<T> {
+        self(event)
+    }
+}
+
+// runs published events through a chain of middleware, in the order they
+// were added, before `inner` sees them. adding more doesn't change the type,
+// so a service can be built up from config
+pub struct MiddlewareService<S, T> {
+    inner: S,
+    chain: Vec<Box<dyn Middleware<T>>>,
+    dead_letters: Option<Arc<dyn DeadLetterSink<T>>>,
+}
+
+impl<S, T> MiddlewareService<S, T> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            chain: Vec::new(),
+            dead_letters: None,
+        }
+    }
+
+    pub fn with_middleware(mut self, middleware: impl Middleware<T> + 'static) -> Self {
+        self.chain.push(Box::new(middleware));
+        self
+    }
+
+    // receives the events middleware rejects
+    pub fn with_dead_letters(mut self, sink: impl DeadLetterSink<T> + 'static) -> Self {
+        self.dead_letters = Some(Arc::new(sink));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<T, S> Service<T> for MiddlewareService<S, T>
+where
+    T: Clone,
+    S: Service<T>,
+    S::Error: From<Error>,
+{
+    type Listener = S::Listener;
+    type Error = S::Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        for middleware in &self.chain {
+            // the middleware takes the event, so a rejected one is only
+            // around to dead-letter if it was copied first
+            let kept = self.dead_letters.as_ref().map(|_| event.clone());
+            let err = match middleware.on_publish(event) {
+                Outcome::Continue(next) => {
+                    event = next;
+                    continue;
+                }
+                Outcome::Reject(reason) => Error::Rejected(reason),
+                Outcome::Invalid(errors) => Error::Validation(errors),
+            };
+            if let (Some(sink), Some(kept)) = (&self.dead_letters, kept) {
+                sink.dead_letter(DeadLetter::new(kept, DeadLetterStage::Rejected, &err));
+            }
+            return Err(err.into());
+        }
+        self.inner.publish(event)
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.inner.listener()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        self.inner.listener_from(seq)
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::middleware::Outcome;
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Error, Event, EventMeta, Listener, Service, Syncable};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn middleware_runs_in_order() {
+        let audit = Arc::new(Mutex::new(Vec::new()));
+        let log = audit.clone();
+        let service = BroadcastService::<DoggoEvent>::new()
+            .with_middleware(|event: DoggoEvent| match event.data() {
+                Some(doggo) if doggo.name.is_empty() => Outcome::Reject("unnamed".to_owned()),
+                _ => Outcome::Continue(event),
+            })
+            .with_middleware(|mut event: DoggoEvent| {
+                *event.meta_mut() = EventMeta::new().with_origin("api");
+                Outcome::Continue(event)
+            })
+            .with_middleware(move |event: DoggoEvent| {
+                log.lock().unwrap().push(event.verb().name());
+                Outcome::Continue(event)
+            });
+        let mut listener = service.listener();
+        let named = |name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(1)
+        };
+
+        assert!(matches!(
+            service.publish(named("").to_upsert_event()),
+            Err(Error::Rejected(reason)) if reason == "unnamed"
+        ));
+        service.publish(named("Barky").to_upsert_event()).unwrap();
+        let event = block_on(listener.recv()).unwrap();
+        assert_eq!(event.meta().unwrap().origin(), Some("api"));
+        assert_eq!(*audit.lock().unwrap(), vec!["upsert"]);
+    }
+}
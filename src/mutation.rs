@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{ConflictError, Mutate, ValidationErrors, WsBody};
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Mutate<ID, T, C, P> {
+    pub fn accept(&self, seq: u64) -> MutationResult<ID, C> {
+        MutationResult::accepted(self.request_id, seq)
+    }
+
+    pub fn reject(&self, rejection: Rejection<ID, C>) -> MutationResult<ID, C> {
+        MutationResult::rejected(self.request_id, rejection)
+    }
+}
+
+// why the server refused a write
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+#[ts(export)]
+pub enum Rejection<ID, C> {
+    Conflict(ConflictError<ID, C>),
+    NotFound,
+    Forbidden,
+    Invalid(String),
+    // the record broke the rules of its `Validate`, field by field
+    Validation(ValidationErrors),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+#[ts(export)]
+pub enum MutationStatus<ID, C> {
+    Accepted,
+    Rejected(Rejection<ID, C>),
+}
+
+// server -> client outcome of a `Mutate`. an accepted write also
+// arrives as an event with `seq`; `assigned_id` is the id the server gave a
+// record the client inserted without one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct MutationResult<ID, C> {
+    pub request_id: u32,
+    pub status: MutationStatus<ID, C>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_id: Option<ID>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number")]
+    pub seq: Option<u64>,
+}
+
+impl<ID, C> MutationResult<ID, C> {
+    pub fn accepted(request_id: u32, seq: u64) -> Self {
+        Self {
+            request_id,
+            status: MutationStatus::Accepted,
+            assigned_id: None,
+            seq: Some(seq),
+        }
+    }
+
+    pub fn rejected(request_id: u32, rejection: Rejection<ID, C>) -> Self {
+        Self {
+            request_id,
+            status: MutationStatus::Rejected(rejection),
+            assigned_id: None,
+            seq: None,
+        }
+    }
+
+    pub fn with_assigned_id(mut self, id: ID) -> Self {
+        self.assigned_id = Some(id);
+        self
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        matches!(self.status, MutationStatus::Accepted)
+    }
+
+    pub fn rejection(&self) -> Option<&Rejection<ID, C>> {
+        match &self.status {
+            MutationStatus::Accepted => None,
+            MutationStatus::Rejected(rejection) => Some(rejection),
+        }
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+impl<ID, C> From<ConflictError<ID, C>> for Rejection<ID, C> {
+    fn from(conflict: ConflictError<ID, C>) -> Self {
+        Self::Conflict(conflict)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Command, Mutate, MutationResult, Rejection, Syncable, WsBody};
+
+    #[test]
+    fn results_answer_requests() {
+        let doggo = doggo(1);
+        let update = doggo.to_update_event().with_expected_revision(3);
+        let request = Mutate::new(7, update);
+        let json = Command::<u32, DoggoRecord, Collection>::Mutate(request.clone())
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"type":"mutate","payload":{"request_id":7,"event":{"verb":{"type":"update","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"},"expected_revision":3}}}}}}"###);
+
+        let conflict = request.event.check_revision(Some(4)).unwrap_err();
+        let results = [
+            request.accept(12).with_assigned_id(1),
+            request.reject(conflict.into()),
+            request.reject(Rejection::Invalid("name is too long".to_string())),
+        ];
+        let json: Vec<_> = results
+            .into_iter()
+            .map(|result| result.into_ws_body().try_json().unwrap())
+            .collect();
+        insta::assert_snapshot!(json.join("\n"), @r###"
+        {"data":{"request_id":7,"status":{"type":"accepted"},"assigned_id":1,"seq":12}}
+        {"data":{"request_id":7,"status":{"type":"rejected","payload":{"type":"conflict","payload":{"collection":"Dogs","id":1,"expected_revision":3,"current_revision":4}}}}}
+        {"data":{"request_id":7,"status":{"type":"rejected","payload":{"type":"invalid","payload":"name is too long"}}}}
+        "###);
+
+        let body = WsBody::<MutationResult<u32, Collection>>::from_json(&json[1]).unwrap();
+        let result = body.into_data();
+        assert!(!result.is_accepted());
+        assert!(matches!(result.rejection(), Some(Rejection::Conflict(_))));
+    }
+}
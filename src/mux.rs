@@ -0,0 +1,190 @@
+// several independent subscriptions over one connection, instead of a socket
+// per collection. each channel is a listener of its own behind an
+// `AckListener`, so seqs, acks and redelivery on one never touch another.
+// what goes out is tagged with its channel, and acks coming back are routed by
+// theirs
+use std::collections::BTreeMap;
+use std::future::poll_fn;
+use std::task::Poll;
+
+use serde::Serialize;
+
+use crate::{AckListener, Acknowledgement, Error, Listener, Sequenced, WsBody};
+
+pub struct Mux<L: Listener> {
+    channels: BTreeMap<u32, AckListener<L>>,
+    // the channel polled first next time, so a busy one can't starve the rest
+    next: u32,
+}
+
+impl<L: Listener> Mux<L> {
+    pub fn new() -> Self {
+        Self {
+            channels: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    pub fn with_channel(mut self, channel: u32, listener: L) -> Self {
+        self.open(channel, listener);
+        self
+    }
+
+    // replaces the listener on `channel`, if there was one, unacked events
+    // and all
+    pub fn open(&mut self, channel: u32, listener: L) {
+        self.channels.insert(channel, AckListener::new(listener));
+    }
+
+    // false if there was no such channel
+    pub fn close(&mut self, channel: u32) -> bool {
+        self.channels.remove(&channel).is_some()
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = u32> + '_ {
+        self.channels.keys().copied()
+    }
+
+    pub fn ack(&mut self, channel: u32, seq: u64) {
+        if let Some(listener) = self.channels.get_mut(&channel) {
+            listener.ack(seq);
+        }
+    }
+
+    pub fn nack(&mut self, channel: u32, seq: u64) {
+        if let Some(listener) = self.channels.get_mut(&channel) {
+            listener.nack(seq);
+        }
+    }
+
+    // false if the body isn't on an open channel
+    pub fn handle(&mut self, body: &WsBody<Acknowledgement>) -> bool {
+        let listener = body
+            .channel()
+            .and_then(|channel| self.channels.get_mut(&channel));
+        match listener {
+            Some(listener) => {
+                listener.handle(body.data());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // e.g. after the client reconnected
+    pub fn redeliver_unacked(&mut self) {
+        self.channels
+            .values_mut()
+            .for_each(AckListener::redeliver_unacked);
+    }
+
+    pub fn unacked(&self, channel: u32) -> usize {
+        self.channels.get(&channel).map_or(0, AckListener::unacked)
+    }
+}
+
+impl<L: Listener> Default for Mux<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// channels closing on their own are dropped, and the mux closes once none are
+// left. every channel is polled with a fresh `recv` each time, so listeners
+// must not lose an event when a pending one is dropped, which none in this
+// crate do
+#[async_trait::async_trait]
+impl<L> Listener for Mux<L>
+where
+    L: Listener<Error = Error> + Send,
+    L::Item: Clone + Sequenced + Serialize + Send,
+{
+    type Error = Error;
+    type Item = WsBody<L::Item>;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            let order: Vec<u32> = self
+                .channels
+                .range(self.next..)
+                .chain(self.channels.range(..self.next))
+                .map(|(channel, _)| *channel)
+                .collect();
+            for channel in order {
+                let Some(listener) = self.channels.get_mut(&channel) else {
+                    continue;
+                };
+                let polled = listener.recv().as_mut().poll(cx);
+                match polled {
+                    Poll::Ready(Ok(event)) => {
+                        self.next = channel.wrapping_add(1);
+                        return Poll::Ready(Ok(WsBody::new(event).with_channel(channel)));
+                    }
+                    Poll::Ready(Err(Error::Closed)) => {
+                        self.channels.remove(&channel);
+                    }
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {}
+                }
+            }
+            match self.channels.is_empty() {
+                true => Poll::Ready(Err(Error::Closed)),
+                false => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, Collection};
+    use crate::{Acknowledgement, BroadcastService, Error, Event, Listener, Mux, Service, WsBody};
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    #[test]
+    fn channels_keep_their_own_acks() {
+        let dogs = BroadcastService::<DeleteEvent>::new();
+        let cats = BroadcastService::<DeleteEvent>::new();
+        let mut mux = Mux::new()
+            .with_channel(1, dogs.listener())
+            .with_channel(2, cats.listener());
+        dogs.publish(Event::new_delete_event(10, Collection::Dogs))
+            .unwrap();
+        dogs.publish(Event::new_delete_event(11, Collection::Dogs))
+            .unwrap();
+        cats.publish(Event::new_delete_event(20, Collection::Cats))
+            .unwrap();
+
+        block_on(async {
+            let mut bodies = Vec::new();
+            for _ in 0..3 {
+                bodies.push(mux.recv().await.unwrap().try_json().unwrap());
+            }
+            insta::assert_snapshot!(bodies.join("\n"), @r###"
+            {"channel":1,"data":{"seq":0,"verb":{"type":"delete","payload":{"location":{"id":10,"txn_id":null,"collection":"Dogs"}}}}}
+            {"channel":2,"data":{"seq":0,"verb":{"type":"delete","payload":{"location":{"id":20,"txn_id":null,"collection":"Cats"}}}}}
+            {"channel":1,"data":{"seq":1,"verb":{"type":"delete","payload":{"location":{"id":11,"txn_id":null,"collection":"Dogs"}}}}}
+            "###);
+
+            // seq 0 is on both channels, but only the dogs' one is nacked
+            let nack = WsBody::<Acknowledgement>::from_json(
+                r#"{"channel":1,"data":{"type":"nack","payload":{"seq":0,"reason":null}}}"#,
+            )
+            .unwrap();
+            assert!(mux.handle(&nack));
+            mux.ack(2, 0);
+            assert_eq!((mux.unacked(1), mux.unacked(2)), (2, 0));
+            let redelivered = mux.recv().await.unwrap();
+            assert_eq!(redelivered.channel(), Some(1));
+            assert_eq!(redelivered.into_data().id(), Some(&10));
+
+            assert!(mux.close(1));
+            assert!(!mux.handle(&nack));
+            drop(cats);
+            assert!(matches!(mux.recv().await, Err(Error::Closed)));
+            assert_eq!(mux.channels().count(), 0);
+        });
+    }
+}
@@ -0,0 +1,613 @@
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use async_nats::{Client, ConnectOptions};
+use futures_util::{stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::bus::{self, BusListener, Going};
+use crate::{base64, Error, EventStore, Listener, Replay, Routable, Sequenced, Service, Shutdown};
+
+// connects to the nats server at `addr` on the runtime the nats services run
+// their client on. tls, auth and reconnection are configured on `options`.
+// hand them a client connected this way: one connected on the caller's own
+// runtime stalls whenever a publish blocks that runtime
+pub fn connect_nats(addr: &str, options: ConnectOptions) -> Result<Client, Error> {
+    let addr = addr.to_owned();
+    bus::run(async move { options.connect(addr).await }).map_err(transport)
+}
+
+// publishes every event as json to a nats subject per collection
+// (`<prefix>.<collection>`, or just `<prefix>` for events without one), so
+// the protocol can ride on an existing bus. collection names have to be valid
+// subject tokens. seqs are numbered by this instance, carrying on from the
+// store if there is one, and `listener_from` replays from that store; use a
+// `JetStreamStore` to keep the history in nats itself. subscriptions survive
+// the client reconnecting. once it's shut down, publishes fail and each
+// listener gets `Error::GoingAway` after the events that had reached it
+pub struct NatsService<T> {
+    client: Client,
+    prefix: String,
+    next_seq: Mutex<u64>,
+    store: Option<Arc<dyn EventStore<T>>>,
+    going: Going,
+}
+
+pub struct NatsListener<T>(BusListener<T>);
+
+impl<T> NatsService<T> {
+    pub fn new(client: Client, prefix: impl Into<String>) -> Self {
+        Self::build(client, prefix.into(), None)
+    }
+
+    pub fn with_store(
+        client: Client,
+        prefix: impl Into<String>,
+        store: impl EventStore<T> + 'static,
+    ) -> Self {
+        Self::build(client, prefix.into(), Some(Arc::new(store)))
+    }
+
+    fn build(client: Client, prefix: String, store: Option<Arc<dyn EventStore<T>>>) -> Self {
+        // a store that can't report where it left off starts a fresh stream
+        let next_seq = store
+            .as_ref()
+            .and_then(|store| store.next_seq().ok())
+            .unwrap_or(0);
+        Self {
+            client,
+            prefix,
+            next_seq: Mutex::new(next_seq),
+            store,
+            going: Going::default(),
+        }
+    }
+
+    // how long clients wait before reconnecting once the service shuts down
+    pub fn with_reconnect_after(mut self, reconnect_after: Duration) -> Self {
+        self.going.reconnect_after = reconnect_after;
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, u64> {
+        self.next_seq
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn subject<C: Serialize>(&self, collection: Option<&C>) -> Result<String, Error> {
+        let Some(collection) = collection else {
+            return Ok(self.prefix.clone());
+        };
+        let name = match serde_json::to_value(collection).map_err(Error::Encode)? {
+            Value::String(name) => name,
+            other => other.to_string(),
+        };
+        Ok(format!("{}.{name}", self.prefix))
+    }
+
+    // a listener for only these collections (and events without one). unlike
+    // `listener_for_collections`, other collections never leave nats
+    pub fn collection_listener<C: Serialize>(
+        &self,
+        collections: impl IntoIterator<Item = C>,
+    ) -> NatsListener<T>
+    where
+        T: Routable + DeserializeOwned + Send + 'static,
+        T::Collection: PartialEq + Send,
+        T::Id: PartialEq + Send,
+    {
+        let subjects = collections
+            .into_iter()
+            .map(|collection| self.subject(Some(&collection)))
+            .chain([Ok(self.prefix.clone())])
+            .collect::<Result<Vec<_>, _>>();
+        NatsListener(BusListener::spawn(&self.going, || {
+            subscribe(&self.client, subjects?)
+        }))
+    }
+}
+
+impl<T> Service<T> for NatsService<T>
+where
+    T: Routable + Sequenced + Serialize + DeserializeOwned + Send + 'static,
+    T::Collection: Serialize + PartialEq + Send,
+    T::Id: PartialEq + Send,
+{
+    type Listener = NatsListener<T>;
+    type Error = Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        let mut next_seq = self.lock();
+        self.going.check()?;
+        event.set_seq(*next_seq);
+        if let Some(store) = &self.store {
+            store.append(&event)?;
+        }
+        let subject = self.subject(event.collection())?;
+        let json = serde_json::to_vec(&event).map_err(Error::Encode)?;
+        let client = self.client.clone();
+        // flushing fails this publish rather than a later one if the
+        // connection is gone, within the client's request timeout while it
+        // tries to reconnect
+        bus::run(async move {
+            let flushed = async {
+                client
+                    .publish(subject, json.into())
+                    .await
+                    .map_err(transport)?;
+                client.flush().await.map_err(transport)
+            };
+            match client.timeout() {
+                Some(timeout) => tokio::time::timeout(timeout, flushed)
+                    .await
+                    .map_err(transport)?,
+                None => flushed.await,
+            }
+        })?;
+        *next_seq += 1;
+        Ok(())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        let subjects = vec![self.prefix.clone(), format!("{}.>", self.prefix)];
+        NatsListener(BusListener::spawn(&self.going, || {
+            subscribe(&self.client, subjects)
+        }))
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        let NatsListener(mut listener) = self.listener();
+        listener.replay(self.store.as_ref(), seq, self.head_seq());
+        NatsListener(listener)
+    }
+
+    fn head_seq(&self) -> u64 {
+        *self.lock()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        // after any publish already under way
+        let _publishing = self.lock();
+        self.going.shutdown(deadline)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Sequenced + Send> Listener for NatsListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        self.0.recv().await
+    }
+}
+
+// subscribes to `subjects`, yielding the payloads of their messages
+fn subscribe(
+    client: &Client,
+    subjects: Vec<String>,
+) -> Result<impl stream::Stream<Item = Vec<u8>> + Send + Unpin + 'static, Error> {
+    let client = client.clone();
+    let subscribers = bus::run(async move {
+        let mut subscribers = Vec::new();
+        for subject in subjects {
+            subscribers.push(client.subscribe(subject).await.map_err(transport)?);
+        }
+        // once the server answers, it has processed the subscriptions
+        client.flush().await.map_err(transport)?;
+        Ok::<_, Error>(subscribers)
+    })?;
+    Ok(stream::select_all(subscribers).map(|message| message.payload.to_vec()))
+}
+
+// an `EventStore` keeping events in a jetstream stream. the stream has to
+// exist and capture `subject`; events are read back with the stream message
+// get api, so replay walks the stream from its start
+pub struct JetStreamStore<T> {
+    client: Client,
+    stream: String,
+    subject: String,
+    marker: PhantomData<fn() -> T>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    code: u16,
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct PubAck {
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct MsgGet {
+    message: Option<StoredMessage>,
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct StoredMessage {
+    seq: u64,
+    data: String,
+}
+
+impl<T> JetStreamStore<T> {
+    pub fn new(client: Client, stream: impl Into<String>, subject: impl Into<String>) -> Self {
+        Self {
+            client,
+            stream: stream.into(),
+            subject: subject.into(),
+            marker: PhantomData,
+        }
+    }
+
+    fn request(&self, subject: String, payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let client = self.client.clone();
+        bus::run(async move { client.request(subject, payload.into()).await })
+            .map(|reply| reply.payload.to_vec())
+            .map_err(transport)
+    }
+
+    // the message at or after stream seq `from`, if there is one
+    fn get(&self, request: Value) -> Result<Option<StoredMessage>, Error> {
+        let subject = format!("$JS.API.STREAM.MSG.GET.{}", self.stream);
+        let request = serde_json::to_vec(&request).map_err(Error::Encode)?;
+        let reply: MsgGet =
+            serde_json::from_slice(&self.request(subject, request)?).map_err(Error::Decode)?;
+        match (reply.message, reply.error) {
+            (_, Some(ApiError { code: 404, .. })) => Ok(None),
+            (_, Some(err)) => Err(Error::Store(err.description)),
+            (message, None) => Ok(message),
+        }
+    }
+
+    fn decode(&self, message: &StoredMessage) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let data = base64::decode(&message.data)
+            .ok_or_else(|| Error::Store("stream message is not valid base64".to_owned()))?;
+        serde_json::from_slice(&data).map_err(Error::Decode)
+    }
+}
+
+impl<T> EventStore<T> for JetStreamStore<T>
+where
+    T: Serialize + DeserializeOwned + Sequenced + 'static,
+{
+    fn append(&self, event: &T) -> Result<(), Error> {
+        let json = serde_json::to_vec(event).map_err(Error::Encode)?;
+        let ack: PubAck = serde_json::from_slice(&self.request(self.subject.clone(), json)?)
+            .map_err(Error::Decode)?;
+        match ack.error {
+            Some(err) => Err(Error::Store(err.description)),
+            None => Ok(()),
+        }
+    }
+
+    fn replay(&self, from_seq: u64) -> Result<Replay<'_, T>, Error> {
+        let mut events = Vec::new();
+        let mut stream_seq = 1;
+        while let Some(message) =
+            self.get(serde_json::json!({ "seq": stream_seq, "next_by_subj": self.subject }))?
+        {
+            stream_seq = message.seq + 1;
+            let event = self.decode(&message)?;
+            if event.seq().is_some_and(|seq| seq >= from_seq) {
+                events.push(event);
+            }
+        }
+        Ok(Box::new(events.into_iter()))
+    }
+
+    fn next_seq(&self) -> Result<u64, Error> {
+        let last = self.get(serde_json::json!({ "last_by_subj": self.subject }))?;
+        let Some(message) = last else {
+            return Ok(0);
+        };
+        Ok(self.decode(&message)?.seq().map_or(0, |seq| seq + 1))
+    }
+}
+
+fn transport(err: impl std::fmt::Display) -> Error {
+    Error::Transport(err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use async_nats::{Client, ConnectOptions};
+    use serde_json::Value;
+
+    use crate::test::{block_on, Collection};
+    use crate::{
+        base64, connect_nats, Error, Event, EventStore, InMemoryEventStore, JetStreamStore,
+        Listener, NatsService, Service,
+    };
+
+    type DoggoEvent = Event<u32, (), Collection>;
+
+    fn delete(id: u32) -> DoggoEvent {
+        DoggoEvent::new_delete_event(id, Collection::Dogs)
+    }
+
+    // every connection the server accepted, for it to drop them
+    #[derive(Clone, Default)]
+    struct Accepted(Arc<Mutex<Vec<TcpStream>>>);
+
+    impl Accepted {
+        fn drop_all(&self) {
+            for stream in self.0.lock().unwrap().drain(..) {
+                stream.shutdown(Shutdown::Both).unwrap();
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Bus {
+        // (subject, sid, connection)
+        subscriptions: Vec<(String, String, TcpStream)>,
+        // the stream capturing `events.stored`
+        stored: Vec<Vec<u8>>,
+    }
+
+    fn matches(pattern: &str, subject: &str) -> bool {
+        let (mut pattern, mut subject) = (pattern.split('.'), subject.split('.'));
+        loop {
+            match (pattern.next(), subject.next()) {
+                (Some(">"), Some(_)) => return true,
+                (Some(token), Some(other)) if token == "*" || token == other => {}
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    fn client(addr: &str) -> Client {
+        connect_nats(addr, ConnectOptions::new()).unwrap()
+    }
+
+    fn fake_nats() -> String {
+        fake_nats_accepting().0
+    }
+
+    // a nats server with a single jetstream stream named `events`. requests
+    // to `denied` are refused, and ones to `garbled` get something other
+    // than json back
+    fn fake_nats_accepting() -> (String, Accepted) {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let bus = Arc::new(Mutex::new(Bus::default()));
+        let accepted = Accepted::default();
+        let connections = accepted.clone();
+        thread::spawn(move || {
+            for stream in server.incoming() {
+                let stream = stream.unwrap();
+                connections
+                    .0
+                    .lock()
+                    .unwrap()
+                    .push(stream.try_clone().unwrap());
+                let bus = bus.clone();
+                thread::spawn(move || serve(stream, bus));
+            }
+        });
+        (addr, accepted)
+    }
+
+    fn deliver(bus: &mut Bus, subject: &str, payload: &[u8]) {
+        for (pattern, sid, stream) in &mut bus.subscriptions {
+            if matches(pattern, subject) {
+                let mut frame = format!("MSG {subject} {sid} {}\r\n", payload.len()).into_bytes();
+                frame.extend_from_slice(payload);
+                frame.extend_from_slice(b"\r\n");
+                let _ = stream.write_all(&frame);
+            }
+        }
+    }
+
+    fn serve(stream: TcpStream, bus: Arc<Mutex<Bus>>) {
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        writer
+            .write_all(b"INFO {\"max_payload\":1048576}\r\n")
+            .unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            let parts: Vec<String> = line.split_whitespace().map(str::to_owned).collect();
+            line.clear();
+            match parts[0].as_str() {
+                "PING" if writer.write_all(b"PONG\r\n").is_err() => return,
+                "SUB" => bus.lock().unwrap().subscriptions.push((
+                    parts[1].clone(),
+                    parts[2].clone(),
+                    writer.try_clone().unwrap(),
+                )),
+                "PUB" => {
+                    let len: usize = parts.last().unwrap().parse().unwrap();
+                    let mut payload = vec![0; len + 2];
+                    if reader.read_exact(&mut payload).is_err() {
+                        return;
+                    }
+                    payload.truncate(len);
+                    let subject = parts[1].as_str();
+                    let mut bus = bus.lock().unwrap();
+                    let reply = (parts.len() == 4).then(|| parts[2].clone());
+                    if subject == "events.stored" {
+                        bus.stored.push(payload.clone());
+                    }
+                    let answer = if subject == "denied" {
+                        Some(
+                            r#"{"error":{"code":503,"description":"no stream for denied"}}"#
+                                .to_owned(),
+                        )
+                    } else if subject == "garbled" {
+                        Some("garbled".to_owned())
+                    } else if subject == "events.stored" {
+                        Some(format!(
+                            r#"{{"stream":"events","seq":{}}}"#,
+                            bus.stored.len()
+                        ))
+                    } else if subject == "$JS.API.STREAM.MSG.GET.events" {
+                        let request: Value = serde_json::from_slice(&payload).unwrap();
+                        let found = match request["seq"].as_u64() {
+                            Some(seq) => bus.stored.get(seq as usize - 1).map(|data| (seq, data)),
+                            None => bus
+                                .stored
+                                .last()
+                                .map(|data| (bus.stored.len() as u64, data)),
+                        };
+                        Some(match found {
+                            Some((seq, data)) => format!(
+                                r#"{{"message":{{"subject":"events.stored","seq":{seq},"data":"{}"}}}}"#,
+                                base64::encode(data)
+                            ),
+                            None => r#"{"error":{"code":404,"description":"no message found"}}"#
+                                .to_owned(),
+                        })
+                    } else {
+                        deliver(&mut bus, subject, &payload);
+                        None
+                    };
+                    if let (Some(reply), Some(answer)) = (reply, answer) {
+                        deliver(&mut bus, &reply, answer.as_bytes());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn events_ride_on_nats() {
+        let addr = fake_nats();
+        let delete = |id, collection| DoggoEvent::new_delete_event(id, collection);
+
+        let store = JetStreamStore::new(client(&addr), "events", "events.stored");
+        let service = NatsService::with_store(client(&addr), "rsp", store);
+        let mut everything = service.listener();
+        let mut cats = service.collection_listener([Collection::Cats]);
+
+        service.publish(delete(1, Collection::Dogs)).unwrap();
+        service.publish(delete(2, Collection::Cats)).unwrap();
+        assert_eq!(service.head_seq(), 2);
+
+        block_on(async {
+            assert_eq!(everything.recv().await.unwrap().seq(), Some(0));
+            assert_eq!(everything.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(cats.recv().await.unwrap().id(), Some(&2));
+        });
+
+        // a restarted instance picks up the numbering and history from jetstream
+        let store = JetStreamStore::<DoggoEvent>::new(client(&addr), "events", "events.stored");
+        assert_eq!(store.next_seq().unwrap(), 2);
+        let service = NatsService::with_store(client(&addr), "rsp", store);
+        assert_eq!(service.head_seq(), 2);
+        let mut resumed = service.listener_from(1);
+        block_on(async {
+            assert_eq!(resumed.recv().await.unwrap().id(), Some(&2));
+        });
+    }
+
+    #[test]
+    fn stored_events_are_replayed_to_resuming_listeners() {
+        let addr = fake_nats();
+        let service = NatsService::with_store(client(&addr), "rsp", InMemoryEventStore::new(16));
+        (1..=3).for_each(|id| service.publish(delete(id)).unwrap());
+
+        let mut from_start = service.listener_from(0);
+        let mut from_head = service.listener_from(3);
+        let mut from_future = service.listener_from(10);
+        let storeless_service = NatsService::<DoggoEvent>::new(client(&addr), "rsp");
+        let mut storeless = storeless_service.listener_from(0);
+        service.publish(delete(4)).unwrap();
+
+        block_on(async {
+            for seq in 0..4 {
+                assert_eq!(from_start.recv().await.unwrap().seq(), Some(seq));
+            }
+            for listener in [&mut from_head, &mut from_future, &mut storeless] {
+                assert_eq!(listener.recv().await.unwrap().seq(), Some(3));
+            }
+        });
+    }
+
+    #[test]
+    fn subscriptions_survive_the_server_dropping_them() {
+        let (addr, accepted) = fake_nats_accepting();
+        let service = NatsService::new(client(&addr), "rsp");
+        let mut listener = service.listener();
+        service.publish(delete(1)).unwrap();
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+
+        // the client reconnects and resubscribes on its own. publishes racing
+        // the drop may be lost, as anywhere on core nats
+        accepted.drop_all();
+        while accepted.0.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        service.publish(delete(2)).unwrap();
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(1));
+    }
+
+    #[test]
+    fn malformed_replies_are_refused() {
+        let addr = fake_nats();
+        let append = |subject| {
+            JetStreamStore::new(client(&addr), "events", subject)
+                .append(&delete(1))
+                .unwrap_err()
+                .to_string()
+        };
+        insta::assert_snapshot!(append("denied"), @"event store failed: no stream for denied");
+        insta::assert_snapshot!(append("garbled"), @"could not decode message: expected value at line 1 column 1");
+
+        // a message that isn't an event ends the subscription
+        let service = NatsService::new(client(&addr), "rsp");
+        let mut listener = service.listener();
+        let mut raw = BufReader::new(TcpStream::connect(&addr).unwrap());
+        raw.get_mut()
+            .write_all(b"PUB rsp.Dogs 8\r\n{\"data\":\r\nPING\r\n")
+            .unwrap();
+        let mut line = String::new();
+        while line != "PONG\r\n" {
+            line.clear();
+            raw.read_line(&mut line).unwrap();
+        }
+        service.publish(delete(2)).unwrap();
+        assert!(matches!(block_on(listener.recv()), Err(Error::Closed)));
+    }
+
+    #[test]
+    fn shutdowns_refuse_publishes_and_tell_listeners() {
+        let addr = fake_nats();
+        let service = NatsService::new(client(&addr), "rsp")
+            .with_reconnect_after(Duration::from_millis(1_500));
+        let mut listener = service.listener();
+        service.publish(delete(1)).unwrap();
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+
+        let shutdown = service.shutdown(Duration::from_millis(500));
+        assert!(matches!(
+            service.publish(delete(2)),
+            Err(Error::GoingAway(_))
+        ));
+        let Err(Error::GoingAway(going_away)) = block_on(listener.recv()) else {
+            panic!("expected the service to be going away");
+        };
+        assert_eq!(going_away.reconnect_after(), Duration::from_millis(1_500));
+        block_on(shutdown).unwrap();
+        let mut late = service.listener();
+        assert!(matches!(block_on(late.recv()), Err(Error::GoingAway(_))));
+    }
+}
@@ -0,0 +1,258 @@
+// the transactional outbox: instead of publishing while it writes, which
+// loses the event if the process dies after the write commits (or publishes
+// one for a write that then rolls back), application code stages the event
+// in the same database transaction as its writes. a relay publishes what
+// committed afterwards and marks it done. a crash between the two republishes
+// the entry under the same idempotency key, for the service to drop
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::timer::sleep;
+use crate::{Clock, Error, Idempotent, Service, SystemClock};
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxEntry<T> {
+    pub key: String,
+    pub event: T,
+}
+
+// the outbox table, next to the application's own
+pub trait OutboxStore<T>: Send + Sync {
+    // the application's database transaction
+    type Txn;
+
+    // writes the entry as part of `txn`, so it commits or rolls back with
+    // everything else in it
+    fn stage(&self, txn: &mut Self::Txn, entry: OutboxEntry<T>) -> Result<(), Error>;
+
+    // committed entries not yet published, oldest first
+    fn pending(&self, limit: usize) -> Result<Vec<OutboxEntry<T>>, Error>;
+
+    fn mark_published(&self, key: &str) -> Result<(), Error>;
+}
+
+pub struct Outbox<T, S> {
+    store: S,
+    batch_size: usize,
+    next_key: AtomicU64,
+    marker: PhantomData<fn(T)>,
+}
+
+impl<T: Idempotent, S: OutboxStore<T>> Outbox<T, S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            batch_size: DEFAULT_BATCH_SIZE,
+            next_key: AtomicU64::new(0),
+            marker: PhantomData,
+        }
+    }
+
+    // how many entries a relay reads at once
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(
+            batch_size > 0,
+            "outbox batch size must be greater than zero"
+        );
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    // stages `event` under a generated key, unique across producers and
+    // restarts, and returns the key
+    pub fn stage(&self, txn: &mut S::Txn, event: T) -> Result<String, Error> {
+        let key = format!(
+            "{:x}-{:x}-{:x}",
+            SystemClock.now(),
+            std::process::id(),
+            self.next_key.fetch_add(1, Ordering::Relaxed)
+        );
+        self.stage_with_key(txn, key.clone(), event)?;
+        Ok(key)
+    }
+
+    // for keys derived from what caused the event, e.g. the request id, so
+    // a retried request stages the same key again
+    pub fn stage_with_key(
+        &self,
+        txn: &mut S::Txn,
+        key: impl Into<String>,
+        event: T,
+    ) -> Result<(), Error> {
+        let entry = OutboxEntry {
+            key: key.into(),
+            event,
+        };
+        self.store.stage(txn, entry)
+    }
+
+    // publishes every pending entry, stopping at the first that fails so
+    // order is kept, and returns how many went out
+    pub fn relay<V>(&self, service: &V) -> Result<usize, Error>
+    where
+        V: Service<T, Error = Error>,
+    {
+        let mut relayed = 0;
+        loop {
+            let batch = self.store.pending(self.batch_size)?;
+            let done = batch.len() < self.batch_size;
+            for OutboxEntry { key, mut event } in batch {
+                event.set_idempotency_key(key.clone());
+                service.publish(event)?;
+                self.store.mark_published(&key)?;
+                relayed += 1;
+            }
+            if done {
+                return Ok(relayed);
+            }
+        }
+    }
+
+    // relays every `interval` until it fails, and returns why
+    pub async fn run<V>(&self, service: &V, interval: Duration) -> Error
+    where
+        V: Service<T, Error = Error>,
+    {
+        loop {
+            if let Err(err) = self.relay(service) {
+                return err;
+            }
+            sleep(interval).await;
+        }
+    }
+}
+
+// for tests, and for apps whose state lives in memory
+pub struct InMemoryOutbox<T> {
+    entries: Mutex<VecDeque<OutboxEntry<T>>>,
+}
+
+// what a transaction has staged. committing makes it visible to the relay;
+// dropping it uncommitted rolls it back
+pub struct InMemoryTxn<T> {
+    staged: Vec<OutboxEntry<T>>,
+}
+
+impl<T> InMemoryOutbox<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn begin(&self) -> InMemoryTxn<T> {
+        InMemoryTxn { staged: Vec::new() }
+    }
+
+    pub fn commit(&self, txn: InMemoryTxn<T>) {
+        self.lock().extend(txn.staged);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<OutboxEntry<T>>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> Default for InMemoryOutbox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Send> OutboxStore<T> for InMemoryOutbox<T> {
+    type Txn = InMemoryTxn<T>;
+
+    fn stage(&self, txn: &mut Self::Txn, entry: OutboxEntry<T>) -> Result<(), Error> {
+        txn.staged.push(entry);
+        Ok(())
+    }
+
+    fn pending(&self, limit: usize) -> Result<Vec<OutboxEntry<T>>, Error> {
+        Ok(self.lock().iter().take(limit).cloned().collect())
+    }
+
+    fn mark_published(&self, key: &str) -> Result<(), Error> {
+        self.lock().retain(|entry| entry.key != key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::test::{block_on, Collection};
+    use crate::{BroadcastService, Event, InMemoryOutbox, Listener, Outbox, Service};
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    #[test]
+    fn committed_events_are_relayed_once() {
+        let outbox = Arc::new(Outbox::new(InMemoryOutbox::<DeleteEvent>::new()).with_batch_size(2));
+        let producers: Vec<_> = (0..3)
+            .map(|producer| {
+                let outbox = outbox.clone();
+                thread::spawn(move || {
+                    let mut txn = outbox.store().begin();
+                    let event = Event::new_delete_event(producer, Collection::Dogs);
+                    let key = outbox.stage(&mut txn, event).unwrap();
+                    // the second producer's write failed, so it rolls back
+                    if producer != 1 {
+                        outbox.store().commit(txn);
+                    }
+                    key
+                })
+            })
+            .collect();
+        let keys: Vec<_> = producers
+            .into_iter()
+            .map(|producer| producer.join().unwrap())
+            .collect();
+        assert_eq!(outbox.store().len(), 2);
+
+        let service = BroadcastService::new();
+        let mut listener = service.listener();
+        let mut txn = outbox.store().begin();
+        outbox
+            .stage_with_key(
+                &mut txn,
+                "request-7",
+                Event::new_delete_event(7, Collection::Dogs),
+            )
+            .unwrap();
+        outbox.store().commit(txn);
+        assert_eq!(outbox.relay(&service).unwrap(), 3);
+        assert_eq!(outbox.relay(&service).unwrap(), 0);
+        assert!(outbox.store().is_empty());
+
+        let mut relayed = Vec::new();
+        for _ in 0..3 {
+            let event = block_on(listener.recv()).unwrap();
+            relayed.push(event.idempotency_key().unwrap().to_owned());
+        }
+        relayed.sort();
+        let mut expected = vec![keys[0].clone(), keys[2].clone(), "request-7".to_owned()];
+        expected.sort();
+        assert_eq!(relayed, expected);
+    }
+}
@@ -0,0 +1,168 @@
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::throttle::Bucket;
+use crate::timer::start_timer;
+use crate::{Clock, Error, Listener, RateLimit, Sequenced, Service, SystemClock, WsBody};
+
+// server -> client marker that a resuming client has been replayed every
+// event before `live_seq`, the seq that was next to be published when it
+// resumed, and is receiving live events only from here on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct CaughtUp {
+    #[ts(type = "number")]
+    pub live_seq: u64,
+}
+
+impl CaughtUp {
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+// what a `PacedCatchUp` yields. it encodes as the event or the marker itself
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum CatchUpItem<T> {
+    Event(T),
+    CaughtUp(CaughtUp),
+}
+
+// replays the events a client resuming from an old seq missed no faster than
+// `limit`, so a long absence doesn't flood it, and hands it live events as
+// they're published in between. `CaughtUp` follows the last replayed event.
+// until then seqs don't arrive in order, so a client that reconnects before
+// `CaughtUp` should resume after the last replayed seq, not the highest
+pub struct PacedCatchUp<L> {
+    // `None` once the replay has caught up
+    replay: Option<L>,
+    live: L,
+    live_seq: u64,
+    limit: RateLimit,
+    bucket: Bucket,
+    // set once the wait for the next token is over
+    waiting: Option<Arc<AtomicBool>>,
+    // the `CaughtUp` marker is next
+    caught_up: bool,
+}
+
+impl<L: Listener> PacedCatchUp<L> {
+    pub fn new<T, S>(service: &S, seq: u64, limit: RateLimit) -> Self
+    where
+        S: Service<T, Listener = L> + ?Sized,
+    {
+        let live_seq = service.head_seq();
+        let live = service.listener_from(live_seq);
+        let replay = (seq < live_seq).then(|| service.listener_from(seq));
+        Self {
+            caught_up: replay.is_none(),
+            replay,
+            live,
+            live_seq,
+            limit,
+            bucket: Bucket::full(&limit, SystemClock.now()),
+            waiting: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for PacedCatchUp<L>
+where
+    L: Listener<Error = Error> + Send,
+    L::Item: Sequenced + Send,
+{
+    type Error = Error;
+    type Item = CatchUpItem<L::Item>;
+
+    // like `Mux`, it polls both listeners with a fresh `recv` each time
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            if self.caught_up {
+                self.caught_up = false;
+                let marker = CaughtUp {
+                    live_seq: self.live_seq,
+                };
+                return Poll::Ready(Ok(CatchUpItem::CaughtUp(marker)));
+            }
+            if let Some(replay) = &mut self.replay {
+                let now = SystemClock.now();
+                let wait = self.bucket.wait(&self.limit, now);
+                if wait.is_zero() {
+                    let polled = replay.recv().as_mut().poll(cx);
+                    if let Poll::Ready(event) = polled {
+                        let event = event?;
+                        self.bucket.try_take(&self.limit, now);
+                        if event.seq().is_none_or(|seq| seq + 1 >= self.live_seq) {
+                            self.replay = None;
+                            self.caught_up = true;
+                        }
+                        return Poll::Ready(Ok(CatchUpItem::Event(event)));
+                    }
+                } else {
+                    // one timer at a time, however often it's polled
+                    let timed = self
+                        .waiting
+                        .as_ref()
+                        .is_some_and(|waiting| !waiting.load(Ordering::Acquire));
+                    if !timed {
+                        let waiting = Arc::new(AtomicBool::new(false));
+                        start_timer(wait, &waiting, cx);
+                        self.waiting = Some(waiting);
+                    }
+                }
+            }
+            match self.live.recv().as_mut().poll(cx) {
+                Poll::Ready(event) => Poll::Ready(event.map(CatchUpItem::Event)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::test::{block_on, doggo};
+    use crate::{BroadcastService, CatchUpItem, Listener, RateLimit, Service, Syncable, WsBody};
+
+    #[test]
+    fn replays_are_paced_around_live_events() {
+        let upsert = |id| doggo(id).to_upsert_event();
+        let service = BroadcastService::new();
+        for id in 0..4 {
+            service.publish(upsert(id)).unwrap();
+        }
+        let started = Instant::now();
+        let mut client = service.listener_paced(1, RateLimit::per_second(20).with_burst(1));
+        service.publish(upsert(4)).unwrap();
+
+        let items: Vec<_> = (0..5).map(|_| block_on(client.recv()).unwrap()).collect();
+        let seqs: Vec<_> = items
+            .iter()
+            .map(|item| match item {
+                CatchUpItem::Event(event) => event.seq(),
+                CatchUpItem::CaughtUp(_) => None,
+            })
+            .collect();
+        // the live event goes out while the replay waits for its limit
+        assert_eq!(seqs, [Some(1), Some(4), Some(2), Some(3), None]);
+        insta::assert_snapshot!(WsBody::new(&items[4]).try_json().unwrap(), @r###"{"data":{"live_seq":4}}"###);
+        assert!(started.elapsed() >= Duration::from_millis(90));
+
+        service.publish(upsert(5)).unwrap();
+        let CatchUpItem::Event(event) = block_on(client.recv()).unwrap() else {
+            panic!("expected a live event");
+        };
+        assert_eq!(event.seq(), Some(5));
+    }
+}
@@ -0,0 +1,286 @@
+use std::marker::PhantomData;
+
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::tls::{MakeTlsConnect, TlsConnect};
+use postgres::{Client, Config, Socket};
+use serde::de::DeserializeOwned;
+
+use crate::{Error, Service};
+
+// a NOTIFY delivered on one of the channels a `PgListenerSource` listens on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub process_id: u32,
+    pub channel: String,
+    pub payload: String,
+}
+
+impl From<::postgres::Notification> for Notification {
+    fn from(notification: ::postgres::Notification) -> Self {
+        Self {
+            process_id: notification.process_id() as u32,
+            channel: notification.channel().to_owned(),
+            payload: notification.payload().to_owned(),
+        }
+    }
+}
+
+// turns postgres notifications into events for a `Service`, bridging database
+// changes (e.g. NOTIFYs sent from triggers) to connected clients. `decoder`
+// maps each notification to an event, or to `None` to skip it
+pub struct PgListenerSource<T, D> {
+    client: Client,
+    decoder: D,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, D> PgListenerSource<T, D>
+where
+    D: FnMut(&Notification) -> Result<Option<T>, Error>,
+{
+    // connects as `config` says, over `tls` (`postgres::NoTls` for plain
+    // tcp), with whatever authentication the server asks for
+    pub fn connect<M>(
+        config: &Config,
+        tls: M,
+        channels: impl IntoIterator<Item = impl AsRef<str>>,
+        decoder: D,
+    ) -> Result<Self, Error>
+    where
+        M: MakeTlsConnect<Socket> + Send + 'static,
+        M::TlsConnect: Send,
+        M::Stream: Send,
+        <M::TlsConnect as TlsConnect<Socket>>::Future: Send,
+    {
+        let mut client = config.connect(tls).map_err(transport)?;
+        let listens: String = channels
+            .into_iter()
+            .map(|channel| format!("LISTEN {};", quote_identifier(channel.as_ref())))
+            .collect();
+        client.batch_execute(&listens).map_err(transport)?;
+        Ok(Self {
+            client,
+            decoder,
+            marker: PhantomData,
+        })
+    }
+
+    // blocks until a notification decodes to an event
+    pub fn next_event(&mut self) -> Result<T, Error> {
+        loop {
+            let notification = self
+                .client
+                .notifications()
+                .blocking_iter()
+                .next()
+                .map_err(transport)?
+                .ok_or(Error::Closed)?;
+            if let Some(event) = (self.decoder)(&notification.into())? {
+                return Ok(event);
+            }
+        }
+    }
+
+    // publishes the next event to `service`
+    pub fn forward_next<S>(&mut self, service: &S) -> Result<(), S::Error>
+    where
+        S: Service<T>,
+        S::Error: From<Error>,
+    {
+        let event = self.next_event()?;
+        service.publish(event)
+    }
+
+    // publishes events to `service` until the connection or the service fails
+    pub fn run<S>(mut self, service: &S) -> S::Error
+    where
+        S: Service<T>,
+        S::Error: From<Error>,
+    {
+        loop {
+            if let Err(err) = self.forward_next(service) {
+                return err;
+            }
+        }
+    }
+}
+
+// a decoder for notifications whose payload is an event serialized as json,
+// e.g. `pg_notify('events', row_to_json(...)::text)`
+pub fn json_decoder<T: DeserializeOwned>() -> impl FnMut(&Notification) -> Result<Option<T>, Error>
+{
+    |notification| {
+        serde_json::from_str(&notification.payload)
+            .map(Some)
+            .map_err(Error::Decode)
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// the server's own message for errors it sent
+fn transport(err: ::postgres::Error) -> Error {
+    let message = match err.as_db_error() {
+        Some(err) => err.message().to_owned(),
+        None => err.to_string(),
+    };
+    Error::Transport(message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use postgres::{Config, NoTls};
+
+    use super::{json_decoder, PgListenerSource};
+    use crate::test::{block_on, Collection};
+    use crate::{BroadcastService, Error, Event, Listener, Service};
+
+    type DoggoEvent = Event<u32, (), Collection>;
+
+    fn message(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut frame = vec![tag];
+        frame.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn read_message(stream: &mut TcpStream, tagged: bool) -> Vec<u8> {
+        let mut header = vec![0; if tagged { 5 } else { 4 }];
+        stream.read_exact(&mut header).unwrap();
+        let len = i32::from_be_bytes(header[header.len() - 4..].try_into().unwrap());
+        let mut body = vec![0; len as usize - 4];
+        stream.read_exact(&mut body).unwrap();
+        body
+    }
+
+    fn notify(channel: &str, payload: &str) -> Vec<u8> {
+        let body = [
+            &42i32.to_be_bytes()[..],
+            channel.as_bytes(),
+            b"\0",
+            payload.as_bytes(),
+            b"\0",
+        ];
+        message(b'A', &body.concat())
+    }
+
+    // a postgres server that asks for a password the way `auth` says,
+    // accepts the LISTENs and then sends a few notifications
+    fn fake_postgres(auth: &'static str) -> Config {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            let startup = read_message(&mut stream, false);
+            let has = |param: &[u8]| startup.windows(param.len()).any(|window| window == param);
+            assert!(has(b"user\0rsp\0") && has(b"database\0doggos\0"));
+            match auth {
+                "cleartext" => {
+                    stream
+                        .write_all(&message(b'R', &3i32.to_be_bytes()))
+                        .unwrap();
+                    assert_eq!(read_message(&mut stream, true), b"hunter2\0");
+                }
+                _ => {
+                    let salted = [&5i32.to_be_bytes()[..], &[1, 2, 3, 4]].concat();
+                    stream.write_all(&message(b'R', &salted)).unwrap();
+                    // "md5", the hex of the salted hash and a nul
+                    let hashed = read_message(&mut stream, true);
+                    assert!(hashed.starts_with(b"md5") && hashed.len() == 36);
+                }
+            }
+            stream
+                .write_all(&message(b'R', &0i32.to_be_bytes()))
+                .unwrap();
+            stream.write_all(&message(b'Z', b"I")).unwrap();
+
+            assert_eq!(
+                read_message(&mut stream, true),
+                b"LISTEN \"doggo \"\"events\"\"\";\0"
+            );
+            stream.write_all(&message(b'C', b"LISTEN\0")).unwrap();
+            stream.write_all(&message(b'Z', b"I")).unwrap();
+
+            let event = DoggoEvent::new_delete_event(1, Collection::Dogs);
+            let json = serde_json::to_string(&event).unwrap();
+            stream
+                .write_all(&notify("doggo \"events\"", "skip me"))
+                .unwrap();
+            stream
+                .write_all(&notify("doggo \"events\"", &json))
+                .unwrap();
+            // once the client is waiting on notifications rather than on the
+            // LISTENs
+            thread::sleep(Duration::from_millis(50));
+            stream
+                .write_all(&message(
+                    b'E',
+                    b"SFATAL\0VFATAL\0C57P01\0Mshutting down\0\0",
+                ))
+                .unwrap();
+        });
+        let mut config = Config::new();
+        config
+            .host("127.0.0.1")
+            .port(port)
+            .user("rsp")
+            .dbname("doggos")
+            .password("hunter2");
+        config
+    }
+
+    #[test]
+    fn notifications_are_published_as_events() {
+        for auth in ["cleartext", "md5"] {
+            let mut decode = json_decoder::<DoggoEvent>();
+            let source = PgListenerSource::connect(
+                &fake_postgres(auth),
+                NoTls,
+                ["doggo \"events\""],
+                |notification| match notification.payload.as_str() {
+                    "skip me" => Ok(None),
+                    _ => decode(notification),
+                },
+            )
+            .unwrap();
+
+            let service = BroadcastService::new();
+            let mut listener = service.listener();
+            let err = source.run(&service);
+            assert!(matches!(err, Error::Transport(message) if message == "shutting down"));
+
+            block_on(async {
+                let event = listener.recv().await.unwrap();
+                assert_eq!(event.seq(), Some(0));
+                assert_eq!(event.id(), Some(&1));
+            });
+        }
+    }
+
+    #[test]
+    fn bogus_lengths_are_refused() {
+        for len in [-1, 3, i32::MAX] {
+            let server = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = server.local_addr().unwrap().port();
+            thread::spawn(move || {
+                let (mut stream, _) = server.accept().unwrap();
+                read_message(&mut stream, false);
+                let mut header = vec![b'R'];
+                header.extend_from_slice(&len.to_be_bytes());
+                stream.write_all(&header).unwrap();
+            });
+            let mut config = Config::new();
+            config.host("127.0.0.1").port(port).user("rsp");
+            let connected =
+                PgListenerSource::connect(&config, NoTls, ["events"], json_decoder::<DoggoEvent>());
+            assert!(matches!(connected, Err(Error::Transport(_))));
+        }
+    }
+}
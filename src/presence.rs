@@ -0,0 +1,333 @@
+// who is in a room and what they are up to. connections `Join` a room with a
+// `PresenceState`, keep it alive with `PresenceHeartbeat`s and `Leave` it (or
+// disconnect, or go quiet for longer than the server's timeout). everyone in
+// the room gets a `PresenceDelta` for each change; a connection joining gets
+// one with everybody already there
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{ConnectionId, WsBody};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PresenceStatus {
+    Online,
+    Away,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct PresenceState {
+    pub status: PresenceStatus,
+    // anything app specific, e.g. a cursor position or what is being typed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "unknown")]
+    pub payload: Option<Value>,
+}
+
+impl PresenceState {
+    pub fn online() -> Self {
+        Self {
+            status: PresenceStatus::Online,
+            payload: None,
+        }
+    }
+
+    pub fn away() -> Self {
+        Self {
+            status: PresenceStatus::Away,
+            payload: None,
+        }
+    }
+
+    pub fn with_payload(mut self, payload: impl Into<Value>) -> Self {
+        self.payload = Some(payload.into());
+        self
+    }
+}
+
+// joins `room`, or changes the connection's state in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Join {
+    pub room: String,
+    pub state: PresenceState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Leave {
+    pub room: String,
+}
+
+// keeps the connection's presence in `room` from expiring
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct PresenceHeartbeat {
+    pub room: String,
+}
+
+// a member of a room. the member is the authenticated subject, or the
+// connection for anonymous ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct PresenceEntry {
+    pub member: String,
+    pub state: PresenceState,
+}
+
+// what changed in a room. a join of a member already there is a new state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct PresenceDelta {
+    pub room: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub joins: Vec<PresenceEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leaves: Vec<String>,
+}
+
+impl PresenceDelta {
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+struct Member {
+    member: String,
+    state: PresenceState,
+    // milliseconds since the unix epoch
+    last_seen: u64,
+}
+
+impl Member {
+    fn entry(&self) -> PresenceEntry {
+        PresenceEntry {
+            member: self.member.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+// the members of every room, by connection. changes return the deltas to
+// deliver, addressed to the connections that should get them
+#[derive(Default)]
+pub(crate) struct Rooms {
+    rooms: HashMap<String, HashMap<ConnectionId, Member>>,
+}
+
+impl Rooms {
+    pub(crate) fn join(
+        &mut self,
+        connection: ConnectionId,
+        member: String,
+        join: &Join,
+        now: u64,
+    ) -> Vec<(ConnectionId, PresenceDelta)> {
+        let room = self.rooms.entry(join.room.clone()).or_default();
+        let joined = Member {
+            member,
+            state: join.state.clone(),
+            last_seen: now,
+        };
+        let entry = joined.entry();
+        let rejoined = room.insert(connection, joined).is_some();
+        let mut deltas = broadcast(&join.room, room, vec![entry], Vec::new());
+        if !rejoined {
+            // everybody there, itself included, instead of only itself
+            deltas.retain(|(recipient, _)| *recipient != connection);
+            let everybody = entries(room);
+            deltas.push((connection, delta(&join.room, everybody, Vec::new())));
+        }
+        deltas
+    }
+
+    pub(crate) fn leave(
+        &mut self,
+        connection: ConnectionId,
+        room: &str,
+    ) -> Vec<(ConnectionId, PresenceDelta)> {
+        let Some(members) = self.rooms.get_mut(room) else {
+            return Vec::new();
+        };
+        let Some(left) = members.remove(&connection) else {
+            return Vec::new();
+        };
+        let deltas = match members.values().any(|other| other.member == left.member) {
+            // still there on another connection
+            true => Vec::new(),
+            false => broadcast(room, members, Vec::new(), vec![left.member]),
+        };
+        if members.is_empty() {
+            self.rooms.remove(room);
+        }
+        deltas
+    }
+
+    // false if the connection isn't in `room`
+    pub(crate) fn heartbeat(&mut self, connection: ConnectionId, room: &str, now: u64) -> bool {
+        let member = self
+            .rooms
+            .get_mut(room)
+            .and_then(|members| members.get_mut(&connection));
+        match member {
+            Some(member) => {
+                member.last_seen = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // removes the members not seen since `deadline`
+    pub(crate) fn expire(&mut self, deadline: u64) -> Vec<(ConnectionId, PresenceDelta)> {
+        let expired: Vec<_> = self
+            .rooms
+            .iter()
+            .flat_map(|(room, members)| {
+                members
+                    .iter()
+                    .filter(|(_, member)| member.last_seen < deadline)
+                    .map(|(connection, _)| (*connection, room.clone()))
+            })
+            .collect();
+        expired
+            .into_iter()
+            .flat_map(|(connection, room)| self.leave(connection, &room))
+            .collect()
+    }
+
+    pub(crate) fn disconnect(
+        &mut self,
+        connection: ConnectionId,
+    ) -> Vec<(ConnectionId, PresenceDelta)> {
+        let rooms: Vec<_> = self
+            .rooms
+            .iter()
+            .filter(|(_, members)| members.contains_key(&connection))
+            .map(|(room, _)| room.clone())
+            .collect();
+        rooms
+            .into_iter()
+            .flat_map(|room| self.leave(connection, &room))
+            .collect()
+    }
+
+    pub(crate) fn members(&self, room: &str) -> Vec<PresenceEntry> {
+        self.rooms.get(room).map(entries).unwrap_or_default()
+    }
+}
+
+// sorted by member, so they don't come in hash order
+fn entries(members: &HashMap<ConnectionId, Member>) -> Vec<PresenceEntry> {
+    let mut entries: Vec<_> = members.values().map(Member::entry).collect();
+    entries.sort_by(|left, right| left.member.cmp(&right.member));
+    entries
+}
+
+fn delta(room: &str, joins: Vec<PresenceEntry>, leaves: Vec<String>) -> PresenceDelta {
+    PresenceDelta {
+        room: room.to_owned(),
+        joins,
+        leaves,
+    }
+}
+
+fn broadcast(
+    room: &str,
+    members: &HashMap<ConnectionId, Member>,
+    joins: Vec<PresenceEntry>,
+    leaves: Vec<String>,
+) -> Vec<(ConnectionId, PresenceDelta)> {
+    members
+        .keys()
+        .map(|connection| (*connection, delta(room, joins.clone(), leaves.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::presence::{Join, Leave, PresenceDelta, PresenceHeartbeat, PresenceState};
+    use crate::test::{block_on, Collection, DoggoRecord};
+    use crate::{Event, Listener, SubscriptionManager};
+
+    #[test]
+    fn rooms_track_who_is_present() {
+        let now = Arc::new(AtomicU64::new(1_000));
+        let clock = now.clone();
+        let manager = SubscriptionManager::<Event<u32, DoggoRecord, Collection>>::new()
+            .with_clock(move || clock.load(Ordering::SeqCst));
+        let alice = manager.connect();
+        let bob = manager.connect();
+        let carol = manager.connect();
+        let mut alice_presence = manager.presence_listener(alice.connection());
+        let mut bob_presence = manager.presence_listener(bob.connection());
+        let join = |state: PresenceState| Join {
+            room: "lobby".to_owned(),
+            state,
+        };
+
+        manager.handle_join(alice.connection(), &join(PresenceState::online()));
+        manager.handle_join(
+            bob.connection(),
+            &join(PresenceState::away().with_payload("typing")),
+        );
+        manager.handle_join(carol.connection(), &join(PresenceState::online()));
+        manager.handle_leave(
+            carol.connection(),
+            &Leave {
+                room: "lobby".to_owned(),
+            },
+        );
+        let json = |delta: PresenceDelta| delta.into_ws_body().try_json().unwrap();
+        block_on(async {
+            let mut deltas = Vec::new();
+            for _ in 0..4 {
+                deltas.push(json(alice_presence.recv().await.unwrap()));
+            }
+            insta::assert_snapshot!(deltas.join("\n"), @r###"
+            {"data":{"room":"lobby","joins":[{"member":"connection-0","state":{"status":"online"}}]}}
+            {"data":{"room":"lobby","joins":[{"member":"connection-1","state":{"status":"away","payload":"typing"}}]}}
+            {"data":{"room":"lobby","joins":[{"member":"connection-2","state":{"status":"online"}}]}}
+            {"data":{"room":"lobby","leaves":["connection-2"]}}
+            "###);
+            let joined = json(bob_presence.recv().await.unwrap());
+            insta::assert_snapshot!(joined, @r###"{"data":{"room":"lobby","joins":[{"member":"connection-0","state":{"status":"online"}},{"member":"connection-1","state":{"status":"away","payload":"typing"}}]}}"###);
+        });
+
+        now.store(5_000, Ordering::SeqCst);
+        let heartbeat = PresenceHeartbeat {
+            room: "lobby".to_owned(),
+        };
+        assert!(manager.handle_presence_heartbeat(alice.connection(), &heartbeat));
+        assert!(!manager.handle_presence_heartbeat(carol.connection(), &heartbeat));
+        manager.expire_presence(Duration::from_secs(2));
+        let members: Vec<_> = manager
+            .presence("lobby")
+            .into_iter()
+            .map(|entry| entry.member)
+            .collect();
+        assert_eq!(members, ["connection-0"]);
+        let expired = block_on(alice_presence.recv()).unwrap();
+        assert_eq!(expired.leaves, ["connection-1"]);
+
+        drop(alice);
+        assert!(manager.presence("lobby").is_empty());
+        assert!(block_on(alice_presence.recv()).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{Error, EventStore, Listener, OffsetStore, Sequenced, Service};
+
+// derived state kept up to date from the event log, e.g. a count per
+// collection or an index by some field. events are applied in seq order,
+// each once
+pub trait Projection<E> {
+    fn apply(&mut self, event: &E);
+}
+
+impl<E, F: FnMut(&E)> Projection<E> for F {
+    fn apply(&mut self, event: &E) {
+        self(event)
+    }
+}
+
+// feeds a projection what's in a store and then what's published live,
+// recording the seq of every event applied under the projection's name. a
+// projection whose state is durable picks up where it left off after a
+// restart; one kept in memory should be given a fresh offset store
+pub struct ProjectionRunner<P, O> {
+    projection: P,
+    offsets: O,
+    name: String,
+    last_seq: Option<u64>,
+}
+
+impl<P, O: OffsetStore> ProjectionRunner<P, O> {
+    pub fn new(name: impl Into<String>, projection: P, offsets: O) -> Result<Self, Error> {
+        let name = name.into();
+        let last_seq = offsets.load(&name)?;
+        Ok(Self {
+            projection,
+            offsets,
+            name,
+            last_seq,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    pub fn into_projection(self) -> P {
+        self.projection
+    }
+
+    pub fn last_seq(&self) -> Option<u64> {
+        self.last_seq
+    }
+
+    // the seq of the first event the projection hasn't seen
+    pub fn next_seq(&self) -> u64 {
+        self.last_seq.map_or(0, |seq| seq + 1)
+    }
+
+    // applies `event` unless it's already been applied, returning whether it
+    // was. events without a seq can't be checkpointed, so they always are
+    pub fn apply<E>(&mut self, event: &E) -> Result<bool, Error>
+    where
+        E: Sequenced,
+        P: Projection<E>,
+    {
+        let seq = event.seq();
+        if seq.is_some_and(|seq| seq < self.next_seq()) {
+            return Ok(false);
+        }
+        self.projection.apply(event);
+        if let Some(seq) = seq {
+            self.offsets.save(&self.name, seq)?;
+            self.last_seq = Some(seq);
+        }
+        Ok(true)
+    }
+
+    // applies the stored events after the checkpoint, returning how many
+    pub fn catch_up<E, S>(&mut self, store: &S) -> Result<usize, Error>
+    where
+        E: Sequenced,
+        P: Projection<E>,
+        S: EventStore<E>,
+    {
+        let mut applied = 0;
+        for event in store.replay(self.next_seq())? {
+            if self.apply(&event)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    // catches up from `store`, then follows `service` from there until its
+    // listener fails, and returns why
+    pub async fn run<E, S, V>(&mut self, store: &S, service: &V) -> <V::Listener as Listener>::Error
+    where
+        E: Sequenced,
+        P: Projection<E>,
+        S: EventStore<E>,
+        V: Service<E>,
+        <V::Listener as Listener>::Error: From<Error>,
+    {
+        if let Err(err) = self.catch_up(store) {
+            return err.into();
+        }
+        let mut listener = service.listener_from(self.next_seq());
+        loop {
+            let applied = match listener.recv().await {
+                Ok(event) => self.apply(&event),
+                Err(err) => return err,
+            };
+            if let Err(err) = applied {
+                return err.into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use crate::test::{block_on, Collection};
+    use crate::timer::within;
+    use crate::{
+        BroadcastService, Event, EventStore, InMemoryEventStore, InMemoryOffsetStore, Listener,
+        ProjectionRunner, Service,
+    };
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    #[test]
+    fn projections_catch_up_then_follow_the_stream() {
+        let service = BroadcastService::<DeleteEvent>::new();
+        let store = InMemoryEventStore::new(16);
+        let mut listener = service.listener();
+        for id in 1..=5 {
+            service
+                .publish(Event::new_delete_event(id, Collection::Dogs))
+                .unwrap();
+            // only the first three made it to the store
+            let event = block_on(listener.recv()).unwrap();
+            if id <= 3 {
+                store.append(&event).unwrap();
+            }
+        }
+
+        let deletes = |counts: &mut HashMap<u32, usize>, event: &DeleteEvent| {
+            *counts.entry(*event.id().unwrap()).or_default() += 1;
+        };
+        let offsets = InMemoryOffsetStore::new();
+        let mut counts = HashMap::new();
+        let mut runner = ProjectionRunner::new(
+            "deletes",
+            |event: &DeleteEvent| deletes(&mut counts, event),
+            &offsets,
+        )
+        .unwrap();
+        assert_eq!(runner.catch_up(&store).unwrap(), 3);
+        assert_eq!(runner.last_seq(), Some(2));
+        assert!(block_on(within(
+            Duration::from_millis(20),
+            runner.run(&store, &service)
+        ))
+        .is_none());
+        assert_eq!(runner.last_seq(), Some(4));
+        drop(runner);
+        assert!(counts.values().all(|&count| count == 1));
+        assert_eq!(counts.len(), 5);
+
+        // a restart skips everything already applied
+        let mut runner =
+            ProjectionRunner::new("deletes", |_: &DeleteEvent| panic!(), &offsets).unwrap();
+        assert_eq!(runner.catch_up(&store).unwrap(), 0);
+        assert_eq!(runner.next_seq(), 5);
+    }
+}
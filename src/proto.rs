@@ -0,0 +1,499 @@
+// grpc streaming, for backends that prefer it over websockets. the service is
+// defined in `proto/rsp.proto` (also available as `PROTO`), from which
+// build.rs generates the prost messages here and the tonic `EventStream`
+// server. `GrpcServer` holds the semantics of the three rpcs, and
+// `GrpcService` is it as that server's `EventStream`, served with e.g.
+// `tonic::transport::Server::builder().add_service(service.into_server())`
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use futures_core::Stream;
+use serde::{de::DeserializeOwned, Serialize};
+use tonic::{Code, Request, Response, Status};
+use ts_rs::TS;
+
+use crate::{
+    Ack, AckListener, Acknowledgement, Error, ErrorCode, ErrorMessage, Event, EventBatch,
+    FilteredListener, Listener, Nack, Ping, Predicate, Routable, Sequenced, Service, Snapshot,
+    Snapshottable, WsBody,
+};
+
+pub const PROTO: &str = include_str!("../proto/rsp.proto");
+
+tonic::include_proto!("rsp.v1");
+
+pub use event_stream_server::{EventStream, EventStreamServer};
+pub use stream_message::Kind;
+
+// anything that can be sent on a stream
+pub trait StreamItem: Serialize {
+    fn kind(&self) -> Kind;
+
+    fn stream_seq(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<ID: Serialize, T: Serialize + TS, C: Serialize, P: Serialize + TS> StreamItem
+    for Event<ID, T, C, P>
+{
+    fn kind(&self) -> Kind {
+        Kind::Event
+    }
+
+    fn stream_seq(&self) -> Option<u64> {
+        self.seq()
+    }
+}
+
+impl<ID: Serialize, T: Serialize + TS, C: Serialize, P: Serialize + TS> StreamItem
+    for EventBatch<ID, T, C, P>
+{
+    fn kind(&self) -> Kind {
+        Kind::Batch
+    }
+
+    fn stream_seq(&self) -> Option<u64> {
+        Sequenced::seq(self)
+    }
+}
+
+impl<ID: Serialize, T: Serialize + TS, C: Serialize> StreamItem for Snapshot<ID, T, C> {
+    fn kind(&self) -> Kind {
+        Kind::Snapshot
+    }
+}
+
+impl StreamItem for Ping {
+    fn kind(&self) -> Kind {
+        Kind::Ping
+    }
+}
+
+impl StreamMessage {
+    pub fn new(item: &impl StreamItem) -> Result<Self, Error> {
+        Ok(Self {
+            kind: item.kind().into(),
+            seq: item.stream_seq(),
+            body: WsBody::new(item).try_json()?.into_bytes(),
+            stream_id: 0,
+        })
+    }
+
+    pub fn opened(stream_id: u64) -> Self {
+        Self {
+            kind: Kind::Opened.into(),
+            stream_id,
+            ..Self::default()
+        }
+    }
+
+    // the body as the websocket message it mirrors
+    pub fn to_ws_body<T: Serialize + DeserializeOwned>(&self) -> Result<WsBody<T>, Error> {
+        serde_json::from_slice(&self.body).map_err(Error::Decode)
+    }
+}
+
+// what `GrpcServer::subscribe` streams from a service with listener `L`
+pub type GrpcSubscription<L, T> = GrpcStream<FilteredListener<L, Predicate<T>>>;
+
+// bridges a service into the rpcs of `PROTO`
+pub struct GrpcServer<S> {
+    service: S,
+    streams: Arc<Mutex<HashMap<u64, Sender<Acknowledgement>>>>,
+    next_stream_id: AtomicU64,
+}
+
+impl<S> GrpcServer<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            streams: Arc::default(),
+            next_stream_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    // the streams that are still open
+    pub fn streams(&self) -> usize {
+        lock(&self.streams).len()
+    }
+
+    // the `Subscribe` rpc
+    pub fn subscribe<T>(
+        &self,
+        request: &SubscribeRequest,
+    ) -> Result<GrpcSubscription<S::Listener, T>, Error>
+    where
+        S: Service<T>,
+        S::Listener: Send,
+        T: Routable + Send + 'static,
+        T::Collection: DeserializeOwned + PartialEq + Send + Sync + 'static,
+    {
+        let collections = request
+            .collections
+            .iter()
+            .map(|name| {
+                serde_json::from_value(serde_json::Value::String(name.clone()))
+                    .map_err(|_| Error::InvalidCollection(name.clone()))
+            })
+            .collect::<Result<Vec<T::Collection>, _>>()?;
+        let inner = match request.from_seq {
+            Some(seq) => self.service.listener_from(seq),
+            None => self.service.listener(),
+        };
+        let predicate: Predicate<T> = Box::new(move |event: &T| {
+            collections.is_empty()
+                || event
+                    .collection()
+                    .is_none_or(|collection| collections.contains(collection))
+        });
+
+        let stream_id = self.next_stream_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, acks) = mpsc::channel();
+        lock(&self.streams).insert(stream_id, sender);
+        Ok(GrpcStream {
+            inner: AckListener::new(FilteredListener::new(inner, predicate)),
+            stream_id,
+            opened: false,
+            acks,
+            streams: Arc::clone(&self.streams),
+        })
+    }
+
+    // the `Acknowledge` rpc. fails with `Error::Closed` if the stream is gone
+    pub fn acknowledge(&self, request: &AckRequest) -> Result<(), Error> {
+        let acknowledgement = if request.nack {
+            Acknowledgement::Nack(Nack {
+                seq: request.seq,
+                reason: request.reason.clone(),
+            })
+        } else {
+            Acknowledgement::Ack(Ack { seq: request.seq })
+        };
+        let streams = lock(&self.streams);
+        let sender = streams.get(&request.stream_id).ok_or(Error::Closed)?;
+        sender.send(acknowledgement).map_err(|_| Error::Closed)
+    }
+
+    // the `Snapshot` rpc
+    pub fn snapshot<T, R>(
+        &self,
+        source: &R,
+        request: &SnapshotRequest,
+    ) -> Result<StreamMessage, Error>
+    where
+        S: Service<T>,
+        R: Snapshottable,
+        R::Id: Serialize,
+        R::Record: Serialize + TS,
+        R::Collection: Serialize + DeserializeOwned,
+    {
+        let name = serde_json::Value::String(request.collection.clone());
+        let collection = serde_json::from_value(name)
+            .map_err(|_| Error::InvalidCollection(request.collection.clone()))?;
+        StreamMessage::new(&self.service.snapshot(source, collection))
+    }
+}
+
+// the messages of one `Subscribe` call: `Kind::Opened` first, then the
+// events of the inner listener. acks sent through `GrpcServer::acknowledge`
+// are applied before each event, so a nacked event goes out again next
+pub struct GrpcStream<L: Listener> {
+    inner: AckListener<L>,
+    stream_id: u64,
+    opened: bool,
+    acks: Receiver<Acknowledgement>,
+    streams: Arc<Mutex<HashMap<u64, Sender<Acknowledgement>>>>,
+}
+
+impl<L: Listener> GrpcStream<L> {
+    pub fn stream_id(&self) -> u64 {
+        self.stream_id
+    }
+
+    pub fn unacked(&self) -> usize {
+        self.inner.unacked()
+    }
+}
+
+impl<L: Listener> Drop for GrpcStream<L> {
+    fn drop(&mut self) {
+        lock(&self.streams).remove(&self.stream_id);
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for GrpcStream<L>
+where
+    L: Listener<Error = Error> + Send,
+    L::Item: StreamItem + Clone + Sequenced + Send,
+{
+    type Error = Error;
+    type Item = StreamMessage;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        if !self.opened {
+            self.opened = true;
+            return Ok(StreamMessage::opened(self.stream_id));
+        }
+        while let Ok(acknowledgement) = self.acks.try_recv() {
+            self.inner.handle(&acknowledgement);
+        }
+        let item = self.inner.recv().await?;
+        StreamMessage::new(&item)
+    }
+}
+
+// the rpcs of `GrpcServer` as tonic's `EventStream`, over a service of `T`,
+// snapshotting the collections of `source`
+pub struct GrpcService<S, T, R> {
+    server: GrpcServer<S>,
+    source: R,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<S, T, R> GrpcService<S, T, R> {
+    pub fn new(server: GrpcServer<S>, source: R) -> Self {
+        Self {
+            server,
+            source,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn server(&self) -> &GrpcServer<S> {
+        &self.server
+    }
+
+    pub fn into_server(self) -> EventStreamServer<Self>
+    where
+        Self: EventStream,
+    {
+        EventStreamServer::new(self)
+    }
+}
+
+type MessageStream = Pin<Box<dyn Stream<Item = Result<StreamMessage, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<S, T, R> EventStream for GrpcService<S, T, R>
+where
+    S: Service<T> + Send + Sync + 'static,
+    S::Listener: Listener<Error = Error> + Send + 'static,
+    T: Routable + StreamItem + Clone + Sequenced + Send + 'static,
+    T::Collection: DeserializeOwned + PartialEq + Send + Sync + 'static,
+    R: Snapshottable + Send + Sync + 'static,
+    R::Id: Serialize,
+    R::Record: Serialize + TS,
+    R::Collection: Serialize + DeserializeOwned,
+{
+    type SubscribeStream = MessageStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<MessageStream>, Status> {
+        let stream = self.server.subscribe(request.get_ref()).map_err(status)?;
+        // ends once the listener is closed, or after the error it failed with
+        let messages = futures_util::stream::unfold(Some(stream), |stream| async move {
+            let mut stream = stream?;
+            match stream.recv().await {
+                Ok(message) => Some((Ok(message), Some(stream))),
+                Err(Error::Closed) => None,
+                Err(err) => Some((Err(status(err)), None)),
+            }
+        });
+        Ok(Response::new(Box::pin(messages)))
+    }
+
+    async fn acknowledge(
+        &self,
+        request: Request<AckRequest>,
+    ) -> Result<Response<AckResponse>, Status> {
+        self.server.acknowledge(request.get_ref()).map_err(status)?;
+        Ok(Response::new(AckResponse {}))
+    }
+
+    async fn snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> Result<Response<StreamMessage>, Status> {
+        self.server
+            .snapshot::<T, R>(&self.source, request.get_ref())
+            .map(Response::new)
+            .map_err(status)
+    }
+}
+
+// the grpc status closest to the `ErrorCode` a websocket client would get
+fn status(err: Error) -> Status {
+    let message = ErrorMessage::from(&err);
+    let code = match message.code {
+        ErrorCode::BadCommand => Code::InvalidArgument,
+        ErrorCode::Unauthorized => Code::PermissionDenied,
+        ErrorCode::Conflict => Code::Aborted,
+        ErrorCode::Unsupported => Code::Unimplemented,
+        ErrorCode::Lagged => Code::DataLoss,
+        ErrorCode::Overloaded => Code::ResourceExhausted,
+        ErrorCode::Unavailable => Code::Unavailable,
+        ErrorCode::Internal => Code::Internal,
+    };
+    Status::new(code, message.message)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::StreamExt;
+    use prost::Message;
+    use tonic::{Code, Request};
+
+    use crate::proto::{
+        AckRequest, EventStream, GrpcServer, GrpcService, Kind, SnapshotRequest, StreamMessage,
+        SubscribeRequest, PROTO,
+    };
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, Event, Listener, Service, SnapshotEntry, Snapshottable, Syncable,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn events_stream_with_acks() {
+        assert!(PROTO.contains("service EventStream"));
+        let request = SubscribeRequest {
+            collections: vec!["Dogs".to_owned()],
+            from_seq: Some(300),
+        };
+        assert_eq!(request.encode_to_vec(), b"\x0a\x04Dogs\x10\xac\x02");
+        assert_eq!(
+            SubscribeRequest::decode(&*request.encode_to_vec()).unwrap(),
+            request
+        );
+
+        let doggo = doggo(1);
+        let server = GrpcServer::new(BroadcastService::<DoggoEvent>::new());
+        server
+            .service()
+            .publish(Event::new_delete_event(2, Collection::Cats))
+            .unwrap();
+        server.service().publish(doggo.to_upsert_event()).unwrap();
+
+        let request = SubscribeRequest::decode(&b"\x0a\x04Dogs\x10\x00"[..]).unwrap();
+        let mut stream = server.subscribe(&request).unwrap();
+        block_on(async {
+            let opened = stream.recv().await.unwrap();
+            assert_eq!(opened.kind(), Kind::Opened);
+            assert_eq!(opened.stream_id, stream.stream_id());
+
+            let encoded = stream.recv().await.unwrap().encode_to_vec();
+            let message = StreamMessage::decode(&*encoded).unwrap();
+            assert_eq!(message.kind(), Kind::Event);
+            assert_eq!(message.seq, Some(1));
+            let event = message.to_ws_body::<DoggoEvent>().unwrap().into_data();
+            assert_eq!(event.data().unwrap().name, "Barky");
+
+            let nack = AckRequest {
+                stream_id: stream.stream_id(),
+                seq: 1,
+                nack: true,
+                reason: None,
+            };
+            server
+                .acknowledge(&AckRequest::decode(&*nack.encode_to_vec()).unwrap())
+                .unwrap();
+            assert_eq!(stream.recv().await.unwrap().seq, Some(1));
+        });
+
+        let request = SubscribeRequest {
+            collections: vec!["Birds".to_owned()],
+            from_seq: None,
+        };
+        assert!(server.subscribe(&request).is_err());
+        assert_eq!(server.streams(), 1);
+        drop(stream);
+        assert!(server.acknowledge(&AckRequest::default()).is_err());
+        assert_eq!(server.streams(), 0);
+    }
+
+    struct Kennel;
+
+    impl Snapshottable for Kennel {
+        type Id = u32;
+        type Record = DoggoRecord;
+        type Collection = Collection;
+
+        fn records(&self, _: &Collection) -> Vec<SnapshotEntry<u32, DoggoRecord>> {
+            vec![SnapshotEntry::new(1, doggo(1))]
+        }
+    }
+
+    #[test]
+    fn the_tonic_service_forwards_to_the_server() {
+        let server = GrpcServer::new(BroadcastService::<DoggoEvent>::new());
+        let service = GrpcService::new(server, Kennel);
+        block_on(async {
+            let request = SubscribeRequest {
+                collections: vec!["Dogs".to_owned()],
+                from_seq: None,
+            };
+            let mut messages = service
+                .subscribe(Request::new(request))
+                .await
+                .unwrap()
+                .into_inner();
+            let opened = messages.next().await.unwrap().unwrap();
+            assert_eq!(opened.kind(), Kind::Opened);
+            service
+                .server()
+                .service()
+                .publish(doggo(1).to_upsert_event())
+                .unwrap();
+            let message = messages.next().await.unwrap().unwrap();
+            assert_eq!((message.kind(), message.seq), (Kind::Event, Some(0)));
+
+            let ack = AckRequest {
+                stream_id: opened.stream_id,
+                seq: 0,
+                ..AckRequest::default()
+            };
+            assert!(service.acknowledge(Request::new(ack)).await.is_ok());
+            drop(messages);
+            let err = service
+                .acknowledge(Request::new(AckRequest::default()))
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), Code::Unavailable);
+
+            let request = SnapshotRequest {
+                collection: "Dogs".to_owned(),
+            };
+            let snapshot = service
+                .snapshot(Request::new(request))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(snapshot.kind(), Kind::Snapshot);
+            let request = SnapshotRequest {
+                collection: "Birds".to_owned(),
+            };
+            let err = service.snapshot(Request::new(request)).await.unwrap_err();
+            insta::assert_snapshot!(err.message(), @"invalid collection: Birds");
+            assert_eq!(err.code(), Code::InvalidArgument);
+        });
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{Appendable, Event, Syncable};
+
+// handles the events of one collection of a `define_protocol!` enum. a type
+// implementing it for every record of the protocol can be handed to the
+// enum's `dispatch`, which calls the implementation for the event's record
+pub trait Handle<R: Syncable> {
+    type Output;
+
+    fn handle(&mut self, event: Event<R::Id, R, <R as Appendable>::Collection>) -> Self::Output;
+}
+
+// a closed enum of the events of several collections, one variant per
+// collection, for services that carry more than one record type:
+//
+//     define_protocol! { Dogs => DoggoRecord, Cats => CatRecord }
+//
+// generates `pub enum AnyEvent { Dogs(Event<..DoggoRecord..>), Cats(..) }`.
+// name the enum, make it private or derive more for it with the long form:
+//
+//     define_protocol! {
+//         #[derive(Debug, Clone)]
+//         #[ts(export)]
+//         pub enum ZooEvent { Dogs => DoggoRecord, Cats => CatRecord }
+//     }
+//
+// it's encoded as `{"collection":"Dogs","event":{..}}` and derives `TS`, so
+// clients narrow on `collection`. every record's `Event` converts into it,
+// and `dispatch` hands it to the `Handle` implementation for its record.
+// the crate using it needs `serde` and `ts-rs` for the derives, as its
+// records do
+#[macro_export]
+macro_rules! define_protocol {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($variant:ident => $record:ty),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(serde::Serialize, serde::Deserialize, ts_rs::TS)]
+        #[serde(tag = "collection", content = "event")]
+        $vis enum $name {
+            $(
+                $variant(
+                    $crate::Event<
+                        <$record as $crate::Syncable>::Id,
+                        $record,
+                        <$record as $crate::Appendable>::Collection,
+                    >,
+                ),
+            )+
+        }
+
+        impl $name {
+            // the variant, as the enum is tagged with it
+            pub fn collection_name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant(_) => stringify!($variant),)+
+                }
+            }
+
+            pub fn seq(&self) -> Option<u64> {
+                match self {
+                    $(Self::$variant(event) => event.seq(),)+
+                }
+            }
+
+            pub fn verb_name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant(event) => event.verb().name(),)+
+                }
+            }
+
+            pub fn dispatch<H, O>(self, handler: &mut H) -> O
+            where
+                $(H: $crate::Handle<$record, Output = O>,)+
+            {
+                match self {
+                    $(Self::$variant(event) => {
+                        <H as $crate::Handle<$record>>::handle(handler, event)
+                    })+
+                }
+            }
+
+            pub fn into_ws_body(self) -> $crate::WsBody<Self> {
+                $crate::WsBody::from(self)
+            }
+        }
+
+        $(
+            impl
+                ::std::convert::From<
+                    $crate::Event<
+                        <$record as $crate::Syncable>::Id,
+                        $record,
+                        <$record as $crate::Appendable>::Collection,
+                    >,
+                > for $name
+            {
+                fn from(
+                    event: $crate::Event<
+                        <$record as $crate::Syncable>::Id,
+                        $record,
+                        <$record as $crate::Appendable>::Collection,
+                    >,
+                ) -> Self {
+                    Self::$variant(event)
+                }
+            }
+        )+
+    };
+    ($($variant:ident => $record:ty),+ $(,)?) => {
+        $crate::define_protocol! {
+            pub enum AnyEvent {
+                $($variant => $record),+
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use ts_rs::TS;
+
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Appendable, Event, Handle, Syncable, WsBody};
+
+    #[derive(Clone, Serialize, Deserialize, TS)]
+    pub(crate) struct CatRecord {
+        id: String,
+        lives: u8,
+    }
+
+    impl Appendable for CatRecord {
+        type Collection = Collection;
+
+        fn collection(&self) -> Collection {
+            Collection::Cats
+        }
+    }
+
+    impl Syncable for CatRecord {
+        type Id = String;
+
+        fn id(&self) -> String {
+            self.id.clone()
+        }
+    }
+
+    define_protocol! { Dogs => DoggoRecord, Cats => CatRecord }
+
+    // counts the lives of the cats and names the dogs it sees
+    #[derive(Default)]
+    struct Census {
+        dogs: Vec<String>,
+        lives: u32,
+    }
+
+    impl Handle<DoggoRecord> for Census {
+        type Output = &'static str;
+
+        fn handle(&mut self, event: Event<u32, DoggoRecord, Collection>) -> &'static str {
+            self.dogs
+                .extend(event.data().map(|doggo| doggo.name.clone()));
+            "dog"
+        }
+    }
+
+    impl Handle<CatRecord> for Census {
+        type Output = &'static str;
+
+        fn handle(&mut self, event: Event<String, CatRecord, Collection>) -> &'static str {
+            self.lives += event.data().map_or(0, |cat| u32::from(cat.lives));
+            "cat"
+        }
+    }
+
+    #[test]
+    fn events_of_every_collection_share_an_enum() {
+        let doggo = doggo(1);
+        let cat = CatRecord {
+            id: "tom".to_owned(),
+            lives: 9,
+        };
+        let events = [
+            AnyEvent::from(doggo.to_upsert_event()),
+            AnyEvent::from(cat.to_upsert_event()),
+            AnyEvent::from(Event::<String, CatRecord, Collection>::new_delete_event(
+                "tom".to_owned(),
+                Collection::Cats,
+            )),
+        ];
+        assert!(events.iter().all(|event| event.seq().is_none()));
+        let json: Vec<_> = events
+            .into_iter()
+            .map(|event| event.into_ws_body().try_json().unwrap())
+            .collect();
+        insta::assert_snapshot!(json.join("\n"), @r###"
+        {"data":{"collection":"Dogs","event":{"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}}}}
+        {"data":{"collection":"Cats","event":{"verb":{"type":"upsert","payload":{"location":{"id":"tom","txn_id":null,"collection":"Cats"},"data":{"id":"tom","lives":9}}}}}}
+        {"data":{"collection":"Cats","event":{"verb":{"type":"delete","payload":{"location":{"id":"tom","txn_id":null,"collection":"Cats"}}}}}}
+        "###);
+        insta::assert_snapshot!(AnyEvent::decl(), @r###"type AnyEvent = { "collection": "Dogs", "event": Event<number, DoggoRecord, Collection> } | { "collection": "Cats", "event": Event<string, CatRecord, Collection> };"###);
+
+        let mut census = Census::default();
+        let kinds: Vec<_> = json
+            .iter()
+            .map(|json| {
+                let event = WsBody::<AnyEvent>::from_json(json).unwrap().into_data();
+                let names = (event.collection_name(), event.verb_name());
+                (names, event.dispatch(&mut census))
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (("Dogs", "upsert"), "dog"),
+                (("Cats", "upsert"), "cat"),
+                (("Cats", "delete"), "cat")
+            ]
+        );
+        assert_eq!((census.dogs, census.lives), (vec!["Barky".to_owned()], 9));
+    }
+}
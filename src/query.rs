@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Error, Query, SnapshotEntry, WsBody};
+
+// a page of the records a `Query` asked for, read as of `seq`. live events
+// at or before `seq` are already in it, so a client splicing it into the
+// stream skips those and applies the rest
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct QueryResult<ID, T, C>
+where
+    T: TS,
+{
+    pub(crate) request_id: u32,
+    pub(crate) collection: C,
+    #[ts(type = "number")]
+    pub(crate) seq: u64,
+    pub(crate) records: Vec<SnapshotEntry<ID, T>>,
+    // where the next page starts, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) next_cursor: Option<String>,
+}
+
+impl<ID, T: Serialize + TS, C> QueryResult<ID, T, C> {
+    // answers `query` out of every record of its collection as of `seq`,
+    // taking care of ids, filter, cursor and limit. records are paged in id
+    // order, and a cursor is the json of the last id of the page before
+    pub fn page(
+        query: Query<ID, C>,
+        seq: u64,
+        records: impl IntoIterator<Item = (ID, T)>,
+    ) -> Result<Self, Error>
+    where
+        ID: Serialize + DeserializeOwned + Ord,
+    {
+        if query.limit == Some(0) {
+            return Err(Error::InvalidFrame(
+                "a query limit must be above zero".to_owned(),
+            ));
+        }
+        let after: Option<ID> = query
+            .cursor
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(Error::Decode)?;
+        let mut matching = Vec::new();
+        for (id, record) in records {
+            let wanted = query.ids.as_ref().is_none_or(|ids| ids.contains(&id))
+                && after.as_ref().is_none_or(|after| id > *after)
+                && matches(query.filter.as_ref(), &record)?;
+            if wanted {
+                matching.push((id, record));
+            }
+        }
+        matching.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+        let next_cursor = match matching.len() > limit {
+            true => {
+                matching.truncate(limit);
+                let (last, _) = &matching[limit - 1];
+                Some(serde_json::to_string(last).map_err(Error::Encode)?)
+            }
+            false => None,
+        };
+        Ok(Self {
+            request_id: query.request_id,
+            collection: query.collection,
+            seq,
+            records: matching
+                .into_iter()
+                .map(|(id, record)| SnapshotEntry::new(id, record))
+                .collect(),
+            next_cursor,
+        })
+    }
+
+    pub fn request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    pub fn collection(&self) -> &C {
+        &self.collection
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn records(&self) -> &[SnapshotEntry<ID, T>] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<SnapshotEntry<ID, T>> {
+        self.records
+    }
+
+    pub fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    // whether the live event at `seq` is already reflected in the page
+    pub fn covers(&self, seq: u64) -> bool {
+        seq <= self.seq
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        ID: Serialize,
+        C: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+// whatever answers queries on the server, e.g. a store or a materialized
+// view. it should stamp each result with the seq it read at, so the result can
+// be spliced into the live stream
+pub trait Queryable {
+    type Id;
+    type Record: Serialize + TS;
+    type Collection;
+
+    fn query(&self, query: Query<Self::Id, Self::Collection>) -> Result<Page<Self>, Error>;
+}
+
+type Page<Q> =
+    QueryResult<<Q as Queryable>::Id, <Q as Queryable>::Record, <Q as Queryable>::Collection>;
+
+fn matches<T: Serialize>(
+    filter: Option<&BTreeMap<String, Value>>,
+    record: &T,
+) -> Result<bool, Error> {
+    let Some(filter) = filter.filter(|filter| !filter.is_empty()) else {
+        return Ok(true);
+    };
+    let record = serde_json::to_value(record).map_err(Error::Encode)?;
+    Ok(filter
+        .iter()
+        .all(|(field, value)| record.get(field) == Some(value)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{Error, Query, QueryResult, Queryable};
+
+    struct Kennel {
+        seq: u64,
+        dogs: Vec<DoggoRecord>,
+    }
+
+    impl Queryable for Kennel {
+        type Id = u32;
+        type Record = DoggoRecord;
+        type Collection = Collection;
+
+        fn query(
+            &self,
+            query: Query<u32, Collection>,
+        ) -> Result<QueryResult<u32, DoggoRecord, Collection>, Error> {
+            let records = self.dogs.iter().map(|dog| (dog.id, dog.clone()));
+            QueryResult::page(query, self.seq, records)
+        }
+    }
+
+    #[test]
+    fn queries_page_through_records() {
+        let dog = |id, breed: &str| DoggoRecord {
+            name: format!("dog {id}"),
+            breed: breed.to_string(),
+            ..doggo(id)
+        };
+        let kennel = Kennel {
+            seq: 7,
+            dogs: vec![
+                dog(4, "Poodle"),
+                dog(2, "Poodle"),
+                dog(3, "Beagle"),
+                dog(1, "Poodle"),
+            ],
+        };
+        let poodles = || Query::new(1, Collection::Dogs).with_filter("breed", "Poodle");
+
+        let first = kennel.query(poodles().with_limit(2)).unwrap();
+        let ids = |result: &QueryResult<u32, DoggoRecord, Collection>| -> Vec<u32> {
+            result
+                .records()
+                .iter()
+                .map(|entry| entry.clone().into_parts().0)
+                .collect()
+        };
+        assert_eq!(ids(&first), vec![1, 2]);
+        insta::assert_snapshot!(first.clone().into_ws_body().try_json().unwrap(), @r###"{"data":{"request_id":1,"collection":"Dogs","seq":7,"records":[{"id":1,"data":{"id":1,"name":"dog 1","breed":"Poodle"}},{"id":2,"data":{"id":2,"name":"dog 2","breed":"Poodle"}}],"next_cursor":"2"}}"###);
+
+        let cursor = first.next_cursor().unwrap();
+        let second = kennel
+            .query(poodles().with_limit(2).with_cursor(cursor))
+            .unwrap();
+        assert_eq!((ids(&second), second.next_cursor()), (vec![4], None));
+        assert!(second.covers(7) && !second.covers(8));
+        assert!(kennel.query(poodles().with_limit(0)).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+// live collections for reactive frontends. each collection is materialized
+// and its records pushed into a signal after every event that touches it, so
+// a leptos or yew component re-renders as the server publishes:
+//
+//     let dogs = RwSignal::new(Vec::new());
+//     let live = LiveCollections::new().with_rw_signal(Collection::Dogs, dogs);
+//     spawn_local(live.run(client));
+//
+// `with_rw_signal` comes with the `leptos` feature and `with_use_state`
+// with the `yew` one. without them, anything with a setter is a `Signal`
+use std::hash::Hash;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{ApplyPatch, Event, EventVerb, Listener, Materializer, Snapshot};
+
+// where the records of a collection go, e.g. a leptos `RwSignal<Vec<T>>` or
+// a yew `UseStateHandle<Vec<T>>` behind a closure
+pub trait Signal<T> {
+    fn set(&self, value: T);
+}
+
+impl<T, F: Fn(T)> Signal<T> for F {
+    fn set(&self, value: T) {
+        self(value)
+    }
+}
+
+struct Live<ID, T, C> {
+    collection: C,
+    materializer: Materializer<ID, T>,
+    signal: Box<dyn Signal<Vec<T>>>,
+}
+
+// signals are set with the records in id order, so lists don't reshuffle on
+// every update
+pub struct LiveCollections<ID, T, C> {
+    collections: Vec<Live<ID, T, C>>,
+}
+
+impl<ID, T, C> LiveCollections<ID, T, C>
+where
+    ID: Eq + Hash + Ord + Clone,
+    T: Clone,
+    C: PartialEq,
+{
+    pub fn new() -> Self {
+        Self {
+            collections: Vec::new(),
+        }
+    }
+
+    pub fn with_collection(mut self, collection: C, signal: impl Signal<Vec<T>> + 'static) -> Self {
+        self.collections.push(Live {
+            collection,
+            materializer: Materializer::new(),
+            signal: Box::new(signal),
+        });
+        self
+    }
+
+    // `collection` into a leptos signal, which components read with e.g.
+    // `dogs.get()`
+    #[cfg(feature = "leptos")]
+    pub fn with_rw_signal(self, collection: C, signal: leptos::prelude::RwSignal<Vec<T>>) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        use leptos::prelude::Set;
+
+        self.with_collection(collection, move |records| signal.set(records))
+    }
+
+    // `collection` into the `use_state` of a yew function component, which
+    // re-renders it on every update
+    #[cfg(feature = "yew")]
+    pub fn with_use_state(self, collection: C, state: yew::UseStateHandle<Vec<T>>) -> Self
+    where
+        T: 'static,
+    {
+        self.with_collection(collection, move |records| state.set(records))
+    }
+
+    pub fn get(&self, collection: &C, id: &ID) -> Option<&T> {
+        self.live(collection)?.materializer.get(id)
+    }
+
+    // replaces the collection's records, e.g. with the answer to a `Query`
+    // sent before subscribing
+    pub fn load_snapshot(&mut self, snapshot: Snapshot<ID, T, C>)
+    where
+        T: Serialize + TS,
+    {
+        let Some(index) = self.index(snapshot.collection()) else {
+            return;
+        };
+        let live = &mut self.collections[index];
+        live.materializer.load_snapshot(snapshot);
+        publish(live);
+    }
+
+    // false for events of collections nobody is watching
+    pub fn apply<P>(&mut self, event: Event<ID, T, C, P>) -> bool
+    where
+        ID: Send + 'static,
+        T: Serialize + TS + Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+    {
+        // a transaction's markers concern every collection it touched
+        match *event.verb() {
+            EventVerb::TxnCommit(txn_id) => {
+                for live in &mut self.collections {
+                    let pending = live.materializer.pending();
+                    live.materializer.commit(txn_id);
+                    if live.materializer.pending() < pending {
+                        publish(live);
+                    }
+                }
+                return true;
+            }
+            EventVerb::TxnAbort(txn_id) => {
+                for live in &mut self.collections {
+                    live.materializer.abort(txn_id);
+                }
+                return true;
+            }
+            _ => {}
+        }
+        let Some(index) = event
+            .collection()
+            .and_then(|collection| self.index(collection))
+        else {
+            return false;
+        };
+        let live = &mut self.collections[index];
+        live.materializer.apply(event);
+        publish(live);
+        true
+    }
+
+    // applies everything `listener` yields until it fails, e.g. a `Client`
+    pub async fn run<L, P>(mut self, mut listener: L) -> L::Error
+    where
+        L: Listener<Item = Event<ID, T, C, P>>,
+        ID: Send + 'static,
+        T: Serialize + TS + Send + 'static,
+        C: Send + 'static,
+        P: Serialize + TS + ApplyPatch<T> + Send + 'static,
+    {
+        loop {
+            match listener.recv().await {
+                Ok(event) => {
+                    self.apply(event);
+                }
+                Err(err) => return err,
+            }
+        }
+    }
+
+    fn index(&self, collection: &C) -> Option<usize> {
+        self.collections
+            .iter()
+            .position(|live| live.collection == *collection)
+    }
+
+    fn live(&self, collection: &C) -> Option<&Live<ID, T, C>> {
+        self.collections
+            .iter()
+            .find(|live| live.collection == *collection)
+    }
+}
+
+impl<ID, T, C> Default for LiveCollections<ID, T, C>
+where
+    ID: Eq + Hash + Ord + Clone,
+    T: Clone,
+    C: PartialEq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn publish<ID: Eq + Hash + Ord + Clone, T: Clone, C>(live: &Live<ID, T, C>) {
+    let mut records: Vec<_> = live.materializer.records().iter().collect();
+    records.sort_by_key(|(id, _)| *id);
+    live.signal.set(
+        records
+            .into_iter()
+            .map(|(_, record)| record.clone())
+            .collect(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::reactive::LiveCollections;
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Error, Event, Service, Snapshot, SnapshotEntry, Syncable};
+
+    #[test]
+    fn signals_follow_their_collections() {
+        let dogs = Arc::new(Mutex::new(Vec::new()));
+        let cats = Arc::new(Mutex::new(Vec::new()));
+        let mut live = {
+            let (dogs, cats) = (dogs.clone(), cats.clone());
+            LiveCollections::new()
+                .with_collection(Collection::Dogs, move |records: Vec<DoggoRecord>| {
+                    *dogs.lock().unwrap() = records
+                })
+                .with_collection(Collection::Cats, move |records| {
+                    *cats.lock().unwrap() = records
+                })
+        };
+        let named = |id, name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(id)
+        };
+
+        live.load_snapshot(Snapshot::new(
+            Collection::Cats,
+            0,
+            vec![SnapshotEntry::new(3, named(3, "Fluffy"))],
+        ));
+        assert!(live.get(&Collection::Cats, &3).is_some());
+
+        let service = BroadcastService::<Event<u32, DoggoRecord, Collection>>::new();
+        let listener = service.listener();
+        service
+            .publish(named(2, "Woofy").to_upsert_event())
+            .unwrap();
+        service
+            .publish(named(1, "Barky").to_upsert_event())
+            .unwrap();
+        service
+            .publish(Event::new_delete_event(2, Collection::Dogs))
+            .unwrap();
+        drop(service);
+        let err = block_on(live.run(listener));
+
+        assert!(matches!(err, Error::Closed));
+        let names = |records: &Mutex<Vec<DoggoRecord>>| -> Vec<String> {
+            records
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|record| record.name.clone())
+                .collect()
+        };
+        assert_eq!(names(&dogs), vec!["Barky"]);
+        assert_eq!(names(&cats), vec!["Fluffy"]);
+    }
+
+    #[cfg(feature = "leptos")]
+    #[test]
+    fn leptos_signals_follow_their_collections() {
+        use leptos::prelude::{GetUntracked, RwSignal};
+
+        let dogs = RwSignal::new(Vec::new());
+        let mut live = LiveCollections::new().with_rw_signal(Collection::Dogs, dogs);
+        live.apply(doggo(1).to_upsert_event());
+        live.apply(doggo(2).to_upsert_event());
+        live.apply(Event::<u32, DoggoRecord, Collection>::new_delete_event(
+            1,
+            Collection::Dogs,
+        ));
+
+        let ids: Vec<_> = dogs.get_untracked().iter().map(|dog| dog.id).collect();
+        assert_eq!(ids, [2]);
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Serialize;
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{
+    AppendableResource, Bytes, Error, Event, EventVerb, PatchResource, Routable, Serialized,
+    UpdatableResource, WsBody,
+};
+
+// implemented by anything whose record fields can be left out of its json
+pub trait Redact {
+    // the `WsBody` json without `fields` of the record. patches lose their
+    // operations on those fields
+    fn redacted_json(&self, fields: &[&str]) -> Result<Bytes, Error>;
+}
+
+impl<ID, T, C, P> Redact for Event<ID, T, C, P>
+where
+    ID: Serialize,
+    T: Serialize + TS,
+    C: Serialize,
+    P: Serialize + TS,
+{
+    fn redacted_json(&self, fields: &[&str]) -> Result<Bytes, Error> {
+        let verb = match &self.verb {
+            _ if fields.is_empty() => None,
+            EventVerb::Insert(resource) => Some(EventVerb::Insert(AppendableResource {
+                location: resource.location.as_ref(),
+                data: Stripped::new(&resource.data, fields)?,
+            })),
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => {
+                let resource = UpdatableResource {
+                    location: resource.location.as_ref(),
+                    data: Stripped::new(&resource.data, fields)?,
+                    expected_revision: resource.expected_revision,
+                };
+                match &self.verb {
+                    EventVerb::Update(_) => Some(EventVerb::Update(resource)),
+                    _ => Some(EventVerb::Upsert(resource)),
+                }
+            }
+            EventVerb::Patch(resource) => Some(EventVerb::Patch(PatchResource {
+                location: resource.location.as_ref(),
+                data: Stripped::new(&resource.data, fields)?,
+            })),
+            _ => None,
+        };
+        let Some(verb) = verb else {
+            return Ok(WsBody::new(self).try_json()?.into_bytes().into());
+        };
+        // the same event, only with the record stripped
+        let event = Event {
+            seq: self.seq,
+            occurred_at: self.occurred_at,
+            schema_version: self.schema_version,
+            scope: self.scope.clone(),
+            idempotency_key: self.idempotency_key.clone(),
+            expires_at: self.expires_at,
+            meta: self.meta.clone(),
+            causality: self.causality.clone(),
+            verb,
+        };
+        Ok(WsBody::new(event).try_json()?.into_bytes().into())
+    }
+}
+
+// audiences that may see every field share the encoding of the event
+impl<T: Redact + Serialize> Redact for Serialized<T> {
+    fn redacted_json(&self, fields: &[&str]) -> Result<Bytes, Error> {
+        match fields {
+            [] => self.json(),
+            fields => self.event().redacted_json(fields),
+        }
+    }
+}
+
+// a record or patch without some of its fields. its json keys come out
+// sorted, but only the record's
+struct Stripped(Value);
+
+impl Stripped {
+    fn new(data: &impl Serialize, fields: &[&str]) -> Result<Self, Error> {
+        let mut data = serde_json::to_value(data).map_err(Error::Encode)?;
+        strip(&mut data, fields);
+        Ok(Self(data))
+    }
+}
+
+impl Serialize for Stripped {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+// only needed to put it in an `Event`, it is never exported
+impl TS for Stripped {
+    fn name() -> String {
+        "unknown".to_owned()
+    }
+
+    fn inline() -> String {
+        Self::name()
+    }
+
+    fn dependencies() -> Vec<ts_rs::Dependency> {
+        Vec::new()
+    }
+
+    fn transparent() -> bool {
+        false
+    }
+}
+
+fn strip(data: &mut Value, fields: &[&str]) {
+    match data {
+        Value::Object(record) => record.retain(|field, _| !fields.contains(&field.as_str())),
+        // a json patch
+        Value::Array(operations) => operations.retain(|operation| {
+            ["path", "from"].iter().all(|key| {
+                let Some(path) = operation.get(key).and_then(Value::as_str) else {
+                    return true;
+                };
+                let field = path.trim_start_matches('/').split('/').next();
+                !field.is_some_and(|field| fields.contains(&field))
+            })
+        }),
+        _ => {}
+    }
+}
+
+struct Rule<C, A> {
+    collection: C,
+    field: String,
+    visible_to: Box<dyn Fn(&A) -> bool + Send + Sync>,
+}
+
+// which fields of which collections each audience may see, e.g. only admins
+// see the `email` of users. the publish path encodes an event once per
+// audience instead of once per event type, and audiences that may see every
+// field of it all get the same bytes
+pub struct RedactionPolicy<C, A> {
+    rules: Vec<Rule<C, A>>,
+}
+
+impl<C, A> RedactionPolicy<C, A> {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    // hides `field` of `collection` from audiences `visible_to` refuses
+    pub fn redact(
+        mut self,
+        collection: C,
+        field: impl Into<String>,
+        visible_to: impl Fn(&A) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.rules.push(Rule {
+            collection,
+            field: field.into(),
+            visible_to: Box::new(visible_to),
+        });
+        self
+    }
+
+    // the fields of `collection` hidden from `audience`
+    pub fn hidden_fields(&self, collection: &C, audience: &A) -> Vec<&str>
+    where
+        C: PartialEq,
+    {
+        self.rules
+            .iter()
+            .filter(|rule| rule.collection == *collection && !(rule.visible_to)(audience))
+            .map(|rule| rule.field.as_str())
+            .collect()
+    }
+
+    // the `WsBody` json of `event` as `audience` may see it
+    pub fn encode<T>(&self, event: &T, audience: &A) -> Result<Bytes, Error>
+    where
+        T: Redact + Routable<Collection = C>,
+        C: PartialEq,
+    {
+        let fields = match event.collection() {
+            Some(collection) => self.hidden_fields(collection, audience),
+            None => Vec::new(),
+        };
+        event.redacted_json(&fields)
+    }
+}
+
+impl<C, A> Default for RedactionPolicy<C, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection, DoggoPatch};
+    use crate::{
+        BroadcastService, Event, Listener, Patchable, PublishSerialized, RedactionPolicy, Service,
+        Syncable,
+    };
+
+    #[derive(PartialEq)]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    #[test]
+    fn audiences_get_their_own_payloads() {
+        let policy = RedactionPolicy::new().redact(Collection::Dogs, "breed", |role: &Role| {
+            *role == Role::Admin
+        });
+        let doggo = doggo(1);
+        let service = BroadcastService::new();
+        let mut listener = service.listener();
+        service
+            .publish_serialized(doggo.clone().to_upsert_event().into_patchable())
+            .unwrap();
+        service
+            .publish_serialized(doggo.to_patch_event(DoggoPatch {
+                name: Some("Woofy".to_string()),
+            }))
+            .unwrap();
+        service
+            .publish_serialized(Event::new_delete_event(1, Collection::Dogs))
+            .unwrap();
+
+        block_on(async {
+            let upsert = listener.recv().await.unwrap();
+            let admin = policy.encode(&upsert, &Role::Admin).unwrap();
+            assert!(admin == upsert.json().unwrap());
+            let guest = policy.encode(&upsert, &Role::Guest).unwrap();
+            insta::assert_snapshot!(std::str::from_utf8(&guest).unwrap(), @r###"{"data":{"seq":0,"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky"}}}}}"###);
+
+            let patch = listener.recv().await.unwrap();
+            let guest = policy.encode(&patch, &Role::Guest).unwrap();
+            insta::assert_snapshot!(std::str::from_utf8(&guest).unwrap(), @r###"{"data":{"seq":1,"verb":{"type":"patch","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"name":"Woofy"}}}}}"###);
+            let delete = listener.recv().await.unwrap();
+            assert!(policy.encode(&delete, &Role::Guest).unwrap() == delete.json().unwrap());
+        });
+    }
+}
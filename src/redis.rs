@@ -0,0 +1,466 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use redis::aio::MultiplexedConnection;
+use redis::{Client, Cmd, FromRedisValue, RedisError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::bus::{self, BusListener, Going};
+use crate::{Error, EventStore, Listener, Routable, Sequenced, Service, Shutdown};
+
+// publishes every event as json to a redis channel per collection
+// (`<prefix>:<collection>`, or just `<prefix>` for events without one), so
+// several server instances can fan each other's events out to their clients.
+// seqs come from a shared counter (`<prefix>:seq`), so they are unique across
+// instances, but events published concurrently by different instances may
+// arrive slightly out of order. pub/sub keeps no history: `listener_from` can
+// only replay what the optional store has. tls and auth are configured on
+// the `redis::Client`. once it's shut down, publishes fail and each listener
+// gets `Error::GoingAway` after the events that had reached it
+pub struct RedisService<T> {
+    client: Client,
+    prefix: String,
+    connection: Mutex<Option<MultiplexedConnection>>,
+    store: Option<Arc<dyn EventStore<T>>>,
+    going: Going,
+}
+
+pub struct RedisListener<T>(BusListener<T>);
+
+impl<T> RedisService<T> {
+    pub fn new(client: Client, prefix: impl Into<String>) -> Self {
+        Self::build(client, prefix.into(), None)
+    }
+
+    pub fn with_store(
+        client: Client,
+        prefix: impl Into<String>,
+        store: impl EventStore<T> + 'static,
+    ) -> Self {
+        Self::build(client, prefix.into(), Some(Arc::new(store)))
+    }
+
+    fn build(client: Client, prefix: String, store: Option<Arc<dyn EventStore<T>>>) -> Self {
+        Self {
+            client,
+            prefix,
+            connection: Mutex::new(None),
+            store,
+            going: Going::default(),
+        }
+    }
+
+    // how long clients wait before reconnecting once the service shuts down
+    pub fn with_reconnect_after(mut self, reconnect_after: Duration) -> Self {
+        self.going.reconnect_after = reconnect_after;
+        self
+    }
+
+    fn seq_key(&self) -> String {
+        format!("{}:seq", self.prefix)
+    }
+
+    fn channel<C: Serialize>(&self, collection: Option<&C>) -> Result<String, Error> {
+        let Some(collection) = collection else {
+            return Ok(self.prefix.clone());
+        };
+        let name = match serde_json::to_value(collection).map_err(Error::Encode)? {
+            Value::String(name) => name,
+            other => other.to_string(),
+        };
+        Ok(format!("{}:{name}", self.prefix))
+    }
+
+    // runs `command` on the shared connection, reconnecting first if the
+    // last command failed
+    fn query<R: FromRedisValue + Send + 'static>(&self, command: Cmd) -> Result<R, Error> {
+        let mut connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (client, shared) = (self.client.clone(), connection.take());
+        let result = bus::run(async move {
+            let mut connection = match shared {
+                Some(connection) => connection,
+                None => client.get_multiplexed_async_connection().await?,
+            };
+            let reply = command.query_async(&mut connection).await?;
+            Ok::<_, RedisError>((connection, reply))
+        });
+        let (opened, reply) = result.map_err(transport)?;
+        *connection = Some(opened);
+        Ok(reply)
+    }
+
+    // a listener for only these collections (and events without one). unlike
+    // `listener_for_collections`, other collections never leave redis
+    pub fn collection_listener<C: Serialize>(
+        &self,
+        collections: impl IntoIterator<Item = C>,
+    ) -> RedisListener<T>
+    where
+        T: Routable + DeserializeOwned + Send + 'static,
+        T::Collection: PartialEq + Send,
+        T::Id: PartialEq + Send,
+    {
+        let channels = collections
+            .into_iter()
+            .map(|collection| self.channel(Some(&collection)))
+            .chain([Ok(self.prefix.clone())])
+            .collect::<Result<Vec<_>, _>>();
+        RedisListener(BusListener::spawn(&self.going, || {
+            self.subscribe(channels?, Vec::new())
+        }))
+    }
+
+    // opens a dedicated connection subscribed to `channels` and `patterns`,
+    // yielding the payloads of their messages
+    fn subscribe(
+        &self,
+        channels: Vec<String>,
+        patterns: Vec<String>,
+    ) -> Result<impl futures_util::Stream<Item = Vec<u8>> + Send + Unpin + 'static, Error> {
+        let client = self.client.clone();
+        let messages = bus::run(async move {
+            let mut pubsub = client.get_async_pubsub().await?;
+            if !channels.is_empty() {
+                pubsub.subscribe(channels).await?;
+            }
+            if !patterns.is_empty() {
+                pubsub.psubscribe(patterns).await?;
+            }
+            Ok::<_, RedisError>(pubsub.into_on_message())
+        })
+        .map_err(transport)?;
+        Ok(messages.map(|message| message.get_payload_bytes().to_vec()))
+    }
+}
+
+impl<T> Service<T> for RedisService<T>
+where
+    T: Routable + Sequenced + Serialize + DeserializeOwned + Send + 'static,
+    T::Collection: Serialize + PartialEq + Send,
+    T::Id: PartialEq + Send,
+{
+    type Listener = RedisListener<T>;
+    type Error = Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        self.going.check()?;
+        let next: u64 = self.query(redis::cmd("INCR").arg(self.seq_key()).clone())?;
+        event.set_seq(next.saturating_sub(1));
+        if let Some(store) = &self.store {
+            store.append(&event)?;
+        }
+        let channel = self.channel(event.collection())?;
+        let json = serde_json::to_vec(&event).map_err(Error::Encode)?;
+        self.query::<()>(redis::cmd("PUBLISH").arg(channel).arg(json).clone())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        let pattern = format!("{}:*", self.prefix);
+        RedisListener(BusListener::spawn(&self.going, || {
+            self.subscribe(vec![self.prefix.clone()], vec![pattern])
+        }))
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        let RedisListener(mut listener) = self.listener();
+        listener.replay(self.store.as_ref(), seq, self.head_seq());
+        RedisListener(listener)
+    }
+
+    // 0 if redis can't be reached
+    fn head_seq(&self) -> u64 {
+        self.query::<Option<u64>>(redis::cmd("GET").arg(self.seq_key()).clone())
+            .ok()
+            .flatten()
+            .unwrap_or(0)
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.going.shutdown(deadline)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Sequenced + Send> Listener for RedisListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        self.0.recv().await
+    }
+}
+
+fn transport(err: RedisError) -> Error {
+    Error::Transport(err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use redis::Client;
+
+    use crate::test::{block_on, Collection};
+    use crate::{Error, Event, InMemoryEventStore, Listener, RedisService, Service};
+
+    type Subscribers = Arc<Mutex<Vec<(Vec<String>, TcpStream)>>>;
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    fn client(addr: &str) -> Client {
+        Client::open(format!("redis://{addr}")).unwrap()
+    }
+
+    fn encode(args: &[&[u8]]) -> Vec<u8> {
+        let mut out = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            out.extend_from_slice(arg);
+            out.extend_from_slice(b"\r\n");
+        }
+        out
+    }
+
+    // the next command a client sent, `None` once it hung up
+    fn read_command(reader: &mut impl BufRead) -> Option<Vec<Vec<u8>>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let count: usize = line.strip_prefix('*')?.trim_end().parse().ok()?;
+        (0..count)
+            .map(|_| {
+                line.clear();
+                reader.read_line(&mut line).ok()?;
+                let len: usize = line.strip_prefix('$')?.trim_end().parse().ok()?;
+                let mut arg = vec![0; len + 2];
+                reader.read_exact(&mut arg).ok()?;
+                arg.truncate(len);
+                Some(arg)
+            })
+            .collect()
+    }
+
+    fn delete(id: u32) -> DeleteEvent {
+        DeleteEvent::new_delete_event(id, Collection::Dogs)
+    }
+
+    // every connection the server accepted, for it to drop them
+    #[derive(Clone, Default)]
+    struct Accepted(Arc<Mutex<Vec<TcpStream>>>);
+
+    impl Accepted {
+        fn drop_all(&self) {
+            for stream in self.0.lock().unwrap().drain(..) {
+                stream.shutdown(Shutdown::Both).unwrap();
+            }
+        }
+    }
+
+    fn fake_redis() -> String {
+        fake_redis_accepting().0
+    }
+
+    // a redis that understands INCR, GET, PUBLISH and (P)SUBSCRIBE, where
+    // patterns are only ever `<prefix>:*`. counters under `broken:` answer
+    // INCR with a string
+    fn fake_redis_accepting() -> (String, Accepted) {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let counters = Arc::new(Mutex::new(HashMap::<String, i64>::new()));
+        let subscribers = Subscribers::default();
+        let accepted = Accepted::default();
+        let connections = accepted.clone();
+        thread::spawn(move || {
+            for stream in server.incoming() {
+                let stream = stream.unwrap();
+                connections
+                    .0
+                    .lock()
+                    .unwrap()
+                    .push(stream.try_clone().unwrap());
+                let (counters, subscribers) = (counters.clone(), subscribers.clone());
+                thread::spawn(move || serve(stream, counters, subscribers));
+            }
+        });
+        (addr, accepted)
+    }
+
+    fn serve(stream: TcpStream, counters: Arc<Mutex<HashMap<String, i64>>>, subs: Subscribers) {
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        while let Some(args) = read_command(&mut reader) {
+            let text = |index: usize| String::from_utf8(args[index].clone()).unwrap();
+            let reply = match text(0).as_str() {
+                "INCR" if text(1).starts_with("broken:") => "$3\r\nabc\r\n".to_owned(),
+                "INCR" => {
+                    let mut counters = counters.lock().unwrap();
+                    let count = counters.entry(text(1)).or_default();
+                    *count += 1;
+                    format!(":{count}\r\n")
+                }
+                "GET" => match counters.lock().unwrap().get(&text(1)) {
+                    Some(count) => format!("${}\r\n{count}\r\n", count.to_string().len()),
+                    None => "$-1\r\n".to_owned(),
+                },
+                "PUBLISH" => {
+                    let channel = text(1);
+                    let mut subs = subs.lock().unwrap();
+                    for (names, stream) in subs.iter_mut() {
+                        let matches = names.iter().any(|name| match name.strip_suffix('*') {
+                            Some(prefix) => channel.starts_with(prefix),
+                            None => *name == channel,
+                        });
+                        if matches {
+                            let message: [&[u8]; 3] = [b"message", channel.as_bytes(), &args[2]];
+                            let _ = stream.write_all(&encode(&message));
+                        }
+                    }
+                    ":1\r\n".to_owned()
+                }
+                command @ ("SUBSCRIBE" | "PSUBSCRIBE") => {
+                    let names: Vec<_> = (1..args.len()).map(text).collect();
+                    let kind = command.to_lowercase();
+                    let mut reply = String::new();
+                    for (count, name) in names.iter().enumerate() {
+                        reply += &format!("*3\r\n${}\r\n{kind}\r\n", kind.len());
+                        reply += &format!("${}\r\n{name}\r\n:{}\r\n", name.len(), count + 1);
+                    }
+                    let stream = writer.try_clone().unwrap();
+                    let mut subs = subs.lock().unwrap();
+                    match subs
+                        .iter_mut()
+                        .find(|(_, other)| other.peer_addr().ok() == stream.peer_addr().ok())
+                    {
+                        Some((existing, _)) => existing.extend(names),
+                        None => subs.push((names, stream)),
+                    }
+                    reply
+                }
+                // the client's CLIENT SETINFO when it connects
+                _ => "-ERR unknown command\r\n".to_owned(),
+            };
+            if writer.write_all(reply.as_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn events_fan_out_through_redis() {
+        let addr = fake_redis();
+        let cat = |id| DeleteEvent::new_delete_event(id, Collection::Cats);
+
+        let first = RedisService::with_store(client(&addr), "rsp", InMemoryEventStore::new(16));
+        let second = RedisService::new(client(&addr), "rsp");
+        let mut everything = second.listener();
+        let mut dogs = second.collection_listener([Collection::Dogs]);
+
+        first.publish(delete(1)).unwrap();
+        second.publish(cat(2)).unwrap();
+        first.publish(delete(3)).unwrap();
+        assert_eq!(first.head_seq(), 3);
+
+        let mut resumed = first.listener_from(1);
+        block_on(async {
+            for seq in 0..3 {
+                assert_eq!(everything.recv().await.unwrap().seq(), Some(seq));
+            }
+            assert_eq!(dogs.recv().await.unwrap().seq(), Some(0));
+            assert_eq!(dogs.recv().await.unwrap().seq(), Some(2));
+            // `second` has no store, so only seq 2 (published by `first`) is replayed
+            assert_eq!(resumed.recv().await.unwrap().seq(), Some(2));
+        });
+    }
+
+    #[test]
+    fn stored_events_are_replayed_to_resuming_listeners() {
+        let addr = fake_redis();
+        let service = RedisService::with_store(client(&addr), "rsp", InMemoryEventStore::new(16));
+        (1..=3).for_each(|id| service.publish(delete(id)).unwrap());
+
+        let mut from_start = service.listener_from(0);
+        let mut from_head = service.listener_from(3);
+        let mut from_future = service.listener_from(10);
+        let storeless_service = RedisService::<DeleteEvent>::new(client(&addr), "rsp");
+        let mut storeless = storeless_service.listener_from(0);
+        service.publish(delete(4)).unwrap();
+
+        block_on(async {
+            for seq in 0..4 {
+                assert_eq!(from_start.recv().await.unwrap().seq(), Some(seq));
+            }
+            for listener in [&mut from_head, &mut from_future, &mut storeless] {
+                assert_eq!(listener.recv().await.unwrap().seq(), Some(3));
+            }
+        });
+    }
+
+    #[test]
+    fn publishes_reconnect_after_the_server_drops_them() {
+        let (addr, accepted) = fake_redis_accepting();
+        let service = RedisService::new(client(&addr), "rsp");
+        let mut listener = service.listener();
+        service.publish(delete(1)).unwrap();
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+
+        accepted.drop_all();
+        assert!(matches!(
+            service.publish(delete(2)),
+            Err(Error::Transport(_))
+        ));
+        // a subscription isn't reopened, its listener just closes
+        assert!(matches!(block_on(listener.recv()), Err(Error::Closed)));
+
+        let mut listener = service.listener();
+        service.publish(delete(3)).unwrap();
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(1));
+    }
+
+    #[test]
+    fn malformed_replies_are_refused() {
+        let addr = fake_redis();
+        let broken = RedisService::new(client(&addr), "broken");
+        insta::assert_snapshot!(broken.publish(delete(1)).unwrap_err().to_string(), @r###"transport failed: Incompatible type - "Could not convert from string." (value was bulk-string('"abc"'))"###);
+
+        // a message that isn't an event ends the subscription
+        let service = RedisService::new(client(&addr), "rsp");
+        let mut listener = service.listener();
+        let mut raw = BufReader::new(TcpStream::connect(&addr).unwrap());
+        let publish: [&[u8]; 3] = [b"PUBLISH", b"rsp:Dogs", b"{\"data\":"];
+        raw.get_mut().write_all(&encode(&publish)).unwrap();
+        raw.read_line(&mut String::new()).unwrap();
+        service.publish(delete(2)).unwrap();
+        assert!(matches!(block_on(listener.recv()), Err(Error::Closed)));
+    }
+
+    #[test]
+    fn shutdowns_refuse_publishes_and_tell_listeners() {
+        let addr = fake_redis();
+        let service = RedisService::new(client(&addr), "rsp")
+            .with_reconnect_after(Duration::from_millis(1_500));
+        let mut listener = service.listener();
+        service.publish(delete(1)).unwrap();
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+
+        let shutdown = service.shutdown(Duration::from_millis(500));
+        assert!(matches!(
+            service.publish(delete(2)),
+            Err(Error::GoingAway(_))
+        ));
+        let Err(Error::GoingAway(going_away)) = block_on(listener.recv()) else {
+            panic!("expected the service to be going away");
+        };
+        assert_eq!(going_away.reconnect_after(), Duration::from_millis(1_500));
+        block_on(shutdown).unwrap();
+        let mut late = service.listener();
+        assert!(matches!(block_on(late.recv()), Err(Error::GoingAway(_))));
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::WsBody;
+
+// a payload type's typescript declaration, fingerprinted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SchemaEntry {
+    name: String,
+    // fnv-1a of the declaration as 16 hex digits, so it survives a trip
+    // through a js number
+    hash: String,
+}
+
+impl SchemaEntry {
+    pub fn of<T: TS>() -> Self {
+        Self {
+            name: T::name(),
+            hash: schema_hash::<T>(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+}
+
+// the fingerprints of every payload type a server speaks, built at startup and
+// sent to clients so they can tell when their generated types have drifted
+// from the server's. a type is fingerprinted without the types it refers to,
+// so those should be registered too
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SchemaManifest {
+    schemas: Vec<SchemaEntry>,
+}
+
+impl SchemaManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_type<T: TS>(mut self) -> Self {
+        let entry = SchemaEntry::of::<T>();
+        match self
+            .schemas
+            .iter_mut()
+            .find(|other| other.name == entry.name)
+        {
+            Some(other) => *other = entry,
+            None => self.schemas.push(entry),
+        }
+        self
+    }
+
+    pub fn schemas(&self) -> &[SchemaEntry] {
+        &self.schemas
+    }
+
+    pub fn hash_of(&self, name: &str) -> Option<&str> {
+        self.schemas
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(SchemaEntry::hash)
+    }
+
+    // the names of the types in `self` that `other` is missing or declares
+    // differently
+    pub fn drift<'a>(&'a self, other: &SchemaManifest) -> Vec<&'a str> {
+        self.schemas
+            .iter()
+            .filter(|entry| other.hash_of(&entry.name) != Some(entry.hash.as_str()))
+            .map(SchemaEntry::name)
+            .collect()
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+// stable across builds and platforms, unlike std's hashers
+pub fn schema_hash<T: TS>() -> String {
+    let hash = T::decl().bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod test {
+    use ts_rs::TS;
+
+    use crate::test::DoggoRecord;
+    use crate::{EventMeta, SchemaManifest};
+
+    // the same record, as a client generated from an older server sees it
+    #[derive(TS)]
+    #[ts(rename = "DoggoRecord")]
+    #[allow(dead_code)]
+    struct OutdatedDoggoRecord {
+        id: u32,
+        name: String,
+    }
+
+    #[test]
+    fn manifests_show_drift() {
+        let server = SchemaManifest::new()
+            .with_type::<DoggoRecord>()
+            .with_type::<EventMeta>();
+        let client = SchemaManifest::new()
+            .with_type::<DoggoRecord>()
+            .with_type::<EventMeta>()
+            .with_type::<DoggoRecord>();
+        assert_eq!(server, client);
+        assert!(client.drift(&server).is_empty());
+        assert_eq!(server.hash_of("DoggoRecord").unwrap().len(), 16);
+
+        let outdated = SchemaManifest::new()
+            .with_type::<OutdatedDoggoRecord>()
+            .with_type::<EventMeta>();
+        assert_eq!(outdated.drift(&server), vec!["DoggoRecord"]);
+        assert!(
+            SchemaManifest::new().drift(&server).is_empty(),
+            "types only the server has aren't drift on the client"
+        );
+        assert_eq!(server.drift(&SchemaManifest::new()).len(), 2);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+// an isolated sync domain within one server, e.g. `workspace:123`. events of
+// one scope never reach listeners or connections bound to another, so the
+// same collections can be hosted for many tenants side by side
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Scope(String);
+
+impl Scope {
+    pub fn new(scope: impl Into<String>) -> Self {
+        Self(scope.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for Scope {
+    fn from(scope: &str) -> Self {
+        Self::new(scope)
+    }
+}
+
+impl From<String> for Scope {
+    fn from(scope: String) -> Self {
+        Self(scope)
+    }
+}
+
+// implemented by anything that can be put into a scope when it is
+// published. the scope is read through `Routable::scope`
+pub trait Scoped {
+    fn set_scope(&mut self, scope: Scope);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, Collection, DoggoRecord};
+    use crate::{BroadcastService, Event, Listener, Service, SubscriptionManager};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn scopes_are_isolated() {
+        let delete = |id| DoggoEvent::new_delete_event(id, Collection::Dogs);
+        let service = BroadcastService::<DoggoEvent>::new();
+        let mut acme = service.listener_for_scope("workspace:1");
+        service.publish_scoped("workspace:2", delete(1)).unwrap();
+        service.publish(delete(2)).unwrap();
+        service.publish_scoped("workspace:1", delete(3)).unwrap();
+        let event = block_on(acme.recv()).unwrap();
+        assert_eq!((event.id(), event.seq()), (Some(&3), Some(2)));
+        assert_eq!(
+            event.scope().map(|scope| scope.as_str()),
+            Some("workspace:1")
+        );
+
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let mut first = manager.connect();
+        let mut admin = manager.connect();
+        for connection in [first.connection(), admin.connection()] {
+            manager.subscribe(connection, Collection::Dogs, None);
+        }
+        manager.bind_scope(first.connection(), "workspace:1");
+        assert_eq!(manager.publish(delete(4).with_scope("workspace:2")), 1);
+        assert_eq!(manager.publish(delete(5).with_scope("workspace:1")), 2);
+        assert_eq!(block_on(first.recv()).unwrap().id(), Some(&5));
+        assert_eq!(block_on(admin.recv()).unwrap().id(), Some(&4));
+        insta::assert_snapshot!(delete(6).with_scope("workspace:1").into_ws_body().try_json().unwrap(), @r###"{"data":{"scope":"workspace:1","verb":{"type":"delete","payload":{"location":{"id":6,"txn_id":null,"collection":"Dogs"}}}}}"###);
+    }
+}
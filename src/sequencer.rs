@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Error, Event, Routable, Service, Shutdown};
+
+// implemented by anything that carries its record's revision
+pub trait Versioned: Routable {
+    fn revision(&self) -> Option<u64>;
+    fn set_revision(&mut self, revision: u64);
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Versioned for Event<ID, T, C, P> {
+    fn revision(&self) -> Option<u64> {
+        Event::revision(self)
+    }
+
+    fn set_revision(&mut self, revision: u64) {
+        if let Some(location) = self.verb.location_mut() {
+            location.revision = Some(revision);
+        }
+    }
+}
+
+// what a `KeyedSequencer` does with a publish that isn't newer than its record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderPolicy {
+    // fails the publish with `Error::OutOfOrder`
+    Reject,
+    // drops the event and reports success, e.g. for producers that replay
+    // events they may have published already
+    Drop,
+}
+
+// enforces ordering per record. services deliver events in the order they
+// were published, but two producers racing to publish changes to the same
+// record can still get them out of order. every event published through a
+// sequencer is stamped with its record's next revision: events without one
+// get the revision after the current one, and events that already have one
+// (e.g. a version column from the database) have to be newer than the current
+// one or they are rejected (or dropped). stamping and publishing to `inner`
+// happen under one lock, so the revisions listeners see for a record always
+// go up. events without a collection and id (inserts that haven't been given
+// an id yet, transaction markers) pass through as they are.
+//
+// revisions are kept per record for as long as the sequencer lives, deletes
+// included, so a stale update can't resurrect a deleted record
+pub struct KeyedSequencer<S, C, ID> {
+    inner: S,
+    policy: OrderPolicy,
+    state: Mutex<State<C, ID>>,
+}
+
+struct State<C, ID> {
+    revisions: HashMap<(C, ID), u64>,
+    dropped: u64,
+}
+
+impl<S, C, ID> KeyedSequencer<S, C, ID> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            policy: OrderPolicy::Reject,
+            state: Mutex::new(State {
+                revisions: HashMap::new(),
+                dropped: 0,
+            }),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: OrderPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // events dropped under `OrderPolicy::Drop`
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    // the revision the record was last published at
+    pub fn revision(&self, collection: &C, id: &ID) -> Option<u64>
+    where
+        C: Clone + Eq + Hash,
+        ID: Clone + Eq + Hash,
+    {
+        let key = (collection.clone(), id.clone());
+        self.lock().revisions.get(&key).copied()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<C, ID>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T, S, C, ID> Service<T> for KeyedSequencer<S, C, ID>
+where
+    T: Versioned<Collection = C, Id = ID>,
+    S: Service<T, Error = Error>,
+    C: Clone + Eq + Hash + Send,
+    ID: Clone + Eq + Hash + Send,
+{
+    type Listener = S::Listener;
+    type Error = Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Error> {
+        let key = match (event.collection(), event.id()) {
+            (Some(collection), Some(id)) => (collection.clone(), id.clone()),
+            _ => return self.inner.publish(event),
+        };
+        let mut state = self.lock();
+        let current = state.revisions.get(&key).copied();
+        let revision = match event.revision() {
+            None => current.map_or(1, |current| current + 1),
+            Some(found) if current.is_none_or(|current| found > current) => found,
+            Some(found) => {
+                let current = current.unwrap_or_default();
+                return match self.policy {
+                    OrderPolicy::Reject => Err(Error::OutOfOrder { current, found }),
+                    OrderPolicy::Drop => {
+                        state.dropped += 1;
+                        Ok(())
+                    }
+                };
+            }
+        };
+        event.set_revision(revision);
+        self.inner.publish(event)?;
+        state.revisions.insert(key, revision);
+        Ok(())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.inner.listener()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        self.inner.listener_from(seq)
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection};
+    use crate::{
+        BroadcastService, Error, KeyedSequencer, Listener, OrderPolicy, Service, Syncable,
+    };
+
+    #[test]
+    fn publishes_are_ordered_per_record() {
+        let service = KeyedSequencer::new(BroadcastService::new());
+        let mut listener = service.listener();
+
+        service.publish(doggo(1).to_upsert_event()).unwrap();
+        service
+            .publish(doggo(1).to_update_event().with_revision(5))
+            .unwrap();
+        let stale = service.publish(doggo(1).to_update_event().with_revision(3));
+        assert!(matches!(
+            stale,
+            Err(Error::OutOfOrder {
+                current: 5,
+                found: 3
+            })
+        ));
+        assert_eq!(
+            stale.unwrap_err().to_string(),
+            "publish at revision 3 is out of order, the record is at revision 5"
+        );
+        service.publish(doggo(2).to_upsert_event()).unwrap();
+        service.publish(doggo(1).to_delete_event()).unwrap();
+        assert_eq!(service.revision(&Collection::Dogs, &1), Some(6));
+
+        block_on(async {
+            for revision in [1, 5, 1, 6] {
+                assert_eq!(listener.recv().await.unwrap().revision(), Some(revision));
+            }
+        });
+
+        let service = KeyedSequencer::new(BroadcastService::new()).with_policy(OrderPolicy::Drop);
+        service
+            .publish(doggo(1).to_upsert_event().with_revision(2))
+            .unwrap();
+        service
+            .publish(doggo(1).to_upsert_event().with_revision(2))
+            .unwrap();
+        assert_eq!(service.dropped(), 1);
+        assert_eq!(service.head_seq(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use bytes::Bytes;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    metrics, DeadLetter, DeadLetterSink, DeadLetterStage, Error, Routable, Scope, Sequenced,
+    Service, WsBody,
+};
+
+// an event shared by every listener that receives it, along with its json
+// body. the body is encoded by whichever listener asks for it first (after
+// the service stamped the seq) and every other one gets the same bytes, so
+// fanning out to many connections serializes the event once instead of once
+// per connection
+pub struct Serialized<T> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    event: T,
+    json: OnceLock<Bytes>,
+}
+
+impl<T> Serialized<T> {
+    pub fn new(event: T) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                event,
+                json: OnceLock::new(),
+            }),
+        }
+    }
+
+    pub fn event(&self) -> &T {
+        &self.inner.event
+    }
+
+    // the `WsBody` json of the event, ready to be written to a websocket
+    pub fn json(&self) -> Result<Bytes, Error>
+    where
+        T: Serialize,
+    {
+        if let Some(json) = self.inner.json.get() {
+            return Ok(json.clone());
+        }
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("serialize", bytes = tracing::field::Empty);
+        let started = Instant::now();
+        let json = Bytes::from(WsBody::new(&self.inner.event).try_json()?.into_bytes());
+        metrics::seconds(metrics::SERIALIZATION_SECONDS, started.elapsed());
+        #[cfg(feature = "tracing")]
+        span.record("bytes", json.len());
+        // if another listener got there first, both encoded the same event
+        Ok(self.inner.json.get_or_init(|| json).clone())
+    }
+}
+
+impl<T> Serialized<T> {
+    // like `json`, handing the event to `sink` instead of failing, for
+    // connection writers that skip what they can't encode
+    pub fn json_or_dead_letter(&self, sink: &impl DeadLetterSink<T>) -> Option<Bytes>
+    where
+        T: Serialize + Clone,
+    {
+        self.json()
+            .map_err(|err| {
+                sink.dead_letter(DeadLetter::new(
+                    self.event().clone(),
+                    DeadLetterStage::Serialization,
+                    &err,
+                ))
+            })
+            .ok()
+    }
+
+    // the length of the json, if it was encoded already
+    #[cfg(feature = "tracing")]
+    pub(crate) fn encoded_len(&self) -> Option<usize> {
+        self.inner.json.get().map(Bytes::len)
+    }
+}
+
+impl<T> Clone for Serialized<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Serialized<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Serialized")
+            .field(&self.inner.event)
+            .finish()
+    }
+}
+
+impl<T: Clone + Sequenced> Sequenced for Serialized<T> {
+    fn seq(&self) -> Option<u64> {
+        self.inner.event.seq()
+    }
+
+    // a new seq invalidates the json. services stamp events before sharing
+    // them, so this normally doesn't copy the event
+    fn set_seq(&mut self, seq: u64) {
+        match Arc::get_mut(&mut self.inner) {
+            Some(inner) => {
+                inner.event.set_seq(seq);
+                inner.json = OnceLock::new();
+            }
+            None => {
+                let mut event = self.inner.event.clone();
+                event.set_seq(seq);
+                *self = Self::new(event);
+            }
+        }
+    }
+}
+
+impl<T: Routable> Routable for Serialized<T> {
+    type Collection = T::Collection;
+    type Id = T::Id;
+
+    fn collection(&self) -> Option<&Self::Collection> {
+        self.inner.event.collection()
+    }
+
+    fn id(&self) -> Option<&Self::Id> {
+        self.inner.event.id()
+    }
+
+    fn origin(&self) -> Option<&str> {
+        self.inner.event.origin()
+    }
+
+    fn scope(&self) -> Option<&Scope> {
+        self.inner.event.scope()
+    }
+}
+
+impl<T: Serialize> Serialize for Serialized<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.event.serialize(serializer)
+    }
+}
+
+// publishing to a service of `Serialized` events without wrapping them first
+pub trait PublishSerialized<T>: Service<Serialized<T>> {
+    fn publish_serialized(&self, event: T) -> Result<(), Self::Error> {
+        self.publish(Serialized::new(event))
+    }
+}
+
+impl<T, S: Service<Serialized<T>>> PublishSerialized<T> for S {}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Event, Listener, PublishSerialized, Service, Syncable};
+
+    #[test]
+    fn listeners_share_one_encoding() {
+        let doggo = doggo(1);
+        let service = BroadcastService::new();
+        let mut listeners = [service.listener(), service.listener()];
+        service
+            .publish_serialized(Event::<u32, DoggoRecord, Collection>::new_delete_event(
+                2,
+                Collection::Dogs,
+            ))
+            .unwrap();
+        service.publish_serialized(doggo.to_upsert_event()).unwrap();
+
+        block_on(async {
+            let [first, second] = &mut listeners;
+            assert_eq!(first.recv().await.unwrap().event().seq(), Some(0));
+            second.recv().await.unwrap();
+            let a = first.recv().await.unwrap().json().unwrap();
+            let b = second.recv().await.unwrap().json().unwrap();
+            assert!(std::ptr::eq(a.as_ptr(), b.as_ptr()));
+            insta::assert_snapshot!(std::str::from_utf8(&a).unwrap(), @r###"{"data":{"seq":1,"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}}}"###);
+        });
+    }
+}
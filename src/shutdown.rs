@@ -0,0 +1,168 @@
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::timer::start_timer;
+use crate::{Error, WsBody};
+
+// server -> client notice that the server is shutting down. it's sent once
+// the connection has been handed everything published before, and the server
+// closes the connection after it. the client reconnects, to another server,
+// after `reconnect_after` milliseconds and resubscribes where it left off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct GoingAway {
+    #[ts(type = "number")]
+    pub reconnect_after: u64,
+}
+
+impl GoingAway {
+    pub fn new(reconnect_after: Duration) -> Self {
+        Self {
+            reconnect_after: reconnect_after.as_millis() as u64,
+        }
+    }
+
+    pub fn reconnect_after(&self) -> Duration {
+        Duration::from_millis(self.reconnect_after)
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self> {
+        WsBody::new(self)
+    }
+}
+
+// how long a client told a service is going away waits before reconnecting,
+// unless the service is configured otherwise
+pub(crate) const DEFAULT_RECONNECT_AFTER: Duration = Duration::from_secs(1);
+
+type Drained = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+// resolves once every listener of a service that is shutting down has
+// drained, or fails with `Error::Undrained` when the deadline passes first
+pub struct Shutdown {
+    // `None` for services without listeners of their own
+    drain: Option<Drained>,
+}
+
+impl Shutdown {
+    pub fn drained() -> Self {
+        Self { drain: None }
+    }
+}
+
+impl Future for Shutdown {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.drain {
+            Some(drain) => drain.as_mut().poll(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+// the listeners of a service that haven't been told it's going away yet
+#[derive(Default)]
+pub(crate) struct Drain {
+    state: Mutex<DrainState>,
+}
+
+#[derive(Default)]
+struct DrainState {
+    undrained: usize,
+    wakers: Vec<Waker>,
+}
+
+impl Drain {
+    fn lock(&self) -> MutexGuard<'_, DrainState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn add(&self) {
+        self.lock().undrained += 1;
+    }
+
+    // a listener was told, or went away without being told
+    pub(crate) fn done(&self) {
+        let mut state = self.lock();
+        state.undrained -= 1;
+        state.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    pub(crate) fn wait(self: &Arc<Self>, deadline: Duration) -> Shutdown {
+        let drain = self.clone();
+        let expired = Arc::new(AtomicBool::new(false));
+        let mut started = false;
+        Shutdown {
+            drain: Some(Box::pin(poll_fn(move |cx| {
+                let mut state = drain.lock();
+                if state.undrained == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                if expired.load(Ordering::Acquire) {
+                    return Poll::Ready(Err(Error::Undrained(state.undrained)));
+                }
+                if !started {
+                    started = true;
+                    start_timer(deadline, &expired, cx);
+                }
+                state.wakers.push(cx.waker().clone());
+                Poll::Pending
+            }))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Error, Event, Listener, Service, Syncable};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn shutdowns_drain_listeners() {
+        let upsert = |id| doggo(id).to_upsert_event();
+        let service = BroadcastService::<DoggoEvent>::new()
+            .with_reconnect_after(Duration::from_millis(1_500));
+        let mut caught_up = service.listener();
+        let mut behind = service.listener();
+        service.publish(upsert(1)).unwrap();
+        service.publish(upsert(2)).unwrap();
+        assert_eq!(block_on(caught_up.recv()).unwrap().seq(), Some(0));
+        assert_eq!(block_on(caught_up.recv()).unwrap().seq(), Some(1));
+
+        let shutdown = service.shutdown(Duration::from_millis(20));
+        assert!(matches!(
+            service.publish(upsert(3)),
+            Err(Error::GoingAway(_))
+        ));
+        let Err(Error::GoingAway(going_away)) = block_on(caught_up.recv()) else {
+            panic!("expected the service to be going away");
+        };
+        insta::assert_snapshot!(going_away.into_ws_body().try_json().unwrap(), @r###"{"data":{"reconnect_after":1500}}"###);
+        // one listener still has two events to go
+        assert!(matches!(block_on(shutdown), Err(Error::Undrained(1))));
+
+        let shutdown = service.shutdown(Duration::from_secs(5));
+        assert_eq!(block_on(behind.recv()).unwrap().seq(), Some(0));
+        assert_eq!(block_on(behind.recv()).unwrap().seq(), Some(1));
+        assert!(matches!(
+            block_on(behind.recv()),
+            Err(Error::GoingAway(going_away)) if going_away.reconnect_after() == Duration::from_millis(1_500)
+        ));
+        block_on(shutdown).unwrap();
+    }
+}
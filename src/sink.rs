@@ -0,0 +1,171 @@
+// publishing as a `futures::Sink`, so a stream of events can be `forward`ed
+// into a service the way it would be into any other sink
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_sink::Sink;
+
+use crate::throttle::Bucket;
+use crate::timer::start_timer;
+use crate::{Clock, RateLimit, Service, SystemClock};
+
+const DEFAULT_CAPACITY: usize = 64;
+
+// publishes what's sent to it through `service`. up to `capacity` events are
+// held until a flush publishes them, and a sink over its capacity (or its rate
+// limit) stays pending, so a producer never runs further ahead of the service
+// than that. a failed publish fails the sink with the service's error, and
+// the events after it stay unpublished
+pub struct ServiceSink<'a, S, T> {
+    service: &'a S,
+    pending: VecDeque<T>,
+    capacity: usize,
+    limit: Option<(RateLimit, Bucket)>,
+    // set once the wait for the next token is over
+    waiting: Option<Arc<AtomicBool>>,
+}
+
+impl<'a, S, T> ServiceSink<'a, S, T> {
+    pub fn new(service: &'a S) -> Self {
+        Self {
+            service,
+            pending: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            limit: None,
+            waiting: None,
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "sink capacity must be greater than zero");
+        self.capacity = capacity;
+        self
+    }
+
+    // publishes no faster than `limit`, holding the producer back instead
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.limit = Some((limit, Bucket::full(&limit, SystemClock.now())));
+        self
+    }
+
+    // events sent but not yet published
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<S: Service<T>, T> Sink<T> for ServiceSink<'_, S, T> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        if self.pending.len() < self.capacity {
+            return Poll::Ready(Ok(()));
+        }
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, event: T) -> Result<(), S::Error> {
+        self.get_mut().pending.push_back(event);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        let this = self.get_mut();
+        while !this.pending.is_empty() {
+            if let Some((limit, bucket)) = &mut this.limit {
+                let now = SystemClock.now();
+                if !bucket.try_take(limit, now) {
+                    // one timer at a time, however often the sink is polled
+                    let timed = this
+                        .waiting
+                        .as_ref()
+                        .is_some_and(|waiting| !waiting.load(Ordering::Acquire));
+                    if !timed {
+                        let waiting = Arc::new(AtomicBool::new(false));
+                        start_timer(bucket.wait(limit, now), &waiting, cx);
+                        this.waiting = Some(waiting);
+                    }
+                    return Poll::Pending;
+                }
+            }
+            let event = this.pending.pop_front().expect("the sink isn't empty");
+            this.service.publish(event)?;
+        }
+        this.waiting = None;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+// the events are never pinned, only queued
+impl<S, T> Unpin for ServiceSink<'_, S, T> {}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use futures_util::{stream, StreamExt};
+
+    use crate::middleware::Outcome;
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Error, Event, Listener, RateLimit, Service, Syncable};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn streams_forward_into_services() {
+        let named = |id, name: &str| {
+            Ok(DoggoRecord {
+                name: name.to_owned(),
+                ..doggo(id)
+            }
+            .to_upsert_event())
+        };
+        // what a cdc reader would produce
+        let changes = || stream::iter([named(1, "Barky"), named(2, ""), named(3, "Woofy")]);
+
+        let service = BroadcastService::new();
+        let mut listener = service.listener();
+        let sink = service
+            .sink()
+            .with_capacity(1)
+            .with_rate_limit(RateLimit::per_second(50).with_burst(1));
+        let started = Instant::now();
+        block_on(changes().forward(sink)).unwrap();
+        // the second and third wait for a token
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        let names: Vec<_> = (0..3)
+            .map(|_| {
+                block_on(listener.recv())
+                    .unwrap()
+                    .data()
+                    .unwrap()
+                    .name
+                    .clone()
+            })
+            .collect();
+        assert_eq!(names, ["Barky", "", "Woofy"]);
+
+        // a rejected event stops the forward
+        let service =
+            BroadcastService::<DoggoEvent>::new().with_middleware(|event: DoggoEvent| match event
+                .data()
+            {
+                Some(doggo) if doggo.name.is_empty() => Outcome::Reject("unnamed".to_owned()),
+                _ => Outcome::Continue(event),
+            });
+        let mut listener = service.listener();
+        assert!(matches!(
+            block_on(changes().forward(service.sink())),
+            Err(Error::Rejected(reason)) if reason == "unnamed"
+        ));
+        assert_eq!(service.head_seq(), 1);
+        assert_eq!(block_on(listener.recv()).unwrap().seq(), Some(0));
+    }
+}
@@ -0,0 +1,424 @@
+use std::vec;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{flush, write_all, Error, WsBody};
+
+// implemented by whatever holds the current state of the synced collections
+pub trait Snapshottable {
+    type Id;
+    type Record: Serialize + TS;
+    type Collection;
+
+    fn records(&self, collection: &Self::Collection) -> Vec<SnapshotEntry<Self::Id, Self::Record>>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct SnapshotEntry<ID, T>
+where
+    T: TS,
+{
+    id: ID,
+    data: T,
+}
+
+impl<ID, T: TS> SnapshotEntry<ID, T> {
+    pub fn new(id: ID, data: T) -> Self {
+        Self { id, data }
+    }
+
+    pub fn into_parts(self) -> (ID, T) {
+        (self.id, self.data)
+    }
+}
+
+// every record of a collection as of `seq`. clients apply it, then the event
+// stream from `seq` onwards
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct Snapshot<ID, T, C>
+where
+    T: TS,
+{
+    collection: C,
+    #[ts(type = "number")]
+    seq: u64,
+    records: Vec<SnapshotEntry<ID, T>>,
+}
+
+impl<ID, T: Serialize + TS, C> Snapshot<ID, T, C> {
+    pub fn new(collection: C, seq: u64, records: Vec<SnapshotEntry<ID, T>>) -> Self {
+        Self {
+            collection,
+            seq,
+            records,
+        }
+    }
+
+    pub fn collection(&self) -> &C {
+        &self.collection
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn records(&self) -> &[SnapshotEntry<ID, T>] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<SnapshotEntry<ID, T>> {
+        self.records
+    }
+
+    // splits the snapshot into chunks of at most `records_per_chunk` records,
+    // so a large collection goes out a message at a time. an empty snapshot
+    // is a single empty chunk
+    pub fn into_chunks(self, records_per_chunk: usize) -> SnapshotChunks<ID, T, C> {
+        assert!(
+            records_per_chunk > 0,
+            "chunks must hold at least one record"
+        );
+        let total = self.records.len().div_ceil(records_per_chunk).max(1);
+        SnapshotChunks {
+            collection: self.collection,
+            seq: self.seq,
+            idx: 0,
+            total: total as u32,
+            records_per_chunk,
+            records: self.records.into_iter(),
+        }
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+impl<ID, T, C> WsBody<Snapshot<ID, T, C>>
+where
+    ID: Serialize,
+    T: Serialize + TS,
+    C: Serialize,
+{
+    // the json of `write_json_to_async`, but encoded and written a record at
+    // a time, so a large snapshot is never held encoded all at once
+    pub async fn stream_json_to<W>(&self, writer: &mut W) -> Result<(), Error>
+    where
+        W: futures_io::AsyncWrite + Unpin + ?Sized,
+    {
+        let snapshot = &self.data;
+        let mut head = String::from("{");
+        if let Some(version) = self.protocol_version {
+            head += &format!("\"protocol_version\":{version},");
+        }
+        if let Some(channel) = self.channel {
+            head += &format!("\"channel\":{channel},");
+        }
+        let collection = serde_json::to_string(&snapshot.collection).map_err(Error::Encode)?;
+        head += &format!(
+            "\"data\":{{\"collection\":{collection},\"seq\":{},\"records\":[",
+            snapshot.seq
+        );
+        write_all(writer, head.as_bytes()).await?;
+        for (index, record) in snapshot.records.iter().enumerate() {
+            let mut json = match index {
+                0 => Vec::new(),
+                _ => b",".to_vec(),
+            };
+            serde_json::to_writer(&mut json, record).map_err(Error::Encode)?;
+            write_all(writer, &json).await?;
+        }
+        write_all(writer, b"]}}").await?;
+        flush(writer).await
+    }
+}
+
+// chunk `idx` of the `total` a snapshot was split into. clients collect them
+// (e.g. with a `SnapshotAssembler`) and apply the snapshot once all arrived
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct SnapshotChunk<ID, T, C>
+where
+    T: TS,
+{
+    pub(crate) collection: C,
+    #[ts(type = "number")]
+    pub(crate) seq: u64,
+    pub(crate) idx: u32,
+    pub(crate) total: u32,
+    pub(crate) records: Vec<SnapshotEntry<ID, T>>,
+}
+
+impl<ID, T: Serialize + TS, C> SnapshotChunk<ID, T, C> {
+    pub fn collection(&self) -> &C {
+        &self.collection
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn idx(&self) -> u32 {
+        self.idx
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.idx + 1 == self.total
+    }
+
+    pub fn records(&self) -> &[SnapshotEntry<ID, T>] {
+        &self.records
+    }
+
+    pub fn into_ws_body(self) -> WsBody<Self>
+    where
+        Self: Serialize,
+    {
+        WsBody::new(self)
+    }
+}
+
+// yields the chunks of a snapshot, moving the records into one at a time
+pub struct SnapshotChunks<ID, T: TS, C> {
+    collection: C,
+    seq: u64,
+    idx: u32,
+    total: u32,
+    records_per_chunk: usize,
+    records: vec::IntoIter<SnapshotEntry<ID, T>>,
+}
+
+impl<ID, T: TS, C: Clone> Iterator for SnapshotChunks<ID, T, C> {
+    type Item = SnapshotChunk<ID, T, C>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx == self.total {
+            return None;
+        }
+        let chunk = SnapshotChunk {
+            collection: self.collection.clone(),
+            seq: self.seq,
+            idx: self.idx,
+            total: self.total,
+            records: self.records.by_ref().take(self.records_per_chunk).collect(),
+        };
+        self.idx += 1;
+        Some(chunk)
+    }
+}
+
+// puts a chunked snapshot back together, whatever order the chunks come in
+pub struct SnapshotAssembler<ID, T: TS, C> {
+    collection: Option<C>,
+    seq: u64,
+    chunks: Vec<Option<Vec<SnapshotEntry<ID, T>>>>,
+}
+
+impl<ID, T: Serialize + TS, C> SnapshotAssembler<ID, T, C> {
+    pub fn new() -> Self {
+        Self {
+            collection: None,
+            seq: 0,
+            chunks: Vec::new(),
+        }
+    }
+
+    // the snapshot once `chunk` completes it. chunks of another snapshot (a
+    // different seq or total) than the first one fail with
+    // `Error::InvalidFrame`; repeated chunks are ignored
+    pub fn push(
+        &mut self,
+        chunk: SnapshotChunk<ID, T, C>,
+    ) -> Result<Option<Snapshot<ID, T, C>>, Error> {
+        if chunk.idx >= chunk.total {
+            return Err(Error::InvalidFrame(format!(
+                "snapshot chunk {} of {}",
+                chunk.idx, chunk.total
+            )));
+        }
+        if self.collection.is_none() {
+            self.seq = chunk.seq;
+            self.chunks = (0..chunk.total).map(|_| None).collect();
+        } else if chunk.seq != self.seq || chunk.total as usize != self.chunks.len() {
+            return Err(Error::InvalidFrame(format!(
+                "snapshot chunk of seq {} while assembling seq {}",
+                chunk.seq, self.seq
+            )));
+        }
+        self.collection.get_or_insert(chunk.collection);
+        self.chunks[chunk.idx as usize].get_or_insert(chunk.records);
+
+        if self.chunks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+        let records = self.chunks.drain(..).flatten().flatten().collect();
+        Ok(self
+            .collection
+            .take()
+            .map(|collection| Snapshot::new(collection, self.seq, records)))
+    }
+}
+
+impl<ID, T: Serialize + TS, C> Default for SnapshotAssembler<ID, T, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, Event, Service, Snapshot, SnapshotAssembler, SnapshotChunk,
+        SnapshotEntry, Snapshottable, Syncable, WsBody,
+    };
+
+    type DoggoChunk = SnapshotChunk<u32, DoggoRecord, Collection>;
+
+    struct Kennel(Vec<DoggoRecord>);
+
+    impl Snapshottable for Kennel {
+        type Id = u32;
+        type Record = DoggoRecord;
+        type Collection = Collection;
+
+        fn records(&self, collection: &Collection) -> Vec<SnapshotEntry<u32, DoggoRecord>> {
+            match collection {
+                Collection::Dogs => self
+                    .0
+                    .iter()
+                    .map(|doggo| SnapshotEntry::new(doggo.id(), doggo.clone()))
+                    .collect(),
+                Collection::Cats => Vec::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_is_valid_at_head() {
+        let kennel = Kennel(vec![doggo(1)]);
+
+        let service = BroadcastService::new();
+        service
+            .publish(Event::<u32, DoggoRecord, Collection>::new_delete_event(
+                2,
+                Collection::Dogs,
+            ))
+            .unwrap();
+
+        let snapshot = service.snapshot(&kennel, Collection::Dogs);
+        assert_eq!(snapshot.seq(), 1);
+        let json = snapshot.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"collection":"Dogs","seq":1,"records":[{"id":1,"data":{"id":1,"name":"Barky","breed":"Poodle"}}]}}"###);
+    }
+
+    // every write it was given, as they came
+    struct Writes(Vec<String>);
+
+    impl futures_io::AsyncWrite for Writes {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            bytes: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.0.push(String::from_utf8(bytes.to_vec()).unwrap());
+            Poll::Ready(Ok(bytes.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn snapshots_stream_a_record_at_a_time() {
+        let records = (0..3).map(|id| SnapshotEntry::new(id, doggo(id))).collect();
+        let body = Snapshot::new(Collection::Dogs, 9, records)
+            .into_ws_body()
+            .with_protocol_version(1)
+            .with_channel(2);
+
+        let mut writes = Writes(Vec::new());
+        block_on(body.stream_json_to(&mut writes)).unwrap();
+        assert_eq!(writes.0.len(), 5);
+        insta::assert_snapshot!(writes.0[0], @r###"{"protocol_version":1,"channel":2,"data":{"collection":"Dogs","seq":9,"records":["###);
+        insta::assert_snapshot!(writes.0[2], @r###",{"id":1,"data":{"id":1,"name":"Barky","breed":"Poodle"}}"###);
+        assert_eq!(writes.0.concat(), body.try_json().unwrap());
+
+        let empty = Snapshot::<u32, DoggoRecord, _>::new(Collection::Cats, 0, Vec::new());
+        let empty = empty.into_ws_body();
+        let mut json = Vec::new();
+        block_on(empty.stream_json_to(&mut json)).unwrap();
+        assert_eq!(String::from_utf8(json).unwrap(), empty.try_json().unwrap());
+    }
+
+    #[test]
+    fn large_snapshots_go_out_in_chunks() {
+        let records = (0..5)
+            .map(|id| {
+                let doggo = doggo(id);
+                SnapshotEntry::new(id, doggo)
+            })
+            .collect();
+        let snapshot = Snapshot::new(Collection::Dogs, 9, records);
+
+        let mut frames = Vec::new();
+        for chunk in snapshot.into_chunks(2) {
+            let mut frame = Vec::new();
+            if chunk.is_last() {
+                block_on(chunk.into_ws_body().write_json_to_async(&mut frame)).unwrap();
+            } else {
+                chunk.into_ws_body().write_json_to(&mut frame).unwrap();
+            }
+            frames.push(String::from_utf8(frame).unwrap());
+        }
+        insta::assert_snapshot!(frames[2], @r###"{"data":{"collection":"Dogs","seq":9,"idx":2,"total":3,"records":[{"id":4,"data":{"id":4,"name":"Barky","breed":"Poodle"}}]}}"###);
+
+        let mut assembler = SnapshotAssembler::new();
+        let mut assembled = None;
+        for frame in frames.iter().rev() {
+            let chunk = WsBody::<DoggoChunk>::from_json(frame).unwrap().into_data();
+            assert!(assembled.is_none());
+            assembled = assembler.push(chunk).unwrap();
+        }
+        let assembled = assembled.unwrap();
+        assert_eq!(assembled.seq(), 9);
+        let ids: Vec<_> = assembled
+            .into_records()
+            .into_iter()
+            .map(|entry| entry.into_parts().0)
+            .collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+
+        let empty = Snapshot::<u32, DoggoRecord, _>::new(Collection::Cats, 3, Vec::new());
+        let chunks: Vec<_> = empty.into_chunks(2).collect();
+        assert_eq!(chunks.len(), 1);
+        let mut assembler = SnapshotAssembler::new();
+        let first = WsBody::<DoggoChunk>::from_json(&frames[0])
+            .unwrap()
+            .into_data();
+        assert!(assembler.push(first).unwrap().is_none());
+        assert!(assembler.push(chunks.into_iter().next().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use ts_rs::TS;
+
+use crate::{Error, Event, EventStore, OffsetStore, Replay};
+
+// persists every event as a JSON row keyed by seq, with its collection, id and
+// txn id in their own columns for replay and compaction
+pub struct SqliteEventStore {
+    db: Mutex<Connection>,
+}
+
+impl SqliteEventStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::with_connection(Connection::open(path).map_err(store_error)?)
+    }
+
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::with_connection(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    // creates the table in a connection opened by the caller
+    pub fn with_connection(db: Connection) -> Result<Self, Error> {
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS events (
+                seq INTEGER PRIMARY KEY,
+                collection TEXT,
+                record_id TEXT,
+                txn_id INTEGER,
+                body TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS events_by_record ON events (collection, record_id);",
+        )
+        .map_err(store_error)?;
+        Ok(Self { db: Mutex::new(db) })
+    }
+
+    // drops all but the newest event of every (collection, id), so replaying
+    // from the start yields each record's latest state (or its delete) once.
+    // unlike `EventStore::compact`, tombstones are kept however old they are
+    pub fn vacuum(&self) -> Result<usize, Error> {
+        self.lock()
+            .execute(
+                "DELETE FROM events WHERE record_id IS NOT NULL AND seq NOT IN (
+                    SELECT MAX(seq) FROM events WHERE record_id IS NOT NULL
+                    GROUP BY collection, record_id
+                )",
+                [],
+            )
+            .map_err(store_error)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.db
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn store_error(err: rusqlite::Error) -> Error {
+    Error::Store(err.to_string())
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, Error> {
+    serde_json::to_string(value).map_err(Error::Encode)
+}
+
+// sqlite integers are signed, so seqs past `i64::MAX` can't be stored
+fn to_int(seq: u64) -> Result<i64, Error> {
+    i64::try_from(seq).map_err(|_| Error::Store(format!("seq {seq} is too large to store")))
+}
+
+impl<ID, T, C, P> EventStore<Event<ID, T, C, P>> for SqliteEventStore
+where
+    ID: Serialize + DeserializeOwned + 'static,
+    T: Serialize + DeserializeOwned + TS + 'static,
+    C: Serialize + DeserializeOwned + 'static,
+    P: Serialize + DeserializeOwned + TS + 'static,
+{
+    fn append(&self, event: &Event<ID, T, C, P>) -> Result<(), Error> {
+        let seq = event
+            .seq()
+            .ok_or_else(|| Error::Store("cannot store an event without a seq".to_owned()))?;
+        let collection = event.collection().map(to_json).transpose()?;
+        let record_id = event.id().map(to_json).transpose()?;
+        let body = to_json(event)?;
+
+        let txn_id = event.txn_id().map(|txn_id| txn_id as i64);
+
+        self.lock()
+            .execute(
+                "INSERT INTO events (seq, collection, record_id, txn_id, body)
+                VALUES (?, ?, ?, ?, ?)",
+                params![to_int(seq)?, collection, record_id, txn_id, body],
+            )
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    fn replay(&self, from_seq: u64) -> Result<Replay<'_, Event<ID, T, C, P>>, Error> {
+        // nothing is stored that far on
+        let Ok(from_seq) = i64::try_from(from_seq) else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let db = self.lock();
+        let mut stmt = db
+            .prepare("SELECT body FROM events WHERE seq >= ? ORDER BY seq")
+            .map_err(store_error)?;
+        let mut rows = stmt.query([from_seq]).map_err(store_error)?;
+        let mut events = Vec::new();
+        while let Some(row) = rows.next().map_err(store_error)? {
+            let body: String = row.get(0).map_err(store_error)?;
+            events.push(serde_json::from_str(&body).map_err(Error::Decode)?);
+        }
+        Ok(Box::new(events.into_iter()))
+    }
+
+    fn next_seq(&self) -> Result<u64, Error> {
+        let next: i64 = self
+            .lock()
+            .query_row("SELECT COALESCE(MAX(seq) + 1, 0) FROM events", [], |row| {
+                row.get(0)
+            })
+            .map_err(store_error)?;
+        Ok(next as u64)
+    }
+
+    fn compact(&self, before: u64) -> Result<usize, Error> {
+        let db = self.lock();
+        // only deletes carry `deleted_at`, so this narrows the rows to decode
+        let mut stmt = db
+            .prepare(
+                "SELECT seq, collection, record_id, body FROM events
+                WHERE record_id IS NOT NULL AND body LIKE '%\"deleted_at\"%'
+                AND seq < (SELECT MAX(seq) FROM events)",
+            )
+            .map_err(store_error)?;
+        let mut rows = stmt.query([]).map_err(store_error)?;
+        let mut expired = Vec::new();
+        while let Some(row) = rows.next().map_err(store_error)? {
+            let body: String = row.get(3).map_err(store_error)?;
+            let event: Event<ID, T, C, P> = serde_json::from_str(&body).map_err(Error::Decode)?;
+            if event.deleted_at().is_some_and(|at| at < before) {
+                let seq: i64 = row.get(0).map_err(store_error)?;
+                let collection: Option<String> = row.get(1).map_err(store_error)?;
+                let record_id: String = row.get(2).map_err(store_error)?;
+                expired.push((seq, collection, record_id));
+            }
+        }
+        drop(rows);
+
+        let mut delete = db
+            .prepare("DELETE FROM events WHERE collection IS ? AND record_id = ? AND seq <= ?")
+            .map_err(store_error)?;
+        let mut purged = 0;
+        for (seq, collection, record_id) in expired {
+            purged += delete
+                .execute(params![collection, record_id, seq])
+                .map_err(store_error)?;
+        }
+        Ok(purged)
+    }
+}
+
+// consumer offsets in an `offsets` table, which can sit in the same database
+// as the events or the consumer's own tables
+pub struct SqliteOffsetStore {
+    db: Mutex<Connection>,
+}
+
+impl SqliteOffsetStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::with_connection(Connection::open(path).map_err(store_error)?)
+    }
+
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::with_connection(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    // creates the table in a connection opened by the caller
+    pub fn with_connection(db: Connection) -> Result<Self, Error> {
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS offsets (
+                consumer TEXT PRIMARY KEY,
+                seq INTEGER NOT NULL
+            );",
+        )
+        .map_err(store_error)?;
+        Ok(Self { db: Mutex::new(db) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.db
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl OffsetStore for SqliteOffsetStore {
+    fn load(&self, consumer: &str) -> Result<Option<u64>, Error> {
+        let seq: Option<i64> = self
+            .lock()
+            .query_row(
+                "SELECT seq FROM offsets WHERE consumer = ?",
+                [consumer],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(store_error)?;
+        Ok(seq.map(|seq| seq as u64))
+    }
+
+    fn save(&self, consumer: &str, seq: u64) -> Result<(), Error> {
+        self.lock()
+            .execute(
+                "INSERT INTO offsets (consumer, seq) VALUES (?, ?)
+                ON CONFLICT (consumer) DO UPDATE SET seq = excluded.seq",
+                params![consumer, to_int(seq)?],
+            )
+            .map_err(store_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, Error, Event, EventStore, OffsetStore, Sequenced, Service,
+        SqliteEventStore, SqliteOffsetStore, Syncable,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn events_survive_a_restart_and_compact() {
+        let dir = std::env::temp_dir().join(format!("rsp-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.db");
+        let _ = std::fs::remove_file(&path);
+
+        let named = |name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(1)
+        };
+
+        let service = BroadcastService::with_store(8, SqliteEventStore::open(&path).unwrap());
+        service.publish(named("Barky").to_upsert_event()).unwrap();
+        service.publish(named("Woofy").to_upsert_event()).unwrap();
+        drop(service);
+
+        let store = SqliteEventStore::open(&path).unwrap();
+        let replayed: Vec<DoggoEvent> = store.replay(0).unwrap().collect();
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(EventStore::<DoggoEvent>::next_seq(&store).unwrap(), 2);
+
+        let service = BroadcastService::with_store(8, store);
+        service
+            .publish(DoggoEvent::new_delete_event(2, Collection::Dogs))
+            .unwrap();
+        assert_eq!(service.head_seq(), 3);
+        drop(service);
+
+        let store = SqliteEventStore::open(&path).unwrap();
+        assert_eq!(store.vacuum().unwrap(), 1);
+        let seqs: Vec<_> = EventStore::<DoggoEvent>::replay(&store, 0)
+            .unwrap()
+            .map(|event| event.seq())
+            .collect();
+        assert_eq!(seqs, [Some(1), Some(2)]);
+        // seqs past what sqlite stores are past every event, not before them
+        assert_eq!(
+            EventStore::<DoggoEvent>::replay(&store, u64::MAX)
+                .unwrap()
+                .count(),
+            0
+        );
+        let mut huge = DoggoEvent::new_delete_event(3, Collection::Dogs);
+        huge.set_seq(u64::MAX);
+        assert!(matches!(store.append(&huge), Err(Error::Store(_))));
+
+        // the tombstone is the newest event, so it outlives its retention
+        assert_eq!(
+            EventStore::<DoggoEvent>::compact(&store, u64::MAX).unwrap(),
+            0
+        );
+        for (mut event, seq) in [
+            named("Woofy").to_delete_event_at(&|| 10),
+            named("Rex").to_upsert_event(),
+        ]
+        .into_iter()
+        .zip(3..)
+        {
+            event.set_seq(seq);
+            store.append(&event).unwrap();
+        }
+        assert_eq!(EventStore::<DoggoEvent>::compact(&store, 20).unwrap(), 2);
+
+        // offsets can live next to the events
+        let offsets = SqliteOffsetStore::open(&path).unwrap();
+        assert_eq!(offsets.load("search").unwrap(), None);
+        offsets.save("search", 2).unwrap();
+        offsets.save("search", 4).unwrap();
+        drop(offsets);
+        let offsets = SqliteOffsetStore::open(&path).unwrap();
+        assert_eq!(offsets.load("search").unwrap(), Some(4));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
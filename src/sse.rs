@@ -0,0 +1,182 @@
+// server-sent events, for clients that can't hold a websocket. each message
+// becomes one `text/event-stream` frame whose `data` is the same json body a
+// websocket client would get, named after the verb and identified by its seq.
+// `SseListener` yields ready-to-write frames from any listener, so a handler
+// only has to turn it into the response body of its http stack, e.g. with
+// `futures::stream::unfold` for axum's `Sse` or hyper's `Body::wrap_stream`
+use std::fmt::Write;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Error, Event, EventBatch, Listener, Ping, Service, Snapshot, WsBody};
+
+pub const CONTENT_TYPE: &str = "text/event-stream";
+
+// the header browsers send with the id of the last frame when they reconnect
+pub const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+// anything that can be sent as a frame
+pub trait SseEvent: Serialize {
+    fn event_name(&self) -> &str;
+
+    // frames without an id don't move the client's `Last-Event-ID`
+    fn event_id(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<ID: Serialize, T: Serialize + TS, C: Serialize, P: Serialize + TS> SseEvent
+    for Event<ID, T, C, P>
+{
+    fn event_name(&self) -> &str {
+        self.verb().name()
+    }
+
+    fn event_id(&self) -> Option<u64> {
+        self.seq()
+    }
+}
+
+impl<ID: Serialize, T: Serialize + TS, C: Serialize, P: Serialize + TS> SseEvent
+    for EventBatch<ID, T, C, P>
+{
+    fn event_name(&self) -> &str {
+        "batch"
+    }
+}
+
+impl<ID: Serialize, T: Serialize + TS, C: Serialize> SseEvent for Snapshot<ID, T, C> {
+    fn event_name(&self) -> &str {
+        "snapshot"
+    }
+}
+
+impl SseEvent for Ping {
+    fn event_name(&self) -> &str {
+        "ping"
+    }
+}
+
+pub fn frame(event: &impl SseEvent) -> Result<String, Error> {
+    let json = WsBody::new(event).try_json()?;
+    let mut frame = String::new();
+    if let Some(id) = event.event_id() {
+        let _ = writeln!(frame, "id: {id}");
+    }
+    let _ = writeln!(frame, "event: {}", event.event_name());
+    // compact json has no raw newlines, but a frame must never contain one
+    for line in json.lines() {
+        let _ = writeln!(frame, "data: {line}");
+    }
+    frame.push('\n');
+    Ok(frame)
+}
+
+// ignored by clients, but keeps proxies from closing an idle stream
+pub fn comment(text: &str) -> String {
+    text.lines()
+        .map(|line| format!(": {line}\n"))
+        .collect::<String>()
+        + "\n"
+}
+
+// how long a client waits before reconnecting
+pub fn retry(millis: u64) -> String {
+    format!("retry: {millis}\n\n")
+}
+
+// the seq to resume from given the `Last-Event-ID` header, if there was one.
+// an id with no seq after it can't be resumed from, so it's ignored too
+pub fn resume_from(last_event_id: Option<&str>) -> Option<u64> {
+    last_event_id?.trim().parse::<u64>().ok()?.checked_add(1)
+}
+
+// a listener picking up where the client's `Last-Event-ID` left off
+pub fn listener<T, S: Service<T>>(
+    service: &S,
+    last_event_id: Option<&str>,
+) -> SseListener<S::Listener> {
+    let inner = match resume_from(last_event_id) {
+        Some(seq) => service.listener_from(seq),
+        None => service.listener(),
+    };
+    SseListener::new(inner)
+}
+
+// yields the frames of the events of the inner listener
+pub struct SseListener<L> {
+    inner: L,
+}
+
+impl<L> SseListener<L> {
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for SseListener<L>
+where
+    L: Listener<Error = Error> + Send,
+    L::Item: SseEvent + Send,
+{
+    type Error = Error;
+    type Item = String;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        let event = self.inner.recv().await?;
+        frame(&event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{block_on, doggo};
+    use crate::{sse, BroadcastService, Listener, Ping, Service, Syncable};
+
+    #[test]
+    fn events_render_as_frames() {
+        let doggo = doggo(1);
+
+        let service = BroadcastService::new();
+        service.publish(doggo.to_delete_event()).unwrap();
+        service.publish(doggo.to_upsert_event()).unwrap();
+
+        let mut listener = sse::listener(&service, Some("0"));
+        let frame = block_on(listener.recv()).unwrap();
+        let mut lines = frame.lines();
+        assert_eq!(lines.next(), Some("id: 1"));
+        assert_eq!(lines.next(), Some("event: upsert"));
+        insta::assert_snapshot!(lines.next().unwrap(), @r###"data: {"data":{"seq":1,"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}}}"###);
+        assert!(frame.ends_with("}}\n\n"));
+
+        let frame = sse::frame(&Ping { sent_at: 7 }).unwrap();
+        assert_eq!(frame, "event: ping\ndata: {\"data\":{\"sent_at\":7}}\n\n");
+        assert_eq!(sse::comment("keepalive"), ": keepalive\n\n");
+    }
+
+    #[test]
+    fn last_event_ids_resume_after_themselves() {
+        assert_eq!(sse::resume_from(None), None);
+        assert_eq!(sse::resume_from(Some(" 41 ")), Some(42));
+        assert_eq!(sse::resume_from(Some("nope")), None);
+        assert_eq!(sse::resume_from(Some("-1")), None);
+        assert_eq!(
+            sse::resume_from(Some(&(u64::MAX - 1).to_string())),
+            Some(u64::MAX)
+        );
+        // no seq follows the last one, so the client starts over live
+        assert_eq!(sse::resume_from(Some(&u64::MAX.to_string())), None);
+
+        let service = BroadcastService::new();
+        service.publish(doggo(1).to_upsert_event()).unwrap();
+        let mut listener = sse::listener(&service, Some(&u64::MAX.to_string()));
+        service.publish(doggo(2).to_upsert_event()).unwrap();
+        assert!(block_on(listener.recv()).unwrap().starts_with("id: 1\n"));
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{ApplyPatch, Clock, Error, Event, Routable, Sequenced};
+
+pub type Replay<'a, T> = Box<dyn Iterator<Item = T> + 'a>;
+
+// where a service persists what it publishes, so events can be replayed to
+// subscribers that connect (or reconnect) after they were broadcast
+pub trait EventStore<T>: Send + Sync {
+    // `event` has already been stamped with its seq
+    fn append(&self, event: &T) -> Result<(), Error>;
+
+    // every stored event with a seq of at least `from_seq`, in order
+    fn replay(&self, from_seq: u64) -> Result<Replay<'_, T>, Error>;
+
+    // the seq after the newest stored event, so a service restarted on top of
+    // a durable store keeps numbering where it left off
+    fn next_seq(&self) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    // like `replay`, keeping events without a collection (e.g. transaction
+    // markers) the same way a filtered listener does
+    fn replay_collection<'a>(
+        &'a self,
+        collection: &'a T::Collection,
+        from_seq: u64,
+    ) -> Result<Replay<'a, T>, Error>
+    where
+        T: Routable + 'a,
+        T::Collection: PartialEq,
+    {
+        let events = self.replay(from_seq)?;
+        Ok(Box::new(events.filter(move |event| {
+            event.collection().is_none_or(|other| other == collection)
+        })))
+    }
+
+    // purges tombstones deleted before `before`, along with the events of the
+    // same record that came ahead of them, and returns how many events went.
+    // the newest event is always kept so `next_seq` doesn't move back. stores
+    // that can't purge keep everything
+    fn compact(&self, _before: u64) -> Result<usize, Error>
+    where
+        T: Tombstone,
+        T::Collection: PartialEq,
+        T::Id: PartialEq,
+    {
+        Ok(0)
+    }
+
+    // keeps tombstones for `retention`, so clients that sync within that
+    // window still learn about the deletes
+    fn compact_expired(&self, retention: Duration, clock: &impl Clock) -> Result<usize, Error>
+    where
+        Self: Sized,
+        T: Tombstone,
+        T::Collection: PartialEq,
+        T::Id: PartialEq,
+    {
+        let before = clock.now().saturating_sub(retention.as_millis() as u64);
+        self.compact(before)
+    }
+}
+
+// a stored item that may record the deletion of its record
+pub trait Tombstone: Routable {
+    fn deleted_at(&self) -> Option<u64>;
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Tombstone for Event<ID, T, C, P> {
+    fn deleted_at(&self) -> Option<u64> {
+        Event::deleted_at(self)
+    }
+}
+
+// whether `a` and `b` are about the same record
+fn same_record<T>(a: &T, b: &T) -> bool
+where
+    T: Routable,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    a.id().is_some() && a.id() == b.id() && a.collection() == b.collection()
+}
+
+// keeps the last `capacity` events in memory
+pub struct InMemoryEventStore<T> {
+    events: Mutex<VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T> InMemoryEventStore<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<T>> {
+        self.events
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: Clone + Send + Sequenced> EventStore<T> for InMemoryEventStore<T> {
+    fn append(&self, event: &T) -> Result<(), Error> {
+        let mut events = self.lock();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+        Ok(())
+    }
+
+    fn replay(&self, from_seq: u64) -> Result<Replay<'_, T>, Error> {
+        let events: Vec<_> = self
+            .lock()
+            .iter()
+            .filter(|event| event.seq().is_some_and(|seq| seq >= from_seq))
+            .cloned()
+            .collect();
+        Ok(Box::new(events.into_iter()))
+    }
+
+    fn next_seq(&self) -> Result<u64, Error> {
+        Ok(self
+            .lock()
+            .back()
+            .and_then(Sequenced::seq)
+            .map_or(0, |seq| seq + 1))
+    }
+
+    fn compact(&self, before: u64) -> Result<usize, Error>
+    where
+        T: Tombstone,
+        T::Collection: PartialEq,
+        T::Id: PartialEq,
+    {
+        let mut events = self.lock();
+        let newest = events.len().saturating_sub(1);
+        let expired: Vec<usize> = (0..newest)
+            .filter(|&index| events[index].deleted_at().is_some_and(|at| at < before))
+            .collect();
+        let doomed: Vec<bool> = (0..events.len())
+            .map(|index| {
+                expired.iter().any(|&tombstone| {
+                    tombstone >= index && same_record(&events[tombstone], &events[index])
+                })
+            })
+            .collect();
+        let mut doomed = doomed.into_iter();
+        let before_len = events.len();
+        events.retain(|_| !doomed.next().unwrap_or(false));
+        Ok(before_len - events.len())
+    }
+}
+
+impl<ID, T, C, P> InMemoryEventStore<Event<ID, T, C, P>>
+where
+    ID: Eq + Hash + Clone,
+    T: Serialize + TS + Clone,
+    C: Eq + Hash + Clone,
+    P: Serialize + TS + ApplyPatch<T>,
+{
+    // replaces the stored events with `compact_history` of them, returning
+    // how many went
+    pub fn compact_history(&self) -> usize {
+        let mut events = self.lock();
+        let before = events.len();
+        *events = crate::compact_history(events.drain(..)).into();
+        before - events.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::test::{block_on, doggo, Collection};
+    use crate::{
+        BroadcastService, Event, EventStore, InMemoryEventStore, Listener, Sequenced, Service,
+        Syncable,
+    };
+
+    #[test]
+    fn late_listeners_catch_up_from_the_store() {
+        let store = InMemoryEventStore::new(16);
+        let service = BroadcastService::with_store(1, store);
+        for (id, collection) in [
+            (1, Collection::Dogs),
+            (2, Collection::Cats),
+            (3, Collection::Dogs),
+        ] {
+            service
+                .publish(Event::<u32, (), Collection>::new_delete_event(
+                    id, collection,
+                ))
+                .unwrap();
+        }
+
+        // only seq 2 is still in the broadcast buffer
+        let mut listener = service.listener_from(0);
+        block_on(async {
+            for seq in 0..3 {
+                assert_eq!(listener.recv().await.unwrap().seq(), Some(seq));
+            }
+        });
+
+        let store = InMemoryEventStore::new(16);
+        for seq in 0..3 {
+            let mut event =
+                Event::<u32, (), Collection>::new_delete_event(seq as u32, Collection::Dogs);
+            event.set_seq(seq);
+            store.append(&event).unwrap();
+        }
+        assert_eq!(store.replay(1).unwrap().count(), 2);
+        assert_eq!(
+            store
+                .replay_collection(&Collection::Cats, 0)
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn expired_tombstones_are_compacted() {
+        let store = InMemoryEventStore::new(16);
+        let events = [
+            doggo(1).to_upsert_event(),
+            doggo(2).to_upsert_event(),
+            doggo(1).to_delete_event_at(&|| 100),
+            doggo(2).to_delete_event_at(&|| 500),
+            doggo(3).to_upsert_event(),
+            doggo(3).to_delete_event_at(&|| 100),
+        ];
+        for (seq, mut event) in events.into_iter().enumerate() {
+            event.set_seq(seq as u64);
+            store.append(&event).unwrap();
+        }
+        assert_eq!(
+            store.replay(0).unwrap().nth(2).unwrap().deleted_at(),
+            Some(100)
+        );
+
+        // dog 3's tombstone is the newest event, so it stays for `next_seq`
+        let now = || 1_000;
+        assert_eq!(
+            store
+                .compact_expired(Duration::from_millis(600), &now)
+                .unwrap(),
+            2
+        );
+        let seqs: Vec<_> = store.replay(0).unwrap().map(|event| event.seq()).collect();
+        assert_eq!(seqs, [Some(1), Some(3), Some(4), Some(5)]);
+        assert_eq!(store.next_seq().unwrap(), 6);
+        assert_eq!(store.compact(1_000).unwrap(), 2);
+    }
+}
@@ -0,0 +1,278 @@
+// listeners as `futures::Stream`s, so the combinators of the futures
+// ecosystem apply, and `EventStreamExt` with the ones specific to events
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::timer::start_timer;
+use crate::{Event, Listener, Routable};
+
+type Recv<L> = Pin<
+    Box<dyn Future<Output = (L, Result<<L as Listener>::Item, <L as Listener>::Error>)> + Send>,
+>;
+
+// the items of a listener. a listener that fails is done, so the stream ends
+// after yielding its error
+pub struct ListenerStream<L: Listener> {
+    listener: Option<L>,
+    // takes the listener while it waits for an item, and gives it back
+    recv: Option<Recv<L>>,
+}
+
+impl<L: Listener> ListenerStream<L> {
+    pub fn new(listener: L) -> Self {
+        Self {
+            listener: Some(listener),
+            recv: None,
+        }
+    }
+}
+
+// the listener is never pinned, only moved into and out of `recv`
+impl<L: Listener> Unpin for ListenerStream<L> {}
+
+impl<L> Stream for ListenerStream<L>
+where
+    L: Listener + Send + 'static,
+    L::Item: Send,
+    L::Error: Send,
+{
+    type Item = Result<L::Item, L::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.recv.is_none() {
+            let Some(mut listener) = this.listener.take() else {
+                return Poll::Ready(None);
+            };
+            this.recv = Some(Box::pin(async move {
+                let item = listener.recv().await;
+                (listener, item)
+            }));
+        }
+        let recv = this.recv.as_mut().expect("a recv was just started");
+        let (listener, item) = ready!(recv.as_mut().poll(cx));
+        this.recv = None;
+        if item.is_ok() {
+            this.listener = Some(listener);
+        }
+        Poll::Ready(Some(item))
+    }
+}
+
+pub trait EventStreamExt: Stream {
+    // only the events of `collection`. errors pass through
+    fn filter_collection<R, E>(
+        self,
+        collection: R::Collection,
+    ) -> FilterCollection<Self, R::Collection>
+    where
+        Self: Stream<Item = Result<R, E>> + Sized,
+        R: Routable,
+        R::Collection: PartialEq + Sized,
+    {
+        FilterCollection {
+            stream: self,
+            collection,
+        }
+    }
+
+    // the events with their records passed through `f`, see `Event::map_data`
+    fn map_data<ID, T, C, P, E, U, F>(self, f: F) -> MapData<Self, F>
+    where
+        Self: Stream<Item = Result<Event<ID, T, C, P>, E>> + Sized,
+        T: Serialize + TS,
+        P: Serialize + TS,
+        U: Serialize + TS,
+        F: FnMut(T) -> U,
+    {
+        MapData { stream: self, f }
+    }
+
+    // the items that arrive within `window` of the first one, together. a
+    // batch is never empty; the last one comes as soon as the stream ends
+    fn batched(self, window: Duration) -> Batched<Self>
+    where
+        Self: Sized,
+    {
+        Batched {
+            stream: self,
+            window,
+            batch: Vec::new(),
+            expired: None,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream + ?Sized> EventStreamExt for S {}
+
+pub struct FilterCollection<S, C> {
+    stream: S,
+    collection: C,
+}
+
+impl<S, C> Stream for FilterCollection<S, C>
+where
+    S: Stream + Unpin,
+    C: Unpin,
+    Self: FilterItem<S::Item>,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = self.get_mut();
+        loop {
+            match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(item) if !this.keeps(&item) => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+// whether a `FilterCollection` lets an item through
+pub trait FilterItem<I> {
+    fn keeps(&self, item: &I) -> bool;
+}
+
+impl<S, R, E> FilterItem<Result<R, E>> for FilterCollection<S, R::Collection>
+where
+    R: Routable,
+    R::Collection: PartialEq + Sized,
+{
+    fn keeps(&self, item: &Result<R, E>) -> bool {
+        match item {
+            Ok(event) => event.collection() == Some(&self.collection),
+            Err(_) => true,
+        }
+    }
+}
+
+pub struct MapData<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F, ID, T, C, P, E, U> Stream for MapData<S, F>
+where
+    S: Stream<Item = Result<Event<ID, T, C, P>, E>> + Unpin,
+    T: Serialize + TS,
+    P: Serialize + TS,
+    U: Serialize + TS,
+    F: FnMut(T) -> U + Unpin,
+{
+    type Item = Result<Event<ID, U, C, P>, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = ready!(Pin::new(&mut this.stream).poll_next(cx));
+        Poll::Ready(item.map(|item| item.map(|event| event.map_data(&mut this.f))))
+    }
+}
+
+pub struct Batched<S: Stream> {
+    stream: S,
+    window: Duration,
+    batch: Vec<S::Item>,
+    // set once the window of the batch being filled has passed
+    expired: Option<Arc<AtomicBool>>,
+    done: bool,
+}
+
+// the items are never pinned, only collected
+impl<S: Stream + Unpin> Unpin for Batched<S> {}
+
+impl<S: Stream + Unpin> Stream for Batched<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        // a stream that is never pending still ends its batches on time
+        let expired = |this: &Self| {
+            this.expired
+                .as_ref()
+                .is_some_and(|expired| expired.load(Ordering::Acquire))
+        };
+        while !this.done && !expired(this) {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if this.batch.is_empty() {
+                        let expired = Arc::new(AtomicBool::new(false));
+                        start_timer(this.window, &expired, cx);
+                        this.expired = Some(expired);
+                    }
+                    this.batch.push(item);
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        this.expired = None;
+        match this.batch.is_empty() {
+            true => Poll::Ready(None),
+            false => Poll::Ready(Some(mem::take(&mut this.batch))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Error, Event, EventStreamExt, Listener, Service, Syncable};
+
+    #[test]
+    fn listeners_compose_as_streams() {
+        let named = |id: u32, name: &str| DoggoRecord {
+            name: name.to_owned(),
+            ..doggo(id)
+        };
+        let service = BroadcastService::new();
+        let mut names = service
+            .listener()
+            .into_stream()
+            .filter_collection(Collection::Dogs)
+            .map_data(|doggo: DoggoRecord| doggo.name)
+            .batched(Duration::from_millis(20));
+
+        service
+            .publish(named(1, "Barky").to_upsert_event())
+            .unwrap();
+        service
+            .publish(Event::new_insert_event(named(2, "Tom"), Collection::Cats))
+            .unwrap();
+        service
+            .publish(named(3, "Woofy").to_upsert_event())
+            .unwrap();
+        // the window closes with both dogs in it
+        let batch = block_on(names.next()).unwrap();
+        let batch: Vec<_> = batch
+            .into_iter()
+            .map(|event| event.unwrap().data().cloned().unwrap())
+            .collect();
+        assert_eq!(batch, ["Barky", "Woofy"]);
+
+        service
+            .publish(named(4, "Fluffy").to_upsert_event())
+            .unwrap();
+        drop(service);
+        let batch = block_on(names.next()).unwrap();
+        assert!(matches!(
+            &batch[..],
+            [Ok(event), Err(Error::Closed)] if event.data().unwrap() == "Fluffy"
+        ));
+        assert!(block_on(names.next()).is_none());
+    }
+}
@@ -0,0 +1,1330 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+use crate::backpressure::Buffer;
+use crate::metrics::{BUFFER_DEPTH, EVENTS_DELIVERED, EVENTS_DROPPED, EVENTS_PUBLISHED, FAN_OUT};
+use crate::presence::{Join, Leave, PresenceDelta, PresenceEntry, PresenceHeartbeat, Rooms};
+use serde::Serialize;
+
+use crate::{
+    AuthResult, Authenticate, Authenticator, Claims, Clock, CollectionPattern, ConnectionEvent,
+    DeadLetter, DeadLetterSink, DeadLetterStage, DisconnectReason, Ephemeral, Error, Listener,
+    OverflowPolicy, Routable, Scope, Sequenced, Subscribe, SystemClock, Unsubscribe,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(u64);
+
+// how published events have been fanned out so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FanOutMetrics {
+    pub published: u64,
+    // one per connection an event was routed to
+    pub delivered: u64,
+    // published events that matched no connection
+    pub unrouted: u64,
+    // routed events a full connection buffer didn't take
+    pub dropped: u64,
+    pub connections: usize,
+    pub subscriptions: usize,
+}
+
+impl FanOutMetrics {
+    pub fn mean_fan_out(&self) -> f64 {
+        match self.published {
+            0 => 0.0,
+            published => self.delivered as f64 / published as f64,
+        }
+    }
+}
+
+// routes every published event only to the connections subscribed to its
+// collection, and within that to its id if the subscription names ids.
+// events without a collection (e.g. transaction markers) go to every
+// connection with at least one subscription. with an authenticator,
+// connections only receive events of the collections their claims allow, and
+// nothing until they have authenticated. it also tracks who is present in
+// which room, see `presence`
+pub struct SubscriptionManager<T: Routable> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct SubscriptionListener<T: Routable> {
+    shared: Arc<Shared<T>>,
+    connection: ConnectionId,
+}
+
+// the `PresenceDelta`s of the rooms a connection is in, next to its events
+pub struct PresenceListener<T: Routable> {
+    shared: Arc<Shared<T>>,
+    connection: ConnectionId,
+}
+
+// the `ConnectionEvent`s of every connection, from when it was made on
+pub struct ConnectionEvents<T: Routable> {
+    shared: Arc<Shared<T>>,
+    observer: u64,
+}
+
+// the `Ephemeral` messages routed to a connection, next to its events
+pub struct EphemeralListener<T: Routable> {
+    shared: Arc<Shared<T>>,
+    connection: ConnectionId,
+}
+
+struct Shared<T: Routable> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T: Routable> {
+    connections: HashMap<ConnectionId, Connection<T>>,
+    next_connection: u64,
+    next_seq: u64,
+    managers: usize,
+    metrics: FanOutMetrics,
+    authenticator: Option<Arc<dyn Authenticator<T::Collection>>>,
+    dead_letters: Option<Arc<dyn DeadLetterSink<T>>>,
+    rooms: Rooms,
+    clock: Box<dyn Clock + Send>,
+    // the `ConnectionEvent`s some `ConnectionEvents` listener has yet to
+    // receive, the first of them at `lifecycle_head`
+    lifecycle: VecDeque<ConnectionEvent<T::Collection>>,
+    lifecycle_head: u64,
+    observers: HashMap<u64, Observer>,
+    next_observer: u64,
+}
+
+// a `ConnectionEvents` listener's position in the lifecycle
+struct Observer {
+    next: u64,
+    waker: Option<Waker>,
+}
+
+struct Connection<T: Routable> {
+    subscriptions: Vec<Subscription<T::Collection, T::Id>>,
+    patterns: Vec<PatternSubscription<T::Collection>>,
+    // events from here are the client's own and not sent back
+    origin: Option<String>,
+    claims: Option<Claims<T::Collection>>,
+    // only events of this scope are routed here, if set
+    scope: Option<Scope>,
+    queue: Buffer<T>,
+    waker: Option<Waker>,
+    presence: VecDeque<PresenceDelta>,
+    presence_waker: Option<Waker>,
+    ephemeral: VecDeque<Ephemeral<T::Id, T::Collection>>,
+    ephemeral_waker: Option<Waker>,
+    // reported as `Lagging` since the queue last ran dry
+    lagging: bool,
+}
+
+struct Subscription<C, ID> {
+    collection: C,
+    // `None` for the whole collection
+    ids: Option<Vec<ID>>,
+}
+
+struct PatternSubscription<C> {
+    pattern: CollectionPattern,
+    // `CollectionPattern::matches_collection`, bound when the pattern was
+    // subscribed to so publishing doesn't need collections to serialize
+    matches: fn(&CollectionPattern, &C) -> bool,
+}
+
+impl<T: Routable> Connection<T> {
+    // of every listener waiting on the connection
+    fn take_wakers(&mut self) -> impl Iterator<Item = Waker> {
+        [
+            self.waker.take(),
+            self.presence_waker.take(),
+            self.ephemeral_waker.take(),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl<T: Routable> Connection<T>
+where
+    T::Collection: PartialEq,
+{
+    // replaces any earlier subscription to `collection`
+    fn subscribe(&mut self, collection: T::Collection, ids: Option<Vec<T::Id>>) {
+        self.subscriptions
+            .retain(|subscription| subscription.collection != collection);
+        self.subscriptions.push(Subscription { collection, ids });
+    }
+
+    // `guarded` if connections have to authenticate
+    fn can_read(&self, collection: &T::Collection, guarded: bool) -> bool {
+        match &self.claims {
+            Some(claims) => claims.can_read(collection),
+            None => !guarded,
+        }
+    }
+}
+
+impl<T: Routable> Connection<T> {
+    fn subscribe_pattern(&mut self, pattern: CollectionPattern)
+    where
+        T::Collection: Serialize,
+    {
+        self.patterns
+            .retain(|subscription| subscription.pattern != pattern);
+        self.patterns.push(PatternSubscription {
+            pattern,
+            matches: CollectionPattern::matches_collection,
+        });
+    }
+}
+
+impl<T> Connection<T>
+where
+    T: Routable,
+    T::Collection: PartialEq,
+    T::Id: PartialEq,
+{
+    fn wants<R>(&self, event: &R, guarded: bool) -> bool
+    where
+        R: Routable<Collection = T::Collection, Id = T::Id>,
+    {
+        if self.origin.is_some() && event.origin() == self.origin.as_deref() {
+            return false;
+        }
+        if self.scope.is_some() && event.scope() != self.scope.as_ref() {
+            return false;
+        }
+        let Some(collection) = event.collection() else {
+            let subscribed = !self.subscriptions.is_empty() || !self.patterns.is_empty();
+            return subscribed && (self.claims.is_some() || !guarded);
+        };
+        if !self.can_read(collection, guarded) {
+            return false;
+        }
+        self.subscriptions.iter().any(|subscription| {
+            subscription.collection == *collection
+                && subscription
+                    .ids
+                    .as_ref()
+                    .is_none_or(|ids| event.id().is_some_and(|id| ids.contains(id)))
+        }) || self
+            .patterns
+            .iter()
+            .any(|subscription| (subscription.matches)(&subscription.pattern, collection))
+    }
+}
+
+impl<T: Routable> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: Routable> State<T> {
+    fn deliver(&mut self, deltas: Vec<(ConnectionId, PresenceDelta)>) {
+        for (recipient, delta) in deltas {
+            if let Some(connection) = self.connections.get_mut(&recipient) {
+                connection.presence.push_back(delta);
+                if let Some(waker) = connection.presence_waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    // the connection, and its presence in every room
+    fn remove(
+        &mut self,
+        connection: ConnectionId,
+        mut reason: DisconnectReason,
+    ) -> Option<Connection<T>> {
+        let removed = self.connections.remove(&connection)?;
+        let deltas = self.rooms.disconnect(connection);
+        self.deliver(deltas);
+        if reason == DisconnectReason::Closed && removed.queue.is_closed() {
+            reason = DisconnectReason::Overflowed;
+        }
+        self.emit(ConnectionEvent::Disconnected { connection, reason });
+        Some(removed)
+    }
+
+    fn emit(&mut self, event: ConnectionEvent<T::Collection>) {
+        // once every manager is gone the observers are closing, and anything
+        // after their `Error::Closed` would arrive out of order
+        if self.observers.is_empty() || self.managers == 0 {
+            return;
+        }
+        self.lifecycle.push_back(event);
+        for observer in self.observers.values_mut() {
+            if let Some(waker) = observer.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    // drops the lifecycle events every observer has received
+    fn trim_lifecycle(&mut self) {
+        let received = self
+            .observers
+            .values()
+            .map(|observer| observer.next)
+            .min()
+            .unwrap_or(self.lifecycle_head + self.lifecycle.len() as u64);
+        while self.lifecycle_head < received {
+            self.lifecycle.pop_front();
+            self.lifecycle_head += 1;
+        }
+    }
+}
+
+impl<T: Routable> SubscriptionManager<T> {
+    pub fn new() -> Self {
+        let state = State {
+            connections: HashMap::new(),
+            next_connection: 0,
+            next_seq: 0,
+            managers: 1,
+            metrics: FanOutMetrics::default(),
+            authenticator: None,
+            dead_letters: None,
+            rooms: Rooms::default(),
+            clock: Box::new(SystemClock),
+            lifecycle: VecDeque::new(),
+            lifecycle_head: 0,
+            observers: HashMap::new(),
+            next_observer: 0,
+        };
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+            }),
+        }
+    }
+
+    pub fn with_authenticator(
+        self,
+        authenticator: impl Authenticator<T::Collection> + 'static,
+    ) -> Self {
+        self.shared.lock().authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    // for when presence was last seen
+    pub fn with_clock(self, clock: impl Clock + Send + 'static) -> Self {
+        self.shared.lock().clock = Box::new(clock);
+        self
+    }
+
+    // receives the events lost when a connection overflows with
+    // `OverflowPolicy::CloseConnection`
+    pub fn with_dead_letters(self, sink: impl DeadLetterSink<T> + 'static) -> Self {
+        self.shared.lock().dead_letters = Some(Arc::new(sink));
+        self
+    }
+
+    // a new connection without any subscriptions, and the listener it receives
+    // its events on. dropping the listener disconnects it
+    pub fn connect(&self) -> SubscriptionListener<T> {
+        self.connect_with(Buffer::unbounded())
+    }
+
+    // like `connect`, holding at most `capacity` undelivered events for the
+    // connection
+    pub fn connect_buffered(
+        &self,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> SubscriptionListener<T> {
+        self.connect_with(Buffer::bounded(capacity, policy))
+    }
+
+    fn connect_with(&self, queue: Buffer<T>) -> SubscriptionListener<T> {
+        let mut state = self.shared.lock();
+        let connection = ConnectionId(state.next_connection);
+        state.next_connection += 1;
+        state.connections.insert(
+            connection,
+            Connection {
+                subscriptions: Vec::new(),
+                patterns: Vec::new(),
+                origin: None,
+                claims: None,
+                scope: None,
+                queue,
+                waker: None,
+                presence: VecDeque::new(),
+                presence_waker: None,
+                ephemeral: VecDeque::new(),
+                ephemeral_waker: None,
+                lagging: false,
+            },
+        );
+        state.emit(ConnectionEvent::Connected { connection });
+        SubscriptionListener {
+            shared: self.shared.clone(),
+            connection,
+        }
+    }
+
+    // replaces any earlier subscription of `connection` to `collection`
+    pub fn subscribe(
+        &self,
+        connection: ConnectionId,
+        collection: T::Collection,
+        ids: Option<Vec<T::Id>>,
+    ) where
+        T::Collection: PartialEq + Clone,
+    {
+        let mut state = self.shared.lock();
+        if let Some(subscribed) = state.connections.get_mut(&connection) {
+            subscribed.subscribe(collection.clone(), ids);
+            state.emit(ConnectionEvent::Subscribed {
+                connection,
+                collections: vec![collection],
+                patterns: Vec::new(),
+            });
+        }
+    }
+
+    // subscribes `connection` to every collection whose name matches
+    // `pattern`, including ones first published to later
+    pub fn subscribe_pattern(&self, connection: ConnectionId, pattern: CollectionPattern)
+    where
+        T::Collection: Serialize,
+    {
+        let mut state = self.shared.lock();
+        if let Some(subscribed) = state.connections.get_mut(&connection) {
+            subscribed.subscribe_pattern(pattern.clone());
+            state.emit(ConnectionEvent::Subscribed {
+                connection,
+                collections: Vec::new(),
+                patterns: vec![pattern],
+            });
+        }
+    }
+
+    // only drops the pattern itself, not collections subscribed to by name
+    pub fn unsubscribe_pattern(&self, connection: ConnectionId, pattern: &CollectionPattern) {
+        let mut state = self.shared.lock();
+        if let Some(connection) = state.connections.get_mut(&connection) {
+            connection
+                .patterns
+                .retain(|subscription| subscription.pattern != *pattern);
+        }
+    }
+
+    // stops echoing events that came from `origin` back to `connection`, for
+    // clients that already applied their own mutations optimistically
+    pub fn suppress_origin(&self, connection: ConnectionId, origin: impl Into<String>) {
+        let mut state = self.shared.lock();
+        if let Some(connection) = state.connections.get_mut(&connection) {
+            connection.origin = Some(origin.into());
+        }
+    }
+
+    // keeps `connection` to the events and ephemeral messages of `scope`.
+    // connections that aren't bound get those of every scope
+    pub fn bind_scope(&self, connection: ConnectionId, scope: impl Into<Scope>) {
+        let mut state = self.shared.lock();
+        if let Some(connection) = state.connections.get_mut(&connection) {
+            connection.scope = Some(scope.into());
+        }
+    }
+
+    pub fn unsubscribe(&self, connection: ConnectionId, collection: &T::Collection)
+    where
+        T::Collection: PartialEq,
+    {
+        let mut state = self.shared.lock();
+        if let Some(connection) = state.connections.get_mut(&connection) {
+            connection
+                .subscriptions
+                .retain(|subscription| subscription.collection != *collection);
+        }
+    }
+
+    // resuming from `from_seq` is up to the service the events come from
+    pub fn handle_subscribe(&self, connection: ConnectionId, subscribe: &Subscribe<T::Collection>)
+    where
+        T::Collection: PartialEq + Clone + Serialize,
+    {
+        let mut state = self.shared.lock();
+        let Some(subscribed) = state.connections.get_mut(&connection) else {
+            return;
+        };
+        for collection in &subscribe.collections {
+            subscribed.subscribe(collection.clone(), None);
+        }
+        for pattern in &subscribe.patterns {
+            subscribed.subscribe_pattern(pattern.clone());
+        }
+        state.emit(ConnectionEvent::Subscribed {
+            connection,
+            collections: subscribe.collections.clone(),
+            patterns: subscribe.patterns.clone(),
+        });
+    }
+
+    pub fn handle_unsubscribe(
+        &self,
+        connection: ConnectionId,
+        unsubscribe: &Unsubscribe<T::Collection>,
+    ) where
+        T::Collection: PartialEq,
+    {
+        for collection in &unsubscribe.collections {
+            self.unsubscribe(connection, collection);
+        }
+        for pattern in &unsubscribe.patterns {
+            self.unsubscribe_pattern(connection, pattern);
+        }
+    }
+
+    // checks the token with the authenticator, and on success lets the
+    // connection read what the claims allow. a refused token drops any claims
+    // the connection had. without an authenticator every token is refused
+    pub fn handle_authenticate(
+        &self,
+        connection: ConnectionId,
+        authenticate: &Authenticate,
+    ) -> AuthResult<T::Collection>
+    where
+        T::Collection: Clone,
+    {
+        let authenticator = self.shared.lock().authenticator.clone();
+        // tokens are checked without the lock, it may take a while
+        let result = match authenticator {
+            Some(authenticator) => authenticator.authenticate(&authenticate.token),
+            None => Err(Error::Unauthorized("no authenticator".to_owned())),
+        };
+        let mut state = self.shared.lock();
+        if let Some(authenticated) = state.connections.get_mut(&connection) {
+            authenticated.claims = result.as_ref().ok().cloned();
+            if let Ok(claims) = &result {
+                let subject = claims.subject().to_owned();
+                state.emit(ConnectionEvent::Authenticated {
+                    connection,
+                    subject,
+                });
+            }
+        }
+        result.into()
+    }
+
+    // the claims of the connection's token, e.g. to check its mutations
+    pub fn claims(&self, connection: ConnectionId) -> Option<Claims<T::Collection>>
+    where
+        T::Collection: Clone,
+    {
+        let state = self.shared.lock();
+        state
+            .connections
+            .get(&connection)
+            .and_then(|connection| connection.claims.clone())
+    }
+
+    // whether events of `collection` may be routed to the connection, e.g. to
+    // refuse a subscription up front
+    pub fn can_read(&self, connection: ConnectionId, collection: &T::Collection) -> bool
+    where
+        T::Collection: PartialEq,
+    {
+        let state = self.shared.lock();
+        let guarded = state.authenticator.is_some();
+        state
+            .connections
+            .get(&connection)
+            .is_some_and(|connection| connection.can_read(collection, guarded))
+    }
+
+    pub fn disconnect(&self, connection: ConnectionId) {
+        self.disconnect_with(connection, DisconnectReason::Closed);
+    }
+
+    // like `disconnect`, reporting why, e.g. the error the transport failed
+    // with
+    pub fn disconnect_with(&self, connection: ConnectionId, reason: impl Into<DisconnectReason>) {
+        let mut state = self.shared.lock();
+        if let Some(mut removed) = state.remove(connection, reason.into()) {
+            removed.take_wakers().for_each(Waker::wake);
+        }
+    }
+
+    // every connection's `ConnectionEvent`s from now on
+    pub fn connection_events(&self) -> ConnectionEvents<T> {
+        let mut state = self.shared.lock();
+        let observer = state.next_observer;
+        state.next_observer += 1;
+        let next = state.lifecycle_head + state.lifecycle.len() as u64;
+        state
+            .observers
+            .insert(observer, Observer { next, waker: None });
+        ConnectionEvents {
+            shared: self.shared.clone(),
+            observer,
+        }
+    }
+
+    // where the connection's `PresenceDelta`s arrive
+    pub fn presence_listener(&self, connection: ConnectionId) -> PresenceListener<T> {
+        PresenceListener {
+            shared: self.shared.clone(),
+            connection,
+        }
+    }
+
+    // members are the subject of the connection's claims, or the connection
+    // itself if it hasn't authenticated
+    pub fn handle_join(&self, connection: ConnectionId, join: &Join) {
+        let mut state = self.shared.lock();
+        let Some(joining) = state.connections.get(&connection) else {
+            return;
+        };
+        let member = match &joining.claims {
+            Some(claims) => claims.subject().to_owned(),
+            None => format!("connection-{}", connection.0),
+        };
+        let now = state.clock.now();
+        let deltas = state.rooms.join(connection, member, join, now);
+        state.deliver(deltas);
+    }
+
+    pub fn handle_leave(&self, connection: ConnectionId, leave: &Leave) {
+        let mut state = self.shared.lock();
+        let deltas = state.rooms.leave(connection, &leave.room);
+        state.deliver(deltas);
+    }
+
+    // false if the connection isn't in the room, e.g. because it expired
+    // and has to join again
+    pub fn handle_presence_heartbeat(
+        &self,
+        connection: ConnectionId,
+        heartbeat: &PresenceHeartbeat,
+    ) -> bool {
+        let mut state = self.shared.lock();
+        let now = state.clock.now();
+        state.rooms.heartbeat(connection, &heartbeat.room, now)
+    }
+
+    // removes the members that haven't joined or sent a heartbeat within
+    // `timeout`, as if they left. meant to be called on a timer
+    pub fn expire_presence(&self, timeout: Duration) {
+        let mut state = self.shared.lock();
+        let deadline = state.clock.now().saturating_sub(timeout.as_millis() as u64);
+        let deltas = state.rooms.expire(deadline);
+        state.deliver(deltas);
+    }
+
+    pub fn presence(&self, room: &str) -> Vec<PresenceEntry> {
+        self.shared.lock().rooms.members(room)
+    }
+
+    // stamps `event` with its seq and queues it for every matching
+    // connection, returning how many took it
+    pub fn publish(&self, mut event: T) -> usize
+    where
+        T: Clone + Sequenced,
+        T::Collection: PartialEq,
+        T::Id: PartialEq,
+    {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "route",
+            seq = tracing::field::Empty,
+            delivered = tracing::field::Empty,
+            dropped = tracing::field::Empty
+        );
+        let mut state = self.shared.lock();
+        event.set_seq(state.next_seq);
+        state.next_seq += 1;
+
+        let (mut fan_out, mut dropped, mut depth) = (0, 0u64, 0);
+        let guarded = state.authenticator.is_some();
+        let dead_letters = state.dead_letters.clone();
+        let mut letters = Vec::new();
+        let mut lagging = Vec::new();
+        for (&id, connection) in state.connections.iter_mut() {
+            if !connection.wants(&event, guarded) {
+                continue;
+            }
+            let before = connection.queue.dropped();
+            match connection.queue.push(event.clone()) {
+                true => fan_out += 1,
+                false => dropped += 1,
+            }
+            if connection.queue.dropped() > before && !connection.lagging {
+                connection.lagging = true;
+                lagging.push((id, connection.queue.dropped()));
+            }
+            if dead_letters.is_some() {
+                let err = connection.queue.overflow_error();
+                letters.extend(
+                    connection
+                        .queue
+                        .take_discarded()
+                        .into_iter()
+                        .map(|event| DeadLetter::new(event, DeadLetterStage::Overflow, &err)),
+                );
+            }
+            depth = depth.max(connection.queue.len());
+            if let Some(waker) = connection.waker.take() {
+                waker.wake();
+            }
+        }
+
+        for (connection, dropped) in lagging {
+            state.emit(ConnectionEvent::Lagging {
+                connection,
+                dropped,
+            });
+        }
+        let metrics = &mut state.metrics;
+        metrics.published += 1;
+        metrics.delivered += fan_out as u64;
+        metrics.dropped += dropped;
+        if fan_out == 0 && dropped == 0 {
+            metrics.unrouted += 1;
+        }
+        crate::metrics::counter(EVENTS_PUBLISHED, 1);
+        crate::metrics::counter(EVENTS_DELIVERED, fan_out as u64);
+        crate::metrics::counter(EVENTS_DROPPED, dropped);
+        crate::metrics::histogram(FAN_OUT, fan_out as f64);
+        crate::metrics::gauge(BUFFER_DEPTH, depth as f64);
+        #[cfg(feature = "tracing")]
+        {
+            span.record("seq", state.next_seq - 1);
+            span.record("delivered", fan_out);
+            span.record("dropped", dropped);
+            if dropped > 0 {
+                tracing::event!(
+                    parent: &span,
+                    tracing::Level::WARN,
+                    dropped,
+                    "dropped the event for lagging connections"
+                );
+            }
+        }
+        drop(state);
+        if let Some(sink) = dead_letters {
+            letters
+                .into_iter()
+                .for_each(|letter| sink.dead_letter(letter));
+        }
+        fan_out
+    }
+
+    // routes `message` like an event of its collection, without a seq and
+    // without it counting towards the metrics, returning how many
+    // connections took it
+    pub fn publish_ephemeral(&self, message: Ephemeral<T::Id, T::Collection>) -> usize
+    where
+        T::Collection: PartialEq + Clone,
+        T::Id: PartialEq + Clone,
+    {
+        let mut state = self.shared.lock();
+        let guarded = state.authenticator.is_some();
+        let mut fan_out = 0;
+        for connection in state.connections.values_mut() {
+            if !connection.wants(&message, guarded) {
+                continue;
+            }
+            connection.ephemeral.push_back(message.clone());
+            fan_out += 1;
+            if let Some(waker) = connection.ephemeral_waker.take() {
+                waker.wake();
+            }
+        }
+        fan_out
+    }
+
+    pub fn ephemeral_listener(&self, connection: ConnectionId) -> EphemeralListener<T> {
+        EphemeralListener {
+            shared: self.shared.clone(),
+            connection,
+        }
+    }
+
+    pub fn metrics(&self) -> FanOutMetrics {
+        let state = self.shared.lock();
+        FanOutMetrics {
+            connections: state.connections.len(),
+            subscriptions: state
+                .connections
+                .values()
+                .map(|connection| connection.subscriptions.len() + connection.patterns.len())
+                .sum(),
+            ..state.metrics
+        }
+    }
+}
+
+impl<T: Routable> Default for SubscriptionManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Routable> Clone for SubscriptionManager<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().managers += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: Routable> Drop for SubscriptionManager<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.managers -= 1;
+        if state.managers == 0 {
+            state
+                .connections
+                .values_mut()
+                .flat_map(Connection::take_wakers)
+                .for_each(Waker::wake);
+            state
+                .observers
+                .values_mut()
+                .filter_map(|observer| observer.waker.take())
+                .for_each(Waker::wake);
+        }
+    }
+}
+
+impl<T: Routable> SubscriptionListener<T> {
+    pub fn connection(&self) -> ConnectionId {
+        self.connection
+    }
+}
+
+impl<T: Routable> Drop for SubscriptionListener<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(mut removed) = state.remove(self.connection, DisconnectReason::Closed) {
+            removed.take_wakers().for_each(Waker::wake);
+        }
+    }
+}
+
+impl<T: Routable> Drop for ConnectionEvents<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.observers.remove(&self.observer);
+        state.trim_lifecycle();
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Listener for SubscriptionListener<T>
+where
+    T: Routable + Send,
+    T::Collection: Send,
+    T::Id: Send,
+{
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            let mut state = self.shared.lock();
+            let managers = state.managers;
+            let Some(connection) = state.connections.get_mut(&self.connection) else {
+                return Poll::Ready(Err(Error::Closed));
+            };
+            if let Some(event) = connection.queue.pop()? {
+                return Poll::Ready(Ok(event));
+            }
+            // caught up, so dropping events again is lagging anew
+            connection.lagging = false;
+            if managers == 0 {
+                return Poll::Ready(Err(Error::Closed));
+            }
+            connection.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Listener for PresenceListener<T>
+where
+    T: Routable + Send,
+    T::Collection: Send,
+    T::Id: Send,
+{
+    type Error = Error;
+    type Item = PresenceDelta;
+
+    // fails with `Error::Closed` once the connection is gone
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            let mut state = self.shared.lock();
+            let managers = state.managers;
+            let Some(connection) = state.connections.get_mut(&self.connection) else {
+                return Poll::Ready(Err(Error::Closed));
+            };
+            if let Some(delta) = connection.presence.pop_front() {
+                return Poll::Ready(Ok(delta));
+            }
+            if managers == 0 {
+                return Poll::Ready(Err(Error::Closed));
+            }
+            connection.presence_waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Listener for EphemeralListener<T>
+where
+    T: Routable + Send,
+    T::Collection: Send,
+    T::Id: Send,
+{
+    type Error = Error;
+    type Item = Ephemeral<T::Id, T::Collection>;
+
+    // fails with `Error::Closed` once the connection is gone
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            let mut state = self.shared.lock();
+            let managers = state.managers;
+            let Some(connection) = state.connections.get_mut(&self.connection) else {
+                return Poll::Ready(Err(Error::Closed));
+            };
+            if let Some(message) = connection.ephemeral.pop_front() {
+                return Poll::Ready(Ok(message));
+            }
+            if managers == 0 {
+                return Poll::Ready(Err(Error::Closed));
+            }
+            connection.ephemeral_waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Listener for ConnectionEvents<T>
+where
+    T: Routable + Send,
+    T::Collection: Clone + Send,
+    T::Id: Send,
+{
+    type Error = Error;
+    type Item = ConnectionEvent<T::Collection>;
+
+    // fails with `Error::Closed` once every manager is gone
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        poll_fn(|cx| {
+            let mut guard = self.shared.lock();
+            let state = &mut *guard;
+            let Some(observer) = state.observers.get_mut(&self.observer) else {
+                return Poll::Ready(Err(Error::Closed));
+            };
+            let index = (observer.next - state.lifecycle_head) as usize;
+            if let Some(event) = state.lifecycle.get(index).cloned() {
+                observer.next += 1;
+                state.trim_lifecycle();
+                return Poll::Ready(Ok(event));
+            }
+            if state.managers == 0 {
+                return Poll::Ready(Err(Error::Closed));
+            }
+            observer.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{
+        Authenticate, Claims, CollectionPattern, ConnectionEvent, ConnectionEvents,
+        DisconnectReason, Error, Event, EventMeta, Listener, OverflowPolicy, Subscribe,
+        SubscriptionManager, Syncable, Txn,
+    };
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    // the lifecycle events reported so far, without waiting for more
+    fn reported(events: &mut ConnectionEvents<DoggoEvent>) -> Vec<ConnectionEvent<Collection>> {
+        std::iter::from_fn(|| match poll_once(events.recv()) {
+            Poll::Ready(Ok(event)) => Some(event),
+            _ => None,
+        })
+        .collect()
+    }
+
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        std::pin::pin!(future)
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn events_only_reach_subscribers() {
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let mut dogs = manager.connect();
+        let mut one_dog = manager.connect();
+        let mut cats = manager.connect();
+        let idle = manager.connect();
+        manager.handle_subscribe(
+            dogs.connection(),
+            &Subscribe {
+                collections: vec![Collection::Dogs],
+                patterns: Vec::new(),
+                from_seq: None,
+            },
+        );
+        manager.subscribe(one_dog.connection(), Collection::Dogs, Some(vec![2]));
+        manager.subscribe(cats.connection(), Collection::Cats, None);
+
+        assert_eq!(manager.publish(doggo(1).to_upsert_event()), 1);
+        assert_eq!(manager.publish(doggo(2).to_upsert_event()), 2);
+        assert_eq!(manager.publish(Txn::begin().begin_event()), 3);
+        assert_eq!(
+            manager.publish(DoggoEvent::new_delete_event(3, Collection::Cats)),
+            1
+        );
+        drop(idle);
+
+        let metrics = manager.metrics();
+        assert_eq!((metrics.published, metrics.delivered), (4, 7));
+        assert_eq!((metrics.unrouted, metrics.dropped), (0, 0));
+        assert_eq!((metrics.connections, metrics.subscriptions), (3, 3));
+        assert_eq!(metrics.mean_fan_out(), 1.75);
+
+        block_on(async {
+            assert_eq!(dogs.recv().await.unwrap().seq(), Some(0));
+            assert_eq!(dogs.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(one_dog.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(one_dog.recv().await.unwrap().seq(), Some(2));
+            assert_eq!(cats.recv().await.unwrap().seq(), Some(2));
+            assert_eq!(cats.recv().await.unwrap().seq(), Some(3));
+
+            manager.disconnect(cats.connection());
+            assert!(matches!(cats.recv().await, Err(Error::Closed)));
+            drop(manager);
+            assert_eq!(dogs.recv().await.unwrap().seq(), Some(2));
+            assert!(matches!(dogs.recv().await, Err(Error::Closed)));
+        });
+    }
+
+    #[test]
+    fn full_buffers_drop_or_close_slow_connections() {
+        let cat = |id| DoggoEvent::new_delete_event(id, Collection::Cats);
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let mut newest = manager.connect_buffered(2, OverflowPolicy::DropNewest);
+        let mut oldest = manager.connect_buffered(2, OverflowPolicy::DropOldest);
+        let mut slow = manager.connect_buffered(2, OverflowPolicy::CloseConnection);
+        let mut fast = manager.connect_buffered(2, OverflowPolicy::CloseConnection);
+        for listener in [&newest, &oldest, &slow, &fast] {
+            manager.subscribe(listener.connection(), Collection::Cats, None);
+        }
+
+        assert_eq!(manager.publish(cat(1)), 4);
+        assert_eq!(manager.publish(cat(2)), 4);
+        // every buffer is full, but one connection keeps up
+        assert_eq!(block_on(fast.recv()).unwrap().seq(), Some(0));
+        assert_eq!(manager.publish(cat(3)), 2);
+        assert_eq!(block_on(fast.recv()).unwrap().seq(), Some(1));
+        assert_eq!(manager.publish(cat(4)), 2);
+
+        let metrics = manager.metrics();
+        assert_eq!((metrics.delivered, metrics.dropped), (12, 4));
+        assert_eq!(metrics.unrouted, 0);
+        block_on(async {
+            for seq in [0, 1] {
+                assert_eq!(newest.recv().await.unwrap().seq(), Some(seq));
+            }
+            for seq in [2, 3] {
+                assert_eq!(oldest.recv().await.unwrap().seq(), Some(seq));
+                assert_eq!(fast.recv().await.unwrap().seq(), Some(seq));
+            }
+            // the slow connection loses what it had queued too
+            assert!(matches!(slow.recv().await, Err(Error::Overflowed(2))));
+            assert!(matches!(slow.recv().await, Err(Error::Closed)));
+        });
+    }
+
+    #[test]
+    fn own_events_are_not_echoed() {
+        let from = |origin: &str| {
+            DoggoEvent::new_delete_event(1, Collection::Dogs)
+                .with_meta(EventMeta::new().with_origin(origin))
+        };
+
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let mut phone = manager.connect();
+        let mut laptop = manager.connect();
+        for connection in [phone.connection(), laptop.connection()] {
+            manager.subscribe(connection, Collection::Dogs, None);
+        }
+        manager.suppress_origin(phone.connection(), "phone");
+
+        assert_eq!(manager.publish(from("phone")), 1);
+        assert_eq!(manager.publish(from("laptop")), 2);
+        block_on(async {
+            assert_eq!(phone.recv().await.unwrap().seq(), Some(1));
+            assert_eq!(laptop.recv().await.unwrap().seq(), Some(0));
+        });
+    }
+
+    #[test]
+    fn patterns_match_collections_published_later() {
+        let order =
+            |collection: &str| Event::<u32, (), String>::new_delete_event(1, collection.to_owned());
+        let manager = SubscriptionManager::new();
+        let mut eu = manager.connect();
+        let mut orders = manager.connect();
+        let pattern = |pattern| CollectionPattern::new(pattern).unwrap();
+        manager.subscribe_pattern(eu.connection(), pattern("orders/eu/*"));
+        manager.handle_subscribe(
+            orders.connection(),
+            &Subscribe {
+                collections: Vec::new(),
+                patterns: vec![pattern("orders/**")],
+                from_seq: None,
+            },
+        );
+
+        assert_eq!(manager.publish(order("orders/eu/paris")), 2);
+        assert_eq!(manager.publish(order("orders/us/austin")), 1);
+        assert_eq!(manager.publish(order("invoices/eu/paris")), 0);
+        manager.unsubscribe_pattern(eu.connection(), &pattern("orders/eu/*"));
+        assert_eq!(manager.publish(order("orders/eu/rome")), 1);
+        block_on(async {
+            assert_eq!(eu.recv().await.unwrap().seq(), Some(0));
+            for seq in [0, 1, 3] {
+                assert_eq!(orders.recv().await.unwrap().seq(), Some(seq));
+            }
+        });
+    }
+
+    #[test]
+    fn connects_are_reported_from_when_observed() {
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let _unobserved = manager.connect();
+        let mut early = manager.connection_events();
+        let first = manager.connect().connection();
+        let mut late = manager.connection_events();
+        let second = manager.connect().connection();
+
+        assert!(
+            reported(&mut early)
+                == [
+                    ConnectionEvent::Connected { connection: first },
+                    ConnectionEvent::Disconnected {
+                        connection: first,
+                        reason: DisconnectReason::Closed
+                    },
+                    ConnectionEvent::Connected { connection: second },
+                    ConnectionEvent::Disconnected {
+                        connection: second,
+                        reason: DisconnectReason::Closed
+                    },
+                ]
+        );
+        assert!(reported(&mut late)[0] == ConnectionEvent::Connected { connection: second });
+    }
+
+    #[test]
+    fn only_accepted_tokens_are_reported() {
+        let manager =
+            SubscriptionManager::<DoggoEvent>::new().with_authenticator(
+                |token: &str| match token {
+                    "secret" => Ok(Claims::new("walker")),
+                    _ => Err(Error::Unauthorized("unknown token".to_owned())),
+                },
+            );
+        let client = manager.connect();
+        let mut events = manager.connection_events();
+        let authenticate = |token: &str| Authenticate {
+            token: token.to_owned(),
+        };
+        manager.handle_authenticate(client.connection(), &authenticate("guess"));
+        manager.handle_authenticate(client.connection(), &authenticate("secret"));
+        let gone = manager.connect().connection();
+        manager.disconnect(gone);
+        manager.handle_authenticate(gone, &authenticate("secret"));
+
+        let lifecycle = reported(&mut events);
+        assert_eq!(lifecycle.len(), 3);
+        assert!(
+            lifecycle[0]
+                == ConnectionEvent::Authenticated {
+                    connection: client.connection(),
+                    subject: "walker".to_owned()
+                }
+        );
+        assert!(lifecycle[1..]
+            .iter()
+            .all(|event| event.connection() == gone));
+    }
+
+    #[test]
+    fn subscribes_report_what_they_added() {
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let client = manager.connect();
+        let connection = client.connection();
+        let mut events = manager.connection_events();
+        let pattern = CollectionPattern::new("pets/*").unwrap();
+        manager.subscribe(connection, Collection::Dogs, Some(vec![1]));
+        manager.subscribe_pattern(connection, pattern.clone());
+        manager.handle_subscribe(
+            connection,
+            &Subscribe {
+                collections: vec![Collection::Cats],
+                patterns: vec![pattern.clone()],
+                from_seq: Some(3),
+            },
+        );
+        // unsubscribing isn't reported
+        manager.unsubscribe(connection, &Collection::Dogs);
+        manager.unsubscribe_pattern(connection, &pattern);
+
+        let subscribed = |collections, patterns| ConnectionEvent::Subscribed {
+            connection,
+            collections,
+            patterns,
+        };
+        assert!(
+            reported(&mut events)
+                == [
+                    subscribed(vec![Collection::Dogs], Vec::new()),
+                    subscribed(Vec::new(), vec![pattern.clone()]),
+                    subscribed(vec![Collection::Cats], vec![pattern]),
+                ]
+        );
+    }
+
+    #[test]
+    fn lagging_is_reported_again_only_after_catching_up() {
+        let upsert = |id| doggo(id).to_upsert_event();
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let mut slow = manager.connect_buffered(1, OverflowPolicy::DropOldest);
+        let mut keeping_up = manager.connect_buffered(1, OverflowPolicy::DropOldest);
+        for listener in [&slow, &keeping_up] {
+            manager.subscribe(listener.connection(), Collection::Dogs, None);
+        }
+        let mut events = manager.connection_events();
+        let connection = slow.connection();
+        let lagging = |dropped| ConnectionEvent::Lagging {
+            connection,
+            dropped,
+        };
+
+        for id in 0..3 {
+            manager.publish(upsert(id));
+            assert_eq!(block_on(keeping_up.recv()).unwrap().seq(), Some(id.into()));
+        }
+        assert!(reported(&mut events) == [lagging(1)]);
+        manager.unsubscribe(keeping_up.connection(), &Collection::Dogs);
+
+        // emptying the buffer isn't catching up until a recv finds it empty
+        assert_eq!(block_on(slow.recv()).unwrap().seq(), Some(2));
+        manager.publish(upsert(3));
+        manager.publish(upsert(4));
+        assert!(reported(&mut events).is_empty());
+        assert_eq!(block_on(slow.recv()).unwrap().seq(), Some(4));
+        assert!(poll_once(slow.recv()).is_pending());
+        manager.publish(upsert(5));
+        manager.publish(upsert(6));
+        assert!(reported(&mut events) == [lagging(4)]);
+    }
+
+    #[test]
+    fn disconnects_are_reported_once_with_their_reason() {
+        let cat = |id| DoggoEvent::new_delete_event(id, Collection::Cats);
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let closed = manager.connect();
+        let dropped = manager.connect();
+        let overflowed = manager.connect_buffered(1, OverflowPolicy::CloseConnection);
+        let mut failed = manager.connect();
+        manager.subscribe(overflowed.connection(), Collection::Cats, None);
+        manager.publish(cat(1));
+        manager.publish(cat(2));
+        let mut events = manager.connection_events();
+
+        manager.disconnect(closed.connection());
+        manager.disconnect(closed.connection());
+        drop(closed);
+        let dropped_connection = dropped.connection();
+        drop(dropped);
+        let overflowed_connection = overflowed.connection();
+        drop(overflowed);
+        manager.disconnect_with(failed.connection(), &Error::ConnectionTimedOut(30_000));
+
+        let reasons: Vec<_> = reported(&mut events)
+            .into_iter()
+            .map(|event| match event {
+                ConnectionEvent::Disconnected { connection, reason } => (connection, reason),
+                _ => panic!("expected only disconnects"),
+            })
+            .collect();
+        assert_eq!(
+            reasons.iter().map(|(_, reason)| reason).collect::<Vec<_>>(),
+            [
+                &DisconnectReason::Closed,
+                &DisconnectReason::Closed,
+                &DisconnectReason::Overflowed,
+                &DisconnectReason::Failed(
+                    "connection timed out after 30000ms without a heartbeat".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(reasons[1].0, dropped_connection);
+        assert_eq!(reasons[2].0, overflowed_connection);
+        assert!(matches!(block_on(failed.recv()), Err(Error::Closed)));
+    }
+
+    #[test]
+    fn going_away_delivers_what_was_sent_before_closing() {
+        let manager = SubscriptionManager::<DoggoEvent>::new();
+        let mut events = manager.connection_events();
+        let mut client = manager.connect();
+        manager.subscribe(client.connection(), Collection::Dogs, None);
+        manager.publish(doggo(1).to_upsert_event());
+
+        // a clone keeps it around
+        drop(manager.clone());
+        assert!(poll_once(client.recv()).is_ready());
+        assert!(poll_once(client.recv()).is_pending());
+        manager.publish(doggo(2).to_upsert_event());
+        drop(manager);
+
+        block_on(async {
+            assert_eq!(client.recv().await.unwrap().seq(), Some(1));
+            assert!(matches!(client.recv().await, Err(Error::Closed)));
+            assert!(matches!(
+                events.recv().await,
+                Ok(ConnectionEvent::Connected { .. })
+            ));
+            assert!(matches!(
+                events.recv().await,
+                Ok(ConnectionEvent::Subscribed { .. })
+            ));
+            assert!(matches!(events.recv().await, Err(Error::Closed)));
+            // the connection closing after its manager isn't reported
+            drop(client);
+            assert!(matches!(events.recv().await, Err(Error::Closed)));
+        });
+    }
+}
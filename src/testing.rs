@@ -0,0 +1,546 @@
+// a conformance harness for services and the transports behind them. every
+// scenario runs against a fresh service from the factory, publishing
+// `ProbeEvent`s and checking what comes out of its listeners, so an adapter
+// only has to be instantiated with `ProbeEvent` to prove it keeps the
+// protocol's guarantees:
+//
+//     Conformance::new(|| MyService::new()).run_blocking().assert_ok();
+//
+// for unit-testing the handlers built on top of a service, `MockService`
+// records what they publish and `ScriptedListener` feeds them a fixed
+// sequence of events, errors and delays.
+//
+// with the `testing` feature, every protocol message implements proptest's
+// `Arbitrary` and `check_round_trips` fuzzes it through every codec:
+//
+//     check_round_trips::<WsBody<Event<u32, MyRecord, MyCollection>>>(0, 500)?;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Display};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+#[cfg(any(test, feature = "testing"))]
+mod arbitrary;
+
+#[cfg(any(test, feature = "testing"))]
+pub use arbitrary::{check_round_trips, key, list, round_trip, string, value, RoundTripFailure};
+
+use crate::timer::{sleep, within};
+use crate::{
+    Appendable, BroadcastListener, BroadcastService, Error, Event, Listener, Sequenced, Service,
+    Shutdown, Syncable,
+};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// the record the scenarios publish
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+pub struct Probe {
+    pub id: u64,
+    pub collection: String,
+}
+
+impl Appendable for Probe {
+    type Collection = String;
+
+    fn collection(&self) -> String {
+        self.collection.clone()
+    }
+}
+
+impl Syncable for Probe {
+    type Id = u64;
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+pub type ProbeEvent = Event<u64, Probe, String>;
+
+fn probe(id: u64, collection: &str) -> ProbeEvent {
+    Probe {
+        id,
+        collection: collection.to_owned(),
+    }
+    .to_upsert_event()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub scenario: &'static str,
+    pub message: String,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.scenario, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub passed: Vec<&'static str>,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    // panics listing every failed scenario, for use in a test
+    pub fn assert_ok(&self) {
+        if !self.is_ok() {
+            let failures: Vec<_> = self.failures.iter().map(Failure::to_string).collect();
+            panic!("conformance failed:\n{}", failures.join("\n"));
+        }
+    }
+}
+
+pub struct Conformance<F> {
+    new_service: F,
+    timeout: Duration,
+}
+
+type Outcome = Result<(), String>;
+
+impl<F, S> Conformance<F>
+where
+    F: Fn() -> S,
+    S: Service<ProbeEvent>,
+    S::Error: Debug,
+    S::Listener: Send,
+    <S::Listener as Listener>::Error: Debug,
+{
+    pub fn new(new_service: F) -> Self {
+        Self {
+            new_service,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    // how long a scenario waits for an event before it fails. transports
+    // that deliver asynchronously may need more than `DEFAULT_TIMEOUT`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn run(&self) -> Report {
+        let mut report = Report::default();
+        let scenarios: [(&'static str, Outcome); 4] = [
+            ("ordering", self.ordering().await),
+            ("resume", self.resume().await),
+            ("ack redelivery", self.ack_redelivery().await),
+            ("filtering", self.filtering().await),
+        ];
+        for (scenario, outcome) in scenarios {
+            match outcome {
+                Ok(()) => report.passed.push(scenario),
+                Err(message) => report.failures.push(Failure { scenario, message }),
+            }
+        }
+        report
+    }
+
+    // `run` for callers without an async runtime
+    pub fn run_blocking(&self) -> Report {
+        block_on(self.run())
+    }
+
+    // a listener sees every event once, in publish order, with rising seqs
+    async fn ordering(&self) -> Outcome {
+        let service = (self.new_service)();
+        let mut listener = service.listener();
+        for id in 1..=5 {
+            publish(&service, probe(id, "dogs"))?;
+        }
+        let mut last_seq = None;
+        for id in 1..=5 {
+            let event = self.recv(&mut listener).await?;
+            expect_id(&event, id)?;
+            let seq = event.seq().ok_or("delivered an event without a seq")?;
+            if last_seq.is_some_and(|last| seq <= last) {
+                return Err(format!("seq {seq} came after {}", last_seq.unwrap_or(0)));
+            }
+            last_seq = Some(seq);
+        }
+        Ok(())
+    }
+
+    // a listener from the seq after the last one a client saw picks up
+    // exactly where it left off
+    async fn resume(&self) -> Outcome {
+        let service = (self.new_service)();
+        let mut listener = service.listener();
+        for id in 1..=3 {
+            publish(&service, probe(id, "dogs"))?;
+        }
+        let mut seen = None;
+        for _ in 1..=2 {
+            seen = self.recv(&mut listener).await?.seq();
+        }
+        drop(listener);
+        let seen = seen.ok_or("delivered an event without a seq")?;
+        publish(&service, probe(4, "dogs"))?;
+        if service.head_seq() <= seen + 2 {
+            return Err(format!(
+                "head seq {} is behind the published events",
+                service.head_seq()
+            ));
+        }
+
+        let mut listener = service.listener_from(seen + 1);
+        for id in 3..=4 {
+            expect_id(&self.recv(&mut listener).await?, id)?;
+        }
+        Ok(())
+    }
+
+    // a nacked event is redelivered before anything new, an acked one never
+    async fn ack_redelivery(&self) -> Outcome {
+        let service = (self.new_service)();
+        let mut listener = service.acked_listener();
+        for id in 1..=3 {
+            publish(&service, probe(id, "dogs"))?;
+        }
+        let first = self.recv(&mut listener).await?;
+        let second = self.recv(&mut listener).await?;
+        let (Some(first_seq), Some(second_seq)) = (first.seq(), second.seq()) else {
+            return Err("delivered an event without a seq".to_owned());
+        };
+        listener.ack(second_seq);
+        listener.nack(first_seq);
+        expect_id(&self.recv(&mut listener).await?, 1)?;
+        listener.ack(first_seq);
+        expect_id(&self.recv(&mut listener).await?, 3)?;
+        match listener.unacked() {
+            1 => Ok(()),
+            unacked => Err(format!("{unacked} events unacked, expected 1")),
+        }
+    }
+
+    // a listener for some collections only sees their events
+    async fn filtering(&self) -> Outcome {
+        let service = (self.new_service)();
+        let mut listener = service.listener_for_collections(["dogs".to_owned()]);
+        publish(&service, probe(1, "cats"))?;
+        publish(&service, probe(2, "dogs"))?;
+        publish(&service, probe(3, "birds"))?;
+        publish(&service, probe(4, "dogs"))?;
+        for id in [2, 4] {
+            let event = self.recv(&mut listener).await?;
+            expect_id(&event, id)?;
+        }
+        Ok(())
+    }
+
+    async fn recv<L>(&self, listener: &mut L) -> Result<ProbeEvent, String>
+    where
+        L: Listener<Item = ProbeEvent>,
+        L::Error: Debug,
+    {
+        match within(self.timeout, listener.recv()).await {
+            Some(Ok(event)) => Ok(event),
+            Some(Err(err)) => Err(format!("listener failed: {err:?}")),
+            None => Err(format!("no event within {:?}", self.timeout)),
+        }
+    }
+}
+
+// a service that keeps everything published to it, stamped with its seq.
+// listeners get the published events like those of a `BroadcastService`
+pub struct MockService<T> {
+    inner: BroadcastService<T>,
+    published: Mutex<Vec<T>>,
+    failures: Mutex<VecDeque<Error>>,
+}
+
+impl<T> MockService<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: BroadcastService::new(),
+            published: Mutex::new(Vec::new()),
+            failures: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // the next publish fails with `err` instead of publishing. queued
+    // failures are used up one publish at a time
+    pub fn fail_next_publish(&self, err: Error) {
+        lock(&self.failures).push_back(err);
+    }
+
+    pub fn published(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        lock(&self.published).clone()
+    }
+
+    pub fn take_published(&self) -> Vec<T> {
+        std::mem::take(&mut *lock(&self.published))
+    }
+}
+
+impl<T> Default for MockService<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Send + Sequenced> Service<T> for MockService<T> {
+    type Listener = BroadcastListener<T>;
+    type Error = Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        if let Some(err) = lock(&self.failures).pop_front() {
+            return Err(err);
+        }
+        event.set_seq(self.inner.head_seq());
+        lock(&self.published).push(event.clone());
+        self.inner.publish(event)
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.inner.listener()
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        self.inner.listener_from(seq)
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+enum Step<T> {
+    Item(T),
+    Error(Error),
+    Delay(Duration),
+}
+
+// yields a predefined sequence, then fails with `Error::Closed` like a
+// listener whose service is gone
+pub struct ScriptedListener<T> {
+    steps: VecDeque<Step<T>>,
+}
+
+impl<T> ScriptedListener<T> {
+    pub fn new() -> Self {
+        Self {
+            steps: VecDeque::new(),
+        }
+    }
+
+    pub fn then(mut self, item: T) -> Self {
+        self.steps.push_back(Step::Item(item));
+        self
+    }
+
+    pub fn then_error(mut self, err: Error) -> Self {
+        self.steps.push_back(Step::Error(err));
+        self
+    }
+
+    // the next `recv` waits for `delay` before moving on
+    pub fn then_delay(mut self, delay: Duration) -> Self {
+        self.steps.push_back(Step::Delay(delay));
+        self
+    }
+
+    // items and errors that haven't been yielded yet
+    pub fn remaining(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| !matches!(step, Step::Delay(_)))
+            .count()
+    }
+}
+
+impl<T> Default for ScriptedListener<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FromIterator<T> for ScriptedListener<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        Self {
+            steps: items.into_iter().map(Step::Item).collect(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Send> Listener for ScriptedListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        loop {
+            match self.steps.pop_front() {
+                Some(Step::Item(item)) => return Ok(item),
+                Some(Step::Error(err)) => return Err(err),
+                Some(Step::Delay(delay)) => sleep(delay).await,
+                None => return Err(Error::Closed),
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn publish<S>(service: &S, event: ProbeEvent) -> Outcome
+where
+    S: Service<ProbeEvent>,
+    S::Error: Debug,
+{
+    service
+        .publish(event)
+        .map_err(|err| format!("publish failed: {err:?}"))
+}
+
+fn expect_id(event: &ProbeEvent, id: u64) -> Outcome {
+    match event.id() {
+        Some(&found) if found == id => Ok(()),
+        found => Err(format!("expected record {id}, got {found:?}")),
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// for the blocking apis built on async ones
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::test::block_on;
+    use crate::testing::{Conformance, MockService, Probe, ProbeEvent, ScriptedListener};
+    use crate::{BroadcastService, Error, InMemoryEventStore, Listener, Service, Syncable};
+
+    // drops the last event a listener would see, so ordering and resume
+    // notice it missing
+    struct Lossy(BroadcastService<ProbeEvent>);
+
+    impl Service<ProbeEvent> for Lossy {
+        type Listener = <BroadcastService<ProbeEvent> as Service<ProbeEvent>>::Listener;
+        type Error = crate::Error;
+
+        fn publish(&self, event: ProbeEvent) -> Result<(), Self::Error> {
+            match event.id() {
+                Some(&id) if id >= 4 => Ok(()),
+                _ => self.0.publish(event),
+            }
+        }
+
+        fn listener(&self) -> Self::Listener {
+            self.0.listener()
+        }
+
+        fn listener_from(&self, seq: u64) -> Self::Listener {
+            self.0.listener_from(seq)
+        }
+
+        fn head_seq(&self) -> u64 {
+            self.0.head_seq()
+        }
+    }
+
+    #[test]
+    fn broadcast_service_conforms() {
+        Conformance::new(BroadcastService::new)
+            .run_blocking()
+            .assert_ok();
+        Conformance::new(|| BroadcastService::with_store(8, InMemoryEventStore::new(16)))
+            .run_blocking()
+            .assert_ok();
+
+        let report = Conformance::new(|| Lossy(BroadcastService::new()))
+            .with_timeout(Duration::from_millis(20))
+            .run_blocking();
+        assert_eq!(report.passed, ["ack redelivery"]);
+        let failures: Vec<_> = report.failures.iter().map(ToString::to_string).collect();
+        insta::assert_snapshot!(failures.join("\n"), @r###"
+        ordering: no event within 20ms
+        resume: head seq 3 is behind the published events
+        filtering: no event within 20ms
+        "###);
+    }
+
+    #[test]
+    fn mocks_record_and_replay() {
+        let probe = |id| Probe {
+            id,
+            collection: "dogs".to_owned(),
+        };
+        let service = MockService::new();
+        let mut listener = service.listener();
+        service.fail_next_publish(Error::Closed);
+        assert!(matches!(
+            service.publish(probe(1).to_upsert_event()),
+            Err(Error::Closed)
+        ));
+        service.publish(probe(2).to_upsert_event()).unwrap();
+        service.publish(probe(3).to_delete_event()).unwrap();
+        let seqs: Vec<_> = service
+            .published()
+            .iter()
+            .map(|event| (event.id().copied(), event.seq()))
+            .collect();
+        assert_eq!(seqs, [(Some(2), Some(0)), (Some(3), Some(1))]);
+        assert_eq!(service.take_published().len(), 2);
+        assert!(service.published().is_empty());
+
+        let mut scripted = [probe(4).to_upsert_event()]
+            .into_iter()
+            .collect::<ScriptedListener<ProbeEvent>>()
+            .then_error(Error::Lagged(3))
+            .then_delay(Duration::from_millis(20))
+            .then(probe(5).to_upsert_event());
+        assert_eq!(scripted.remaining(), 3);
+        block_on(async {
+            assert_eq!(listener.recv().await.unwrap().id(), Some(&2));
+            assert_eq!(scripted.recv().await.unwrap().id(), Some(&4));
+            assert!(matches!(scripted.recv().await, Err(Error::Lagged(3))));
+            let started = Instant::now();
+            assert_eq!(scripted.recv().await.unwrap().id(), Some(&5));
+            assert!(started.elapsed() >= Duration::from_millis(20));
+            assert!(matches!(scripted.recv().await, Err(Error::Closed)));
+        });
+    }
+}
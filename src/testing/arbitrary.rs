@@ -0,0 +1,1036 @@
+use std::any::TypeId;
+use std::fmt::{self, Debug, Display};
+
+use proptest::arbitrary::{any, Arbitrary};
+use proptest::collection::{btree_map, vec};
+use proptest::option;
+use proptest::prelude::{prop_oneof, BoxedStrategy, Just, Strategy};
+use proptest::sample::select;
+use proptest::strategy::{LazyJust, Union};
+use proptest::test_runner::{Config, RngSeed, TestCaseError, TestError, TestRunner};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use ts_rs::TS;
+
+#[cfg(feature = "cbor")]
+use crate::codec::CborCodec;
+#[cfg(feature = "msgpack")]
+use crate::codec::MessagePackCodec;
+use crate::codec::{JsonCodec, StyledJsonCodec, WireCodec, WireStyle};
+use crate::presence::{
+    Join, Leave, PresenceDelta, PresenceEntry, PresenceHeartbeat, PresenceState, PresenceStatus,
+};
+use crate::{
+    Ack, Acknowledgement, AppendableResource, AuthResult, Authenticate, Claims, CollectionPattern,
+    Command, ConflictError, DeletableResource, Ephemeral, ErrorCode, ErrorMessage, Event,
+    EventBatch, EventMeta, EventVerb, Filter, Hello, HelloAck, JsonPatch, LiveQuery,
+    LiveQueryChange, LiveQueryUpdate, Location, Mutate, MutationResult, MutationStatus, Nack,
+    NoPatch, PatchOperation, PatchResource, Ping, Pong, Query, QueryResult, Rejection, Scope,
+    Snapshot, SnapshotChunk, SnapshotEntry, Subscribe, Unsubscribe, UpdatableResource,
+    ValidationErrors, VectorClock, WsBody,
+};
+
+// the most elements a generated collection or string has
+const SIZE: usize = 4;
+
+// how deep values, filters and the like nest
+const MAX_DEPTH: u32 = 3;
+
+// up to `SIZE` `T`s
+pub fn list<T: Arbitrary>() -> impl Strategy<Value = Vec<T>> {
+    vec(any::<T>(), 0..=SIZE)
+}
+
+// any text, including quotes, escapes and characters outside ascii
+pub fn string() -> impl Strategy<Value = String> {
+    const CHARS: &[char] = &[
+        'a', 'b', 'z', 'A', 'Z', '0', '9', ' ', '_', '-', '/', '"', '\\', '\n', 'é', 'ß', '中',
+        '🐕',
+    ];
+    vec(select(CHARS), 0..=SIZE).prop_map(String::from_iter)
+}
+
+// a name for a map key: lowercase letters only, so it is never one of the
+// protocol's own names a `WireStyle` renames
+pub fn key() -> impl Strategy<Value = String> {
+    "[a-z]{1,6}"
+}
+
+// never `null` itself, since an `Option<Value>` of `null` decodes as `None`.
+// numbers are integers: json doesn't promise floats come back bit for bit
+pub fn value() -> BoxedStrategy<Value> {
+    let leaf = prop_oneof![
+        any::<bool>().prop_map(Value::Bool),
+        any::<u64>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        string().prop_map(Value::String),
+    ];
+    leaf.prop_recursive(MAX_DEPTH, 32, SIZE as u32, |inner| {
+        // nulls only inside arrays and objects
+        let element = prop_oneof![1 => Just(Value::Null), 5 => inner];
+        prop_oneof![
+            vec(element.clone(), 0..=SIZE).prop_map(Value::Array),
+            btree_map(key(), element, 0..=SIZE)
+                .prop_map(|entries| Value::Object(entries.into_iter().collect::<Map<_, _>>())),
+        ]
+    })
+    .boxed()
+}
+
+// `NoPatch` has no values, so events without patches never get a `Patch`
+// verb and this is never sampled
+impl Arbitrary for NoPatch {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        Just(())
+            .prop_map(|()| unreachable!("`NoPatch` has no values"))
+            .boxed()
+    }
+}
+
+impl Arbitrary for Scope {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        string().prop_map(Scope::new).boxed()
+    }
+}
+
+impl Arbitrary for CollectionPattern {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let segment = prop_oneof![1 => Just("*".to_owned()), 3 => key()];
+        (vec(segment, 1..=SIZE + 1), any::<bool>())
+            .prop_map(|(mut segments, recursive)| {
+                if recursive {
+                    segments.push("**".to_owned());
+                }
+                CollectionPattern::new(segments.join("/")).expect("generated patterns are valid")
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for EventMeta {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            option::of(string()),
+            option::of(string()),
+            option::of(string()),
+            option::of(string()),
+            vec((key(), value()), 0..=SIZE),
+        )
+            .prop_map(|(actor_id, origin, correlation_id, causation_id, extra)| {
+                let mut meta = EventMeta::new();
+                if let Some(actor_id) = actor_id {
+                    meta = meta.with_actor_id(actor_id);
+                }
+                if let Some(origin) = origin {
+                    meta = meta.with_origin(origin);
+                }
+                if let Some(correlation_id) = correlation_id {
+                    meta = meta.with_correlation_id(correlation_id);
+                }
+                if let Some(causation_id) = causation_id {
+                    meta = meta.with_causation_id(causation_id);
+                }
+                for (key, value) in extra {
+                    meta = meta.with_extra(key, value);
+                }
+                meta
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for VectorClock {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        vec((key(), 1..=1_000u64), 0..=SIZE)
+            .prop_map(|entries| {
+                let mut clock = VectorClock::new();
+                for (actor, count) in entries {
+                    clock.add(&actor, count);
+                }
+                clock
+            })
+            .boxed()
+    }
+}
+
+// an id of `()` encodes as `null` whether it is there or not, so events with
+// them don't round-trip a `Some(())` id; use a real id type
+impl<ID, C> Arbitrary for Location<ID, C>
+where
+    ID: Arbitrary + 'static,
+    C: Arbitrary + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<Option<ID>>(),
+            any::<Option<u32>>(),
+            any::<C>(),
+            any::<Option<u64>>(),
+        )
+            .prop_map(|(id, txn_id, collection, revision)| Location {
+                id,
+                txn_id,
+                collection,
+                revision,
+            })
+            .boxed()
+    }
+}
+
+impl<ID, T, C, P> Arbitrary for EventVerb<ID, T, C, P>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + Serialize + TS + 'static,
+    C: Arbitrary + 'static,
+    P: Arbitrary + Serialize + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let updatable = || {
+            (any::<Location<ID, C>>(), any::<T>(), any::<Option<u64>>()).prop_map(
+                |(location, data, expected_revision)| UpdatableResource {
+                    location,
+                    data,
+                    expected_revision,
+                },
+            )
+        };
+        let mut verbs = vec![
+            (any::<Location<ID, C>>(), any::<T>())
+                .prop_map(|(location, data)| {
+                    EventVerb::Insert(AppendableResource { location, data })
+                })
+                .boxed(),
+            updatable().prop_map(EventVerb::Update).boxed(),
+            updatable().prop_map(EventVerb::Upsert).boxed(),
+            (any::<Location<ID, C>>(), any::<Option<u64>>())
+                .prop_map(|(location, deleted_at)| {
+                    EventVerb::Delete(DeletableResource {
+                        location,
+                        deleted_at,
+                    })
+                })
+                .boxed(),
+            any::<u32>().prop_map(EventVerb::TxnBegin).boxed(),
+            any::<u32>().prop_map(EventVerb::TxnCommit).boxed(),
+            any::<u32>().prop_map(EventVerb::TxnAbort).boxed(),
+        ];
+        if TypeId::of::<P>() != TypeId::of::<NoPatch>() {
+            let patch = (any::<Location<ID, C>>(), any::<P>())
+                .prop_map(|(location, data)| EventVerb::Patch(PatchResource { location, data }));
+            verbs.push(patch.boxed());
+        }
+        Union::new(verbs).boxed()
+    }
+}
+
+impl<ID, T, C, P> Arbitrary for Event<ID, T, C, P>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + Serialize + TS + 'static,
+    C: Arbitrary + 'static,
+    P: Arbitrary + Serialize + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<Option<u64>>(),
+            any::<Option<u64>>(),
+            any::<Option<u32>>(),
+            any::<Option<Scope>>(),
+            option::of(string()),
+            any::<Option<u64>>(),
+            any::<Option<EventMeta>>(),
+            any::<Option<VectorClock>>(),
+            any::<EventVerb<ID, T, C, P>>(),
+        )
+            .prop_map(
+                |(
+                    seq,
+                    occurred_at,
+                    schema_version,
+                    scope,
+                    idempotency_key,
+                    expires_at,
+                    meta,
+                    causality,
+                    verb,
+                )| Event {
+                    seq,
+                    occurred_at,
+                    schema_version,
+                    scope,
+                    idempotency_key,
+                    expires_at,
+                    meta,
+                    causality,
+                    verb,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl<ID, T, C, P> Arbitrary for EventBatch<ID, T, C, P>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + Serialize + TS + 'static,
+    C: Arbitrary + 'static,
+    P: Arbitrary + Serialize + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<u64>(), any::<Option<u32>>(), list())
+            .prop_map(|(seq, txn_id, events)| {
+                let mut batch = EventBatch::new(seq);
+                if let Some(txn_id) = txn_id {
+                    batch = batch.with_txn_id(txn_id);
+                }
+                for event in events {
+                    batch.push(event);
+                }
+                batch
+            })
+            .boxed()
+    }
+}
+
+impl<T: Arbitrary + Serialize + 'static> Arbitrary for WsBody<T> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<Option<u32>>(), any::<Option<u32>>(), any::<T>())
+            .prop_map(|(protocol_version, channel, data)| WsBody {
+                protocol_version,
+                channel,
+                data,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for PatchOperation {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            (string(), value()).prop_map(|(path, value)| PatchOperation::Add { path, value }),
+            string().prop_map(|path| PatchOperation::Remove { path }),
+            (string(), value()).prop_map(|(path, value)| PatchOperation::Replace { path, value }),
+            (string(), string()).prop_map(|(from, path)| PatchOperation::Move { from, path }),
+            (string(), string()).prop_map(|(from, path)| PatchOperation::Copy { from, path }),
+            (string(), value()).prop_map(|(path, value)| PatchOperation::Test { path, value }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for JsonPatch {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        list::<PatchOperation>().prop_map(JsonPatch::from).boxed()
+    }
+}
+
+impl Arbitrary for Filter {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let leaf = prop_oneof![
+            (string(), value()).prop_map(|(field, value)| Filter::Eq { field, value }),
+            (string(), value()).prop_map(|(field, value)| Filter::Ne { field, value }),
+            (string(), value()).prop_map(|(field, value)| Filter::Lt { field, value }),
+            (string(), value()).prop_map(|(field, value)| Filter::Lte { field, value }),
+            (string(), value()).prop_map(|(field, value)| Filter::Gt { field, value }),
+            (string(), value()).prop_map(|(field, value)| Filter::Gte { field, value }),
+            (string(), vec(value(), 0..=SIZE))
+                .prop_map(|(field, values)| Filter::In { field, values }),
+        ];
+        leaf.prop_recursive(MAX_DEPTH, 16, SIZE as u32, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..=SIZE).prop_map(|filters| Filter::And { filters }),
+                vec(inner.clone(), 0..=SIZE).prop_map(|filters| Filter::Or { filters }),
+                inner.prop_map(|filter| Filter::Not {
+                    filter: Box::new(filter)
+                }),
+            ]
+        })
+        .boxed()
+    }
+}
+
+impl<C: Arbitrary + 'static> Arbitrary for Subscribe<C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (list(), list(), any::<Option<u64>>())
+            .prop_map(|(collections, patterns, from_seq)| Subscribe {
+                collections,
+                patterns,
+                from_seq,
+            })
+            .boxed()
+    }
+}
+
+impl<C: Arbitrary + 'static> Arbitrary for Unsubscribe<C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (list(), list())
+            .prop_map(|(collections, patterns)| Unsubscribe {
+                collections,
+                patterns,
+            })
+            .boxed()
+    }
+}
+
+impl<ID: Arbitrary + 'static, C: Arbitrary + 'static> Arbitrary for Query<ID, C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<u32>(),
+            any::<C>(),
+            option::of(list()),
+            option::of(btree_map(key(), value(), 0..=SIZE)),
+            option::of(string()),
+            any::<Option<u32>>(),
+        )
+            .prop_map(
+                |(request_id, collection, ids, filter, cursor, limit)| Query {
+                    request_id,
+                    collection,
+                    ids,
+                    filter,
+                    cursor,
+                    limit,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl<C: Arbitrary + 'static> Arbitrary for LiveQuery<C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<u32>(), any::<C>(), any::<Filter>())
+            .prop_map(|(query_id, collection, filter)| LiveQuery {
+                query_id,
+                collection,
+                filter,
+            })
+            .boxed()
+    }
+}
+
+impl<ID, T, C, P> Arbitrary for Mutate<ID, T, C, P>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + Serialize + TS + 'static,
+    C: Arbitrary + 'static,
+    P: Arbitrary + Serialize + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<u32>(), any::<Event<ID, T, C, P>>())
+            .prop_map(|(request_id, event)| Mutate { request_id, event })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Acknowledgement {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<u64>().prop_map(|seq| Acknowledgement::Ack(Ack { seq })),
+            (any::<u64>(), option::of(string()))
+                .prop_map(|(seq, reason)| Acknowledgement::Nack(Nack { seq, reason })),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Ping {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<u64>().prop_map(|sent_at| Ping { sent_at }).boxed()
+    }
+}
+
+impl Arbitrary for Pong {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<u64>(), any::<u64>())
+            .prop_map(|(ping_sent_at, sent_at)| Pong {
+                ping_sent_at,
+                sent_at,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Authenticate {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        string().prop_map(|token| Authenticate { token }).boxed()
+    }
+}
+
+impl Arbitrary for PresenceState {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let status = select(&[PresenceStatus::Online, PresenceStatus::Away][..]);
+        (status, option::of(value()))
+            .prop_map(|(status, payload)| PresenceState { status, payload })
+            .boxed()
+    }
+}
+
+impl Arbitrary for PresenceEntry {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (string(), any::<PresenceState>())
+            .prop_map(|(member, state)| PresenceEntry { member, state })
+            .boxed()
+    }
+}
+
+impl Arbitrary for PresenceDelta {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (string(), list(), vec(string(), 0..=SIZE))
+            .prop_map(|(room, joins, leaves)| PresenceDelta {
+                room,
+                joins,
+                leaves,
+            })
+            .boxed()
+    }
+}
+
+impl<ID: Arbitrary + 'static, C: Arbitrary + 'static> Arbitrary for Ephemeral<ID, C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<C>(),
+            value(),
+            any::<Option<ID>>(),
+            option::of(string()),
+            any::<Option<Scope>>(),
+        )
+            .prop_map(|(collection, data, id, origin, scope)| {
+                let mut ephemeral = Ephemeral::new(collection, data);
+                if let Some(id) = id {
+                    ephemeral = ephemeral.with_id(id);
+                }
+                if let Some(origin) = origin {
+                    ephemeral = ephemeral.with_origin(origin);
+                }
+                if let Some(scope) = scope {
+                    ephemeral = ephemeral.with_scope(scope);
+                }
+                ephemeral
+            })
+            .boxed()
+    }
+}
+
+impl<ID, T, C, P> Arbitrary for Command<ID, T, C, P>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + Serialize + TS + 'static,
+    C: Arbitrary + 'static,
+    P: Arbitrary + Serialize + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<Subscribe<C>>().prop_map(Command::Subscribe),
+            any::<Unsubscribe<C>>().prop_map(Command::Unsubscribe),
+            any::<Query<ID, C>>().prop_map(Command::Query),
+            any::<LiveQuery<C>>().prop_map(Command::LiveQuery),
+            any::<Mutate<ID, T, C, P>>().prop_map(Command::Mutate),
+            any::<Acknowledgement>().prop_map(Command::Ack),
+            any::<Ping>().prop_map(Command::Ping),
+            any::<Pong>().prop_map(Command::Pong),
+            any::<Authenticate>().prop_map(Command::Authenticate),
+            (string(), any::<PresenceState>())
+                .prop_map(|(room, state)| Command::Join(Join { room, state })),
+            string().prop_map(|room| Command::Leave(Leave { room })),
+            string().prop_map(|room| Command::PresenceHeartbeat(PresenceHeartbeat { room })),
+            any::<Ephemeral<ID, C>>().prop_map(Command::Ephemeral),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Hello {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (list(), vec(string(), 0..=SIZE))
+            .prop_map(|(versions, codecs)| Hello::new(versions, codecs))
+            .boxed()
+    }
+}
+
+impl Arbitrary for HelloAck {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<u32>(), string())
+            .prop_map(|(version, codec)| {
+                Hello::new(vec![version], vec![codec.clone()])
+                    .negotiate(&[version], &[&codec])
+                    .expect("a hello of one version and codec negotiates them")
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for ErrorCode {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        select(
+            &[
+                ErrorCode::BadCommand,
+                ErrorCode::Unauthorized,
+                ErrorCode::Conflict,
+                ErrorCode::Unsupported,
+                ErrorCode::Lagged,
+                ErrorCode::Overloaded,
+                ErrorCode::Unavailable,
+                ErrorCode::Internal,
+            ][..],
+        )
+        .boxed()
+    }
+}
+
+impl Arbitrary for ErrorMessage {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<ErrorCode>(), string(), any::<Option<u64>>())
+            .prop_map(|(code, text, related_seq)| {
+                let message = ErrorMessage::new(code, text);
+                match related_seq {
+                    Some(seq) => message.with_related_seq(seq),
+                    None => message,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl<C: Arbitrary + 'static> Arbitrary for Claims<C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (string(), option::of(list()))
+            .prop_map(|(subject, collections)| {
+                let claims = Claims::new(subject);
+                match collections {
+                    Some(collections) => claims.with_collections(collections),
+                    None => claims,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl<C: Arbitrary + 'static> Arbitrary for AuthResult<C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<Claims<C>>().prop_map(AuthResult::Accepted),
+            string().prop_map(AuthResult::Rejected),
+        ]
+        .boxed()
+    }
+}
+
+impl<ID: Arbitrary + 'static, C: Arbitrary + 'static> Arbitrary for ConflictError<ID, C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<C>(),
+            any::<Option<ID>>(),
+            any::<u64>(),
+            any::<Option<u64>>(),
+        )
+            .prop_map(|(collection, id, expected, current)| {
+                ConflictError::new(collection, id, expected, current)
+            })
+            .boxed()
+    }
+}
+
+impl<ID: Arbitrary + 'static, C: Arbitrary + 'static> Arbitrary for MutationResult<ID, C> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let status = prop_oneof![
+            LazyJust::new(|| MutationStatus::Accepted),
+            any::<ConflictError<ID, C>>()
+                .prop_map(|conflict| MutationStatus::Rejected(Rejection::Conflict(conflict))),
+            LazyJust::new(|| MutationStatus::Rejected(Rejection::NotFound)),
+            LazyJust::new(|| MutationStatus::Rejected(Rejection::Forbidden)),
+            any::<ValidationErrors>()
+                .prop_map(|errors| MutationStatus::Rejected(Rejection::Validation(errors))),
+            string().prop_map(|reason| MutationStatus::Rejected(Rejection::Invalid(reason))),
+        ];
+        (
+            any::<u32>(),
+            status,
+            any::<Option<ID>>(),
+            any::<Option<u64>>(),
+        )
+            .prop_map(|(request_id, status, assigned_id, seq)| MutationResult {
+                request_id,
+                status,
+                assigned_id,
+                seq,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for ValidationErrors {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        vec((key(), key(), string()), 0..=SIZE)
+            .prop_map(|fields| {
+                let mut errors = ValidationErrors::new();
+                for (field, code, message) in fields {
+                    errors.push(field, code, message);
+                }
+                errors
+            })
+            .boxed()
+    }
+}
+
+impl<ID, T> Arbitrary for SnapshotEntry<ID, T>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<ID>(), any::<T>())
+            .prop_map(|(id, data)| SnapshotEntry::new(id, data))
+            .boxed()
+    }
+}
+
+impl<ID, T, C> Arbitrary for Snapshot<ID, T, C>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + Serialize + TS + 'static,
+    C: Arbitrary + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<C>(), any::<u64>(), list())
+            .prop_map(|(collection, seq, records)| Snapshot::new(collection, seq, records))
+            .boxed()
+    }
+}
+
+impl<ID, T, C> Arbitrary for SnapshotChunk<ID, T, C>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + TS + 'static,
+    C: Arbitrary + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<C>(), any::<u64>(), any::<u32>(), any::<u32>(), list())
+            .prop_map(|(collection, seq, idx, total, records)| SnapshotChunk {
+                collection,
+                seq,
+                idx,
+                total,
+                records,
+            })
+            .boxed()
+    }
+}
+
+impl<ID, T, C> Arbitrary for QueryResult<ID, T, C>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + TS + 'static,
+    C: Arbitrary + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<u32>(),
+            any::<C>(),
+            any::<u64>(),
+            list(),
+            option::of(string()),
+        )
+            .prop_map(
+                |(request_id, collection, seq, records, next_cursor)| QueryResult {
+                    request_id,
+                    collection,
+                    seq,
+                    records,
+                    next_cursor,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl<ID, T> Arbitrary for LiveQueryUpdate<ID, T>
+where
+    ID: Arbitrary + 'static,
+    T: Arbitrary + TS + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let change = prop_oneof![
+            (any::<ID>(), any::<T>()).prop_map(|(id, data)| LiveQueryChange::Added { id, data }),
+            (any::<ID>(), any::<T>()).prop_map(|(id, data)| LiveQueryChange::Updated { id, data }),
+            any::<ID>().prop_map(|id| LiveQueryChange::Removed { id }),
+            (any::<ID>(), any::<T>())
+                .prop_map(|(id, data)| LiveQueryChange::EnteredResultSet { id, data }),
+            any::<ID>().prop_map(|id| LiveQueryChange::LeftResultSet { id }),
+        ];
+        (any::<u32>(), any::<Option<u64>>(), change)
+            .prop_map(|(query_id, seq, change)| LiveQueryUpdate {
+                query_id,
+                seq,
+                change,
+            })
+            .boxed()
+    }
+}
+
+// a generated value that didn't survive a round trip through a codec
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundTripFailure {
+    pub codec: &'static str,
+    // the smallest value proptest shrank the failure to, debug formatted
+    pub value: String,
+    pub message: String,
+}
+
+impl Display for RoundTripFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} through {}: {}", self.value, self.codec, self.message)
+    }
+}
+
+// encodes `value`, decodes it and encodes it again, failing unless both
+// encodings carry the same message. they're compared as json values, so maps
+// whose entries come out in another order still match
+pub fn round_trip<T, W>(codec: &W, value: &T) -> Result<(), String>
+where
+    T: Serialize + DeserializeOwned,
+    W: WireCodec,
+{
+    let first = codec
+        .encode(value)
+        .map_err(|err| format!("encode failed: {err}"))?;
+    let decoded: T = codec
+        .decode(&first)
+        .map_err(|err| format!("decode failed: {err}"))?;
+    let second = codec
+        .encode(&decoded)
+        .map_err(|err| format!("re-encode failed: {err}"))?;
+    let (first, second): (Value, Value) = match (codec.decode(&first), codec.decode(&second)) {
+        (Ok(first), Ok(second)) => (first, second),
+        (Err(err), _) | (_, Err(err)) => return Err(format!("decode as json failed: {err}")),
+    };
+    match first == second {
+        true => Ok(()),
+        false => Err(format!("{first} came back as {second}")),
+    }
+}
+
+// generates `cases` values of `T` and round-trips each through json,
+// camelCase json and, with their features, messagepack and cbor. the same
+// seed always generates the same values, so a failure replays with it
+pub fn check_round_trips<T>(seed: u64, cases: u32) -> Result<(), RoundTripFailure>
+where
+    T: Arbitrary + Serialize + DeserializeOwned,
+{
+    check::<T, _>(seed, cases, "json", &JsonCodec)?;
+    let camel_case = StyledJsonCodec::new(WireStyle::CamelCase);
+    check::<T, _>(seed, cases, "camel_case_json", &camel_case)?;
+    #[cfg(feature = "msgpack")]
+    check::<T, _>(seed, cases, "msgpack", &MessagePackCodec)?;
+    #[cfg(feature = "cbor")]
+    check::<T, _>(seed, cases, "cbor", &CborCodec)?;
+    Ok(())
+}
+
+fn check<T, W>(seed: u64, cases: u32, codec: &'static str, wire: &W) -> Result<(), RoundTripFailure>
+where
+    T: Arbitrary + Serialize + DeserializeOwned,
+    W: WireCodec,
+{
+    let outcome = runner(seed, cases).run(&any::<T>(), |value| {
+        round_trip(wire, &value).map_err(TestCaseError::fail)
+    });
+    match outcome {
+        Ok(()) => Ok(()),
+        Err(TestError::Fail(reason, value)) => Err(RoundTripFailure {
+            codec,
+            value: format!("{value:?}"),
+            message: reason.message().to_owned(),
+        }),
+        Err(TestError::Abort(reason)) => Err(RoundTripFailure {
+            codec,
+            value: String::new(),
+            message: reason.message().to_owned(),
+        }),
+    }
+}
+
+// a runner that draws its cases from `seed`, and doesn't persist failures
+// to the source tree of whoever calls it
+fn runner(seed: u64, cases: u32) -> TestRunner {
+    TestRunner::new(Config {
+        cases,
+        rng_seed: RngSeed::Fixed(seed),
+        failure_persistence: None,
+        ..Config::default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::arbitrary::{any, Arbitrary};
+    use proptest::strategy::{Strategy, ValueTree};
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use crate::presence::PresenceDelta;
+    use crate::testing::arbitrary::runner;
+    use crate::testing::check_round_trips;
+    use crate::{
+        AuthResult, Command, ConflictError, Ephemeral, ErrorMessage, Event, EventBatch, Hello,
+        HelloAck, JsonPatch, LiveQueryUpdate, MutationResult, QueryResult, Snapshot, SnapshotChunk,
+        WsBody,
+    };
+
+    type TestEvent = Event<u32, String, String, JsonPatch>;
+
+    fn assert_round_trips<T: Arbitrary + Serialize + DeserializeOwned>() {
+        if let Err(failure) = check_round_trips::<T>(0, 200) {
+            panic!("{} {failure}", std::any::type_name::<T>());
+        }
+    }
+
+    #[test]
+    fn protocol_messages_round_trip() {
+        assert_round_trips::<WsBody<TestEvent>>();
+        assert_round_trips::<WsBody<Event<u32, String, String>>>();
+        assert_round_trips::<WsBody<EventBatch<u32, String, String, JsonPatch>>>();
+        assert_round_trips::<WsBody<Command<u32, String, String, JsonPatch>>>();
+        assert_round_trips::<Hello>();
+        assert_round_trips::<HelloAck>();
+        assert_round_trips::<WsBody<ErrorMessage>>();
+        assert_round_trips::<WsBody<AuthResult<String>>>();
+        assert_round_trips::<WsBody<MutationResult<u32, String>>>();
+        assert_round_trips::<WsBody<ConflictError<u32, String>>>();
+        assert_round_trips::<WsBody<QueryResult<u32, String, String>>>();
+        assert_round_trips::<WsBody<Snapshot<u32, String, String>>>();
+        assert_round_trips::<WsBody<SnapshotChunk<u32, String, String>>>();
+        assert_round_trips::<WsBody<LiveQueryUpdate<u32, String>>>();
+        assert_round_trips::<WsBody<PresenceDelta>>();
+        assert_round_trips::<WsBody<Ephemeral<u32, String>>>();
+
+        // the same seed generates the same value
+        let event = |seed| {
+            let tree = any::<TestEvent>().new_tree(&mut runner(seed, 1)).unwrap();
+            serde_json::to_string(&tree.current()).unwrap()
+        };
+        assert_eq!(event(7), event(7));
+        assert_ne!(event(7), event(8));
+    }
+}
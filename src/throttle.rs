@@ -0,0 +1,299 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::timer::within;
+use crate::{Clock, Coalescer, Event, Listener, NoPatch, SystemClock};
+
+// at most `per_second` events a second on average, and up to `burst` at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    per_second: u32,
+    burst: u32,
+}
+
+impl RateLimit {
+    // a burst of one second's worth
+    pub fn per_second(per_second: u32) -> Self {
+        assert!(per_second > 0, "a rate limit must allow some events");
+        Self {
+            per_second,
+            burst: per_second,
+        }
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        assert!(burst > 0, "a rate limit must allow some events");
+        self.burst = burst;
+        self
+    }
+}
+
+// a token bucket
+pub(crate) struct Bucket {
+    tokens: f64,
+    updated: u64,
+}
+
+impl Bucket {
+    // a whole burst to start with
+    pub(crate) fn full(limit: &RateLimit, now: u64) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: u64) {
+        let elapsed = now.saturating_sub(self.updated) as f64;
+        self.tokens = (self.tokens + elapsed * f64::from(limit.per_second) / 1000.0)
+            .min(f64::from(limit.burst));
+        self.updated = now;
+    }
+
+    pub(crate) fn try_take(&mut self, limit: &RateLimit, now: u64) -> bool {
+        self.refill(limit, now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    // how long until there's a token again
+    pub(crate) fn wait(&mut self, limit: &RateLimit, now: u64) -> Duration {
+        self.refill(limit, now);
+        let millis = (1.0 - self.tokens).max(0.0) * 1000.0 / f64::from(limit.per_second);
+        Duration::from_millis(millis.ceil() as u64)
+    }
+}
+
+struct Lane<ID, T: Serialize + TS, C, P: Serialize + TS> {
+    collection: C,
+    // `None` for collections that aren't throttled
+    limit: Option<RateLimit>,
+    bucket: Bucket,
+    held: Coalescer<ID, T, C, P>,
+}
+
+impl<ID, T: Serialize + TS, C, P: Serialize + TS> Lane<ID, T, C, P> {
+    fn try_take(&mut self, now: u64) -> bool {
+        match &self.limit {
+            Some(limit) => self.bucket.try_take(limit, now),
+            None => true,
+        }
+    }
+}
+
+// keeps a connection under a rate limit per collection, e.g. so a mobile
+// client isn't flooded by a firehose. events over the limit are held back,
+// coalesced like a `Coalescer` does (so a record updated ten times while held
+// goes out once, as its latest state), and released as the limit allows.
+// events without a collection are never held
+pub struct Throttle<L, ID, T: Serialize + TS, C, P: Serialize + TS = NoPatch, K = SystemClock> {
+    inner: L,
+    limit: Option<RateLimit>,
+    overrides: Vec<(C, Option<RateLimit>)>,
+    lanes: Vec<Lane<ID, T, C, P>>,
+    clock: K,
+    throttled: u64,
+}
+
+impl<L, ID, T: Serialize + TS, C, P: Serialize + TS> Throttle<L, ID, T, C, P> {
+    pub fn new(inner: L, limit: RateLimit) -> Self {
+        Self::with_clock(inner, limit, SystemClock)
+    }
+}
+
+impl<L, ID, T: Serialize + TS, C, P: Serialize + TS, K> Throttle<L, ID, T, C, P, K> {
+    pub fn with_clock(inner: L, limit: RateLimit, clock: K) -> Self {
+        Self {
+            inner,
+            limit: Some(limit),
+            overrides: Vec::new(),
+            lanes: Vec::new(),
+            clock,
+            throttled: 0,
+        }
+    }
+
+    // `collection` gets `limit` instead of the default one
+    pub fn with_collection_limit(mut self, collection: C, limit: RateLimit) -> Self {
+        self.overrides.push((collection, Some(limit)));
+        self
+    }
+
+    pub fn unthrottled(mut self, collection: C) -> Self {
+        self.overrides.push((collection, None));
+        self
+    }
+
+    // events held back so far
+    pub fn throttled(&self) -> u64 {
+        self.throttled
+    }
+
+    // held events that were replaced or dropped
+    pub fn coalesced(&self) -> u64 {
+        self.lanes.iter().map(|lane| lane.held.collapsed()).sum()
+    }
+
+    // events waiting for their collection's limit
+    pub fn held(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.held.len()).sum()
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L, ID, T, C, P, K> Throttle<L, ID, T, C, P, K>
+where
+    ID: PartialEq,
+    T: Serialize + TS,
+    C: Clone + PartialEq,
+    P: Serialize + TS,
+    K: Clock,
+{
+    // the oldest held event of a collection that may send again
+    fn release(&mut self, now: u64) -> Option<Event<ID, T, C, P>> {
+        self.lanes
+            .iter_mut()
+            .filter(|lane| !lane.held.is_empty())
+            .find_map(|lane| lane.try_take(now).then(|| lane.held.pop())?)
+    }
+
+    // how long until a held event may go
+    fn next_release(&mut self, now: u64) -> Option<Duration> {
+        self.lanes
+            .iter_mut()
+            .filter(|lane| !lane.held.is_empty())
+            .filter_map(|lane| Some(lane.bucket.wait(lane.limit.as_ref()?, now)))
+            .min()
+    }
+
+    fn lane(&mut self, collection: &C, now: u64) -> &mut Lane<ID, T, C, P> {
+        let index = match self
+            .lanes
+            .iter()
+            .position(|lane| &lane.collection == collection)
+        {
+            Some(index) => index,
+            None => {
+                let limit = self
+                    .overrides
+                    .iter()
+                    .find(|(other, _)| other == collection)
+                    .map_or(self.limit, |(_, limit)| *limit);
+                self.lanes.push(Lane {
+                    collection: collection.clone(),
+                    limit,
+                    bucket: Bucket {
+                        tokens: limit.map_or(0.0, |limit| f64::from(limit.burst)),
+                        updated: now,
+                    },
+                    held: Coalescer::new(Duration::ZERO),
+                });
+                self.lanes.len() - 1
+            }
+        };
+        &mut self.lanes[index]
+    }
+}
+
+#[async_trait::async_trait]
+impl<L, ID, T, C, P, K> Listener for Throttle<L, ID, T, C, P, K>
+where
+    L: Listener<Item = Event<ID, T, C, P>> + Send,
+    ID: PartialEq + Send,
+    T: Serialize + TS + Send,
+    C: Clone + PartialEq + Send,
+    P: Serialize + TS + Send,
+    K: Clock + Send,
+{
+    type Error = L::Error;
+    type Item = Event<ID, T, C, P>;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        loop {
+            let now = self.clock.now();
+            if let Some(event) = self.release(now) {
+                return Ok(event);
+            }
+            let event = match self.next_release(now) {
+                None => self.inner.recv().await?,
+                // listen for new events until a held one may go
+                Some(wait) => match within(wait, self.inner.recv()).await {
+                    Some(event) => event?,
+                    None => continue,
+                },
+            };
+
+            let now = self.clock.now();
+            let Some(collection) = event.collection() else {
+                return Ok(event);
+            };
+            let lane = self.lane(&collection.clone(), now);
+            // nothing overtakes events of its collection that are held back
+            if lane.held.is_empty() && lane.try_take(now) {
+                return Ok(event);
+            }
+            lane.held.push(event);
+            self.throttled += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::{BroadcastService, Listener, RateLimit, Service, Syncable, Throttle};
+
+    #[test]
+    fn firehoses_are_throttled_and_coalesced() {
+        let named = |id, name: &str| DoggoRecord {
+            name: name.to_string(),
+            ..doggo(id)
+        };
+        let now = Arc::new(AtomicU64::new(0));
+        let clock = {
+            let now = now.clone();
+            move || now.load(Ordering::Relaxed)
+        };
+        let service = BroadcastService::new();
+        let limit = RateLimit::per_second(1).with_burst(2);
+        let mut throttle =
+            Throttle::with_clock(service.listener(), limit, clock).unthrottled(Collection::Cats);
+
+        for (id, name) in [(1, "a"), (2, "b"), (1, "c"), (1, "d")] {
+            service.publish(named(id, name).to_update_event()).unwrap();
+        }
+        service.publish(named(3, "e").to_delete_event()).unwrap();
+        service
+            .publish(crate::Event::new_delete_event(4, Collection::Cats))
+            .unwrap();
+
+        block_on(async {
+            let name = |event: crate::Event<u32, DoggoRecord, Collection>| {
+                event.data().map(|doggo| doggo.name.clone())
+            };
+            assert_eq!(name(throttle.recv().await.unwrap()).as_deref(), Some("a"));
+            assert_eq!(name(throttle.recv().await.unwrap()).as_deref(), Some("b"));
+            // the dogs are over their limit, the cats aren't limited
+            assert_eq!(throttle.recv().await.unwrap().id(), Some(&4));
+            assert_eq!(throttle.held(), 2);
+            assert_eq!(throttle.coalesced(), 1);
+
+            now.store(1_000, Ordering::Relaxed);
+            assert_eq!(name(throttle.recv().await.unwrap()).as_deref(), Some("d"));
+            now.store(2_000, Ordering::Relaxed);
+            assert_eq!(throttle.recv().await.unwrap().id(), Some(&3));
+            assert_eq!(throttle.throttled(), 3);
+        });
+    }
+}
@@ -0,0 +1,57 @@
+// timers that don't depend on an async runtime: a thread sleeps for the
+// delay and wakes the waiting task
+use std::future::{poll_fn, Future};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+// `None` if `future` isn't ready within `timeout`
+pub(crate) async fn within<F: Future>(timeout: Duration, future: F) -> Option<F::Output> {
+    let mut future = std::pin::pin!(future);
+    let expired = Arc::new(AtomicBool::new(false));
+    let mut started = false;
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        if expired.load(Ordering::Acquire) {
+            return Poll::Ready(None);
+        }
+        if !started {
+            started = true;
+            start_timer(timeout, &expired, cx);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+// waits without blocking the executor, so it works under any runtime
+pub(crate) async fn sleep(delay: Duration) {
+    let expired = Arc::new(AtomicBool::new(false));
+    let mut started = false;
+    poll_fn(|cx| {
+        if expired.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        if !started {
+            started = true;
+            start_timer(delay, &expired, cx);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+// sets `expired` and wakes the task once `delay` has passed
+pub(crate) fn start_timer(delay: Duration, expired: &Arc<AtomicBool>, cx: &Context<'_>) {
+    let expired = expired.clone();
+    let waker = cx.waker().clone();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        expired.store(true, Ordering::Release);
+        waker.wake();
+    });
+}
@@ -0,0 +1,270 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::shutdown::{Drain, DEFAULT_RECONNECT_AFTER};
+use crate::{metrics, Error, GoingAway, Listener, Sequenced, Service, Shutdown};
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+// `BroadcastService` on a `tokio::sync::broadcast` channel, for servers
+// already running on tokio. a listener that falls more than `capacity` events
+// behind gets `Error::Lagged` and continues from the oldest event still in
+// the channel. the channel keeps no history for new listeners, so one from an
+// older seq starts at the next published event and gets `Error::Lagged` for
+// the ones it missed first. once it's shut down, publishes fail and each
+// listener gets `Error::GoingAway` after the events published before
+pub struct TokioBroadcastService<T> {
+    sender: broadcast::Sender<Message<T>>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    drain: Arc<Drain>,
+}
+
+struct State {
+    // the seq of the next publish, held while sending so seqs go out in order
+    next: u64,
+    going_away: Option<GoingAway>,
+    reconnect_after: Duration,
+}
+
+// the shutdown goes through the channel too, so it reaches each listener
+// after everything published before it
+#[derive(Clone)]
+enum Message<T> {
+    Event(T),
+    GoingAway(GoingAway),
+}
+
+pub struct TokioBroadcastListener<T> {
+    receiver: broadcast::Receiver<Message<T>>,
+    // published before it started, reported by its first recv
+    missed: u64,
+    // once it has been told the service is going away
+    going_away: Option<GoingAway>,
+    // until it has been told, for the service's shutdown to wait on
+    drain: Option<Arc<Drain>>,
+}
+
+impl<T: Clone> TokioBroadcastService<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "broadcast capacity must be greater than zero");
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    next: 0,
+                    going_away: None,
+                    reconnect_after: DEFAULT_RECONNECT_AFTER,
+                }),
+                drain: Arc::default(),
+            }),
+        }
+    }
+
+    // how long clients wait before reconnecting once the service shuts down
+    pub fn with_reconnect_after(self, reconnect_after: Duration) -> Self {
+        self.lock().reconnect_after = reconnect_after;
+        self
+    }
+}
+
+impl<T> TokioBroadcastService<T> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: Clone> Default for TokioBroadcastService<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for TokioBroadcastService<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sequenced> Service<T> for TokioBroadcastService<T> {
+    type Listener = TokioBroadcastListener<T>;
+    type Error = Error;
+
+    fn publish(&self, mut event: T) -> Result<(), Self::Error> {
+        let mut state = self.lock();
+        if let Some(going_away) = state.going_away {
+            return Err(Error::GoingAway(going_away));
+        }
+        event.set_seq(state.next);
+        // with no listeners the event just isn't delivered to anyone
+        let _ = self.sender.send(Message::Event(event));
+        state.next += 1;
+        metrics::counter(metrics::EVENTS_PUBLISHED, 1);
+        Ok(())
+    }
+
+    fn listener(&self) -> Self::Listener {
+        self.listener_from(self.head_seq())
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        // subscribed under the lock, so nothing is published in between
+        let state = self.lock();
+        let drain = state.going_away.is_none().then(|| {
+            self.shared.drain.add();
+            self.shared.drain.clone()
+        });
+        TokioBroadcastListener {
+            receiver: self.sender.subscribe(),
+            missed: state.next.saturating_sub(seq),
+            going_away: state.going_away,
+            drain,
+        }
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.lock().next
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        let mut state = self.lock();
+        if state.going_away.is_none() {
+            let going_away = GoingAway::new(state.reconnect_after);
+            state.going_away = Some(going_away);
+            let _ = self.sender.send(Message::GoingAway(going_away));
+        }
+        self.shared.drain.wait(deadline)
+    }
+}
+
+impl<T> TokioBroadcastListener<T> {
+    fn told(&mut self, going_away: GoingAway) -> Error {
+        self.going_away = Some(going_away);
+        if let Some(drain) = self.drain.take() {
+            drain.done();
+        }
+        Error::GoingAway(going_away)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Clone + Send> Listener for TokioBroadcastListener<T> {
+    type Error = Error;
+    type Item = T;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        if let Some(going_away) = self.going_away {
+            return Err(Error::GoingAway(going_away));
+        }
+        if self.missed > 0 {
+            return Err(Error::Lagged(std::mem::take(&mut self.missed)));
+        }
+        match self.receiver.recv().await {
+            Ok(Message::Event(event)) => {
+                metrics::counter(metrics::EVENTS_DELIVERED, 1);
+                Ok(event)
+            }
+            Ok(Message::GoingAway(going_away)) => Err(self.told(going_away)),
+            Err(RecvError::Lagged(missed)) => Err(Error::Lagged(missed)),
+            Err(RecvError::Closed) => Err(Error::Closed),
+        }
+    }
+}
+
+impl<T> Drop for TokioBroadcastListener<T> {
+    fn drop(&mut self) {
+        if let Some(drain) = self.drain.take() {
+            drain.done();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::test::{block_on, Collection};
+    use crate::{Error, Event, Listener, Service, TokioBroadcastService};
+
+    #[test]
+    fn listeners_receive_lag_and_resume() {
+        let delete = |id| Event::<u32, (), Collection>::new_delete_event(id, Collection::Dogs);
+
+        let service = TokioBroadcastService::with_capacity(2);
+        // nobody is listening yet
+        service.publish(delete(0)).unwrap();
+        let mut early = service.listener();
+        service.publish(delete(1)).unwrap();
+        let mut late = service.listener();
+        service.publish(delete(2)).unwrap();
+        service.publish(delete(3)).unwrap();
+        let mut resumed = service.listener_from(2);
+        service.publish(delete(4)).unwrap();
+
+        block_on(async {
+            assert!(matches!(early.recv().await, Err(Error::Lagged(2))));
+            assert_eq!(early.recv().await.unwrap().seq(), Some(3));
+            assert!(matches!(late.recv().await, Err(Error::Lagged(1))));
+            assert_eq!(late.recv().await.unwrap().seq(), Some(3));
+            assert_eq!(late.recv().await.unwrap().seq(), Some(4));
+            // the channel can't replay 2 and 3
+            assert!(matches!(resumed.recv().await, Err(Error::Lagged(2))));
+            assert_eq!(resumed.recv().await.unwrap().seq(), Some(4));
+
+            drop(service);
+            assert_eq!(early.recv().await.unwrap().seq(), Some(4));
+            assert!(matches!(early.recv().await, Err(Error::Closed)));
+        });
+    }
+
+    #[test]
+    fn shutdowns_drain_listeners() {
+        let delete = |id| Event::<u32, (), Collection>::new_delete_event(id, Collection::Dogs);
+        let service =
+            TokioBroadcastService::new().with_reconnect_after(Duration::from_millis(1_500));
+        let mut behind = service.listener();
+        let mut untold = service.listener();
+        drop(service.listener());
+        service.publish(delete(1)).unwrap();
+
+        let shutdown = service.shutdown(Duration::from_millis(20));
+        assert!(matches!(
+            service.publish(delete(2)),
+            Err(Error::GoingAway(_))
+        ));
+        block_on(async {
+            // what was published before comes first
+            assert_eq!(behind.recv().await.unwrap().seq(), Some(0));
+            let Err(Error::GoingAway(going_away)) = behind.recv().await else {
+                panic!("expected the service to be going away");
+            };
+            assert_eq!(going_away.reconnect_after(), Duration::from_millis(1_500));
+            assert!(matches!(behind.recv().await, Err(Error::GoingAway(_))));
+            assert!(matches!(
+                service.listener().recv().await,
+                Err(Error::GoingAway(_))
+            ));
+            assert!(matches!(shutdown.await, Err(Error::Undrained(1))));
+
+            assert_eq!(untold.recv().await.unwrap().seq(), Some(0));
+            assert!(matches!(untold.recv().await, Err(Error::GoingAway(_))));
+            service.shutdown(Duration::from_millis(20)).await.unwrap();
+        });
+    }
+}
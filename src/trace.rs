@@ -0,0 +1,261 @@
+// `tracing` spans along the publish/deliver path, so operators can follow one
+// event from `publish` through routing and serialization to each `recv`.
+// every span carries what was known about the event at that step
+// (collection, id, verb, seq, bytes) and the seq ties them together. they are
+// recorded by whichever `tracing` subscriber is installed, if any
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use ts_rs::TS;
+
+use crate::{Event, Listener, Serialized, Service, Shutdown};
+
+// the span around a publish
+fn publish_span() -> Span {
+    tracing::info_span!(
+        "publish",
+        collection = Empty,
+        id = Empty,
+        verb = Empty,
+        seq = Empty
+    )
+}
+
+// the span around a `recv`, from the wait to the item
+fn recv_span() -> Span {
+    tracing::info_span!(
+        "recv",
+        collection = Empty,
+        id = Empty,
+        verb = Empty,
+        seq = Empty,
+        bytes = Empty
+    )
+}
+
+// what spans record about an event. fields the span doesn't declare are
+// left out
+pub trait Traced {
+    fn trace(&self, span: &Span);
+}
+
+impl<ID, T, C, P> Traced for Event<ID, T, C, P>
+where
+    ID: Serialize,
+    T: Serialize + TS,
+    C: Serialize,
+    P: Serialize + TS,
+{
+    fn trace(&self, span: &Span) {
+        if span.is_disabled() {
+            return;
+        }
+        if let Some(collection) = self.collection() {
+            span.record("collection", field(collection));
+        }
+        if let Some(id) = self.id() {
+            span.record("id", field(id));
+        }
+        span.record("verb", self.verb().name());
+        if let Some(seq) = self.seq() {
+            span.record("seq", seq);
+        }
+    }
+}
+
+impl<T: Traced> Traced for Serialized<T> {
+    fn trace(&self, span: &Span) {
+        self.event().trace(span);
+        if let Some(bytes) = self.encoded_len() {
+            span.record("bytes", bytes);
+        }
+    }
+}
+
+// strings without their quotes
+fn field(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(value)) => value,
+        Ok(value) => value.to_string(),
+        Err(_) => "?".to_owned(),
+    }
+}
+
+// a "publish" span around every publish of the inner service, and a "recv"
+// span around every item its listeners yield
+pub struct TracedService<S> {
+    inner: S,
+}
+
+impl<S> TracedService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<T: Traced, S: Service<T>> Service<T> for TracedService<S>
+where
+    TracedListener<S::Listener>: Listener<Item = T>,
+{
+    type Listener = TracedListener<S::Listener>;
+    type Error = S::Error;
+
+    // the inner service records the seq it stamps
+    fn publish(&self, event: T) -> Result<(), Self::Error> {
+        let span = publish_span();
+        event.trace(&span);
+        span.in_scope(|| self.inner.publish(event))
+    }
+
+    fn listener(&self) -> Self::Listener {
+        TracedListener::new(self.inner.listener())
+    }
+
+    fn listener_from(&self, seq: u64) -> Self::Listener {
+        TracedListener::new(self.inner.listener_from(seq))
+    }
+
+    fn head_seq(&self) -> u64 {
+        self.inner.head_seq()
+    }
+
+    fn shutdown(&self, deadline: Duration) -> Shutdown {
+        self.inner.shutdown(deadline)
+    }
+}
+
+pub struct TracedListener<L> {
+    inner: L,
+}
+
+impl<L> TracedListener<L> {
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<L> Listener for TracedListener<L>
+where
+    L: Listener + Send,
+    L::Item: Traced + Send,
+{
+    type Error = L::Error;
+    type Item = L::Item;
+
+    // the span includes the wait for the item
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        let span = recv_span();
+        let item = self.inner.recv().instrument(span.clone()).await?;
+        item.trace(&span);
+        Ok(item)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::{Layer, Registry};
+
+    use crate::test::{block_on, doggo, Collection, DoggoRecord};
+    use crate::trace::TracedService;
+    use crate::{
+        BroadcastService, Event, Listener, PublishSerialized, Service, Subscribe,
+        SubscriptionManager, Syncable,
+    };
+
+    // a span's recorded fields as `name=value`, in the order recorded
+    #[derive(Default)]
+    struct Fields(Vec<String>);
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push(format!("{}={value}", field.name()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push(format!("{}={value:?}", field.name()));
+        }
+    }
+
+    // every closed span as a line of its name and fields
+    struct Lines(Arc<Mutex<Vec<String>>>);
+
+    impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Lines {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            values.record(span.extensions_mut().get_mut::<Fields>().unwrap());
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            let span = ctx.span(&id).unwrap();
+            let extensions = span.extensions();
+            let fields = &extensions.get::<Fields>().unwrap().0;
+            let line = format!("{} {}", span.name(), fields.join(" "));
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    #[test]
+    fn one_event_can_be_followed_end_to_end() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(Lines(lines.clone()));
+        let doggo = doggo(1);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let service = TracedService::new(BroadcastService::new());
+            let mut listener = service.listener();
+            service.publish_serialized(doggo.to_upsert_event()).unwrap();
+            block_on(async {
+                let event = listener.recv().await.unwrap();
+                event.json().unwrap();
+                // the second listener to serialize it gets the bytes for free
+                let mut listener = service.listener_from(0);
+                listener.recv().await.unwrap();
+            });
+
+            let manager = SubscriptionManager::<Event<u32, DoggoRecord, Collection>>::new();
+            let connection = manager.connect();
+            manager.handle_subscribe(
+                connection.connection(),
+                &Subscribe {
+                    collections: vec![Collection::Dogs],
+                    patterns: Vec::new(),
+                    from_seq: None,
+                },
+            );
+            manager.publish(Event::new_delete_event(1, Collection::Dogs));
+        });
+
+        insta::assert_snapshot!(lines.lock().unwrap().join("\n"), @r###"
+        publish collection=Dogs id=1 verb=upsert seq=0
+        recv collection=Dogs id=1 verb=upsert seq=0
+        serialize bytes=157
+        recv collection=Dogs id=1 verb=upsert seq=0 bytes=157
+        route seq=0 delivered=1 dropped=0
+        "###);
+    }
+}
@@ -0,0 +1,477 @@
+// generates a typescript client for the collections an application serves,
+// on top of the bindings ts-rs exports: it connects, subscribes, hands every
+// event to the handlers registered for its collection with the record types
+// filled in, answers pings and reconnects, resuming after the last seq it saw.
+// the output imports the bindings it needs from its own directory, so it is
+// meant to be written next to them, e.g. as `bindings/client.ts`.
+//
+// ts-rs drops doc comments, so types deriving `TsDocs` can be exported with
+// `export_documented` instead, which puts them back as jsdoc
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::codec::WireStyle;
+use crate::{Appendable, Error, Patchable, Syncable};
+
+#[derive(Default)]
+pub struct ClientGenerator {
+    collections: Vec<Registration>,
+    // by the name of the documented type
+    docs: BTreeMap<String, TypeDocs>,
+    style: WireStyle,
+    // why a registration was rejected
+    failed: Option<String>,
+}
+
+struct Registration {
+    name: String,
+    id: String,
+    record: String,
+    patch: String,
+}
+
+impl ClientGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a collection of `R`s that are only ever inserted
+    pub fn appendable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Appendable,
+        R::Collection: Serialize,
+    {
+        self.register::<(), R, ()>(collection, "never")
+    }
+
+    pub fn syncable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Syncable,
+        R::Collection: Serialize,
+        R::Id: TS,
+    {
+        self.register::<R::Id, R, ()>(collection, "never")
+    }
+
+    pub fn patchable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Patchable,
+        R::Collection: Serialize,
+        R::Id: TS,
+    {
+        self.register::<R::Id, R, R::Patch>(collection, "")
+    }
+
+    // documents the collections of `R`s in the client with `R`'s docs
+    pub fn with_docs<R: TS + TsDocs>(mut self) -> Self {
+        self.docs.insert(R::name(), R::docs());
+        self
+    }
+
+    // for servers encoding with a `StyledJsonCodec`; the bindings imported
+    // need the same `restyle_bindings`
+    pub fn with_wire_style(mut self, style: WireStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    // `patch` overrides the patch type, unless it is empty
+    fn register<ID: TS, R: TS, P: TS>(mut self, collection: impl Serialize, patch: &str) -> Self {
+        let name = match serde_json::to_value(collection) {
+            Ok(Value::String(name)) => name,
+            Ok(other) => {
+                let reason = format!("{other} does not serialize to a string");
+                self.failed.get_or_insert(reason);
+                return self;
+            }
+            Err(err) => {
+                self.failed.get_or_insert(err.to_string());
+                return self;
+            }
+        };
+        let patch = match patch {
+            "" => type_name::<P>(),
+            patch => patch.to_owned(),
+        };
+        self.collections.push(Registration {
+            name,
+            id: type_name::<ID>(),
+            record: type_name::<R>(),
+            patch,
+        });
+        self
+    }
+
+    pub fn generate(&self) -> Result<String, Error> {
+        if let Some(reason) = &self.failed {
+            return Err(Error::InvalidCollection(reason.clone()));
+        }
+        let mut names = BTreeSet::new();
+        if let Some(duplicate) = self
+            .collections
+            .iter()
+            .find(|registration| !names.insert(&registration.name))
+        {
+            return Err(Error::InvalidCollection(format!(
+                "{} is registered twice",
+                duplicate.name
+            )));
+        }
+
+        let mut out = String::from(HEADER);
+        let mut imports = BTreeSet::from(
+            ["Command", "Event", "EventBatch", "GoingAway", "Ping"].map(String::from),
+        );
+        imports.extend(self.collections.iter().flat_map(|registration| {
+            [&registration.id, &registration.record, &registration.patch]
+                .into_iter()
+                .filter(|name| is_identifier(name))
+                .cloned()
+        }));
+        for import in imports {
+            writeln!(out, "import type {{ {import} }} from \"./{import}\";").unwrap();
+        }
+
+        out.push_str("\nexport type Collections = {\n");
+        for Registration {
+            name,
+            id,
+            record,
+            patch,
+        } in &self.collections
+        {
+            let name = Value::from(name.as_str());
+            if let Some(doc) = self.docs.get(record).and_then(|docs| docs.doc) {
+                writeln!(out, "  {}", jsdoc(doc, "  ")).unwrap();
+            }
+            writeln!(
+                out,
+                "  {name}: {{ id: {id}, record: {record}, patch: {patch} }},"
+            )
+            .unwrap();
+        }
+        out.push_str("};\n\nexport const COLLECTIONS: Array<CollectionName> = [");
+        let names: Vec<_> = self
+            .collections
+            .iter()
+            .map(|registration| Value::from(registration.name.as_str()).to_string())
+            .collect();
+        out.push_str(&names.join(", "));
+        out.push_str("];\n");
+        out.push_str(CLIENT);
+        Ok(self.style.restyle_ts(&out))
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        std::fs::write(path, self.generate()?).map_err(|err| Error::Transport(err.to_string()))
+    }
+}
+
+// what `#[derive(TsDocs)]` collects from a type's doc comments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeDocs {
+    pub doc: Option<&'static str>,
+    // by the name the field or variant serializes under
+    pub fields: &'static [(&'static str, &'static str)],
+    pub variants: &'static [(&'static str, &'static str)],
+}
+
+pub trait TsDocs {
+    fn docs() -> TypeDocs;
+}
+
+// `T`'s binding as ts-rs exports it, with its docs as jsdoc
+pub fn documented_binding<T: TS + TsDocs + 'static>() -> Result<String, Error> {
+    let binding = T::export_to_string().map_err(|err| Error::Transport(err.to_string()))?;
+    Ok(document(&binding, &T::docs()))
+}
+
+// like `T::export()`, writing to the same file
+pub fn export_documented<T: TS + TsDocs + 'static>() -> Result<(), Error> {
+    let path =
+        T::EXPORT_TO.ok_or_else(|| Error::Transport(format!("{} is not exported", T::name())))?;
+    let binding = documented_binding::<T>()?;
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir).map_err(|err| Error::Transport(err.to_string()))?;
+    }
+    std::fs::write(path, binding).map_err(|err| Error::Transport(err.to_string()))
+}
+
+// puts `docs` into a binding: the type's doc, followed by a list of the
+// documented variants, above its declaration and each field's doc in front
+// of the field
+pub fn document(binding: &str, docs: &TypeDocs) -> String {
+    let Some(start) = binding
+        .match_indices("export ")
+        .map(|(start, _)| start)
+        .find(|&start| start == 0 || binding[..start].ends_with('\n'))
+    else {
+        return binding.to_owned();
+    };
+    let mut doc = docs.doc.unwrap_or_default().to_owned();
+    if !docs.variants.is_empty() {
+        if !doc.is_empty() {
+            doc.push_str("\n\n");
+        }
+        let variants: Vec<_> = docs
+            .variants
+            .iter()
+            .map(|(name, variant)| format!("- `{name}`: {}", variant.replace('\n', "\n  ")))
+            .collect();
+        doc.push_str(&variants.join("\n"));
+    }
+
+    let mut out = binding[..start].to_owned();
+    if !doc.is_empty() {
+        out.push_str(&jsdoc(&doc, ""));
+        out.push('\n');
+    }
+    let declaration = &binding[start..];
+    if docs.fields.is_empty() || !declaration.starts_with("export interface ") {
+        out.push_str(declaration);
+        return out;
+    }
+    // fields start after the opening brace or a comma, at the outermost
+    // level of the interface
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut field_next = false;
+    for (index, c) in declaration.char_indices() {
+        if field_next && !c.is_whitespace() {
+            field_next = false;
+            let name = declaration[index..]
+                .split([':', '?'])
+                .next()
+                .unwrap_or_default()
+                .trim_matches('"');
+            if let Some((_, doc)) = docs.fields.iter().find(|(field, _)| *field == name) {
+                out.push_str(&jsdoc(doc, ""));
+                out.push(' ');
+            }
+        }
+        out.push(c);
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '{' | '<' | '(' | '[' => {
+                depth += 1;
+                field_next = depth == 1;
+            }
+            '}' | '>' | ')' | ']' => depth -= 1,
+            ',' => field_next = depth == 1,
+            _ => {}
+        }
+    }
+    out
+}
+
+// a jsdoc comment, continuing lines with `indent`
+fn jsdoc(doc: &str, indent: &str) -> String {
+    let doc = doc.replace("*/", "*\\/");
+    if !doc.contains('\n') {
+        return format!("/** {doc} */");
+    }
+    let mut comment = String::from("/**");
+    for line in doc.lines() {
+        comment.push('\n');
+        comment.push_str(indent);
+        comment.push_str(" *");
+        if !line.is_empty() {
+            comment.push(' ');
+            comment.push_str(line);
+        }
+    }
+    comment.push('\n');
+    comment.push_str(indent);
+    comment.push_str(" */");
+    comment
+}
+
+// rewrites every binding in `dir` for servers encoding with a
+// `StyledJsonCodec`, returning how many changed
+pub fn restyle_bindings(dir: impl AsRef<Path>, style: WireStyle) -> Result<usize, Error> {
+    let io = |err: std::io::Error| Error::Transport(err.to_string());
+    let mut restyled = 0;
+    for entry in std::fs::read_dir(dir).map_err(io)? {
+        let path = entry.map_err(io)?.path();
+        if path.extension().is_none_or(|extension| extension != "ts") {
+            continue;
+        }
+        let ts = std::fs::read_to_string(&path).map_err(io)?;
+        let styled = style.restyle_ts(&ts);
+        if styled != ts {
+            std::fs::write(&path, styled).map_err(io)?;
+            restyled += 1;
+        }
+    }
+    Ok(restyled)
+}
+
+// the name of an exported binding, which the client imports, or the inline
+// type of anything else
+fn type_name<T: TS>() -> String {
+    match T::EXPORT_TO {
+        Some(_) => T::name(),
+        None => T::inline(),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(char::is_alphabetic)
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !matches!(name, "never" | "null" | "number" | "string" | "boolean")
+}
+
+const HEADER: &str = "// This file was generated by rsp::tsgen. Do not edit this file manually.\n";
+
+const CLIENT: &str = r#"
+export type CollectionName = keyof Collections;
+
+export type CollectionEvent<K extends CollectionName> = Event<Collections[K]["id"], Collections[K]["record"], K, Collections[K]["patch"]>;
+
+type AnyEvent = { [K in CollectionName]: CollectionEvent<K> }[CollectionName];
+
+type Handler = (event: AnyEvent) => void;
+
+type ServerMessage = AnyEvent | EventBatch<unknown, unknown, CollectionName, unknown> | Ping | GoingAway;
+
+export interface ClientOptions {
+  url: string,
+  // defaults to every collection
+  collections?: Array<CollectionName>,
+  // milliseconds to wait before reconnecting, defaults to 1000
+  reconnectDelay?: number,
+}
+
+export class RspClient {
+  private socket: WebSocket | null = null;
+  private nextSeq: number | undefined = undefined;
+  private closed = false;
+  // set by a server going away, in place of the reconnect delay
+  private reconnectAfter: number | undefined = undefined;
+  private handlers = new Map<CollectionName, Set<Handler>>();
+
+  constructor(private readonly options: ClientOptions) {}
+
+  // calls `handler` with every event of `collection` until the returned
+  // function is called
+  on<K extends CollectionName>(collection: K, handler: (event: CollectionEvent<K>) => void): () => void {
+    const handlers = this.handlers.get(collection) ?? new Set<Handler>();
+    this.handlers.set(collection, handlers);
+    handlers.add(handler as Handler);
+    return () => {
+      handlers.delete(handler as Handler);
+    };
+  }
+
+  connect(): void {
+    this.closed = false;
+    const socket = new WebSocket(this.options.url);
+    this.socket = socket;
+    socket.onopen = () => {
+      const collections = this.options.collections ?? COLLECTIONS;
+      this.send({ type: "subscribe", payload: { collections, from_seq: this.nextSeq } });
+    };
+    socket.onmessage = (message) => this.receive(JSON.parse(message.data).data);
+    socket.onclose = () => {
+      if (this.socket === socket) {
+        this.socket = null;
+      }
+      if (!this.closed) {
+        const delay = this.reconnectAfter ?? this.options.reconnectDelay ?? 1000;
+        this.reconnectAfter = undefined;
+        setTimeout(() => this.connect(), delay);
+      }
+    };
+  }
+
+  close(): void {
+    this.closed = true;
+    this.socket?.close();
+  }
+
+  send(command: Command<unknown, unknown, CollectionName, unknown>): void {
+    this.socket?.send(JSON.stringify({ data: command }));
+  }
+
+  private receive(message: ServerMessage): void {
+    if ("verb" in message) {
+      this.dispatch(message);
+    } else if ("events" in message) {
+      message.events.forEach((event) => this.dispatch(event as AnyEvent));
+      this.nextSeq = message.seq + 1;
+    } else if ("sent_at" in message) {
+      this.send({ type: "pong", payload: { ping_sent_at: message.sent_at, sent_at: Date.now() } });
+    } else if ("reconnect_after" in message) {
+      this.reconnectAfter = message.reconnect_after;
+    }
+  }
+
+  private dispatch(event: AnyEvent): void {
+    if (event.seq !== undefined) {
+      this.nextSeq = event.seq + 1;
+    }
+    const verb = event.verb;
+    if (verb.type === "txn_begin" || verb.type === "txn_commit" || verb.type === "txn_abort") {
+      return;
+    }
+    this.handlers.get(verb.payload.location.collection)?.forEach((handler) => handler(event));
+  }
+}
+"#;
+
+#[cfg(test)]
+mod test {
+    use crate::codec::WireStyle;
+    use crate::test::{Collection, DoggoRecord};
+    use crate::tsgen::ClientGenerator;
+    use crate::Error;
+
+    #[test]
+    fn generates_a_typed_client() {
+        let client = ClientGenerator::new()
+            .patchable::<DoggoRecord>(Collection::Dogs)
+            .appendable::<DoggoRecord>(Collection::Cats)
+            .generate()
+            .unwrap();
+        let lines: Vec<_> = client.lines().take(15).collect();
+        insta::assert_debug_snapshot!(lines, @r###"
+        [
+            "// This file was generated by rsp::tsgen. Do not edit this file manually.",
+            "import type { Command } from \"./Command\";",
+            "import type { DoggoPatch } from \"./DoggoPatch\";",
+            "import type { DoggoRecord } from \"./DoggoRecord\";",
+            "import type { Event } from \"./Event\";",
+            "import type { EventBatch } from \"./EventBatch\";",
+            "import type { GoingAway } from \"./GoingAway\";",
+            "import type { Ping } from \"./Ping\";",
+            "",
+            "export type Collections = {",
+            "  \"Dogs\": { id: number, record: DoggoRecord, patch: DoggoPatch },",
+            "  \"Cats\": { id: null, record: DoggoRecord, patch: never },",
+            "};",
+            "",
+            "export const COLLECTIONS: Array<CollectionName> = [\"Dogs\", \"Cats\"];",
+        ]
+        "###);
+
+        let twice = ClientGenerator::new()
+            .syncable::<DoggoRecord>(Collection::Dogs)
+            .syncable::<DoggoRecord>(Collection::Dogs)
+            .generate();
+        assert!(matches!(twice, Err(Error::InvalidCollection(_))));
+
+        let camel = ClientGenerator::new()
+            .syncable::<DoggoRecord>(Collection::Dogs)
+            .with_wire_style(WireStyle::CamelCase)
+            .generate()
+            .unwrap();
+        assert!(camel.contains("payload: { collections, fromSeq: this.nextSeq }"));
+        assert!(camel.contains(r#"verb.type === "txnBegin""#));
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{Event, EventBatch, EventVerb};
+
+#[derive(Debug, Default)]
+pub struct TxnBuilder {
+    txn_id: Option<u32>,
+}
+
+impl TxnBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn txn_id(mut self, txn_id: u32) -> Self {
+        self.txn_id = Some(txn_id);
+        self
+    }
+
+    // without a txn id, it gets a random one
+    pub fn build(self) -> Txn {
+        let txn_id = self.txn_id.unwrap_or_else(random_txn_id);
+        Txn { txn_id }
+    }
+}
+
+// random rather than counted, so transactions begun by other processes, other
+// services or before a restart don't share ids. ids only need to differ
+// between the transactions open at once
+fn random_txn_id() -> u32 {
+    let mut bytes = [0; 4];
+    getrandom::getrandom(&mut bytes).expect("the os has a source of randomness");
+    u32::from_le_bytes(bytes)
+}
+
+// an open transaction. clients buffer every event stamped with its id between
+// `TxnBegin` and `TxnCommit`, and discard them on `TxnAbort`
+#[derive(Debug)]
+pub struct Txn {
+    txn_id: u32,
+}
+
+impl Txn {
+    pub fn builder() -> TxnBuilder {
+        TxnBuilder::new()
+    }
+
+    pub fn begin() -> Self {
+        TxnBuilder::new().build()
+    }
+
+    pub fn id(&self) -> u32 {
+        self.txn_id
+    }
+
+    pub fn begin_event<ID, T: Serialize + TS, C, P: Serialize + TS>(&self) -> Event<ID, T, C, P> {
+        Event::new(EventVerb::TxnBegin(self.txn_id))
+    }
+
+    pub fn stamp<ID, T: Serialize + TS, C, P: Serialize + TS>(
+        &self,
+        mut event: Event<ID, T, C, P>,
+    ) -> Event<ID, T, C, P> {
+        if let Some(location) = event.verb.location_mut() {
+            location.txn_id = Some(self.txn_id);
+        }
+        event
+    }
+
+    pub fn commit_event<ID, T: Serialize + TS, C, P: Serialize + TS>(self) -> Event<ID, T, C, P> {
+        Event::new(EventVerb::TxnCommit(self.txn_id))
+    }
+
+    pub fn abort_event<ID, T: Serialize + TS, C, P: Serialize + TS>(self) -> Event<ID, T, C, P> {
+        Event::new(EventVerb::TxnAbort(self.txn_id))
+    }
+
+    // the whole transaction in a single frame: begin, the stamped events, commit
+    pub fn commit_batch<ID, T: Serialize + TS, C, P: Serialize + TS>(
+        self,
+        seq: u64,
+        events: impl IntoIterator<Item = Event<ID, T, C, P>>,
+    ) -> EventBatch<ID, T, C, P> {
+        let mut batch = EventBatch::new(seq).with_txn_id(self.txn_id);
+        batch.push(self.begin_event());
+        batch.extend(events.into_iter().map(|event| self.stamp(event)));
+        batch.push(self.commit_event());
+        batch
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::doggo;
+    use crate::{Syncable, Txn};
+
+    #[test]
+    fn txn_stamps_events() {
+        let doggo = doggo(1);
+
+        let txn = Txn::builder().txn_id(42).build();
+        let json = txn
+            .commit_batch(1, [doggo.to_update_event()])
+            .into_ws_body()
+            .try_json()
+            .unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"seq":1,"txn_id":42,"events":[{"verb":{"type":"txn_begin","payload":42}},{"verb":{"type":"update","payload":{"location":{"id":1,"txn_id":42,"collection":"Dogs"},"data":{"id":1,"name":"Barky","breed":"Poodle"}}}},{"verb":{"type":"txn_commit","payload":42}}]}}"###);
+
+        let first = Txn::begin();
+        let second = Txn::begin();
+        assert_ne!(first.id(), second.id());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Error, Event};
+
+// the version of a payload's serialized shape. bump it, and register an
+// upcaster from the old version, whenever a change to the struct would stop
+// persisted payloads from decoding
+pub trait SchemaVersion {
+    const SCHEMA_VERSION: u32;
+}
+
+type Upcaster = Box<dyn Fn(Value) -> Result<Value, Error> + Send + Sync>;
+
+// migrates payloads written at older schema versions to the current `T` on
+// read, one version at a time, so a long-lived event log survives the struct
+// evolving. payloads written before versioning count as version 1
+pub struct Upcasters<T> {
+    steps: HashMap<u32, Upcaster>,
+    marker: PhantomData<T>,
+}
+
+impl<T: SchemaVersion> Upcasters<T> {
+    pub fn new() -> Self {
+        Self {
+            steps: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+
+    // migrates a payload written at `from` to `from + 1`
+    pub fn with_upcaster(
+        mut self,
+        from: u32,
+        upcaster: impl Fn(Value) -> Result<Value, Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.steps.insert(from, Box::new(upcaster));
+        self
+    }
+
+    pub fn upcast(&self, version: u32, mut payload: Value) -> Result<Value, Error> {
+        if version > T::SCHEMA_VERSION {
+            return Err(self.unsupported(version));
+        }
+        for from in version..T::SCHEMA_VERSION {
+            let upcaster = self
+                .steps
+                .get(&from)
+                .ok_or_else(|| self.unsupported(version))?;
+            payload = upcaster(payload)?;
+        }
+        Ok(payload)
+    }
+
+    pub fn decode(&self, version: u32, payload: Value) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_value(self.upcast(version, payload)?).map_err(Error::Decode)
+    }
+
+    // decodes a persisted event, upcasting its record first. patches aren't
+    // touched, as they are written against a `P` of their own
+    pub fn decode_event<ID, C, P>(&self, json: &str) -> Result<Event<ID, T, C, P>, Error>
+    where
+        ID: DeserializeOwned,
+        T: DeserializeOwned + Serialize + TS,
+        C: DeserializeOwned,
+        P: DeserializeOwned + Serialize + TS,
+    {
+        let mut event: Value = serde_json::from_str(json).map_err(Error::Decode)?;
+        let version = event
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .map_or(1, |version| version as u32);
+        let is_patch = event.pointer("/verb/type").and_then(Value::as_str) == Some("patch");
+        if let Some(data) = event
+            .pointer_mut("/verb/payload/data")
+            .filter(|_| !is_patch)
+        {
+            *data = self.upcast(version, data.take())?;
+        }
+        let event: Event<ID, T, C, P> = serde_json::from_value(event).map_err(Error::Decode)?;
+        Ok(event.with_schema_version(T::SCHEMA_VERSION))
+    }
+
+    fn unsupported(&self, found: u32) -> Error {
+        Error::SchemaVersion {
+            found,
+            current: T::SCHEMA_VERSION,
+        }
+    }
+}
+
+impl<T: SchemaVersion> Default for Upcasters<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+    use ts_rs::TS;
+
+    use crate::test::Collection;
+    use crate::{Error, Event, SchemaVersion, Upcasters};
+
+    // v1 had `name`, v2 split it into `first` and `last`, v3 added `good`
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+    struct Doggo {
+        first: String,
+        last: String,
+        good: bool,
+    }
+
+    impl SchemaVersion for Doggo {
+        const SCHEMA_VERSION: u32 = 3;
+    }
+
+    #[test]
+    fn old_payloads_are_upcast_on_read() {
+        let upcasters = Upcasters::<Doggo>::new()
+            .with_upcaster(1, |mut payload: Value| {
+                let name = payload["name"].take();
+                let (first, last) = name
+                    .as_str()
+                    .unwrap_or("")
+                    .split_once(' ')
+                    .unwrap_or_default();
+                Ok(json!({ "first": first, "last": last }))
+            })
+            .with_upcaster(2, |mut payload: Value| {
+                payload["good"] = Value::Bool(true);
+                Ok(payload)
+            });
+
+        // written before the struct was versioned
+        let v1 = r#"{"seq":4,"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"name":"Barky McBark"}}}}"#;
+        let event: Event<u32, Doggo, Collection> = upcasters.decode_event(v1).unwrap();
+        assert_eq!(
+            event.data(),
+            Some(&Doggo {
+                first: "Barky".to_owned(),
+                last: "McBark".to_owned(),
+                good: true,
+            })
+        );
+        assert_eq!((event.seq(), event.schema_version()), (Some(4), Some(3)));
+
+        // already current, so read back as it was written
+        let json = serde_json::to_string(&event.clone().versioned()).unwrap();
+        assert!(json.contains(r#""schema_version":3"#));
+        let reread: Event<u32, Doggo, Collection> = upcasters.decode_event(&json).unwrap();
+        assert_eq!(reread.data(), event.data());
+
+        assert!(matches!(
+            upcasters.decode(4, json!({})),
+            Err(Error::SchemaVersion {
+                found: 4,
+                current: 3
+            })
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::middleware::{Middleware, Outcome};
+use crate::{Event, EventVerb, Mutate, MutationResult, Rejection};
+
+// implemented by records that have rules serde can't express, e.g. a name
+// that mustn't be empty. inserts, updates and upserts of them are checked
+// before they're accepted; patches and deletes carry no record to check
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+// one field that broke a rule. `field` is a path like `owner.name` or
+// `tags.0`, `code` a stable name for the rule clients can match on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+// everything wrong with a record, so a client can mark every field at once
+// instead of finding them one round trip at a time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[ts(export)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_error(
+        mut self,
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.push(field, code, message);
+        self
+    }
+
+    pub fn push(
+        &mut self,
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            code: code.into(),
+            message: message.into(),
+        });
+    }
+
+    // adds the errors of a nested value, their fields under `field`
+    pub fn nest(&mut self, field: &str, nested: ValidationErrors) {
+        self.errors
+            .extend(nested.errors.into_iter().map(|error| FieldError {
+                field: format!("{field}.{}", error.field),
+                ..error
+            }));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    // `Ok` if nothing was wrong, for the end of a `validate`
+    pub fn into_result(self) -> Result<(), Self> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl<ID, T, C, P> Validate for EventVerb<ID, T, C, P>
+where
+    T: Validate + Serialize + TS,
+    P: Serialize + TS,
+{
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            EventVerb::Insert(resource) => resource.data.validate(),
+            EventVerb::Update(resource) | EventVerb::Upsert(resource) => resource.data.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<ID, T, C, P> Validate for Event<ID, T, C, P>
+where
+    T: Validate + Serialize + TS,
+    P: Serialize + TS,
+{
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        self.verb.validate()
+    }
+}
+
+impl<ID, T, C, P> Mutate<ID, T, C, P>
+where
+    T: Validate + Serialize + TS,
+    P: Serialize + TS,
+{
+    // the rejection to answer with if the write is invalid, before it's
+    // published
+    pub fn check(&self) -> Result<(), MutationResult<ID, C>> {
+        self.event
+            .validate()
+            .map_err(|errors| self.reject(Rejection::Validation(errors)))
+    }
+}
+
+// middleware that refuses events whose record is invalid, failing the
+// publish with `Error::Validation`:
+//
+//     let service = BroadcastService::new().with_middleware(Validated);
+pub struct Validated;
+
+impl<T: Validate> Middleware<T> for Validated {
+    fn on_publish(&self, event: T) -> Outcome<T> {
+        match event.validate() {
+            Ok(()) => Outcome::Continue(event),
+            Err(errors) => Outcome::Invalid(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{doggo, Collection, DoggoRecord};
+    use crate::{
+        BroadcastService, Error, Event, Mutate, Service, Syncable, Validate, Validated,
+        ValidationErrors,
+    };
+
+    impl Validate for DoggoRecord {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if self.name.is_empty() {
+                errors.push("name", "required", "a doggo needs a name");
+            }
+            if self.breed.len() > 8 {
+                errors.push("breed", "too_long", "at most 8 characters");
+            }
+            errors.into_result()
+        }
+    }
+
+    #[test]
+    fn invalid_records_are_refused() {
+        let named = |name: &str, breed: &str| DoggoRecord {
+            name: name.to_owned(),
+            breed: breed.to_owned(),
+            ..doggo(1)
+        };
+        let request = Mutate::<u32, DoggoRecord, Collection>::new(
+            7,
+            named("", "Labradoodle").to_upsert_event(),
+        );
+        let result = request.check().unwrap_err();
+        let json = result.into_ws_body().try_json().unwrap();
+        insta::assert_snapshot!(json, @r###"{"data":{"request_id":7,"status":{"type":"rejected","payload":{"type":"validation","payload":{"errors":[{"field":"name","code":"required","message":"a doggo needs a name"},{"field":"breed","code":"too_long","message":"at most 8 characters"}]}}}}}"###);
+        let delete = Mutate::<u32, DoggoRecord, Collection>::new(
+            8,
+            Event::<u32, DoggoRecord, Collection>::new_delete_event(1, Collection::Dogs),
+        );
+        assert!(delete.check().is_ok());
+
+        let service = BroadcastService::new().with_middleware(Validated);
+        service
+            .publish(named("Barky", "Poodle").to_upsert_event())
+            .unwrap();
+        let Err(Error::Validation(errors)) = service.publish(named("", "Poodle").to_upsert_event())
+        else {
+            panic!("an invalid doggo was published");
+        };
+        assert!(matches!(
+            errors.errors(),
+            [error] if error.field == "name" && error.code == "required"
+        ));
+    }
+}
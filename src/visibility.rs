@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use crate::Listener;
+
+// decides which events a listener may see, given what the server knows about
+// the connection behind it (its tenant, user, claims and so on). servers
+// keep one policy and hand it to every listener with that listener's context,
+// so one tenant's records never reach another tenant's stream
+pub trait Visibility<T, X>: Send + Sync {
+    fn visible(&self, event: &T, context: &X) -> bool;
+}
+
+impl<T, X, F: Fn(&T, &X) -> bool + Send + Sync> Visibility<T, X> for F {
+    fn visible(&self, event: &T, context: &X) -> bool {
+        self(event, context)
+    }
+}
+
+impl<T, X, V: Visibility<T, X> + ?Sized> Visibility<T, X> for Arc<V> {
+    fn visible(&self, event: &T, context: &X) -> bool {
+        (**self).visible(event, context)
+    }
+}
+
+// only yields the items of the inner listener that `visibility` lets
+// `context` see
+pub struct VisibleListener<L, V, X> {
+    inner: L,
+    visibility: V,
+    context: X,
+}
+
+impl<L, V, X> VisibleListener<L, V, X> {
+    pub fn new(inner: L, visibility: V, context: X) -> Self {
+        Self {
+            inner,
+            visibility,
+            context,
+        }
+    }
+
+    pub fn context(&self) -> &X {
+        &self.context
+    }
+
+    // e.g. after the connection re-authenticated as someone else
+    pub fn set_context(&mut self, context: X) {
+        self.context = context;
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<L, V, X> Listener for VisibleListener<L, V, X>
+where
+    L: Listener + Send,
+    L::Item: Send,
+    V: Visibility<L::Item, X>,
+    X: Send + Sync,
+{
+    type Error = L::Error;
+    type Item = L::Item;
+
+    async fn recv(&mut self) -> Result<Self::Item, Self::Error> {
+        loop {
+            let item = self.inner.recv().await?;
+            if self.visibility.visible(&item, &self.context) {
+                return Ok(item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::test::{block_on, Collection, DoggoRecord};
+    use crate::{BroadcastService, Event, EventMeta, Listener, Service, Visibility};
+
+    type DoggoEvent = Event<u32, DoggoRecord, Collection>;
+
+    #[test]
+    fn tenants_only_see_their_records() {
+        let tenant_of = |event: &DoggoEvent| {
+            event
+                .meta()
+                .and_then(|meta| meta.extra().get("tenant"))
+                .and_then(|tenant| tenant.as_str().map(str::to_owned))
+        };
+        let visibility: Arc<dyn Visibility<DoggoEvent, String>> =
+            Arc::new(move |event: &DoggoEvent, tenant: &String| {
+                tenant_of(event).as_ref() == Some(tenant)
+            });
+        let service = BroadcastService::new();
+        let mut acme = service.listener_visible_to(visibility.clone(), "acme".to_owned());
+        let mut globex = service.listener_visible_to(visibility, "globex".to_owned());
+
+        for (id, tenant) in [(1, "acme"), (2, "globex"), (3, "acme")] {
+            let event = DoggoEvent::new_delete_event(id, Collection::Dogs)
+                .with_meta(EventMeta::new().with_extra("tenant", tenant));
+            service.publish(event).unwrap();
+        }
+        service
+            .publish(DoggoEvent::new_delete_event(4, Collection::Dogs))
+            .unwrap();
+        service
+            .publish(
+                DoggoEvent::new_delete_event(5, Collection::Dogs)
+                    .with_meta(EventMeta::new().with_extra("tenant", "globex")),
+            )
+            .unwrap();
+
+        block_on(async {
+            assert_eq!(acme.recv().await.unwrap().id(), Some(&1));
+            assert_eq!(acme.recv().await.unwrap().id(), Some(&3));
+            assert_eq!(globex.recv().await.unwrap().id(), Some(&2));
+            assert_eq!(globex.recv().await.unwrap().id(), Some(&5));
+        });
+        assert_eq!(globex.context(), "globex");
+    }
+}
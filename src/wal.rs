@@ -0,0 +1,399 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::crc32::crc32;
+use crate::{Error, EventStore, Replay, Sequenced};
+
+// 64 MiB
+pub const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+// a record's length and crc, both little endian u32s
+const RECORD_HEADER: usize = 8;
+
+// an append-only log of opaque records, written durably before anything is
+// done with them so they survive a crash. positions count records from the
+// start of the log
+pub trait Wal: Send + Sync {
+    // returns the record's position once it is durable
+    fn append(&self, record: &[u8]) -> Result<u64, Error>;
+
+    // every record at `from` or later, with its position, in order
+    fn read_from(&self, from: u64) -> Result<Replay<'_, (u64, Vec<u8>)>, Error>;
+
+    // the position the next record gets
+    fn next_position(&self) -> u64;
+
+    // drops records before `position` where that's cheap, returning how many
+    // went. logs that can't drop anything keep everything
+    fn truncate_before(&self, _position: u64) -> Result<u64, Error> {
+        Ok(0)
+    }
+}
+
+// a directory of segment files, each named after the position of its first
+// record and rotated once it would grow past the segment size. every record
+// carries a crc, and opening the log scans it: a torn or corrupt record at
+// the very end (a crash mid-append) is cut off, anywhere else it's an error
+pub struct FileWal {
+    dir: PathBuf,
+    segment_size: u64,
+    writer: Mutex<Writer>,
+}
+
+struct Writer {
+    file: File,
+    // the first position of every segment, ascending
+    segments: Vec<u64>,
+    // bytes in the last segment, all of them whole records
+    len: u64,
+    next: u64,
+    // a failed append couldn't be undone, so the last segment may end in a
+    // torn record that later appends would land after
+    poisoned: Option<String>,
+}
+
+impl FileWal {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(store)?;
+        let mut segments: Vec<u64> = fs::read_dir(&dir)
+            .map_err(store)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                match path.extension()?.to_str()? {
+                    "wal" => path.file_stem()?.to_str()?.parse().ok(),
+                    _ => None,
+                }
+            })
+            .collect();
+        segments.sort_unstable();
+        if segments.is_empty() {
+            segments.push(0);
+        }
+
+        let mut next = segments[0];
+        let mut len = 0;
+        for (index, &start) in segments.iter().enumerate() {
+            if start != next {
+                return Err(Error::Store(format!(
+                    "write-ahead log is missing records {next} to {start}"
+                )));
+            }
+            let path = segment_path(&dir, start);
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(store(err)),
+            };
+            let (records, intact) = parse(&bytes);
+            if intact < bytes.len() {
+                if index + 1 < segments.len() {
+                    return Err(Error::Store(format!(
+                        "corrupt record in write-ahead log segment {start}"
+                    )));
+                }
+                // torn by a crash mid-append
+                let file = OpenOptions::new().write(true).open(&path).map_err(store)?;
+                file.set_len(intact as u64).map_err(store)?;
+                file.sync_all().map_err(store)?;
+            }
+            next = start + records.len() as u64;
+            len = intact as u64;
+        }
+
+        let last = *segments.last().unwrap_or(&0);
+        let file = append_to(&segment_path(&dir, last))?;
+        sync_dir(&dir)?;
+        Ok(Self {
+            dir,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            writer: Mutex::new(Writer {
+                file,
+                segments,
+                len,
+                next,
+                poisoned: None,
+            }),
+        })
+    }
+
+    // segments are rotated once a record would take them past this. a
+    // record bigger than it gets a segment of its own
+    pub fn with_segment_size(mut self, segment_size: u64) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn segments(&self) -> usize {
+        self.lock().segments.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Writer> {
+        self.writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Wal for FileWal {
+    fn append(&self, record: &[u8]) -> Result<u64, Error> {
+        let len = u32::try_from(record.len())
+            .map_err(|_| Error::Store(format!("record of {} bytes is too long", record.len())))?;
+        let mut entry = Vec::with_capacity(RECORD_HEADER + record.len());
+        entry.extend(len.to_le_bytes());
+        entry.extend(crc32(record).to_le_bytes());
+        entry.extend_from_slice(record);
+
+        let mut writer = self.lock();
+        if let Some(err) = &writer.poisoned {
+            return Err(Error::Store(format!(
+                "write-ahead log failed earlier: {err}"
+            )));
+        }
+        if writer.len > 0 && writer.len + entry.len() as u64 > self.segment_size {
+            let start = writer.next;
+            let file = append_to(&segment_path(&self.dir, start))?;
+            // the new segment is only durable once its directory entry is
+            sync_dir(&self.dir)?;
+            writer.file = file;
+            writer.segments.push(start);
+            writer.len = 0;
+        }
+        let written = writer
+            .file
+            .write_all(&entry)
+            .and_then(|()| writer.file.sync_data());
+        if let Err(err) = written {
+            // cut off whatever part of the record made it, so the next
+            // append doesn't land after a torn one
+            let len = writer.len;
+            let undone = writer
+                .file
+                .set_len(len)
+                .and_then(|()| writer.file.sync_data());
+            if let Err(undo) = undone {
+                writer.poisoned = Some(undo.to_string());
+            }
+            return Err(store(err));
+        }
+        writer.len += entry.len() as u64;
+        writer.next += 1;
+        Ok(writer.next - 1)
+    }
+
+    fn read_from(&self, from: u64) -> Result<Replay<'_, (u64, Vec<u8>)>, Error> {
+        let writer = self.lock();
+        // the segment holding `from`, or the first one if it's gone
+        let first = writer
+            .segments
+            .partition_point(|&start| start <= from)
+            .saturating_sub(1);
+        let mut records = Vec::new();
+        for &start in &writer.segments[first..] {
+            let bytes = fs::read(segment_path(&self.dir, start)).map_err(store)?;
+            let (parsed, _) = parse(&bytes);
+            records.extend(
+                (start..)
+                    .zip(parsed)
+                    .filter(|(position, _)| *position >= from),
+            );
+        }
+        Ok(Box::new(records.into_iter()))
+    }
+
+    fn next_position(&self) -> u64 {
+        self.lock().next
+    }
+
+    // whole segments only, and never the one being appended to
+    fn truncate_before(&self, position: u64) -> Result<u64, Error> {
+        let mut writer = self.lock();
+        let mut removed = 0;
+        while writer.segments.len() > 1 && writer.segments[1] <= position {
+            let start = writer.segments.remove(0);
+            fs::remove_file(segment_path(&self.dir, start)).map_err(store)?;
+            removed += writer.segments[0] - start;
+        }
+        Ok(removed)
+    }
+}
+
+// the intact records at the start of `bytes`, and how many bytes they take
+fn parse(bytes: &[u8]) -> (Vec<Vec<u8>>, usize) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + RECORD_HEADER) {
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let body = offset + RECORD_HEADER;
+        let Some(record) = bytes.get(body..body + len) else {
+            break;
+        };
+        if crc32(record) != crc {
+            break;
+        }
+        records.push(record.to_vec());
+        offset = body + len;
+    }
+    (records, offset)
+}
+
+fn segment_path(dir: &Path, start: u64) -> PathBuf {
+    dir.join(format!("{start:020}.wal"))
+}
+
+fn append_to(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(store)
+}
+
+// makes the files created in `dir` durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), Error> {
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(store)
+}
+
+// directories can't be opened as files here
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+fn store(err: std::io::Error) -> Error {
+    Error::Store(err.to_string())
+}
+
+// an `EventStore` writing events to a `Wal` as json, so a service with it
+// only acks a publish once the event is durable
+pub struct WalStore<T, W = FileWal> {
+    wal: W,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, W: Wal> WalStore<T, W> {
+    pub fn new(wal: W) -> Self {
+        Self {
+            wal,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn wal(&self) -> &W {
+        &self.wal
+    }
+}
+
+impl<T, W> EventStore<T> for WalStore<T, W>
+where
+    T: Serialize + DeserializeOwned + Sequenced,
+    W: Wal,
+{
+    fn append(&self, event: &T) -> Result<(), Error> {
+        let record = serde_json::to_vec(event).map_err(Error::Encode)?;
+        self.wal.append(&record).map(|_| ())
+    }
+
+    fn replay(&self, from_seq: u64) -> Result<Replay<'_, T>, Error> {
+        let events = self
+            .wal
+            .read_from(0)?
+            .map(|(_, record)| serde_json::from_slice::<T>(&record).map_err(Error::Decode))
+            .filter(|event| {
+                event
+                    .as_ref()
+                    .map_or(true, |event| event.seq().is_some_and(|seq| seq >= from_seq))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Box::new(events.into_iter()))
+    }
+
+    fn next_seq(&self) -> Result<u64, Error> {
+        let Some(last) = self.wal.next_position().checked_sub(1) else {
+            return Ok(0);
+        };
+        let newest = self
+            .wal
+            .read_from(last)?
+            .last()
+            .map(|(_, record)| serde_json::from_slice::<T>(&record).map_err(Error::Decode))
+            .transpose()?;
+        Ok(newest
+            .as_ref()
+            .and_then(Sequenced::seq)
+            .map_or(0, |seq| seq + 1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use crate::test::Collection;
+    use crate::{BroadcastService, Event, EventStore, FileWal, Service, Wal, WalStore};
+
+    type DeleteEvent = Event<u32, (), Collection>;
+
+    #[test]
+    fn the_log_survives_a_crash() {
+        let dir = std::env::temp_dir().join(format!("rsp-wal-{}", std::process::id()));
+        let open = || FileWal::open(&dir).unwrap().with_segment_size(256);
+        let service = BroadcastService::with_store(16, WalStore::<DeleteEvent>::new(open()));
+        for id in 1..=4 {
+            service
+                .publish(Event::new_delete_event(id, Collection::Dogs))
+                .unwrap();
+        }
+        drop(service);
+
+        // a crash halfway through writing a fifth
+        let wal = open();
+        assert!(wal.segments() > 1);
+        let last = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .max()
+            .unwrap();
+        let mut file = OpenOptions::new().append(true).open(last).unwrap();
+        file.write_all(&[40, 0, 0, 0, 1, 2, 3, 4, b'{']).unwrap();
+        drop((file, wal));
+
+        let store = WalStore::<DeleteEvent>::new(open());
+        assert_eq!(store.wal().next_position(), 4);
+        assert_eq!(store.next_seq().unwrap(), 4);
+        let ids: Vec<_> = store
+            .replay(2)
+            .unwrap()
+            .map(|event| *event.id().unwrap())
+            .collect();
+        assert_eq!(ids, [3, 4]);
+
+        let service = BroadcastService::with_store(16, store);
+        service
+            .publish(Event::new_delete_event(5, Collection::Dogs))
+            .unwrap();
+        drop(service);
+        let wal = open();
+        assert_eq!(wal.next_position(), 5);
+        let removed = wal.truncate_before(4).unwrap();
+        assert!(removed > 0);
+        let positions: Vec<_> = wal.read_from(0).unwrap().map(|(at, _)| at).collect();
+        assert_eq!(positions.last(), Some(&4));
+        assert_eq!(positions.len() as u64, 5 - removed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
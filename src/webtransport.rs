@@ -0,0 +1,181 @@
+// experimental: the protocol over a webtransport session, for low latency
+// clients on lossy networks. ordered messages (events, batches, snapshots)
+// go out as `framing` frames on one unidirectional stream, so a lost packet
+// only holds up what comes after it on that stream. ephemeral messages go out
+// as unreliable datagrams, a codec byte and the body, as one that arrives
+// late is worthless anyway. the quic stack (e.g. `wtransport` or `quinn`)
+// stays with the server: it opens the stream as a `futures_io::AsyncWrite`
+// (tokio-util's `compat` turns a tokio stream into one) and hands over the
+// session's datagrams as `Datagrams`
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::codec::WireCodec;
+use crate::framing::{CodecByte, Frame, FrameCodec};
+use crate::{Ephemeral, Error, WsBody};
+
+// the unreliable, unordered datagrams of a session
+#[async_trait::async_trait]
+pub trait Datagrams {
+    // what the path allows right now, which quic finds out as it goes
+    fn max_datagram_size(&self) -> usize;
+
+    async fn send_datagram(&mut self, datagram: Vec<u8>) -> Result<(), Error>;
+}
+
+// how a message went out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Datagram,
+    // an ephemeral message too big for a datagram falls back to the stream
+    Stream,
+}
+
+pub struct WebTransportSender<S, D, W> {
+    stream: S,
+    datagrams: D,
+    codec: W,
+    frames: FrameCodec,
+}
+
+impl<S, D, W> WebTransportSender<S, D, W>
+where
+    S: futures_io::AsyncWrite + Unpin + Send,
+    D: Datagrams + Send,
+    W: WireCodec + Sync,
+{
+    pub fn new(stream: S, datagrams: D, codec: W) -> Self {
+        Self {
+            stream,
+            datagrams,
+            codec,
+            frames: FrameCodec::new(),
+        }
+    }
+
+    pub fn with_frame_codec(mut self, frames: FrameCodec) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    // in order, and reliably
+    pub async fn send<T: Serialize + Sync>(&mut self, body: &WsBody<T>) -> Result<(), Error> {
+        let frame = Frame::encode(body, &self.codec)?;
+        self.frames
+            .write_frame_async(&frame, &mut self.stream)
+            .await
+    }
+
+    pub async fn send_ephemeral<ID, C>(
+        &mut self,
+        ephemeral: Ephemeral<ID, C>,
+    ) -> Result<Delivery, Error>
+    where
+        ID: Serialize + Send + Sync,
+        C: Serialize + Send + Sync,
+    {
+        let body = ephemeral.into_ws_body();
+        let mut datagram = vec![CodecByte::of(&self.codec)?.byte()];
+        datagram.extend(self.codec.encode(&body)?);
+        if datagram.len() > self.datagrams.max_datagram_size() {
+            self.send(&body).await?;
+            return Ok(Delivery::Stream);
+        }
+        self.datagrams.send_datagram(datagram).await?;
+        Ok(Delivery::Datagram)
+    }
+
+    pub fn into_parts(self) -> (S, D) {
+        (self.stream, self.datagrams)
+    }
+}
+
+// the body of a datagram `send_ephemeral` sent
+pub fn decode_datagram<T: DeserializeOwned>(datagram: &[u8]) -> Result<T, Error> {
+    let (codec, body) = datagram
+        .split_first()
+        .ok_or_else(|| Error::InvalidFrame("empty datagram".to_owned()))?;
+    Frame::new(CodecByte::from_byte(*codec)?, body.to_vec()).decode()
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+    use serde_json::json;
+
+    use crate::codec::JsonCodec;
+    use crate::framing::FrameCodec;
+    use crate::test::{block_on, Collection};
+    use crate::webtransport::{decode_datagram, Datagrams, Delivery, WebTransportSender};
+    use crate::{Ephemeral, Error, Event, WsBody};
+
+    struct Sent {
+        max: usize,
+        datagrams: Vec<Vec<u8>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Datagrams for Sent {
+        fn max_datagram_size(&self) -> usize {
+            self.max
+        }
+
+        async fn send_datagram(&mut self, datagram: Vec<u8>) -> Result<(), Error> {
+            self.datagrams.push(datagram);
+            Ok(())
+        }
+    }
+
+    type Cursor = Ephemeral<u32, Collection>;
+
+    #[test]
+    fn ephemerals_ride_datagrams() {
+        let datagrams = Sent {
+            max: 64,
+            datagrams: Vec::new(),
+        };
+        let mut sender = WebTransportSender::new(Vec::new(), datagrams, JsonCodec);
+        let (small, large) = block_on(async {
+            let event = Event::<u32, (), Collection>::new_delete_event(1, Collection::Dogs);
+            sender.send(&event.into_ws_body()).await.unwrap();
+            let small = sender
+                .send_ephemeral(Cursor::new(Collection::Dogs, json!({ "x": 1 })))
+                .await
+                .unwrap();
+            let large = sender
+                .send_ephemeral(Cursor::new(
+                    Collection::Dogs,
+                    json!({ "text": "a".repeat(64) }),
+                ))
+                .await
+                .unwrap();
+            (small, large)
+        });
+        assert_eq!((small, large), (Delivery::Datagram, Delivery::Stream));
+
+        let (stream, sent) = sender.into_parts();
+        assert_eq!(sent.datagrams.len(), 1);
+        let cursor: WsBody<Cursor> = decode_datagram(&sent.datagrams[0]).unwrap();
+        assert_eq!(cursor.into_data().data(), &json!({ "x": 1 }));
+
+        let frames = FrameCodec::new();
+        let mut stream = BytesMut::from(&stream[..]);
+        let event: WsBody<Event<u32, (), Collection>> = frames
+            .decode_frame(&mut stream)
+            .unwrap()
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(event.into_data().id(), Some(&1));
+        let fallback: WsBody<Cursor> = frames
+            .decode_frame(&mut stream)
+            .unwrap()
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(
+            fallback.into_data().data()["text"].as_str().unwrap().len(),
+            64
+        );
+        assert!(stream.is_empty());
+    }
+}
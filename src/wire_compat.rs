@@ -0,0 +1,206 @@
+// a guard against accidental protocol changes. every message checked is
+// encoded as json, and as messagepack with the `msgpack` feature, and
+// compared with the golden fixtures committed under a directory, and the fixtures are decoded and re-encoded
+// to prove they still parse. a message that no longer matches is one the
+// clients already out there would see differently, so `finish` fails:
+//
+//     let mut compat = WireCompat::new("tests/fixtures/wire");
+//     compat.check("hello", &Hello::new(vec![1], vec!["json".to_owned()]));
+//     compat.finish()?;
+//
+// fixtures are compared by shape, so reordering fields doesn't fail. a
+// change made on purpose is blessed by running with `RSP_BLESS_FIXTURES=1`,
+// which writes the fixtures of new messages and rewrites those that changed
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+#[cfg(feature = "msgpack")]
+use crate::codec::{MessagePackCodec, WireCodec};
+use crate::Error;
+
+// set to anything but `0` to rewrite the fixtures instead of failing
+pub const BLESS_VAR: &str = "RSP_BLESS_FIXTURES";
+
+pub struct WireCompat {
+    dir: PathBuf,
+    bless: bool,
+    checked: usize,
+    failures: Vec<String>,
+}
+
+impl WireCompat {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let bless = std::env::var(BLESS_VAR).is_ok_and(|bless| !bless.is_empty() && bless != "0");
+        Self {
+            dir: dir.into(),
+            bless,
+            checked: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn with_bless(mut self, bless: bool) -> Self {
+        self.bless = bless;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // messages checked so far
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    // compares `message` with the fixtures `{name}.json` and `{name}.msgpack`
+    pub fn check<T>(&mut self, name: &str, message: &T) -> &mut Self
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        self.checked += 1;
+        if let Err(err) = self.check_json(name, message) {
+            self.failures.push(format!("{name}.json: {err}"));
+        }
+        #[cfg(feature = "msgpack")]
+        if let Err(err) = self.check_msgpack(name, message) {
+            self.failures.push(format!("{name}.msgpack: {err}"));
+        }
+        self
+    }
+
+    // fails with every mismatch found, if there were any
+    pub fn finish(self) -> Result<(), Error> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let mut report = self.failures.join("\n");
+        report.push_str(&format!(
+            "\nrerun with {BLESS_VAR}=1 if the change is intended"
+        ));
+        Err(Error::WireFormat(report))
+    }
+
+    fn check_json<T>(&self, name: &str, message: &T) -> Result<(), String>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let encoded = serde_json::to_value(message).map_err(|err| err.to_string())?;
+        let path = self.dir.join(format!("{name}.json"));
+        let Some(fixture) = self.fixture(&path)? else {
+            return self.write(&path, pretty(&encoded)?);
+        };
+        let golden: Value = serde_json::from_slice(&fixture)
+            .map_err(|err| format!("fixture is not json: {err}"))?;
+        if encoded != golden {
+            return match self.bless {
+                true => self.write(&path, pretty(&encoded)?),
+                false => Err(format!("encodes as {encoded}")),
+            };
+        }
+        let decoded: T = serde_json::from_value(golden.clone())
+            .map_err(|err| format!("fixture no longer decodes: {err}"))?;
+        let reencoded = serde_json::to_value(&decoded).map_err(|err| err.to_string())?;
+        match reencoded == golden {
+            true => Ok(()),
+            false => Err(format!("fixture re-encodes as {reencoded}")),
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    fn check_msgpack<T>(&self, name: &str, message: &T) -> Result<(), String>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let codec = MessagePackCodec;
+        let encoded = codec.encode(message).map_err(|err| err.to_string())?;
+        let path = self.dir.join(format!("{name}.msgpack"));
+        let Some(golden) = self.fixture(&path)? else {
+            return self.write(&path, encoded);
+        };
+        if encoded != golden {
+            return match self.bless {
+                true => self.write(&path, encoded),
+                false => Err(format!(
+                    "encodes as {} bytes differing from the fixture's {} at byte {}",
+                    encoded.len(),
+                    golden.len(),
+                    encoded
+                        .iter()
+                        .zip(&golden)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                )),
+            };
+        }
+        let decoded: T = codec
+            .decode(&golden)
+            .map_err(|err| format!("fixture no longer decodes: {err}"))?;
+        match codec.encode(&decoded).map_err(|err| err.to_string())? == golden {
+            true => Ok(()),
+            false => Err("fixture re-encodes differently".to_owned()),
+        }
+    }
+
+    // the fixture at `path`, or `None` when there is none yet and blessing
+    // will write it
+    fn fixture(&self, path: &Path) -> Result<Option<Vec<u8>>, String> {
+        match fs::read(path) {
+            Ok(fixture) => Ok(Some(fixture)),
+            Err(_) if self.bless => Ok(None),
+            Err(err) => Err(format!("no fixture ({err})")),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: Vec<u8>) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(path, contents))
+            .map_err(|err| format!("could not write fixture: {err}"))
+    }
+}
+
+fn pretty(value: &Value) -> Result<Vec<u8>, String> {
+    let mut pretty = serde_json::to_vec_pretty(value).map_err(|err| err.to_string())?;
+    pretty.push(b'\n');
+    Ok(pretty)
+}
+
+#[cfg(all(test, feature = "msgpack"))]
+mod test {
+    use crate::wire_compat::WireCompat;
+    use crate::{Error, Hello};
+
+    #[test]
+    fn changed_messages_fail_until_blessed() {
+        let dir = std::env::temp_dir().join(format!("rsp-wire-compat-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let hello = Hello::new(vec![1], vec!["json".to_owned()]);
+
+        // nothing to compare against yet
+        let mut compat = WireCompat::new(&dir).with_bless(false);
+        compat.check("hello", &hello);
+        assert!(matches!(compat.finish(), Err(Error::WireFormat(_))));
+
+        let mut compat = WireCompat::new(&dir).with_bless(true);
+        compat.check("hello", &hello);
+        compat.finish().unwrap();
+        let mut compat = WireCompat::new(&dir).with_bless(false);
+        compat.check("hello", &hello);
+        compat.finish().unwrap();
+
+        let mut compat = WireCompat::new(&dir).with_bless(false);
+        compat.check("hello", &Hello::new(vec![1, 2], vec!["json".to_owned()]));
+        let Err(Error::WireFormat(report)) = compat.finish() else {
+            panic!("a changed message passed");
+        };
+        insta::assert_snapshot!(report, @r###"
+        hello.json: encodes as {"codecs":["json"],"protocol_versions":[1,2]}
+        hello.msgpack: encodes as 35 bytes differing from the fixture's 34 at byte 32
+        rerun with RSP_BLESS_FIXTURES=1 if the change is intended
+        "###);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
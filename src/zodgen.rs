@@ -0,0 +1,617 @@
+// generates zod schemas from the declarations ts-rs exports, so typescript
+// clients can validate frames at runtime instead of trusting a cast. every
+// payload type of the protocol is included; records (and anything they
+// reference) are added by the application, together with a table of the
+// event schema of every collection. generic types become functions taking the
+// schemas of their type parameters, and references go through `z.lazy` so
+// declarations can come in any order. with the `json-schema` feature,
+// `JsonSchemaGenerator` writes the same tables as json schema, for consumers
+// that don't speak typescript
+#[cfg(feature = "json-schema")]
+mod json_schema;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use serde::Serialize;
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::presence::{
+    Join, Leave, PresenceDelta, PresenceEntry, PresenceHeartbeat, PresenceState, PresenceStatus,
+};
+use crate::{
+    Ack, Acknowledgement, Appendable, AppendableResource, AuthResult, Authenticate, CaughtUp,
+    Claims, Command, ConflictError, DeletableResource, Ephemeral, Error, ErrorCode, ErrorMessage,
+    Event, EventBatch, EventMeta, EventVerb, FieldError, Filter, GoingAway, Hello, HelloAck,
+    JsonPatch, LiveQuery, LiveQueryChange, LiveQueryUpdate, Location, Mutate, MutationResult,
+    MutationStatus, Nack, PatchOperation, PatchResource, Patchable, Ping, Pong, Query, QueryResult,
+    Rejection, ResourceIdentifier, Snapshot, SnapshotChunk, SnapshotEntry, Subscribe, Syncable,
+    Unsubscribe, UpdatableResource, ValidationErrors, VectorClock,
+};
+
+#[cfg(feature = "json-schema")]
+pub use json_schema::JsonSchemaGenerator;
+
+pub struct SchemaGenerator {
+    // (name, declaration)
+    declarations: Vec<(String, String)>,
+    // (collection name, event schema)
+    collections: Vec<(String, String)>,
+    // why a registration was rejected
+    failed: Option<String>,
+}
+
+impl Default for SchemaGenerator {
+    fn default() -> Self {
+        let generator = Self {
+            declarations: Vec::new(),
+            collections: Vec::new(),
+            failed: None,
+        };
+        generator
+            .add::<Event<(), (), ()>>()
+            .add::<EventMeta>()
+            .add::<VectorClock>()
+            .add::<EventVerb<(), (), ()>>()
+            .add::<Location<(), ()>>()
+            .add::<AppendableResource<(), (), ()>>()
+            .add::<UpdatableResource<(), (), ()>>()
+            .add::<PatchResource<(), (), ()>>()
+            .add::<DeletableResource<(), ()>>()
+            .add::<EventBatch<(), (), ()>>()
+            .add::<Snapshot<(), (), ()>>()
+            .add::<SnapshotChunk<(), (), ()>>()
+            .add::<SnapshotEntry<(), ()>>()
+            .add::<Command<(), (), ()>>()
+            .add::<Subscribe<()>>()
+            .add::<Unsubscribe<()>>()
+            .add::<Query<(), ()>>()
+            .add::<QueryResult<(), (), ()>>()
+            .add::<LiveQuery<()>>()
+            .add::<Filter>()
+            .add::<LiveQueryUpdate<(), ()>>()
+            .add::<LiveQueryChange<(), ()>>()
+            .add::<Ephemeral<(), ()>>()
+            .add::<Join>()
+            .add::<Leave>()
+            .add::<PresenceHeartbeat>()
+            .add::<PresenceState>()
+            .add::<PresenceStatus>()
+            .add::<PresenceEntry>()
+            .add::<PresenceDelta>()
+            .add::<Mutate<(), (), ()>>()
+            .add::<MutationResult<(), ()>>()
+            .add::<MutationStatus<(), ()>>()
+            .add::<FieldError>()
+            .add::<ValidationErrors>()
+            .add::<Rejection<(), ()>>()
+            .add::<Ack>()
+            .add::<Nack>()
+            .add::<Acknowledgement>()
+            .add::<ConflictError<(), ()>>()
+            .add::<ErrorCode>()
+            .add::<ErrorMessage>()
+            .add::<Hello>()
+            .add::<Authenticate>()
+            .add::<AuthResult<()>>()
+            .add::<Claims<()>>()
+            .add::<HelloAck>()
+            .add::<Ping>()
+            .add::<Pong>()
+            .add::<GoingAway>()
+            .add::<CaughtUp>()
+            .add::<JsonPatch>()
+            .add::<PatchOperation>()
+            .add::<ResourceIdentifier>()
+    }
+}
+
+impl SchemaGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // adds a schema for `T`, e.g. a type a record refers to
+    pub fn add<T: TS>(mut self) -> Self {
+        let name = T::name();
+        let name = name.split('<').next().unwrap_or_default().to_owned();
+        if !self.declarations.iter().any(|(other, _)| *other == name) {
+            self.declarations.push((name, T::decl()));
+        }
+        self
+    }
+
+    // a collection of `R`s that are only ever inserted
+    pub fn appendable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Appendable,
+        R::Collection: Serialize,
+    {
+        self.register::<(), R, ()>(collection, false)
+    }
+
+    pub fn syncable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Syncable,
+        R::Collection: Serialize,
+        R::Id: TS,
+    {
+        self.register::<R::Id, R, ()>(collection, false)
+    }
+
+    pub fn patchable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Patchable,
+        R::Collection: Serialize,
+        R::Id: TS,
+    {
+        self.register::<R::Id, R, R::Patch>(collection, true)
+    }
+
+    fn register<ID: TS, R: TS, P: TS>(mut self, collection: impl Serialize, patch: bool) -> Self {
+        let name = match collection_name(collection) {
+            Ok(name) => name,
+            Err(reason) => {
+                self.failed.get_or_insert(reason);
+                return self;
+            }
+        };
+        self = self.add_exported::<R>();
+        let mut args = vec![type_ref::<ID>(), type_ref::<R>(), format!("{name:?}")];
+        if patch {
+            self = self.add_exported::<P>();
+            args.push(type_ref::<P>());
+        }
+        self.collections
+            .push((name, format!("Event<{}>", args.join(", "))));
+        self
+    }
+
+    fn add_exported<T: TS>(self) -> Self {
+        match T::EXPORT_TO {
+            Some(_) => self.add::<T>(),
+            None => self,
+        }
+    }
+
+    pub fn generate(&self) -> Result<String, Error> {
+        self.check()?;
+        let mut out = String::from(HEADER);
+        for (_, decl) in &self.declarations {
+            out.push('\n');
+            out.push_str(&declaration(decl)?);
+        }
+        out.push_str("\nexport const collections = {\n");
+        for (name, event) in &self.collections {
+            let schema = emit(&parse_type(event)?, &[]);
+            writeln!(out, "  {:?}: {schema},", name).unwrap();
+        }
+        out.push_str("};\n");
+        Ok(out)
+    }
+
+    pub fn write_to(&self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        std::fs::write(path, self.generate()?).map_err(|err| Error::Transport(err.to_string()))
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        check(
+            self.failed.as_ref(),
+            self.collections.iter().map(|(name, _)| name),
+        )
+    }
+}
+
+// the name of a collection, which must serialize to a string
+fn collection_name(collection: impl Serialize) -> Result<String, String> {
+    match serde_json::to_value(collection) {
+        Ok(Value::String(name)) => Ok(name),
+        Ok(other) => Err(format!("{other} does not serialize to a string")),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+// fails with the first rejected registration, or on a collection that was
+// registered twice
+fn check<'a>(
+    failed: Option<&String>,
+    mut names: impl Iterator<Item = &'a String>,
+) -> Result<(), Error> {
+    if let Some(reason) = failed {
+        return Err(Error::InvalidCollection(reason.clone()));
+    }
+    let mut seen = BTreeSet::new();
+    match names.find(|name| !seen.insert(*name)) {
+        Some(duplicate) => Err(Error::InvalidCollection(format!(
+            "{duplicate} is registered twice"
+        ))),
+        None => Ok(()),
+    }
+}
+
+const HEADER: &str = "// This file was generated by rsp::zodgen. Do not edit this file manually.\nimport { z } from \"zod\";\n";
+
+// the name of an exported binding, or the inline type of anything else
+fn type_ref<T: TS>() -> String {
+    match T::EXPORT_TO {
+        Some(_) => T::name(),
+        None => T::inline(),
+    }
+}
+
+// a typescript type, as far as ts-rs writes them
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Literal(String),
+    Named(String, Vec<Type>),
+    Tuple(Vec<Type>),
+    Object(Vec<(String, bool, Type)>),
+    Union(Vec<Type>),
+    Intersection(Vec<Type>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(String),
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c == '"' {
+            let mut escaped = false;
+            let end = chars
+                .by_ref()
+                .find(|(_, c)| {
+                    let end = *c == '"' && !escaped;
+                    escaped = *c == '\\' && !escaped;
+                    end
+                })
+                .map(|(end, _)| end)
+                .ok_or_else(|| unparsable(source))?;
+            tokens.push(Token::Literal(source[start..=end].to_owned()));
+        } else if c.is_alphanumeric() || c == '_' || c == '-' {
+            let mut end = start + c.len_utf8();
+            while let Some((at, c)) =
+                chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '.')
+            {
+                end = at + c.len_utf8();
+            }
+            let word = &source[start..end];
+            match word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                true => tokens.push(Token::Literal(word.to_owned())),
+                false => tokens.push(Token::Ident(word.to_owned())),
+            }
+        } else {
+            tokens.push(Token::Punct(c));
+        }
+    }
+    Ok(tokens)
+}
+
+fn unparsable(source: &str) -> Error {
+    Error::Codegen(format!("could not parse `{source}`"))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(punct));
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: char) -> Option<()> {
+        self.eat(punct).then_some(())
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        match self.next()? {
+            Token::Ident(ident) => Some(ident),
+            _ => None,
+        }
+    }
+
+    fn ty(&mut self) -> Option<Type> {
+        self.eat('|');
+        let mut members = vec![self.intersection()?];
+        while self.eat('|') {
+            members.push(self.intersection()?);
+        }
+        Some(match members.len() {
+            1 => members.remove(0),
+            _ => Type::Union(members),
+        })
+    }
+
+    fn intersection(&mut self) -> Option<Type> {
+        let mut members = vec![self.postfix()?];
+        while self.eat('&') {
+            members.push(self.postfix()?);
+        }
+        Some(match members.len() {
+            1 => members.remove(0),
+            _ => Type::Intersection(members),
+        })
+    }
+
+    fn postfix(&mut self) -> Option<Type> {
+        let mut ty = self.primary()?;
+        while self.peek() == Some(&Token::Punct('['))
+            && self.tokens.get(self.at + 1) == Some(&Token::Punct(']'))
+        {
+            self.at += 2;
+            ty = Type::Named("Array".to_owned(), vec![ty]);
+        }
+        Some(ty)
+    }
+
+    fn primary(&mut self) -> Option<Type> {
+        match self.next()? {
+            Token::Literal(literal) => Some(Type::Literal(literal)),
+            Token::Ident(name) => {
+                let mut args = Vec::new();
+                if self.eat('<') {
+                    args = self.list('>')?;
+                }
+                Some(Type::Named(name, args))
+            }
+            Token::Punct('(') => {
+                let ty = self.ty()?;
+                self.expect(')')?;
+                Some(ty)
+            }
+            Token::Punct('[') => Some(Type::Tuple(self.list(']')?)),
+            Token::Punct('{') => self.object(),
+            Token::Punct(_) => None,
+        }
+    }
+
+    // comma separated types up to `close`
+    fn list(&mut self, close: char) -> Option<Vec<Type>> {
+        let mut items = Vec::new();
+        while !self.eat(close) {
+            items.push(self.ty()?);
+            if !self.eat(',') {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Some(items)
+    }
+
+    fn object(&mut self) -> Option<Type> {
+        let mut fields = Vec::new();
+        while !self.eat('}') {
+            let key = match self.next()? {
+                Token::Ident(key) => format!("{key:?}"),
+                Token::Literal(key) if key.starts_with('"') => key,
+                _ => return None,
+            };
+            let optional = self.eat('?');
+            self.expect(':')?;
+            fields.push((key, optional, self.ty()?));
+            if !self.eat(',') && !self.eat(';') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        Some(Type::Object(fields))
+    }
+
+    // `<A, B = never>`, with the defaults
+    fn params(&mut self) -> Option<Vec<(String, Option<Type>)>> {
+        let mut params = Vec::new();
+        if !self.eat('<') {
+            return Some(params);
+        }
+        while !self.eat('>') {
+            let name = self.ident()?;
+            let default = match self.eat('=') {
+                true => Some(self.ty()?),
+                false => None,
+            };
+            params.push((name, default));
+            if !self.eat(',') {
+                self.expect('>')?;
+                break;
+            }
+        }
+        Some(params)
+    }
+}
+
+fn parse_type(source: &str) -> Result<Type, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        at: 0,
+    };
+    parser
+        .ty()
+        .filter(|_| parser.peek().is_none())
+        .ok_or_else(|| unparsable(source))
+}
+
+// a declaration's name, type parameters (with their defaults) and type
+type Declaration = (String, Vec<(String, Option<Type>)>, Type);
+
+// `interface Name<..> { .. }` or `type Name<..> = ..;`
+fn parse_declaration(decl: &str) -> Result<Declaration, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(decl)?,
+        at: 0,
+    };
+    let parsed = (|| {
+        let keyword = parser.ident()?;
+        let name = parser.ident()?;
+        let params = parser.params()?;
+        let ty = match keyword.as_str() {
+            "interface" => {
+                parser.expect('{')?;
+                parser.object()?
+            }
+            "type" => {
+                parser.expect('=')?;
+                let ty = parser.ty()?;
+                parser.eat(';');
+                ty
+            }
+            _ => return None,
+        };
+        parser.peek().is_none().then_some((name, params, ty))
+    })();
+    parsed.ok_or_else(|| unparsable(decl))
+}
+
+// a declaration as a zod schema
+fn declaration(decl: &str) -> Result<String, Error> {
+    let (name, params, ty) = parse_declaration(decl)?;
+    let names: Vec<_> = params.iter().map(|(name, _)| name.clone()).collect();
+    let schema = emit(&ty, &names);
+    if params.is_empty() {
+        return Ok(format!("export const {name} = {schema};\n"));
+    }
+    let generics: Vec<_> = params
+        .iter()
+        .map(|(param, default)| match default {
+            Some(default) => format!("{param} extends z.ZodTypeAny = {}", zod_type(default)),
+            None => format!("{param} extends z.ZodTypeAny"),
+        })
+        .collect();
+    let args: Vec<_> = params
+        .iter()
+        .map(|(param, default)| match default {
+            Some(default) => format!(
+                "{param}: {param} = {} as unknown as {param}",
+                emit(default, &[])
+            ),
+            None => format!("{param}: {param}"),
+        })
+        .collect();
+    Ok(format!(
+        "export function {name}<{}>({}) {{\n  return {schema};\n}}\n",
+        generics.join(", "),
+        args.join(", ")
+    ))
+}
+
+// the zod type of a schema, for defaults of generic parameters
+fn zod_type(ty: &Type) -> String {
+    match ty {
+        Type::Named(name, args) if args.is_empty() => match name.as_str() {
+            "never" => "z.ZodNever".to_owned(),
+            "null" => "z.ZodNull".to_owned(),
+            "number" => "z.ZodNumber".to_owned(),
+            "string" => "z.ZodString".to_owned(),
+            "boolean" => "z.ZodBoolean".to_owned(),
+            _ => "z.ZodUnknown".to_owned(),
+        },
+        _ => "z.ZodTypeAny".to_owned(),
+    }
+}
+
+// `params` are the type parameters in scope, passed in as schemas
+fn emit(ty: &Type, params: &[String]) -> String {
+    let list = |types: &[Type]| {
+        types
+            .iter()
+            .map(|ty| emit(ty, params))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match ty {
+        Type::Literal(literal) => format!("z.literal({literal})"),
+        Type::Named(name, args) if args.is_empty() => match name.as_str() {
+            "number" | "string" | "boolean" | "null" | "unknown" | "never" | "bigint" | "any" => {
+                format!("z.{name}()")
+            }
+            "undefined" | "void" => "z.undefined()".to_owned(),
+            name if params.iter().any(|param| param == name) => name.to_owned(),
+            name => format!("z.lazy(() => {name})"),
+        },
+        Type::Named(name, args) => match (name.as_str(), args.as_slice()) {
+            ("Array", [item]) => format!("z.array({})", emit(item, params)),
+            ("Record", [key, value]) => {
+                format!("z.record({}, {})", emit(key, params), emit(value, params))
+            }
+            _ => format!("{name}({})", list(args)),
+        },
+        Type::Tuple(items) => format!("z.tuple([{}])", list(items)),
+        Type::Object(fields) => {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(key, optional, ty)| {
+                    let schema = emit(ty, params);
+                    match optional {
+                        true => format!("{key}: {schema}.optional()"),
+                        false => format!("{key}: {schema}"),
+                    }
+                })
+                .collect();
+            format!("z.object({{ {} }})", fields.join(", "))
+        }
+        Type::Union(members) => format!("z.union([{}])", list(members)),
+        Type::Intersection(members) => members
+            .iter()
+            .map(|ty| emit(ty, params))
+            .reduce(|left, right| format!("z.intersection({left}, {right})"))
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{Collection, DoggoRecord};
+    use crate::zodgen::SchemaGenerator;
+
+    #[test]
+    fn emits_schemas_for_payloads_and_collections() {
+        let schemas = SchemaGenerator::new()
+            .patchable::<DoggoRecord>(Collection::Dogs)
+            .generate()
+            .unwrap();
+        // the declaration starting with `prefix`
+        let pick = |prefix: &str| {
+            let start = schemas.find(prefix).unwrap();
+            let end = schemas[start..]
+                .find("\n\n")
+                .map_or(schemas.len(), |end| start + end);
+            schemas[start..end].trim_end().to_owned()
+        };
+        insta::assert_snapshot!(pick("export const Ping "), @r###"export const Ping = z.object({ "sent_at": z.number() });"###);
+        insta::assert_snapshot!(pick("export function Location"), @r###"
+        export function Location<ID extends z.ZodTypeAny, C extends z.ZodTypeAny>(ID: ID, C: C) {
+          return z.object({ "id": z.union([ID, z.null()]), "txn_id": z.union([z.number(), z.null()]), "collection": C, "revision": z.number().optional() });
+        }
+        "###);
+        insta::assert_snapshot!(pick("export const DoggoRecord "), @r###"export const DoggoRecord = z.object({ "id": z.number(), "name": z.string(), "breed": z.string() });"###);
+        insta::assert_snapshot!(pick("export const collections"), @r###"
+        export const collections = {
+          "Dogs": Event(z.number(), z.lazy(() => DoggoRecord), z.literal("Dogs"), z.lazy(() => DoggoPatch)),
+        };
+        "###);
+    }
+}
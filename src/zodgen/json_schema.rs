@@ -0,0 +1,179 @@
+// the json schema counterpart of `SchemaGenerator`, from the `JsonSchema`
+// impls schemars derives. json schema has no generics, so schemars gives every
+// instantiation of a generic type its own definition, and each collection's
+// event schema refers to the one with its types
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Serialize;
+use serde_json::{json, Map};
+
+use super::{check, collection_name};
+use crate::presence::{
+    Join, Leave, PresenceDelta, PresenceEntry, PresenceHeartbeat, PresenceState, PresenceStatus,
+};
+use crate::Error;
+use crate::{
+    Ack, Acknowledgement, Appendable, Authenticate, CaughtUp, ErrorCode, ErrorMessage, Event,
+    EventMeta, FieldError, Filter, GoingAway, Hello, HelloAck, JsonPatch, Nack, PatchOperation,
+    Patchable, Ping, Pong, ResourceIdentifier, Scope, Syncable, ValidationErrors, VectorClock,
+};
+
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+pub struct JsonSchemaGenerator {
+    generator: SchemaGenerator,
+    // (collection name, event schema)
+    collections: Vec<(String, Schema)>,
+    // why a registration was rejected
+    failed: Option<String>,
+}
+
+impl Default for JsonSchemaGenerator {
+    fn default() -> Self {
+        let generator = Self {
+            generator: SchemaSettings::draft2020_12().into_generator(),
+            collections: Vec::new(),
+            failed: None,
+        };
+        // the generic messages are only added with the types of a collection
+        generator
+            .add::<EventMeta>()
+            .add::<VectorClock>()
+            .add::<Scope>()
+            .add::<Filter>()
+            .add::<Join>()
+            .add::<Leave>()
+            .add::<PresenceHeartbeat>()
+            .add::<PresenceState>()
+            .add::<PresenceStatus>()
+            .add::<PresenceEntry>()
+            .add::<PresenceDelta>()
+            .add::<FieldError>()
+            .add::<ValidationErrors>()
+            .add::<Ack>()
+            .add::<Nack>()
+            .add::<Acknowledgement>()
+            .add::<ErrorCode>()
+            .add::<ErrorMessage>()
+            .add::<Hello>()
+            .add::<Authenticate>()
+            .add::<HelloAck>()
+            .add::<Ping>()
+            .add::<Pong>()
+            .add::<GoingAway>()
+            .add::<CaughtUp>()
+            .add::<JsonPatch>()
+            .add::<PatchOperation>()
+            .add::<ResourceIdentifier>()
+    }
+}
+
+impl JsonSchemaGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // adds a definition for `T` (and everything it refers to)
+    pub fn add<T: JsonSchema>(mut self) -> Self {
+        self.generator.subschema_for::<T>();
+        self
+    }
+
+    // a collection of `R`s that are only ever inserted
+    pub fn appendable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Appendable + JsonSchema,
+        R::Collection: Serialize + JsonSchema,
+    {
+        self.register::<Event<(), R, R::Collection>>(collection)
+    }
+
+    pub fn syncable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Syncable + JsonSchema,
+        R::Id: JsonSchema,
+        R::Collection: Serialize + JsonSchema,
+    {
+        self.register::<Event<R::Id, R, R::Collection>>(collection)
+    }
+
+    pub fn patchable<R>(self, collection: R::Collection) -> Self
+    where
+        R: Patchable + JsonSchema,
+        R::Id: JsonSchema,
+        R::Collection: Serialize + JsonSchema,
+        R::Patch: JsonSchema,
+    {
+        self.register::<Event<R::Id, R, R::Collection, R::Patch>>(collection)
+    }
+
+    fn register<E: JsonSchema>(mut self, collection: impl Serialize) -> Self {
+        match collection_name(collection) {
+            Ok(name) => {
+                let schema = self.generator.subschema_for::<E>();
+                self.collections.push((name, schema));
+            }
+            Err(reason) => {
+                self.failed.get_or_insert(reason);
+            }
+        }
+        self
+    }
+
+    // a json schema document with every definition under `$defs` and the
+    // event schema of each collection under `collections`, so it can be
+    // referred to as e.g. `#/collections/dogs`
+    pub fn generate(&self) -> Result<String, Error> {
+        check(
+            self.failed.as_ref(),
+            self.collections.iter().map(|(name, _)| name),
+        )?;
+        let collections: Map<_, _> = self
+            .collections
+            .iter()
+            .map(|(name, schema)| (name.clone(), schema.as_value().clone()))
+            .collect();
+        let document = json!({
+            "$schema": DIALECT,
+            "$defs": self.generator.definitions(),
+            "collections": collections,
+        });
+        serde_json::to_string_pretty(&document).map_err(Error::Encode)
+    }
+
+    pub fn write_to(&self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        std::fs::write(path, self.generate()?).map_err(|err| Error::Transport(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use crate::test::{Collection, DoggoRecord};
+    use crate::zodgen::JsonSchemaGenerator;
+
+    #[test]
+    fn emits_json_schema_for_payloads_and_collections() {
+        let document = JsonSchemaGenerator::new()
+            .patchable::<DoggoRecord>(Collection::Dogs)
+            .generate()
+            .unwrap();
+        let document: Value = serde_json::from_str(&document).unwrap();
+        let pick = |pointer: &str| document.pointer(pointer).unwrap().to_string();
+        insta::assert_snapshot!(pick("/$defs/Ping"), @r###"{"properties":{"sent_at":{"format":"uint64","minimum":0,"type":"integer"}},"required":["sent_at"],"type":"object"}"###);
+        insta::assert_snapshot!(pick("/collections/Dogs"), @r###"{"$ref":"#/$defs/Event"}"###);
+        // generic types are instantiated with the collection's types
+        insta::assert_snapshot!(pick("/$defs/Location"), @r###"{"properties":{"collection":{"$ref":"#/$defs/Collection"},"id":{"format":"uint32","minimum":0,"type":["integer","null"]},"revision":{"format":"uint64","minimum":0,"type":["integer","null"]},"txn_id":{"format":"uint32","minimum":0,"type":["integer","null"]}},"required":["collection"],"type":"object"}"###);
+    }
+
+    #[test]
+    fn rejects_a_collection_registered_twice() {
+        let err = JsonSchemaGenerator::new()
+            .patchable::<DoggoRecord>(Collection::Dogs)
+            .patchable::<DoggoRecord>(Collection::Dogs)
+            .generate()
+            .unwrap_err();
+        insta::assert_snapshot!(err.to_string(), @"invalid collection: Dogs is registered twice");
+    }
+}
@@ -0,0 +1,115 @@
+use rsp::tsgen::{documented_binding, ClientGenerator};
+use rsp::{Appendable, Syncable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+#[derive(Serialize, Deserialize, TS)]
+enum Collection {
+    Dogs,
+}
+
+#[derive(Serialize, TS, rsp_derive::Appendable, rsp_derive::Syncable)]
+#[rsp(collection = Collection::Dogs)]
+struct DoggoRecord {
+    id: u32,
+    name: String,
+}
+
+#[derive(Serialize, TS, rsp_derive::Appendable, rsp_derive::Syncable)]
+#[rsp(collection = "Cats")]
+struct CatRecord {
+    #[rsp(id)]
+    tag: String,
+    name: String,
+}
+
+#[test]
+fn derived_impls_work() {
+    let doggo = DoggoRecord {
+        id: 1,
+        name: "Barky".to_string(),
+    };
+    let json = doggo.to_upsert_event().into_ws_body().try_json().unwrap();
+    insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"upsert","payload":{"location":{"id":1,"txn_id":null,"collection":"Dogs"},"data":{"id":1,"name":"Barky"}}}}}"###);
+
+    let cat = CatRecord {
+        tag: "whiskers".to_string(),
+        name: "Whiskers".to_string(),
+    };
+    assert_eq!(cat.id(), "whiskers");
+    let json = cat.to_insert_event().into_ws_body().try_json().unwrap();
+    insta::assert_snapshot!(json, @r###"{"data":{"verb":{"type":"insert","payload":{"location":{"id":null,"txn_id":null,"collection":"Cats"},"data":{"tag":"whiskers","name":"Whiskers"}}}}}"###);
+}
+
+/// Where a tracked dog is.
+///
+/// Updated by the collar every minute.
+#[derive(Serialize, TS, rsp_derive::Appendable, rsp_derive::Syncable, rsp_derive::TsDocs)]
+#[serde(rename_all = "camelCase")]
+#[rsp(collection = "Positions")]
+struct DoggoPosition {
+    id: u32,
+    /// Degrees north, from -90 to 90.
+    lat_degrees: f64,
+    #[serde(rename = "lng")]
+    /// Degrees east, from -180 to 180.
+    lng_degrees: f64,
+    fix: Fix,
+}
+
+/// How the position was found.
+#[derive(Serialize, Deserialize, TS, rsp_derive::TsDocs)]
+#[serde(rename_all = "snake_case")]
+enum Fix {
+    /// Satellites only.
+    Gps,
+    /// Nearby wifi networks, accurate to */ 50m.
+    WifiScan,
+    Unknown,
+}
+
+#[test]
+fn doc_comments_reach_the_bindings() {
+    insta::assert_snapshot!(documented_binding::<DoggoPosition>().unwrap(), @r###"
+    // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+    import type { Fix } from "./Fix";
+    
+    /**
+     * Where a tracked dog is.
+     *
+     * Updated by the collar every minute.
+     */
+    export interface DoggoPosition { id: number, /** Degrees north, from -90 to 90. */ latDegrees: number, /** Degrees east, from -180 to 180. */ lng: number, fix: Fix, }
+    "###);
+    insta::assert_snapshot!(documented_binding::<Fix>().unwrap(), @r###"
+    // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+    
+    /**
+     * How the position was found.
+     *
+     * - `gps`: Satellites only.
+     * - `wifi_scan`: Nearby wifi networks, accurate to *\/ 50m.
+     */
+    export type Fix = "gps" | "wifi_scan" | "unknown";
+    "###);
+
+    let client = ClientGenerator::new()
+        .syncable::<DoggoPosition>("Positions".to_owned())
+        .with_docs::<DoggoPosition>()
+        .generate()
+        .unwrap();
+    let collections: Vec<_> = client
+        .lines()
+        .skip_while(|line| !line.starts_with("export type Collections"))
+        .take(7)
+        .collect();
+    insta::assert_snapshot!(collections.join("\n"), @r###"
+    export type Collections = {
+      /**
+       * Where a tracked dog is.
+       *
+       * Updated by the collar every minute.
+       */
+      "Positions": { id: number, record: DoggoPosition, patch: never },
+    "###);
+}
@@ -0,0 +1,345 @@
+use rsp::presence::PresenceStatus;
+use rsp::wire_compat::WireCompat;
+use rsp::{
+    Ack, Acknowledgement, Appendable, AuthResult, Authenticate, CaughtUp, Claims, Command,
+    ConflictError, Ephemeral, ErrorCode, ErrorMessage, Event, EventBatch, EventMeta, Filter,
+    GoingAway, Hello, JsonPatch, LiveQuery, LiveQueryTracker, Materializer, Mutate, MutationResult,
+    Nack, PatchOperation, Ping, Pong, Query, QueryResult, Rejection, Snapshot, SnapshotEntry,
+    Subscribe, Syncable, Txn, Unsubscribe, ValidationErrors, VectorClock,
+};
+use rsp::{CollectionPattern, EventVerb};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+// the fixtures every message of the protocol is checked against. run with
+// `RSP_BLESS_FIXTURES=1` after changing the wire format on purpose
+const FIXTURES: &str = "tests/fixtures/wire";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+struct Dog {
+    id: u32,
+    name: String,
+}
+
+impl Appendable for Dog {
+    type Collection = String;
+
+    fn collection(&self) -> String {
+        "dogs".to_owned()
+    }
+}
+
+impl Syncable for Dog {
+    type Id = u32;
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+type DogEvent = Event<u32, Dog, String, JsonPatch>;
+type DogCommand = Command<u32, Dog, String, JsonPatch>;
+
+fn barky() -> Dog {
+    Dog {
+        id: 1,
+        name: "Barky".to_owned(),
+    }
+}
+
+fn rename() -> JsonPatch {
+    JsonPatch::from(vec![PatchOperation::Replace {
+        path: "/name".to_owned(),
+        value: "Woofy".into(),
+    }])
+}
+
+fn events() -> Vec<(&'static str, DogEvent)> {
+    let txn = Txn::builder().txn_id(7).build();
+    let mut clock = VectorClock::new();
+    clock.tick("server-1");
+    vec![
+        (
+            "event_insert",
+            Event::new_insert_event(barky(), "dogs".to_owned()),
+        ),
+        (
+            "event_update",
+            barky().to_update_event().into_patchable().with_revision(3),
+        ),
+        (
+            "event_upsert",
+            barky()
+                .to_upsert_event()
+                .into_patchable()
+                .with_expected_revision(2)
+                .with_expires_at(90_000),
+        ),
+        (
+            "event_patch",
+            Event::new_patch_event(1, rename(), "dogs".to_owned()),
+        ),
+        (
+            "event_delete",
+            DogEvent::new_delete_event(1, "dogs".to_owned()).with_deleted_at(60_000),
+        ),
+        ("event_txn_begin", txn.begin_event()),
+        (
+            "event_txn_member",
+            txn.stamp(Event::new_insert_event(barky(), "dogs".to_owned())),
+        ),
+        ("event_txn_commit", txn.commit_event()),
+        ("event_txn_abort", DogEvent::new(EventVerb::TxnAbort(7))),
+        (
+            "event_envelope",
+            Event::new_insert_event(barky(), "dogs".to_owned())
+                .with_occurred_at(1_000)
+                .with_schema_version(2)
+                .with_scope("tenant-1")
+                .with_idempotency_key("req-1")
+                .with_meta(
+                    EventMeta::new()
+                        .with_actor_id("user-1")
+                        .with_origin("web")
+                        .with_correlation_id("corr-1")
+                        .with_causation_id("cause-1")
+                        .with_extra("region", "eu"),
+                )
+                .with_causality(clock),
+        ),
+    ]
+}
+
+fn commands() -> Vec<(&'static str, DogCommand)> {
+    let subscribe = Subscribe {
+        collections: vec!["dogs".to_owned()],
+        patterns: vec![CollectionPattern::new("org/*/dogs").unwrap()],
+        from_seq: Some(10),
+    };
+    let query = Query::new(1, "dogs".to_owned())
+        .with_ids(vec![1, 2])
+        .with_filter("name", "Barky")
+        .with_cursor("1")
+        .with_limit(10);
+    let live_query = LiveQuery {
+        query_id: 2,
+        collection: "dogs".to_owned(),
+        filter: Filter::eq("name", "Barky").and(Filter::gt("id", 0).negate()),
+    };
+    vec![
+        ("command_subscribe", DogCommand::Subscribe(subscribe)),
+        (
+            "command_unsubscribe",
+            DogCommand::Unsubscribe(Unsubscribe {
+                collections: vec!["dogs".to_owned()],
+                patterns: Vec::new(),
+            }),
+        ),
+        ("command_query", DogCommand::Query(query)),
+        ("command_live_query", DogCommand::LiveQuery(live_query)),
+        (
+            "command_mutate",
+            DogCommand::Mutate(Mutate {
+                request_id: 3,
+                event: Event::new_patch_event(1, rename(), "dogs".to_owned()),
+            }),
+        ),
+        (
+            "command_ack",
+            DogCommand::Ack(Acknowledgement::Ack(Ack { seq: 12 })),
+        ),
+        (
+            "command_nack",
+            DogCommand::Ack(Acknowledgement::Nack(Nack {
+                seq: 13,
+                reason: Some("handler failed".to_owned()),
+            })),
+        ),
+        ("command_ping", DogCommand::Ping(Ping { sent_at: 1_000 })),
+        (
+            "command_pong",
+            DogCommand::Pong(Pong {
+                ping_sent_at: 1_000,
+                sent_at: 1_005,
+            }),
+        ),
+        (
+            "command_authenticate",
+            DogCommand::Authenticate(Authenticate {
+                token: "secret".to_owned(),
+            }),
+        ),
+        (
+            "command_join",
+            DogCommand::Join(rsp::presence::Join {
+                room: "park".to_owned(),
+                state: rsp::presence::PresenceState::online().with_payload("typing"),
+            }),
+        ),
+        (
+            "command_leave",
+            DogCommand::Leave(rsp::presence::Leave {
+                room: "park".to_owned(),
+            }),
+        ),
+        (
+            "command_presence_heartbeat",
+            DogCommand::PresenceHeartbeat(rsp::presence::PresenceHeartbeat {
+                room: "park".to_owned(),
+            }),
+        ),
+        (
+            "command_ephemeral",
+            DogCommand::Ephemeral(
+                Ephemeral::new("dogs".to_owned(), "wags")
+                    .with_id(1)
+                    .with_origin("conn-1")
+                    .with_scope("tenant-1"),
+            ),
+        ),
+    ]
+}
+
+#[test]
+fn the_wire_format_is_unchanged() {
+    let mut compat = WireCompat::new(FIXTURES);
+    for (name, event) in events() {
+        compat.check(name, &event.into_ws_body());
+    }
+    for (name, command) in commands() {
+        compat.check(name, &command.into_ws_body());
+    }
+
+    let mut batch = EventBatch::new(20).with_txn_id(7);
+    batch.push(Event::new_insert_event(barky(), "dogs".to_owned()));
+    batch.push(DogEvent::new_delete_event(2, "dogs".to_owned()));
+    compat.check("event_batch", &batch.into_ws_body());
+
+    let hello = Hello::new(vec![1, 2], vec!["msgpack".to_owned(), "json".to_owned()]);
+    compat.check("hello", &hello);
+    compat.check("hello_ack", &hello.negotiate(&[1], &["json"]).unwrap());
+    compat.check(
+        "error_message",
+        &ErrorMessage::new(ErrorCode::Conflict, "a write raced another one")
+            .with_related_seq(4)
+            .into_ws_body(),
+    );
+
+    compat.check(
+        "auth_accepted",
+        &AuthResult::Accepted(Claims::new("user-1").with_collections(vec!["dogs".to_owned()]))
+            .into_ws_body(),
+    );
+    compat.check(
+        "auth_rejected",
+        &AuthResult::<String>::Rejected("token expired".to_owned()).into_ws_body(),
+    );
+
+    compat.check(
+        "mutation_accepted",
+        &MutationResult::<u32, String>::accepted(4, 21)
+            .with_assigned_id(1)
+            .into_ws_body(),
+    );
+    let conflict = ConflictError::new("dogs".to_owned(), Some(1), 2, Some(3));
+    for (name, rejection) in [
+        ("mutation_conflict", Rejection::Conflict(conflict.clone())),
+        ("mutation_not_found", Rejection::NotFound),
+        ("mutation_forbidden", Rejection::Forbidden),
+        ("mutation_invalid", Rejection::Invalid("no name".to_owned())),
+        (
+            "mutation_validation",
+            Rejection::Validation(ValidationErrors::new().with_error(
+                "name",
+                "required",
+                "a dog needs a name",
+            )),
+        ),
+    ] {
+        compat.check(
+            name,
+            &MutationResult::<u32, String>::rejected(4, rejection).into_ws_body(),
+        );
+    }
+    compat.check("conflict", &conflict.into_ws_body());
+    compat.check("caught_up", &CaughtUp { live_seq: 42 }.into_ws_body());
+    compat.check(
+        "going_away",
+        &GoingAway::new(std::time::Duration::from_secs(2)).into_ws_body(),
+    );
+
+    let records = || [(1, barky()), (2, barky())];
+    let query = Query::new(1, "dogs".to_owned()).with_limit(1);
+    compat.check(
+        "query_result",
+        &QueryResult::page(query, 22, records())
+            .unwrap()
+            .into_ws_body(),
+    );
+    let entries = || {
+        records()
+            .into_iter()
+            .map(|(id, dog)| SnapshotEntry::new(id, dog))
+            .collect::<Vec<_>>()
+    };
+    compat.check(
+        "snapshot",
+        &Snapshot::new("dogs".to_owned(), 22, entries()).into_ws_body(),
+    );
+    let chunk = Snapshot::new("dogs".to_owned(), 22, entries())
+        .into_chunks(1)
+        .next()
+        .unwrap();
+    compat.check("snapshot_chunk", &chunk.into_ws_body());
+
+    let mut materializer = Materializer::new();
+    let mut tracker = LiveQueryTracker::new(
+        LiveQuery {
+            query_id: 2,
+            collection: "dogs".to_owned(),
+            filter: Filter::eq("name", "Barky"),
+        },
+        &materializer,
+    );
+    let insert = barky().to_upsert_event();
+    materializer.apply(insert.clone());
+    compat.check(
+        "live_query_update",
+        &tracker
+            .apply(&insert, &materializer)
+            .unwrap()
+            .into_ws_body(),
+    );
+
+    compat.check(
+        "presence_delta",
+        &rsp::presence::PresenceDelta {
+            room: "park".to_owned(),
+            joins: vec![rsp::presence::PresenceEntry {
+                member: "user-1".to_owned(),
+                state: rsp::presence::PresenceState {
+                    status: PresenceStatus::Away,
+                    payload: None,
+                },
+            }],
+            leaves: vec!["user-2".to_owned()],
+        }
+        .into_ws_body(),
+    );
+    compat.check(
+        "ephemeral",
+        &Ephemeral::<u32, String>::new("dogs".to_owned(), "wags").into_ws_body(),
+    );
+    compat.check(
+        "framed",
+        &DogEvent::new_insert_event(barky(), "dogs".to_owned())
+            .into_ws_body()
+            .with_protocol_version(1)
+            .with_channel(3),
+    );
+
+    assert!(compat.checked() > 40);
+    if let Err(err) = compat.finish() {
+        panic!("{err}");
+    }
+}